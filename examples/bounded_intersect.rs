@@ -0,0 +1,59 @@
+use anyhow::Result;
+use embree4_rs::prelude::*;
+use glam::Vec3;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+// Compares `intersect_1` with `intersect_1_bounded` on rays of which most miss the scene bounds,
+// as in environment-dominated renders.
+pub fn main() -> Result<()> {
+    let device = Device::try_new(None)?;
+    let mut rng = StdRng::seed_from_u64(0);
+
+    let num_tris = 100_000;
+    let mut vertices = Vec::with_capacity(3 * num_tris);
+    let mut indices = Vec::with_capacity(num_tris);
+    for i in 0..num_tris as u32 {
+        let pos = rng.gen::<Vec3>();
+        for _ in 0..3 {
+            let p = pos + 0.01 * rng.gen::<Vec3>();
+            vertices.push((p.x, p.y, p.z));
+        }
+        indices.push((3 * i, 3 * i + 1, 3 * i + 2));
+    }
+
+    let mesh = TriangleMeshGeometry::try_new(&device, &vertices, &indices)?;
+    let scene = Scene::try_new(&device, SceneOptions::default())?;
+    scene.attach_geometry(&mesh)?;
+    let scene = scene.commit()?;
+
+    let num_rays = 1_000_000;
+    let rays: Vec<_> = (0..num_rays)
+        .map(|_| {
+            let origin = 10.0 * (2.0 * rng.gen::<Vec3>() - 1.0);
+            let direction = 2.0 * rng.gen::<Vec3>() - 1.0;
+            RayBuilder::new(
+                (origin.x, origin.y, origin.z),
+                (direction.x, direction.y, direction.z),
+            )
+            .build()
+        })
+        .collect();
+
+    for (name, bounded) in [("intersect_1", false), ("intersect_1_bounded", true)] {
+        let t0 = std::time::Instant::now();
+        let mut hits = 0;
+        for ray in &rays {
+            let hit = if bounded {
+                scene.intersect_1_bounded(*ray)?
+            } else {
+                scene.intersect_1(*ray)?
+            };
+            hits += hit.is_some() as usize;
+        }
+        let elapsed = t0.elapsed();
+        let rays_per_sec = (num_rays as f32 / elapsed.as_secs_f32()) as usize;
+        println!("{name}: {hits} hits in {elapsed:?} ({rays_per_sec} rays/s)");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,187 @@
+use embree4_sys::{RTCBounds, RTCRay};
+
+/// An axis-aligned bounding box.
+///
+/// An empty box has its lower corner above its upper corner, as reported by Embree for empty
+/// scenes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub lower: [f32; 3],
+    pub upper: [f32; 3],
+}
+
+impl Aabb {
+    /// Constructs a new `Aabb` from its lower and upper corners.
+    pub fn new(lower: [f32; 3], upper: [f32; 3]) -> Self {
+        Self { lower, upper }
+    }
+
+    /// Returns `true` if the box contains no point.
+    pub fn is_empty(&self) -> bool {
+        (0..3).any(|axis| self.lower[axis] > self.upper[axis])
+    }
+
+    /// Returns the smallest box containing both boxes.
+    pub fn union(&self, other: &Aabb) -> Self {
+        Self {
+            lower: [0, 1, 2].map(|axis| self.lower[axis].min(other.lower[axis])),
+            upper: [0, 1, 2].map(|axis| self.upper[axis].max(other.upper[axis])),
+        }
+    }
+
+    /// Returns `true` if the boxes share at least one point.
+    pub fn overlaps(&self, other: &Aabb) -> bool {
+        (0..3).all(|axis| {
+            self.lower[axis] <= other.upper[axis] && other.lower[axis] <= self.upper[axis]
+        })
+    }
+
+    /// Returns the box grown by `margin` on every side.
+    pub fn expand(&self, margin: f32) -> Self {
+        Self {
+            lower: self.lower.map(|x| x - margin),
+            upper: self.upper.map(|x| x + margin),
+        }
+    }
+
+    /// Returns `false` if the box is entirely outside of one of the frustum planes.
+    ///
+    /// Planes are given as `[a, b, c, d]`, with the inside of the frustum satisfying
+    /// `a * x + b * y + c * z + d >= 0`, as produced by
+    /// [Camera::tile_frustum](crate::camera::Camera::tile_frustum). The test is conservative: boxes
+    /// near the corners of the frustum may be reported as intersecting it, as are boxes for which
+    /// the test is not finite.
+    pub fn intersects_frustum(&self, planes: &[[f32; 4]; 6]) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+
+        planes.iter().all(|&[a, b, c, d]| {
+            // Corner of the box the furthest along the plane normal
+            let x = if a >= 0.0 {
+                self.upper[0]
+            } else {
+                self.lower[0]
+            };
+            let y = if b >= 0.0 {
+                self.upper[1]
+            } else {
+                self.lower[1]
+            };
+            let z = if c >= 0.0 {
+                self.upper[2]
+            } else {
+                self.lower[2]
+            };
+            // A NaN, e.g. from an unbounded box, does not cull the box
+            let distance = a * x + b * y + c * z + d;
+            distance >= 0.0 || distance.is_nan()
+        })
+    }
+
+    /// Slab test of the `[tnear, tfar]` segment of the ray against the box.
+    ///
+    /// Returns the distances at which the segment enters and exits the box, clipped to
+    /// `[tnear, tfar]`, or `None` if it misses the box. Useful to march rays through the volume of
+    /// a scene, or to skip traversal for rays that miss its bounds. Rays parallel to an axis are
+    /// handled without dividing by their zero direction components.
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::prelude::*;
+    ///
+    /// let aabb = Aabb::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
+    /// let ray = RayBuilder::new((0.5, 0.5, -1.0), (0.0, 0.0, 1.0)).build();
+    /// assert_eq!(aabb.intersect_ray(&ray), Some((1.0, 2.0)));
+    /// ```
+    pub fn intersect_ray(&self, ray: &RTCRay) -> Option<(f32, f32)> {
+        let org = [ray.org_x, ray.org_y, ray.org_z];
+        let dir = [ray.dir_x, ray.dir_y, ray.dir_z];
+
+        let mut t0 = ray.tnear;
+        let mut t1 = ray.tfar;
+        for axis in 0..3 {
+            if dir[axis] == 0.0 {
+                // Parallel to the slab: either always or never inside of it
+                if org[axis] < self.lower[axis] || org[axis] > self.upper[axis] {
+                    return None;
+                }
+                continue;
+            }
+
+            let inv_dir = 1.0 / dir[axis];
+            let a = (self.lower[axis] - org[axis]) * inv_dir;
+            let b = (self.upper[axis] - org[axis]) * inv_dir;
+            t0 = t0.max(a.min(b));
+            t1 = t1.min(a.max(b));
+            if t0 > t1 {
+                return None;
+            }
+        }
+
+        Some((t0, t1))
+    }
+}
+
+impl From<RTCBounds> for Aabb {
+    fn from(bounds: RTCBounds) -> Self {
+        Self {
+            lower: [bounds.lower_x, bounds.lower_y, bounds.lower_z],
+            upper: [bounds.upper_x, bounds.upper_y, bounds.upper_z],
+        }
+    }
+}
+
+#[test]
+fn unit_box_slab_test() {
+    let aabb = Aabb::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
+    let ray = |org: [f32; 3], dir: [f32; 3]| RTCRay {
+        org_x: org[0],
+        org_y: org[1],
+        org_z: org[2],
+        dir_x: dir[0],
+        dir_y: dir[1],
+        dir_z: dir[2],
+        tnear: 0.0,
+        tfar: f32::INFINITY,
+        ..Default::default()
+    };
+
+    // Along each axis, in both directions
+    for axis in 0..3 {
+        for sign in [1.0, -1.0] {
+            let mut org = [0.5; 3];
+            org[axis] = 0.5 - 2.0 * sign;
+            let mut dir = [0.0; 3];
+            dir[axis] = sign;
+            assert_eq!(aabb.intersect_ray(&ray(org, dir)), Some((1.5, 2.5)));
+        }
+    }
+
+    // Diagonal, through opposite corners
+    let (t0, t1) = aabb
+        .intersect_ray(&ray([-1.0, -1.0, -1.0], [1.0, 1.0, 1.0]))
+        .unwrap();
+    assert!((t0 - 1.0).abs() < 1e-6 && (t1 - 2.0).abs() < 1e-6);
+
+    // From inside, the entry is clipped to tnear
+    assert_eq!(
+        aabb.intersect_ray(&ray([0.5, 0.5, 0.5], [0.0, -2.0, 0.0])),
+        Some((0.0, 0.25))
+    );
+
+    // Parallel to the faces but outside of the slabs, and pointing away
+    assert_eq!(
+        aabb.intersect_ray(&ray([2.0, 0.5, -1.0], [0.0, 0.0, 1.0])),
+        None
+    );
+    assert_eq!(
+        aabb.intersect_ray(&ray([0.5, 0.5, 2.0], [0.0, 0.0, 1.0])),
+        None
+    );
+
+    // Too short to reach the box
+    let mut short = ray([0.5, 0.5, -1.0], [0.0, 0.0, 1.0]);
+    short.tfar = 0.5;
+    assert_eq!(aabb.intersect_ray(&short), None);
+}
@@ -0,0 +1,242 @@
+//! A small throughput benchmark, to validate a setup and the threading of this crate.
+//!
+//! Rays are cast from a sphere around the scene towards its inside, on several threads sharing
+//! the same [CommittedScene]. Each thread sets up its own floating point control state, which
+//! this also guards.
+
+use std::{thread, time::Duration, time::Instant};
+
+use anyhow::{bail, Result};
+
+use crate::{context::RayQueryContext, ray::RayBuilder, scene::CommittedScene};
+
+/// Options of [run].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BenchmarkOptions {
+    /// The total number of rays cast, split evenly across the threads.
+    pub ray_count: usize,
+    /// The number of threads casting rays.
+    pub thread_count: usize,
+    /// The seed of the random rays, so that runs can be compared.
+    pub seed: u64,
+    /// How rays are given a query context.
+    pub context: ContextMode,
+}
+
+/// How the rays of a benchmark [run] are given a [RayQueryContext].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ContextMode {
+    /// No context, with [CommittedScene::intersect_1].
+    #[default]
+    None,
+    /// A new context for each ray, with [CommittedScene::intersect_1_with_context].
+    PerCall,
+    /// One context per thread, with [CommittedScene::intersect_1_reuse].
+    Reused,
+}
+
+impl Default for BenchmarkOptions {
+    fn default() -> Self {
+        Self {
+            ray_count: 1_000_000,
+            thread_count: thread::available_parallelism().map_or(1, |n| n.get()),
+            seed: 0,
+            context: ContextMode::None,
+        }
+    }
+}
+
+/// The result of a benchmark [run].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BenchmarkReport {
+    /// The number of rays cast.
+    pub ray_count: usize,
+    /// The number of rays that hit the scene.
+    pub hit_count: usize,
+    /// The wall time taken to cast all rays.
+    pub elapsed: Duration,
+}
+
+impl BenchmarkReport {
+    /// Returns the throughput of the run, in rays per second.
+    pub fn rays_per_second(&self) -> f64 {
+        self.ray_count as f64 / self.elapsed.as_secs_f64().max(f64::MIN_POSITIVE)
+    }
+}
+
+/// Casts random rays at the scene across several threads, and reports the throughput.
+///
+/// # Example
+/// ```
+/// use embree4_rs::{bench::*, prelude::*};
+///
+/// let device = Device::try_new(None).unwrap();
+/// let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 0.0), 1.0).unwrap();
+/// let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+/// scene.attach_geometry(&sphere).unwrap();
+/// let scene = scene.commit().unwrap();
+///
+/// let options = BenchmarkOptions {
+///     ray_count: 10_000,
+///     ..Default::default()
+/// };
+/// let report = run(&scene, &options).unwrap();
+/// println!("{:.0} rays/s", report.rays_per_second());
+/// ```
+pub fn run(scene: &CommittedScene, options: &BenchmarkOptions) -> Result<BenchmarkReport> {
+    if options.thread_count == 0 {
+        bail!("At least one thread is required");
+    }
+
+    let (center, radius) = scene.bounding_sphere()?;
+    let radius = if radius > 0.0 { radius } else { 1.0 };
+
+    let start = Instant::now();
+    let hit_counts = thread::scope(|s| {
+        let workers: Vec<_> = (0..options.thread_count)
+            .map(|index| {
+                // The first threads cast the remainder of the rays
+                let ray_count = options.ray_count / options.thread_count
+                    + usize::from(index < options.ray_count % options.thread_count);
+                let mut rng = SplitMix64(options.seed ^ (index as u64).wrapping_mul(GOLDEN_GAMMA));
+
+                s.spawn(move || -> Result<usize> {
+                    let mut context = RayQueryContext::new();
+                    let mut hit_count = 0;
+                    for _ in 0..ray_count {
+                        // From a point of a sphere twice as large as the scene, towards a point
+                        // inside of the scene
+                        let from = rng.unit_vector();
+                        let to = rng.unit_vector().map(|x| x * rng.next_f32());
+                        let org = [0, 1, 2].map(|k| center[k] + 2.0 * radius * from[k]);
+                        let dir = [0, 1, 2].map(|k| radius * (to[k] - 2.0 * from[k]));
+
+                        let ray =
+                            RayBuilder::new((org[0], org[1], org[2]), (dir[0], dir[1], dir[2]))
+                                .build();
+                        let hit = match options.context {
+                            ContextMode::None => scene.intersect_1(ray)?,
+                            ContextMode::PerCall => {
+                                scene.intersect_1_with_context(ray, &RayQueryContext::new())?
+                            }
+                            ContextMode::Reused => scene.intersect_1_reuse(ray, &mut context)?,
+                        };
+                        if hit.is_some() {
+                            hit_count += 1;
+                        }
+                    }
+                    Ok(hit_count)
+                })
+            })
+            .collect();
+
+        workers
+            .into_iter()
+            .map(|worker| worker.join().expect("Benchmark thread panicked"))
+            .collect::<Result<Vec<_>>>()
+    })?;
+
+    Ok(BenchmarkReport {
+        ray_count: options.ray_count,
+        hit_count: hit_counts.into_iter().sum(),
+        elapsed: start.elapsed(),
+    })
+}
+
+/// Measures the average cost of enabling and restoring the flush-to-zero and denormals-are-zero
+/// modes, which every query of this crate pays so that they do not leak out of Embree calls.
+///
+/// # Example
+/// ```
+/// use embree4_rs::bench::flush_denormals_overhead;
+///
+/// let overhead = flush_denormals_overhead(100_000);
+/// println!("{} ns per call", overhead.as_nanos());
+/// ```
+pub fn flush_denormals_overhead(iterations: usize) -> Duration {
+    let start = Instant::now();
+    for _ in 0..iterations {
+        crate::with_denormals_flushed(|| std::hint::black_box(()));
+    }
+    start.elapsed().div_f64(iterations.max(1) as f64)
+}
+
+const GOLDEN_GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+// A small and fast generator, good enough for spreading rays
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(GOLDEN_GAMMA);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // Uniform in `[0, 1)`
+    fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    // Uniform on the unit sphere
+    fn unit_vector(&mut self) -> [f32; 3] {
+        let z = 2.0 * self.next_f32() - 1.0;
+        let phi = std::f32::consts::TAU * self.next_f32();
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        [r * phi.cos(), r * phi.sin(), z]
+    }
+}
+
+#[test]
+fn benchmark_reports_positive_throughput() {
+    use crate::{
+        device::Device,
+        geometry::SphereGeometry,
+        scene::{Scene, SceneOptions},
+    };
+
+    let device = Device::try_new(None).unwrap();
+    let sphere = SphereGeometry::try_new(&device, (1.0, 2.0, 3.0), 1.0).unwrap();
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    scene.attach_geometry(&sphere).unwrap();
+    let scene = scene.commit().unwrap();
+
+    let options = BenchmarkOptions {
+        ray_count: 10_001,
+        thread_count: 4,
+        seed: 7,
+        context: ContextMode::None,
+    };
+    let report = run(&scene, &options).unwrap();
+    assert_eq!(report.ray_count, 10_001);
+    assert!(report.hit_count > 0 && report.hit_count <= report.ray_count);
+    assert!(report.rays_per_second() > 0.0);
+}
+
+#[test]
+fn context_modes_hit_the_same_rays() {
+    use crate::{
+        device::Device,
+        geometry::SphereGeometry,
+        scene::{Scene, SceneOptions},
+    };
+
+    let device = Device::try_new(None).unwrap();
+    let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 0.0), 1.0).unwrap();
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    scene.attach_geometry(&sphere).unwrap();
+    let scene = scene.commit().unwrap();
+
+    let reports = [ContextMode::None, ContextMode::PerCall, ContextMode::Reused].map(|context| {
+        let options = BenchmarkOptions {
+            ray_count: 10_000,
+            thread_count: 2,
+            seed: 3,
+            context,
+        };
+        run(&scene, &options).unwrap()
+    });
+    assert!(reports.iter().all(|r| r.hit_count == reports[0].hit_count));
+}
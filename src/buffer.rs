@@ -0,0 +1,273 @@
+use std::{
+    alloc::{self, Layout},
+    any::Any,
+    fmt,
+    mem::{align_of, size_of, size_of_val},
+    ops::{Deref, DerefMut},
+    ptr::NonNull,
+    slice,
+};
+
+use anyhow::{bail, Result};
+
+use crate::device::Device;
+
+/// A fixed-size array aligned and padded for Embree's shared buffers.
+///
+/// Embree requires the data of shared buffers to be 16-byte aligned, which a `Vec<f32>` does not
+/// guarantee, and reads the last item with 16-byte loads, so the data is followed by 16 bytes of
+/// zeroed padding.
+///
+/// # Example
+/// ```
+/// use embree4_rs::buffer::AlignedVec;
+///
+/// let vertices = vec![0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0];
+/// let vertices = AlignedVec::from_slice(&vertices);
+/// assert_eq!(vertices.as_ptr() as usize % AlignedVec::<f32>::ALIGNMENT, 0);
+/// assert_eq!(vertices.len(), 9);
+/// ```
+pub struct AlignedVec<T: Copy> {
+    ptr: NonNull<T>,
+    len: usize,
+    layout: Layout,
+}
+
+impl<T: Copy> AlignedVec<T> {
+    /// Alignment of the data, in bytes.
+    pub const ALIGNMENT: usize = 16;
+
+    /// Copies `data` into a new aligned and padded array.
+    ///
+    /// This always copies, whether `data` is aligned or not: the copy is what guarantees the
+    /// alignment and padding of the result.
+    pub fn from_slice(data: &[T]) -> Self {
+        assert!(
+            align_of::<T>() <= Self::ALIGNMENT,
+            "AlignedVec items must not be aligned to more than 16 bytes"
+        );
+
+        let bytes = size_of_val(data);
+        let padded_bytes = bytes.div_ceil(Self::ALIGNMENT) * Self::ALIGNMENT + Self::ALIGNMENT;
+        let layout = Layout::from_size_align(padded_bytes, Self::ALIGNMENT)
+            .expect("AlignedVec size overflows");
+
+        let ptr = unsafe { alloc::alloc_zeroed(layout) } as *mut T;
+        let Some(ptr) = NonNull::new(ptr) else {
+            alloc::handle_alloc_error(layout);
+        };
+        unsafe {
+            ptr.as_ptr()
+                .copy_from_nonoverlapping(data.as_ptr(), data.len());
+        }
+
+        Self {
+            ptr,
+            len: data.len(),
+            layout,
+        }
+    }
+
+    /// Returns `true` if `data` starts on a 16-byte boundary, as required by Embree's shared
+    /// buffers. Its padding is not checked.
+    pub fn is_aligned(data: &[T]) -> bool {
+        (data.as_ptr() as usize).is_multiple_of(Self::ALIGNMENT)
+    }
+}
+
+impl<T: Copy> Deref for AlignedVec<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl<T: Copy> DerefMut for AlignedVec<T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl<T: Copy> Clone for AlignedVec<T> {
+    fn clone(&self) -> Self {
+        Self::from_slice(self)
+    }
+}
+
+impl<T: Copy + fmt::Debug> fmt::Debug for AlignedVec<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: Copy> From<&[T]> for AlignedVec<T> {
+    fn from(data: &[T]) -> Self {
+        Self::from_slice(data)
+    }
+}
+
+impl<T: Copy> Drop for AlignedVec<T> {
+    fn drop(&mut self) {
+        unsafe {
+            alloc::dealloc(self.ptr.as_ptr() as *mut u8, self.layout);
+        }
+    }
+}
+
+unsafe impl<T: Copy + Send> Send for AlignedVec<T> {}
+unsafe impl<T: Copy + Sync> Sync for AlignedVec<T> {}
+
+/// Item types that buffer data can be read as.
+///
+/// # Safety
+/// Every bit pattern of the size of the type must be a valid value, and the type must not have
+/// padding bytes.
+pub unsafe trait BufferItem: Copy + Send + Sync + 'static {}
+
+unsafe impl BufferItem for u8 {}
+unsafe impl BufferItem for u16 {}
+unsafe impl BufferItem for u32 {}
+unsafe impl BufferItem for u64 {}
+unsafe impl BufferItem for i8 {}
+unsafe impl BufferItem for i16 {}
+unsafe impl BufferItem for i32 {}
+unsafe impl BufferItem for i64 {}
+unsafe impl BufferItem for f32 {}
+unsafe impl BufferItem for f64 {}
+unsafe impl<T: BufferItem, const N: usize> BufferItem for [T; N] {}
+
+/// An Embree buffer, either allocated by Embree or sharing memory owned by the buffer.
+///
+/// # Example
+/// ```
+/// use embree4_rs::{buffer::*, prelude::*};
+///
+/// let device = Device::try_new(None).unwrap();
+/// let vertices = [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0];
+/// let buffer = Buffer::try_new_shared(&device, AlignedVec::from_slice(&vertices)).unwrap();
+/// assert_eq!(buffer.data::<f32>(), &vertices);
+/// ```
+pub struct Buffer {
+    handle: embree4_sys::RTCBuffer,
+    byte_size: usize,
+    // Memory read by Embree in place, for shared buffers
+    _shared: Option<Box<dyn Any + Send + Sync>>,
+}
+
+impl Buffer {
+    /// Constructs a new buffer of `byte_size` zeroed bytes, allocated by Embree.
+    pub fn try_new(device: &Device, byte_size: usize) -> Result<Self> {
+        let handle = unsafe { embree4_sys::rtcNewBuffer(device.handle, byte_size) };
+        if handle.is_null() {
+            bail!("Failed to create buffer: {:?}", device.error());
+        }
+
+        // Embree leaves the allocation uninitialized
+        let ptr = unsafe { embree4_sys::rtcGetBufferData(handle) } as *mut u8;
+        if !ptr.is_null() {
+            unsafe { ptr.write_bytes(0, byte_size) };
+        }
+
+        Ok(Self {
+            handle,
+            byte_size,
+            _shared: None,
+        })
+    }
+
+    /// Constructs a new buffer reading `data` in place. The buffer takes ownership of the data.
+    pub fn try_new_shared<T: BufferItem>(device: &Device, data: AlignedVec<T>) -> Result<Self> {
+        let byte_size = size_of_val(&*data);
+        let handle = unsafe {
+            embree4_sys::rtcNewSharedBuffer(device.handle, data.as_ptr() as *mut _, byte_size)
+        };
+        if handle.is_null() {
+            bail!("Failed to create shared buffer: {:?}", device.error());
+        }
+
+        Ok(Self {
+            handle,
+            byte_size,
+            _shared: Some(Box::new(data)),
+        })
+    }
+
+    /// Returns the raw Embree buffer handle.
+    pub fn handle(&self) -> embree4_sys::RTCBuffer {
+        self.handle
+    }
+
+    /// Returns the size of the buffer, in bytes.
+    pub fn byte_size(&self) -> usize {
+        self.byte_size
+    }
+
+    /// Returns Embree's view of the buffer data, as returned by `rtcGetBufferData`.
+    ///
+    /// The data is read as a slice of as many items of `T` as fit in the buffer.
+    ///
+    /// # Panics
+    /// Panics if the data is not aligned for `T`.
+    pub fn data<T: BufferItem>(&self) -> &[T] {
+        let ptr = unsafe { embree4_sys::rtcGetBufferData(self.handle) } as *const T;
+        let len = self.byte_size / size_of::<T>();
+        if ptr.is_null() || len == 0 {
+            return &[];
+        }
+        assert!(
+            ptr.is_aligned(),
+            "Buffer data is not aligned to {} bytes",
+            align_of::<T>()
+        );
+
+        unsafe { slice::from_raw_parts(ptr, len) }
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        unsafe {
+            embree4_sys::rtcReleaseBuffer(self.handle);
+        }
+    }
+}
+
+unsafe impl Send for Buffer {}
+unsafe impl Sync for Buffer {}
+
+#[test]
+fn misaligned_slice_is_copied_aligned() {
+    let storage = [0.0f32, 1.0, 2.0, 3.0, 4.0];
+    let misaligned = if AlignedVec::is_aligned(&storage) {
+        &storage[1..]
+    } else {
+        &storage[..4]
+    };
+    assert!(!AlignedVec::is_aligned(misaligned));
+
+    let aligned = AlignedVec::from_slice(misaligned);
+    assert!(AlignedVec::is_aligned(&aligned));
+    assert_eq!(&aligned[..], misaligned);
+    assert!(AlignedVec::<u32>::from_slice(&[]).is_empty());
+}
+
+#[test]
+fn shared_buffer_data_matches_source() {
+    let device = Device::try_new(None).unwrap();
+    let vertices = [-1.0f32, -1.0, 5.0, 1.0, -1.0, 5.0, 0.0, 1.0, 5.0];
+    let buffer = Buffer::try_new_shared(&device, AlignedVec::from_slice(&vertices)).unwrap();
+
+    assert_eq!(buffer.byte_size(), 36);
+    assert_eq!(buffer.data::<f32>(), &vertices);
+    assert_eq!(buffer.data::<u32>()[2], 5.0f32.to_bits());
+}
+
+#[test]
+fn new_buffer_is_zeroed() {
+    let device = Device::try_new(None).unwrap();
+    let buffer = Buffer::try_new(&device, 64).unwrap();
+
+    assert_eq!(buffer.data::<u32>(), &[0; 16]);
+    assert_eq!(buffer.data::<[f32; 4]>().len(), 4);
+}
@@ -0,0 +1,264 @@
+use std::{
+    mem::{align_of, size_of},
+    os::raw::{c_uint, c_void},
+    ptr, slice,
+};
+
+use anyhow::{bail, Result};
+use embree4_sys::{RTCBounds, RTCBuildPrimitive, RTCRay, RTCThreadLocalAllocator};
+
+use crate::{aabb::Aabb, device::Device, device_error_or};
+
+// Upper bound on the number of primitives referenced by a single leaf
+const MAX_LEAF_SIZE: u32 = 4;
+
+/// A bounding volume hierarchy over arbitrary primitives, built with Embree's BVH builder.
+///
+/// Primitives are only known by their bounds and identified by their index in the slice the
+/// hierarchy was built from. The nodes live in memory owned by the builder and are freed with
+/// the hierarchy.
+pub struct Bvh {
+    handle: embree4_sys::RTCBVH,
+    root: *const Node,
+}
+
+// Binary node, as laid out by the build callbacks. Leaves have no children and reference the
+// primitives they contain.
+#[repr(C)]
+struct Node {
+    children: [*const Node; 2],
+    bounds: [Aabb; 2],
+    primitives: *const u32,
+    primitive_count: usize,
+}
+
+impl Bvh {
+    /// Builds a hierarchy over the given primitive bounds.
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::{aabb::Aabb, bvh::Bvh, device::Device};
+    ///
+    /// let device = Device::try_new(None).unwrap();
+    /// let boxes = [
+    ///     Aabb::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]),
+    ///     Aabb::new([2.0, 0.0, 0.0], [3.0, 1.0, 1.0]),
+    /// ];
+    /// let bvh = Bvh::try_new(&device, &boxes).unwrap();
+    /// ```
+    pub fn try_new(device: &Device, primitives: &[Aabb]) -> Result<Self> {
+        let handle = unsafe { embree4_sys::rtcNewBVH(device.handle) };
+        if handle.is_null() {
+            bail!("Failed to create BVH: {:?}", device.error());
+        }
+
+        let mut bvh = Self {
+            handle,
+            root: ptr::null(),
+        };
+        if primitives.is_empty() {
+            return Ok(bvh);
+        }
+
+        // The builder reorders the primitives in place
+        let mut build_primitives = primitives
+            .iter()
+            .enumerate()
+            .map(|(id, bounds)| RTCBuildPrimitive {
+                lower_x: bounds.lower[0],
+                lower_y: bounds.lower[1],
+                lower_z: bounds.lower[2],
+                geomID: 0,
+                upper_x: bounds.upper[0],
+                upper_y: bounds.upper[1],
+                upper_z: bounds.upper[2],
+                primID: id as u32,
+            })
+            .collect::<Vec<_>>();
+
+        let args = embree4_sys::RTCBuildArguments {
+            byteSize: size_of::<embree4_sys::RTCBuildArguments>(),
+            buildQuality: embree4_sys::RTCBuildQuality::MEDIUM,
+            buildFlags: embree4_sys::RTCBuildFlags::NONE,
+            maxBranchingFactor: 2,
+            maxDepth: 32,
+            sahBlockSize: 1,
+            minLeafSize: 1,
+            maxLeafSize: MAX_LEAF_SIZE,
+            traversalCost: 1.0,
+            intersectionCost: 1.0,
+            bvh: handle,
+            primitives: build_primitives.as_mut_ptr(),
+            primitiveCount: build_primitives.len(),
+            primitiveArrayCapacity: build_primitives.len(),
+            createNode: Some(create_node),
+            setNodeChildren: Some(set_node_children),
+            setNodeBounds: Some(set_node_bounds),
+            createLeaf: Some(create_leaf),
+            splitPrimitive: None,
+            buildProgress: None,
+            userPtr: ptr::null_mut(),
+        };
+
+        let root = unsafe { embree4_sys::rtcBuildBVH(&args) };
+        device_error_or(device, (), "Failed to build BVH")?;
+        if root.is_null() {
+            bail!("Failed to build BVH: no root node");
+        }
+
+        bvh.root = root as *const Node;
+        Ok(bvh)
+    }
+
+    /// Walks the hierarchy, descending into the children whose bounds are accepted by
+    /// `enter_node`, and calls `visit_leaf` with the primitive ids of every leaf reached.
+    ///
+    /// This is the building block of spatial queries: a range search enters the nodes overlapping
+    /// the range, a ray query the nodes pierced by the ray. Leaves are only culled by the bounds
+    /// of their node, so their primitives still have to be tested individually.
+    pub fn traverse_with(
+        &self,
+        mut enter_node: impl FnMut(&Aabb) -> bool,
+        mut visit_leaf: impl FnMut(&[u32]),
+    ) {
+        if self.root.is_null() {
+            return;
+        }
+
+        let mut stack = vec![self.root];
+        while let Some(node) = stack.pop() {
+            let node = unsafe { &*node };
+            if !node.primitives.is_null() {
+                visit_leaf(unsafe { slice::from_raw_parts(node.primitives, node.primitive_count) });
+                continue;
+            }
+
+            for (&child, bounds) in node.children.iter().zip(&node.bounds) {
+                if !child.is_null() && enter_node(bounds) {
+                    stack.push(child);
+                }
+            }
+        }
+    }
+
+    /// Calls `visit_leaf` with the primitive ids of every leaf whose bounds are crossed by the
+    /// `[tnear, tfar]` segment of the ray.
+    pub fn traverse(&self, ray: &RTCRay, visit_leaf: impl FnMut(&[u32])) {
+        self.traverse_with(|bounds| bounds.intersect_ray(ray).is_some(), visit_leaf);
+    }
+
+    /// Calls `visit_leaf` with the primitive ids of every leaf whose bounds overlap `range`.
+    pub fn query_range(&self, range: &Aabb, visit_leaf: impl FnMut(&[u32])) {
+        self.traverse_with(|bounds| bounds.overlaps(range), visit_leaf);
+    }
+}
+
+impl Drop for Bvh {
+    fn drop(&mut self) {
+        unsafe {
+            embree4_sys::rtcReleaseBVH(self.handle);
+        }
+    }
+}
+
+unsafe impl Send for Bvh {}
+unsafe impl Sync for Bvh {}
+
+unsafe fn alloc_node(allocator: RTCThreadLocalAllocator) -> *mut Node {
+    let node = embree4_sys::rtcThreadLocalAlloc(allocator, size_of::<Node>(), align_of::<Node>())
+        as *mut Node;
+    node.write(Node {
+        children: [ptr::null(); 2],
+        bounds: [Aabb::new([0.0; 3], [0.0; 3]); 2],
+        primitives: ptr::null(),
+        primitive_count: 0,
+    });
+    node
+}
+
+unsafe extern "C" fn create_node(
+    allocator: RTCThreadLocalAllocator,
+    _child_count: c_uint,
+    _user_ptr: *mut c_void,
+) -> *mut c_void {
+    alloc_node(allocator) as *mut c_void
+}
+
+unsafe extern "C" fn set_node_children(
+    node: *mut c_void,
+    children: *mut *mut c_void,
+    child_count: c_uint,
+    _user_ptr: *mut c_void,
+) {
+    let node = &mut *(node as *mut Node);
+    let children = slice::from_raw_parts(children, child_count as usize);
+    for (slot, &child) in node.children.iter_mut().zip(children) {
+        *slot = child as *const Node;
+    }
+}
+
+unsafe extern "C" fn set_node_bounds(
+    node: *mut c_void,
+    bounds: *mut *const RTCBounds,
+    child_count: c_uint,
+    _user_ptr: *mut c_void,
+) {
+    let node = &mut *(node as *mut Node);
+    let bounds = slice::from_raw_parts(bounds, child_count as usize);
+    for (slot, &child_bounds) in node.bounds.iter_mut().zip(bounds) {
+        *slot = Aabb::from(*child_bounds);
+    }
+}
+
+unsafe extern "C" fn create_leaf(
+    allocator: RTCThreadLocalAllocator,
+    primitives: *const RTCBuildPrimitive,
+    primitive_count: usize,
+    _user_ptr: *mut c_void,
+) -> *mut c_void {
+    let ids = embree4_sys::rtcThreadLocalAlloc(
+        allocator,
+        primitive_count.max(1) * size_of::<u32>(),
+        align_of::<u32>(),
+    ) as *mut u32;
+    let primitives = slice::from_raw_parts(primitives, primitive_count);
+    for (i, primitive) in primitives.iter().enumerate() {
+        ids.add(i).write(primitive.primID);
+    }
+
+    let node = alloc_node(allocator);
+    (*node).primitives = ids;
+    (*node).primitive_count = primitive_count;
+    node as *mut c_void
+}
+
+#[test]
+fn range_query_returns_overlapping_leaves() {
+    let device = Device::try_new(None).unwrap();
+
+    // A row of unit boxes along x, one unit apart
+    let boxes = (0..16)
+        .map(|i| {
+            let x = 2.0 * i as f32;
+            Aabb::new([x, 0.0, 0.0], [x + 1.0, 1.0, 1.0])
+        })
+        .collect::<Vec<_>>();
+    let bvh = Bvh::try_new(&device, &boxes).unwrap();
+
+    let range = Aabb::new([5.5, 0.25, 0.25], [10.5, 0.75, 0.75]);
+    let mut visited = vec![];
+    bvh.query_range(&range, |leaf| visited.extend_from_slice(leaf));
+
+    // Leaves are culled by their node bounds only
+    assert!(visited.len() < boxes.len());
+
+    let mut found = visited
+        .into_iter()
+        .filter(|&id| boxes[id as usize].overlaps(&range))
+        .collect::<Vec<_>>();
+    found.sort_unstable();
+    assert_eq!(found, vec![3, 4, 5]);
+
+    let empty = Bvh::try_new(&device, &[]).unwrap();
+    empty.query_range(&range, |_| panic!("empty BVH has no leaves"));
+}
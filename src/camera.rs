@@ -0,0 +1,271 @@
+use anyhow::{bail, Result};
+use embree4_sys::{RTCRay, RTCRay4, RTCRay8};
+
+use crate::ray::RayBuilder;
+
+/// Transposes an array of rays into a ray packet.
+macro_rules! pack_rays {
+    ($packet:ident, $rays:expr) => {{
+        let rays = $rays;
+        $packet {
+            org_x: rays.map(|r| r.org_x),
+            org_y: rays.map(|r| r.org_y),
+            org_z: rays.map(|r| r.org_z),
+            tnear: rays.map(|r| r.tnear),
+            dir_x: rays.map(|r| r.dir_x),
+            dir_y: rays.map(|r| r.dir_y),
+            dir_z: rays.map(|r| r.dir_z),
+            time: rays.map(|r| r.time),
+            tfar: rays.map(|r| r.tfar),
+            mask: rays.map(|r| r.mask),
+            id: rays.map(|r| r.id),
+            flags: rays.map(|r| r.flags),
+        }
+    }};
+}
+// For the packet tests of the ray module
+#[cfg(test)]
+pub(crate) use pack_rays;
+
+/// A pinhole camera.
+///
+/// Positions on the image plane are given in normalized screen coordinates: `(0, 0)` is the top
+/// left corner of the image and `(1, 1)` the bottom right one.
+///
+/// # Example
+/// ```
+/// use embree4_rs::camera::Camera;
+///
+/// let camera = Camera::look_at(
+///     (0.0, 0.0, -5.0),
+///     (0.0, 0.0, 0.0),
+///     (0.0, 1.0, 0.0),
+///     60f32.to_radians(),
+///     16.0 / 9.0,
+/// );
+/// let ray = camera.ray(0.5, 0.5);
+/// assert!(ray.dir_z > 0.0);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Camera {
+    origin: [f32; 3],
+    forward: [f32; 3],
+    /// Half-width of the image plane at unit distance
+    right: [f32; 3],
+    /// Half-height of the image plane at unit distance
+    up: [f32; 3],
+}
+
+impl Camera {
+    /// Constructs a camera at `origin` looking towards `target`.
+    ///
+    /// # Arguments
+    /// * `up` - The up direction of the image, it must not be parallel to the view direction.
+    /// * `fov_y` - The vertical field of view, in radians.
+    /// * `aspect` - The width of the image divided by its height.
+    pub fn look_at(
+        origin: (f32, f32, f32),
+        target: (f32, f32, f32),
+        up: (f32, f32, f32),
+        fov_y: f32,
+        aspect: f32,
+    ) -> Self {
+        let origin = [origin.0, origin.1, origin.2];
+        let forward = normalize(sub([target.0, target.1, target.2], origin));
+        let right = normalize(cross(forward, [up.0, up.1, up.2]));
+        let up = cross(right, forward);
+
+        let half_height = (0.5 * fov_y).tan();
+        let half_width = half_height * aspect;
+
+        Self {
+            origin,
+            forward,
+            right: right.map(|x| x * half_width),
+            up: up.map(|x| x * half_height),
+        }
+    }
+
+    /// Returns the primary ray going through the given point of the image plane.
+    pub fn ray(&self, u: f32, v: f32) -> RTCRay {
+        let [x, y, z] = self.direction(u, v);
+        RayBuilder::new((self.origin[0], self.origin[1], self.origin[2]), (x, y, z)).build()
+    }
+
+    /// Returns the primary ray going through the center of pixel `(x, y)` of a `width` by
+    /// `height` image.
+    pub fn pixel_ray(&self, x: usize, y: usize, width: usize, height: usize) -> RTCRay {
+        self.ray(
+            (x as f32 + 0.5) / width as f32,
+            (y as f32 + 0.5) / height as f32,
+        )
+    }
+
+    /// Returns the primary rays of the 2x2 block of pixels whose top left pixel is `(x, y)`, packed
+    /// for [rtcIntersect4](embree4_sys::rtcIntersect4).
+    ///
+    /// Lanes are ordered row by row, lane `i` holding the ray of pixel
+    /// `(x + i % 2, y + i / 2)`. Pixels outside of the image are generated as well, and must be
+    /// masked out by the caller.
+    pub fn rays_packed_4(&self, x: usize, y: usize, width: usize, height: usize) -> RTCRay4 {
+        let rays: [RTCRay; 4] =
+            std::array::from_fn(|i| self.pixel_ray(x + i % 2, y + i / 2, width, height));
+        pack_rays!(RTCRay4, rays)
+    }
+
+    /// Returns the primary rays of the 4x2 block of pixels whose top left pixel is `(x, y)`, packed
+    /// for [rtcIntersect8](embree4_sys::rtcIntersect8).
+    ///
+    /// Lanes are ordered row by row, lane `i` holding the ray of pixel
+    /// `(x + i % 4, y + i / 4)`. Pixels outside of the image are generated as well, and must be
+    /// masked out by the caller.
+    pub fn rays_packed_8(&self, x: usize, y: usize, width: usize, height: usize) -> RTCRay8 {
+        let rays: [RTCRay; 8] =
+            std::array::from_fn(|i| self.pixel_ray(x + i % 4, y + i / 4, width, height));
+        pack_rays!(RTCRay8, rays)
+    }
+
+    /// Returns the six planes bounding the rays of a rectangular tile of the image.
+    ///
+    /// The tile spans `[x, x + w] x [y, y + h]` in normalized screen coordinates. Each plane is
+    /// given as `[a, b, c, d]` with points `p` inside of the frustum satisfying
+    /// `a * p.x + b * p.y + c * p.z + d >= 0`. They are ordered left, right, top, bottom, near and
+    /// far. The near plane goes through the camera origin. The frustum has no far bound: the far
+    /// plane is all zeros, which every point satisfies.
+    ///
+    /// See [Aabb::intersects_frustum](crate::aabb::Aabb::intersects_frustum) to cull tiles.
+    ///
+    /// # Returns
+    /// An error if the tile or the camera are not finite.
+    pub fn tile_frustum(&self, x: f32, y: f32, w: f32, h: f32) -> Result<[[f32; 4]; 6]> {
+        let top_left = self.direction(x, y);
+        let top_right = self.direction(x + w, y);
+        let bottom_left = self.direction(x, y + h);
+        let bottom_right = self.direction(x + w, y + h);
+        let center = self.direction(x + 0.5 * w, y + 0.5 * h);
+
+        // Side planes contain the camera origin and two corner directions, oriented towards the
+        // center of the tile
+        let side = |a: [f32; 3], b: [f32; 3]| {
+            let mut normal = cross(a, b);
+            if dot(normal, center) < 0.0 {
+                normal = normal.map(|x| -x);
+            }
+            plane(normal, self.origin)
+        };
+
+        let planes = [
+            side(top_left, bottom_left),
+            side(top_right, bottom_right),
+            side(top_left, top_right),
+            side(bottom_left, bottom_right),
+            plane(self.forward, self.origin),
+            [0.0; 4],
+        ];
+        if planes.iter().flatten().any(|x| !x.is_finite()) {
+            bail!("The frustum of tile ({x}, {y}, {w}, {h}) is not finite");
+        }
+        Ok(planes)
+    }
+
+    fn direction(&self, u: f32, v: f32) -> [f32; 3] {
+        let sx = 2.0 * u - 1.0;
+        let sy = 1.0 - 2.0 * v;
+        [0, 1, 2].map(|i| self.forward[i] + sx * self.right[i] + sy * self.up[i])
+    }
+}
+
+fn plane(normal: [f32; 3], point: [f32; 3]) -> [f32; 4] {
+    [normal[0], normal[1], normal[2], -dot(normal, point)]
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn normalize(a: [f32; 3]) -> [f32; 3] {
+    let len = dot(a, a).sqrt();
+    a.map(|x| x / len)
+}
+
+#[test]
+fn cull_tiles_outside_of_scene_bounds() {
+    use crate::aabb::Aabb;
+
+    // A unit box in front of the camera, on the right side of the image
+    let bounds = Aabb::new([-2.0, -0.5, 4.0], [-1.0, 0.5, 5.0]);
+    let camera = Camera::look_at(
+        (0.0, 0.0, 0.0),
+        (0.0, 0.0, 1.0),
+        (0.0, 1.0, 0.0),
+        90f32.to_radians(),
+        1.0,
+    );
+
+    let frustum = |x, y, w, h| camera.tile_frustum(x, y, w, h).unwrap();
+    assert!(bounds.intersects_frustum(&frustum(0.0, 0.0, 1.0, 1.0)));
+    assert!(bounds.intersects_frustum(&frustum(0.5, 0.25, 0.5, 0.5)));
+    assert!(!bounds.intersects_frustum(&frustum(0.0, 0.0, 0.5, 1.0)));
+    assert!(camera.tile_frustum(f32::NAN, 0.0, 0.5, 1.0).is_err());
+    assert!(camera.tile_frustum(0.0, 0.0, f32::INFINITY, 1.0).is_err());
+
+    // Unbounded boxes are never culled
+    let infinite = Aabb::new([f32::NEG_INFINITY; 3], [f32::INFINITY; 3]);
+    assert!(infinite.intersects_frustum(&frustum(0.0, 0.0, 0.5, 1.0)));
+
+    // Looking away from the box culls every tile
+    let camera = Camera::look_at(
+        (0.0, 0.0, 0.0),
+        (0.0, 0.0, -1.0),
+        (0.0, 1.0, 0.0),
+        90f32.to_radians(),
+        1.0,
+    );
+    assert!(!bounds.intersects_frustum(&camera.tile_frustum(0.0, 0.0, 1.0, 1.0).unwrap()));
+}
+
+#[test]
+fn packed_rays_match_scalar_rays() {
+    let camera = Camera::look_at(
+        (1.0, 2.0, 3.0),
+        (0.0, 0.0, 0.0),
+        (0.0, 1.0, 0.0),
+        45f32.to_radians(),
+        4.0 / 3.0,
+    );
+    let (width, height) = (64, 48);
+
+    let packet = camera.rays_packed_4(10, 20, width, height);
+    for lane in 0..4 {
+        let ray = camera.pixel_ray(10 + lane % 2, 20 + lane / 2, width, height);
+        assert_eq!(packet.org_x[lane], ray.org_x);
+        assert_eq!(packet.org_y[lane], ray.org_y);
+        assert_eq!(packet.org_z[lane], ray.org_z);
+        assert_eq!(packet.dir_x[lane], ray.dir_x);
+        assert_eq!(packet.dir_y[lane], ray.dir_y);
+        assert_eq!(packet.dir_z[lane], ray.dir_z);
+        assert_eq!(packet.tnear[lane], ray.tnear);
+        assert_eq!(packet.tfar[lane], ray.tfar);
+        assert_eq!(packet.mask[lane], ray.mask);
+    }
+
+    let packet = camera.rays_packed_8(8, 4, width, height);
+    for lane in 0..8 {
+        let ray = camera.pixel_ray(8 + lane % 4, 4 + lane / 4, width, height);
+        assert_eq!(packet.dir_x[lane], ray.dir_x);
+        assert_eq!(packet.dir_y[lane], ray.dir_y);
+        assert_eq!(packet.dir_z[lane], ray.dir_z);
+    }
+}
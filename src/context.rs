@@ -0,0 +1,401 @@
+use anyhow::{bail, Result};
+use embree4_sys::{
+    RTCFeatureFlags, RTCFilterFunctionN, RTCRayQueryContext, RTCRayQueryFlags,
+    RTC_INVALID_GEOMETRY_ID, RTC_MAX_INSTANCE_LEVEL_COUNT,
+};
+
+/// The per-query state passed to Embree along with a ray.
+///
+/// Wraps an [RTCRayQueryContext] together with the query flags and the feature mask of the
+/// intersect and occluded arguments.
+///
+/// # Example
+/// ```
+/// use embree4_rs::prelude::*;
+/// use embree4_sys::*;
+///
+/// // This renderer only ever traces triangles
+/// let context = RayQueryContext::new().feature_mask(RTCFeatureFlags::TRIANGLE);
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct RayQueryContext {
+    context: RTCRayQueryContext,
+    flags: RTCRayQueryFlags,
+    feature_mask: RTCFeatureFlags,
+    filter: RTCFilterFunctionN,
+}
+
+impl RayQueryContext {
+    /// Constructs a new `RayQueryContext` for incoherent rays with all features enabled.
+    ///
+    /// The instance ID stack starts empty, as done by `rtcInitRayQueryContext`, which is inline in
+    /// the C API and thus not part of the bindings.
+    pub fn new() -> Self {
+        Self {
+            context: RTCRayQueryContext {
+                instID: [RTC_INVALID_GEOMETRY_ID; RTC_MAX_INSTANCE_LEVEL_COUNT as usize],
+            },
+            flags: RTCRayQueryFlags::INCOHERENT,
+            feature_mask: RTCFeatureFlags::ALL,
+            filter: None,
+        }
+    }
+
+    /// Sets the features the traversal must support.
+    ///
+    /// Declaring only the geometry types and features actually present in the scene lets Embree
+    /// use smaller and faster traversal kernels. Geometries whose type is not part of the mask may
+    /// not be reported, CPU devices currently ignore the mask.
+    ///
+    /// See [RTCFeatureFlags](https://github.com/embree/embree/blob/master/doc/src/api/RTCFeatureFlags.md).
+    pub fn feature_mask(mut self, feature_mask: RTCFeatureFlags) -> Self {
+        self.feature_mask = feature_mask;
+        self
+    }
+
+    /// Sets a filter function run on the hits of every geometry, in addition to the filters set
+    /// on the geometries themselves.
+    ///
+    /// Setting a filter also enables [Self::invoke_argument_filter], clearing it disables it.
+    ///
+    /// This applies a uniform effect, e.g. an alpha test or stochastic transparency, across the
+    /// whole scene without registering a filter on each geometry. The scene must be created with
+    /// `RTCSceneFlags::FILTER_FUNCTION_IN_ARGUMENTS`, queries on other scenes fail.
+    ///
+    /// See [RTCFilterFunctionN](https://github.com/embree/embree/blob/master/doc/src/api/RTCFilterFunctionN.md).
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::prelude::*;
+    /// use embree4_sys::*;
+    ///
+    /// unsafe extern "C" fn reject_all(args: *const RTCFilterFunctionNArguments) {
+    ///     let args = &*args;
+    ///     std::slice::from_raw_parts_mut(args.valid, args.N as usize).fill(0);
+    /// }
+    ///
+    /// let device = Device::try_new(None).unwrap();
+    /// let options = SceneOptions {
+    ///     flags: RTCSceneFlags::FILTER_FUNCTION_IN_ARGUMENTS,
+    ///     ..Default::default()
+    /// };
+    /// let scene = Scene::try_new(&device, options).unwrap();
+    /// let scene = scene.commit().unwrap();
+    ///
+    /// let context = RayQueryContext::new().filter(Some(reject_all));
+    /// let ray = RayBuilder::new((0.0, 0.0, 0.0), (0.0, 0.0, 1.0)).build();
+    /// assert!(scene.intersect_1_with_context(ray, &context).unwrap().is_none());
+    /// ```
+    pub fn filter(mut self, filter: RTCFilterFunctionN) -> Self {
+        self.filter = filter;
+        self.invoke_argument_filter(filter.is_some())
+    }
+
+    /// Returns `true` if a filter function is set, see [Self::filter].
+    pub fn has_filter(&self) -> bool {
+        self.filter.is_some()
+    }
+
+    /// Sets whether the filter function of the context runs on the hits of every geometry.
+    ///
+    /// When disabled, the filter only runs on the geometries that enabled it with
+    /// [FilterGeometry::enable_argument_filter](crate::filter::FilterGeometry::enable_argument_filter).
+    /// A renderer can thus alpha test some geometries on all rays, and all of them on primary
+    /// rays only, with the same filter.
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::prelude::*;
+    /// use embree4_sys::*;
+    ///
+    /// unsafe extern "C" fn alpha_test(args: *const RTCFilterFunctionNArguments) { /* ... */ }
+    ///
+    /// let primary = RayQueryContext::new().filter(Some(alpha_test));
+    /// let secondary = primary.invoke_argument_filter(false);
+    /// assert!(secondary.has_filter());
+    /// ```
+    pub fn invoke_argument_filter(mut self, invoke: bool) -> Self {
+        if invoke {
+            self.flags |= RTCRayQueryFlags::INVOKE_ARGUMENT_FILTER;
+        } else {
+            self.flags &= RTCRayQueryFlags(!RTCRayQueryFlags::INVOKE_ARGUMENT_FILTER.0);
+        }
+        self
+    }
+
+    /// Pushes an instance ID on the instance stack of the context.
+    ///
+    /// User geometries that implement instancing by forwarding rays to another scene push the ID
+    /// of their instance before tracing the child, and pop it afterwards. Hits report the stack
+    /// through `instID`.
+    ///
+    /// Fails if the stack is full: Embree is built with
+    /// [RTC_MAX_INSTANCE_LEVEL_COUNT] levels.
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::prelude::*;
+    ///
+    /// let mut context = RayQueryContext::new();
+    /// context.push_instance(3).unwrap();
+    /// assert_eq!(context.instance_stack(), &[3]);
+    /// assert_eq!(context.pop_instance(), Some(3));
+    /// assert!(context.instance_stack().is_empty());
+    /// ```
+    pub fn push_instance(&mut self, inst_id: u32) -> Result<()> {
+        let depth = self.instance_stack().len();
+        if depth == self.context.instID.len() {
+            bail!("Instance stack is full ({} levels)", depth);
+        }
+        if inst_id == RTC_INVALID_GEOMETRY_ID {
+            bail!("Cannot push an invalid instance ID");
+        }
+
+        self.context.instID[depth] = inst_id;
+        Ok(())
+    }
+
+    /// Pops the innermost instance ID of the instance stack, if any.
+    pub fn pop_instance(&mut self) -> Option<u32> {
+        let depth = self.instance_stack().len();
+        if depth == 0 {
+            return None;
+        }
+
+        let inst_id = self.context.instID[depth - 1];
+        self.context.instID[depth - 1] = RTC_INVALID_GEOMETRY_ID;
+        Some(inst_id)
+    }
+
+    /// Returns the instance IDs on the stack, from the outermost to the innermost one.
+    pub fn instance_stack(&self) -> &[u32] {
+        let depth = self
+            .context
+            .instID
+            .iter()
+            .position(|&id| id == RTC_INVALID_GEOMETRY_ID)
+            .unwrap_or(self.context.instID.len());
+        &self.context.instID[..depth]
+    }
+
+    pub(crate) fn intersect_arguments(&mut self) -> embree4_sys::RTCIntersectArguments {
+        embree4_sys::RTCIntersectArguments {
+            flags: self.flags,
+            feature_mask: self.feature_mask,
+            context: &mut self.context,
+            filter: self.filter,
+            intersect: None,
+        }
+    }
+
+    pub(crate) fn occluded_arguments(&mut self) -> embree4_sys::RTCOccludedArguments {
+        embree4_sys::RTCOccludedArguments {
+            flags: self.flags,
+            feature_mask: self.feature_mask,
+            context: &mut self.context,
+            filter: self.filter,
+            occluded: None,
+        }
+    }
+}
+
+impl Default for RayQueryContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[test]
+fn user_geometry_forwards_through_pushed_instance() {
+    use embree4_sys::{RTCBounds, RTCRayHit};
+
+    use crate::{
+        device::Device,
+        geometry::{TriangleMeshGeometry, UserGeometry, UserGeometryImpl},
+        ray::RayBuilder,
+        scene::{CommittedScene, Scene, SceneOptions},
+    };
+
+    // Instances a child scene by forwarding rays into it, one level down the instance stack
+    struct Forward<'s> {
+        child: &'s CommittedScene<'s>,
+    }
+
+    impl UserGeometryImpl for Forward<'_> {
+        fn bounds(&self) -> RTCBounds {
+            self.child.bounds().unwrap()
+        }
+
+        fn intersect(
+            &self,
+            geom_id: u32,
+            _prim_id: u32,
+            ctx: &RTCRayQueryContext,
+            ray_hit: &mut RTCRayHit,
+        ) {
+            let mut context = RayQueryContext::new();
+            for &inst_id in ctx
+                .instID
+                .iter()
+                .take_while(|&&id| id != RTC_INVALID_GEOMETRY_ID)
+            {
+                context.push_instance(inst_id).unwrap();
+            }
+            context.push_instance(geom_id).unwrap();
+
+            if let Some(hit) = self
+                .child
+                .intersect_1_with_context(ray_hit.ray, &context)
+                .unwrap()
+            {
+                *ray_hit = hit;
+            }
+        }
+    }
+
+    let device = Device::try_new(None).unwrap();
+    let vertices = [(-1.0, -1.0, 5.0), (1.0, -1.0, 5.0), (0.0, 1.0, 5.0)];
+    let mesh = TriangleMeshGeometry::try_new(&device, &vertices, &[(0, 1, 2)]).unwrap();
+    let child = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    let mesh_id = child.attach_geometry(&mesh).unwrap();
+    let child = child.commit().unwrap();
+
+    let forward = Forward { child: &child };
+    let geometry = UserGeometry::try_new(&device, &forward).unwrap();
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    let forward_id = scene.attach_geometry(&geometry).unwrap();
+    let scene = scene.commit().unwrap();
+
+    let ray = RayBuilder::new((0.0, 0.0, 0.0), (0.0, 0.0, 1.0)).build();
+    let hit = scene
+        .intersect_1_with_context(ray, &RayQueryContext::new())
+        .unwrap()
+        .unwrap();
+    assert_eq!(hit.hit.geomID, mesh_id);
+    assert_eq!(hit.hit.instID[0], forward_id);
+    assert!((hit.ray.tfar - 5.0).abs() < 1e-4);
+
+    let miss = RayBuilder::new((5.0, 0.0, 0.0), (0.0, 0.0, 1.0)).build();
+    assert!(scene.intersect_1(miss).unwrap().is_none());
+
+    let mut full = RayQueryContext::new();
+    for level in 0..RTC_MAX_INSTANCE_LEVEL_COUNT {
+        full.push_instance(level).unwrap();
+    }
+    assert!(full.push_instance(RTC_MAX_INSTANCE_LEVEL_COUNT).is_err());
+}
+
+#[test]
+fn argument_filter_applies_to_every_geometry() {
+    use std::slice;
+
+    use embree4_sys::{RTCFilterFunctionNArguments, RTCSceneFlags};
+
+    use crate::{
+        device::Device,
+        geometry::{SphereGeometry, TriangleMeshGeometry},
+        ray::RayBuilder,
+        scene::{Scene, SceneOptions},
+    };
+
+    unsafe extern "C" fn reject_all(args: *const RTCFilterFunctionNArguments) {
+        let args = &*args;
+        slice::from_raw_parts_mut(args.valid, args.N as usize).fill(0);
+    }
+
+    let device = Device::try_new(None).unwrap();
+    let sphere = SphereGeometry::try_new(&device, (-2.0, 0.0, 5.0), 1.0).unwrap();
+    let triangle = [(1.0, -1.0, 5.0), (3.0, -1.0, 5.0), (2.0, 1.0, 5.0)];
+    let mesh = TriangleMeshGeometry::try_new(&device, &triangle, &[(0, 1, 2)]).unwrap();
+
+    let options = SceneOptions {
+        flags: RTCSceneFlags::FILTER_FUNCTION_IN_ARGUMENTS,
+        ..Default::default()
+    };
+    let scene = Scene::try_new(&device, options).unwrap();
+    scene.attach_geometry(&sphere).unwrap();
+    scene.attach_geometry(&mesh).unwrap();
+    let scene = scene.commit().unwrap();
+
+    let unfiltered = RayQueryContext::new();
+    let filtered = RayQueryContext::new().filter(Some(reject_all));
+    for x in [-2.0, 2.0] {
+        let ray = RayBuilder::new((x, 0.0, 0.0), (0.0, 0.0, 1.0)).build();
+        assert!(scene
+            .intersect_1_with_context(ray, &unfiltered)
+            .unwrap()
+            .is_some());
+        assert!(scene
+            .intersect_1_with_context(ray, &filtered)
+            .unwrap()
+            .is_none());
+        assert!(!scene.occluded_1_with_context(ray, &filtered).unwrap());
+    }
+
+    // The scene flag is required
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    scene.attach_geometry(&sphere).unwrap();
+    let scene = scene.commit().unwrap();
+    let ray = RayBuilder::new((-2.0, 0.0, 0.0), (0.0, 0.0, 1.0)).build();
+    assert!(scene.intersect_1_with_context(ray, &filtered).is_err());
+}
+
+#[test]
+fn argument_filter_runs_only_when_invoked() {
+    use std::{
+        slice,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use embree4_sys::{RTCFilterFunctionNArguments, RTCSceneFlags};
+
+    use crate::{
+        device::Device,
+        filter::FilterGeometry,
+        geometry::TriangleMeshGeometry,
+        ray::RayBuilder,
+        scene::{Scene, SceneOptions},
+    };
+
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+    unsafe extern "C" fn reject_all(args: *const RTCFilterFunctionNArguments) {
+        let args = &*args;
+        CALLS.fetch_add(1, Ordering::SeqCst);
+        slice::from_raw_parts_mut(args.valid, args.N as usize).fill(0);
+    }
+
+    let device = Device::try_new(None).unwrap();
+    let triangle = [(-1.0, -1.0, 5.0), (1.0, -1.0, 5.0), (0.0, 1.0, 5.0)];
+    let mesh = TriangleMeshGeometry::try_new(&device, &triangle, &[(0, 1, 2)]).unwrap();
+    let options = || SceneOptions {
+        flags: RTCSceneFlags::FILTER_FUNCTION_IN_ARGUMENTS,
+        ..Default::default()
+    };
+
+    let invoked = RayQueryContext::new().filter(Some(reject_all));
+    let not_invoked = invoked.invoke_argument_filter(false);
+    let ray = RayBuilder::new((0.0, 0.0, 0.0), (0.0, 0.0, 1.0)).build();
+
+    let scene = Scene::try_new(&device, options()).unwrap();
+    scene.attach_geometry(&mesh).unwrap();
+    let scene = scene.commit().unwrap();
+
+    let hit = scene.intersect_1_with_context(ray, &not_invoked).unwrap();
+    assert!(hit.is_some());
+    assert_eq!(CALLS.load(Ordering::SeqCst), 0);
+
+    let hit = scene.intersect_1_with_context(ray, &invoked).unwrap();
+    assert!(hit.is_none());
+    assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+
+    // Enabled on the geometry, the filter runs without the context flag
+    mesh.enable_argument_filter(true);
+    let scene = Scene::try_new(&device, options()).unwrap();
+    scene.attach_geometry(&mesh).unwrap();
+    let scene = scene.commit().unwrap();
+
+    let hit = scene.intersect_1_with_context(ray, &not_invoked).unwrap();
+    assert!(hit.is_none());
+    assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+}
@@ -1,22 +1,57 @@
 use std::{
-    ffi::{c_void, CStr},
-    marker::PhantomData,
+    cell::Cell,
+    ffi::{c_void, CStr, CString},
     ptr::null_mut,
+    sync::{
+        atomic::{AtomicIsize, Ordering},
+        Arc, Mutex, PoisonError,
+    },
 };
 
 use anyhow::{bail, Result};
-use embree4_sys::RTCError;
+use embree4_sys::{RTCDeviceProperty, RTCError, RTCGeometryType, RTCMemoryMonitorFunction};
 
-use crate::{device_error_raw, Mxcsr};
+use crate::{
+    clear_device_error, device_error_or, device_error_raw, error::EmbreeError,
+    geometry::RawGeometry, Mxcsr,
+};
+
+// Embree only accepts writes to its internal debug properties
+const WRITABLE_PROPERTIES: std::ops::RangeInclusive<u32> = 1_000_000..=1_000_003;
+
+// Instruction sets tried by `Device::try_new_best`, from the fastest to the most widely supported
+const FALLBACK_ISAS: [&str; 4] = ["avx512", "avx2", "sse4.2", "sse2"];
+
+thread_local! {
+    // Set while an error callback runs on this thread
+    static REPORTING_ERROR: Cell<bool> = const { Cell::new(false) };
+}
 
+/// An Embree device.
+///
+/// Embree keeps one error code per device and per thread: an error is only visible to the thread
+/// whose call raised it. A `Device` shared across threads therefore reports each thread its own
+/// errors through [Device::error]. The latest error of any thread can be inspected with
+/// [Device::last_error] while an error callback is registered.
 pub struct Device {
     pub(crate) handle: embree4_sys::RTCDevice,
+    last_error: Arc<Mutex<Option<RTCError>>>,
+    error_callback: Arc<Mutex<Option<RegisteredErrorCallback>>>,
+    // Address of the user data of the memory monitor currently installed, 0 if none
+    memory_monitor: Arc<Mutex<usize>>,
 }
 
 unsafe impl Send for Device {}
 unsafe impl Sync for Device {}
 
 impl Device {
+    /// The number of nested instance levels supported by Embree.
+    ///
+    /// Embree fixes this limit when it is compiled and does not report it as a device property:
+    /// this is the `RTC_MAX_INSTANCE_LEVEL_COUNT` of the headers the bindings were built against,
+    /// which must match the linked Embree.
+    pub const MAX_INSTANCE_LEVEL_COUNT: u32 = embree4_sys::RTC_MAX_INSTANCE_LEVEL_COUNT;
+
     /// Constructs a new `Device` using the provided configuration string.
     ///
     /// # Arguments
@@ -24,7 +59,9 @@ impl Device {
     ///              See [rtcNewDevice](https://github.com/embree/embree/blob/master/doc/src/api/rtcNewDevice.md) for valid configuration values.
     ///
     /// # Returns
-    /// A `Result` containing the created `Device` if successful, or an error if the device creation fails.
+    /// A `Result` containing the created `Device` if successful, or an
+    /// [EmbreeError::DeviceCreation] holding the error code reported by Embree otherwise. A
+    /// configuration string containing a nul byte is reported as `INVALID_ARGUMENT`.
     ///
     /// # Examples
     /// ```
@@ -33,31 +70,138 @@ impl Device {
     /// let device = Device::try_new(Some("verbose=1")).unwrap();
     /// // Use the device...
     /// ```
-    pub fn try_new(config: Option<&str>) -> Result<Self> {
+    pub fn try_new(config: Option<&str>) -> Result<Self, EmbreeError> {
         let handle = match config {
             None => unsafe { embree4_sys::rtcNewDevice(null_mut()) },
-            Some(config) => unsafe {
-                let _mxcsr = Mxcsr::setup();
-                embree4_sys::rtcNewDevice(config.as_bytes() as *const _ as _)
-            },
+            Some(config) => {
+                let config = CString::new(config)
+                    .map_err(|_| EmbreeError::DeviceCreation(RTCError::INVALID_ARGUMENT))?;
+                unsafe {
+                    let _mxcsr = Mxcsr::setup();
+                    embree4_sys::rtcNewDevice(config.as_ptr())
+                }
+            }
         };
 
         if handle.is_null() {
-            let error = device_error_raw(null_mut());
-            bail!("Failed to create device: {:?}", error);
+            let error = device_error_raw(null_mut()).unwrap_or(RTCError::UNKNOWN);
+            return Err(EmbreeError::DeviceCreation(error));
         }
 
-        Ok(Device { handle })
+        Ok(Device {
+            handle,
+            last_error: Default::default(),
+            error_callback: Default::default(),
+            memory_monitor: Default::default(),
+        })
+    }
+
+    /// Constructs a new `Device`, trying the AVX512, AVX2, SSE4.2 and SSE2 instruction sets in
+    /// turn until device creation succeeds.
+    ///
+    /// This works around systems on which creating a device for the default instruction set
+    /// fails, e.g. when the CPU misreports its features.
+    ///
+    /// # Returns
+    /// A `Result` containing the first device created, or the error of the last attempt if all of
+    /// them failed.
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::prelude::*;
+    ///
+    /// let device = Device::try_new_best().unwrap();
+    /// ```
+    pub fn try_new_best() -> Result<Self> {
+        first_created(&FALLBACK_ISAS, |isa| {
+            Ok(Self::try_new(Some(&format!("isa={isa}")))?)
+        })
     }
 
-    /// Returns the error code associated with the device, if any.
+    /// Returns the error code of the calling thread associated with the device, if any.
+    ///
+    /// Errors raised on other threads are not reported, see [Device::last_error].
+    ///
+    /// Embree clears the error code once it has been read, so this behaves like
+    /// [Device::take_error].
     ///
     /// # Returns
     /// `Some(error_code)` if there is an error associated with the device, otherwise `None`.
     pub fn error(&self) -> Option<embree4_sys::RTCError> {
+        self.take_error()
+    }
+
+    /// Reads and clears the error code of the calling thread associated with the device, if any.
+    ///
+    /// Embree only records the first error that occurred since the error code was last read, so an
+    /// error that is not taken would otherwise hide the errors of later calls.
+    ///
+    /// # Returns
+    /// `Some(error_code)` if there is an error associated with the device, otherwise `None`.
+    pub fn take_error(&self) -> Option<embree4_sys::RTCError> {
         device_error_raw(self.handle)
     }
 
+    /// Returns the latest error raised on any thread while an error callback was registered.
+    ///
+    /// Unlike [Device::error], this does not clear the error code of the calling thread.
+    ///
+    /// # Returns
+    /// `Some(error_code)` if an error was reported to the error callback, otherwise `None`.
+    pub fn last_error(&self) -> Option<RTCError> {
+        *self.last_error.lock().unwrap()
+    }
+
+    /// Queries a property of the device, such as its version or the supported features.
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::prelude::*;
+    /// use embree4_sys::RTCDeviceProperty;
+    ///
+    /// let device = Device::try_new(None).unwrap();
+    /// let major = device.property(RTCDeviceProperty::VERSION_MAJOR).unwrap();
+    /// assert_eq!(major, 4);
+    /// ```
+    pub fn property(&self, prop: RTCDeviceProperty) -> Result<isize> {
+        clear_device_error(self.handle);
+        let value = unsafe { embree4_sys::rtcGetDeviceProperty(self.handle, prop) };
+        device_error_or(self, value, "Could not get device property")
+    }
+
+    /// Sets a writable property of the device.
+    ///
+    /// Only Embree's internal debug properties, `RTCDeviceProperty(1000000)` to
+    /// `RTCDeviceProperty(1000003)`, are writable. Other properties are rejected before reaching
+    /// Embree.
+    pub fn set_property(&self, prop: RTCDeviceProperty, value: isize) -> Result<()> {
+        if !WRITABLE_PROPERTIES.contains(&prop.0) {
+            bail!("Device property {prop:?} is read-only");
+        }
+
+        clear_device_error(self.handle);
+        unsafe {
+            embree4_sys::rtcSetDeviceProperty(self.handle, prop, value);
+        }
+        device_error_or(self, (), "Could not set device property")
+    }
+
+    /// Creates a new geometry of the given type, released when the returned [RawGeometry] is
+    /// dropped.
+    ///
+    /// The device error is cleared beforehand, so that a failure reports the error raised by this
+    /// creation rather than one left over by an earlier call.
+    pub fn new_geometry(&self, geometry_type: RTCGeometryType) -> Result<RawGeometry> {
+        clear_device_error(self.handle);
+        let geometry = unsafe { embree4_sys::rtcNewGeometry(self.handle, geometry_type) };
+        device_error_or(self, (), "Failed to create geometry")?;
+        if geometry.is_null() {
+            bail!("Failed to create geometry");
+        }
+
+        Ok(RawGeometry::from_raw(geometry, geometry_type))
+    }
+
     /// Returns the device as a raw handle.
     ///
     /// # Safety
@@ -71,6 +215,10 @@ impl Device {
     ///
     /// For semantic see the reference for [RtcSetDeviceErrorFunction](https://github.com/RenderKit/embree/blob/master/doc/src/api/rtcSetDeviceErrorFunction.md).
     ///
+    /// The callback is invoked from the thread that raised the error, calls from several threads are
+    /// serialized. Each error is also recorded for [Device::last_error]. Errors and advisories raised on
+    /// the thread running the callback, e.g. by using the device from it, are not passed to it.
+    ///
     /// To setup a permanent callback, use [std::mem::forget] on the returned [ErrorCallBackScope] but this will force the callback to have a `'static` lifetime.
     ///
     /// # Example
@@ -84,32 +232,69 @@ impl Device {
     ///     print!("Embree error ({code:?}): {err}");
     /// }));
     /// ```
-    pub fn register_error_callback<'scope, F: FnMut(RTCError, &str) + 'scope>(
+    pub fn register_error_callback<'scope, F: FnMut(RTCError, &str) + Send + 'scope>(
         &self,
-        mut callback: F,
+        callback: F,
     ) -> ErrorCallBackScope<'scope> {
         // adapted from https://adventures.michaelfbryan.com/posts/rust-closures-in-ffi/
-        unsafe extern "C" fn trampoline<'scope, F: FnMut(RTCError, &str) + 'scope>(
+        unsafe extern "C" fn trampoline<'scope, F: FnMut(RTCError, &str) + Send + 'scope>(
             user_ptr: *mut ::std::os::raw::c_void,
             code: RTCError,
             str: *const ::std::os::raw::c_char,
         ) {
-            let f = &mut *(user_ptr as *mut F);
+            let state = &*(user_ptr as *const ErrorCallbackState<F>);
+            // Advisories are reported with RTCError::NONE and are not errors
+            if code != RTCError::NONE {
+                if let Ok(mut last_error) = state.last_error.lock() {
+                    *last_error = Some(code);
+                }
+            }
+
+            // Errors raised from within the callback, e.g. by using the device, are not forwarded
+            // to it: the callback is already running on this thread
+            if REPORTING_ERROR.with(|reporting| reporting.replace(true)) {
+                return;
+            }
             let s = CStr::from_ptr(str);
-            (f)(code, &s.to_string_lossy());
+            if let Ok(mut f) = state.callback.lock() {
+                (f)(code, &s.to_string_lossy());
+            }
+            REPORTING_ERROR.with(|reporting| reporting.set(false));
         }
 
+        // The state is shared so that it stays at the same address until the scope is dropped and
+        // advisories being reported are done with it
+        let state: Arc<dyn CallbackState + 'scope> = Arc::new(ErrorCallbackState {
+            callback: Mutex::new(callback),
+            last_error: self.last_error.clone(),
+        });
+
+        let user_ptr = Arc::as_ptr(&state) as *const () as *mut c_void;
+        let mut registration = self
+            .error_callback
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
         unsafe {
-            embree4_sys::rtcSetDeviceErrorFunction(
-                self.handle,
-                Some(trampoline::<F>),
-                &mut callback as *mut _ as *mut c_void,
-            )
+            embree4_sys::rtcSetDeviceErrorFunction(self.handle, Some(trampoline::<F>), user_ptr)
         };
+        *registration = Some(RegisteredErrorCallback {
+            function: trampoline::<F>,
+            user_ptr,
+            // SAFETY: the scope removes the registration and waits for the advisories holding
+            // this reference before the end of `'scope`
+            _state: unsafe {
+                std::mem::transmute::<
+                    Arc<dyn CallbackState + 'scope>,
+                    Arc<dyn CallbackState + 'static>,
+                >(state.clone())
+            },
+        });
 
         ErrorCallBackScope {
             device: self.handle,
-            lifetime: Default::default(),
+            registration: self.error_callback.clone(),
+            user_ptr,
+            state,
         }
     }
 
@@ -137,7 +322,7 @@ impl Device {
         F: FnMut(isize, bool) -> bool + 'scope,
     >(
         &self,
-        mut callback: F,
+        callback: F,
     ) -> MemoryMonitorCallBackScope<'scope> {
         // adapted from https://adventures.michaelfbryan.com/posts/rust-closures-in-ffi/
         unsafe extern "C" fn trampoline<'scope, F: FnMut(isize, bool) -> bool + 'scope>(
@@ -149,25 +334,105 @@ impl Device {
             f(size, post)
         }
 
-        unsafe {
-            embree4_sys::rtcSetDeviceMemoryMonitorFunction(
-                self.handle,
-                Some(trampoline::<F>),
-                &mut callback as *mut _ as *mut c_void,
-            )
-        };
+        // The callback is boxed so that it stays at the same address until the scope is dropped
+        let mut callback = Box::new(callback);
+        let user_ptr = &mut *callback as *mut F as *mut c_void;
+        self.set_memory_monitor(Some(trampoline::<F>), user_ptr);
         MemoryMonitorCallBackScope {
             device: self.handle,
-            lifetime: Default::default(),
+            registration: self.memory_monitor.clone(),
+            user_ptr,
+            _callback: callback,
         }
     }
 
+    /// Installs a memory monitor rejecting the allocations that would bring the memory allocated
+    /// by Embree past `bytes`, and returns a structure that will remove it on drop.
+    ///
+    /// Embree does not accept custom allocators, but a rejected allocation makes the operation
+    /// that requested it, e.g. a scene commit, fail with `RTCError::OUT_OF_MEMORY` instead of
+    /// growing the memory usage of the process. Only allocations made after this call are
+    /// counted, rejected ones excepted, and the budget replaces any memory monitor callback set on
+    /// the device.
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::prelude::*;
+    ///
+    /// let device = Device::try_new(None).unwrap();
+    /// let budget = device.set_memory_budget(256 << 20);
+    /// let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    /// let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 5.0), 1.0).unwrap();
+    /// scene.attach_geometry(&sphere).unwrap();
+    /// let scene = scene.commit().unwrap();
+    /// assert!(budget.allocated() <= 256 << 20);
+    /// ```
+    pub fn set_memory_budget(&self, bytes: usize) -> MemoryBudgetScope<'_> {
+        unsafe extern "C" fn monitor(user_ptr: *mut c_void, size: isize, _post: bool) -> bool {
+            let budget = &*(user_ptr as *const MemoryBudget);
+            if size <= 0 {
+                budget.allocated.fetch_add(size, Ordering::SeqCst);
+                return true;
+            }
+
+            // Rejected allocations do not happen, so they are not counted
+            budget
+                .allocated
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |allocated| {
+                    let allocated = allocated.checked_add(size)?;
+                    (allocated <= budget.bytes as isize).then_some(allocated)
+                })
+                .is_ok()
+        }
+
+        let state = Box::new(MemoryBudget {
+            bytes: bytes.min(isize::MAX as usize),
+            allocated: AtomicIsize::new(0),
+        });
+        self.set_memory_monitor(Some(monitor), &*state as *const _ as *mut c_void);
+        MemoryBudgetScope {
+            device: self,
+            state,
+        }
+    }
+
+    fn set_memory_monitor(&self, function: RTCMemoryMonitorFunction, user_ptr: *mut c_void) {
+        let mut registration = self.memory_monitor.lock().unwrap();
+        unsafe {
+            embree4_sys::rtcSetDeviceMemoryMonitorFunction(self.handle, function, user_ptr);
+        }
+        *registration = user_ptr as usize;
+    }
+
     /// Remove a previously setup error callback.
     ///
     /// This function should not be needed as the [ErrorCallBackScope] struct should do it automatically.
     pub fn remove_error_callback(&mut self) {
         unsafe {
-            embree4_sys::rtcSetDeviceMemoryMonitorFunction(self.handle, None, null_mut());
+            embree4_sys::rtcSetDeviceErrorFunction(self.handle, None, null_mut());
+        }
+        *self.error_callback.lock().unwrap() = None;
+    }
+
+    /// Reports an advisory message to the registered error callback, if any.
+    ///
+    /// Advisories flag valid but likely unintended usage. They are passed to the callback with the
+    /// `RTCError::NONE` code and are not recorded as errors.
+    pub(crate) fn advise(&self, message: &str) {
+        #[cfg(feature = "log")]
+        log::warn!("{message}");
+
+        // The callback is called without holding the lock, so that it may use the device
+        let registration = self
+            .error_callback
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        if let Some(callback) = registration {
+            let message = CString::new(message).unwrap_or_default();
+            unsafe {
+                (callback.function)(callback.user_ptr, RTCError::NONE, message.as_ptr());
+            }
         }
     }
 
@@ -176,8 +441,24 @@ impl Device {
     /// This function should not be needed as the [MemoryMonitorCallBackScope] struct should do it
     /// automatically.
     pub fn remove_memory_monitor_callback(&mut self) {
+        self.set_memory_monitor(None, null_mut());
+    }
+}
+
+/// Clones share the underlying Embree device, which is reference counted: cloning retains it and
+/// each drop releases it once. The error callback and [Device::last_error] are shared as well, as
+/// Embree keeps them per device.
+impl Clone for Device {
+    fn clone(&self) -> Self {
         unsafe {
-            embree4_sys::rtcSetDeviceMemoryMonitorFunction(self.handle, None, null_mut());
+            embree4_sys::rtcRetainDevice(self.handle);
+        }
+
+        Device {
+            handle: self.handle,
+            last_error: self.last_error.clone(),
+            error_callback: self.error_callback.clone(),
+            memory_monitor: self.memory_monitor.clone(),
         }
     }
 }
@@ -193,16 +474,58 @@ impl Drop for Device {
 /// A type that will remove the device error callback on drop
 ///
 /// # Note:
-/// The previous callback is not restored on drop
+/// The previous callback is not restored on drop, and a callback registered since is left in
+/// place
 pub struct ErrorCallBackScope<'scope> {
     device: embree4_sys::RTCDevice,
-    lifetime: PhantomData<&'scope ()>,
+    registration: Arc<Mutex<Option<RegisteredErrorCallback>>>,
+    user_ptr: *mut c_void,
+    state: Arc<dyn CallbackState + 'scope>,
+}
+
+// The error callback currently installed on a device, so that the crate can report advisories
+// through it.
+#[derive(Clone)]
+struct RegisteredErrorCallback {
+    function: unsafe extern "C" fn(*mut c_void, RTCError, *const ::std::os::raw::c_char),
+    user_ptr: *mut c_void,
+    // Keeps the state alive while an advisory is reported
+    _state: Arc<dyn CallbackState>,
+}
+
+// The callback is required to be `Send`, as Embree may report errors from its worker threads
+unsafe impl Send for RegisteredErrorCallback {}
+
+// Type-erased `ErrorCallbackState`
+trait CallbackState {}
+
+impl<T> CallbackState for T {}
+
+struct ErrorCallbackState<F> {
+    callback: Mutex<F>,
+    last_error: Arc<Mutex<Option<RTCError>>>,
 }
 
 impl Drop for ErrorCallBackScope<'_> {
     fn drop(&mut self) {
-        unsafe {
-            embree4_sys::rtcSetDeviceErrorFunction(self.device, None, null_mut());
+        let mut registration = self
+            .registration
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if registration
+            .as_ref()
+            .is_some_and(|callback| callback.user_ptr == self.user_ptr)
+        {
+            unsafe {
+                embree4_sys::rtcSetDeviceErrorFunction(self.device, None, null_mut());
+            }
+            *registration = None;
+        }
+        drop(registration);
+
+        // Advisories being reported on other threads still use the callback
+        while Arc::strong_count(&self.state) > 1 {
+            std::thread::yield_now();
         }
     }
 }
@@ -210,18 +533,74 @@ impl Drop for ErrorCallBackScope<'_> {
 /// A type that will remove the memory monitor callback on drop
 ///
 /// # Note:
-/// The previous callback is not restored on drop
+/// The previous callback is not restored on drop, and a callback installed since is left in
+/// place
 pub struct MemoryMonitorCallBackScope<'scope> {
     device: embree4_sys::RTCDevice,
-    lifetime: PhantomData<&'scope ()>,
+    registration: Arc<Mutex<usize>>,
+    user_ptr: *mut c_void,
+    _callback: Box<dyn FnMut(isize, bool) -> bool + 'scope>,
 }
 
 impl Drop for MemoryMonitorCallBackScope<'_> {
     fn drop(&mut self) {
+        remove_memory_monitor(self.device, &self.registration, self.user_ptr);
+    }
+}
+
+/// A type that will remove the memory budget of [Device::set_memory_budget] on drop
+///
+/// # Note:
+/// A memory monitor installed since is left in place
+pub struct MemoryBudgetScope<'d> {
+    device: &'d Device,
+    state: Box<MemoryBudget>,
+}
+
+struct MemoryBudget {
+    bytes: usize,
+    allocated: AtomicIsize,
+}
+
+impl MemoryBudgetScope<'_> {
+    /// Returns the number of bytes currently allocated by Embree since the budget was set.
+    pub fn allocated(&self) -> usize {
+        self.state.allocated.load(Ordering::SeqCst).max(0) as usize
+    }
+}
+
+impl Drop for MemoryBudgetScope<'_> {
+    fn drop(&mut self) {
+        let user_ptr = &*self.state as *const _ as *mut c_void;
+        remove_memory_monitor(self.device.handle, &self.device.memory_monitor, user_ptr);
+    }
+}
+
+// Removes the memory monitor of a device if it is still the one reading `user_ptr`
+fn remove_memory_monitor(
+    device: embree4_sys::RTCDevice,
+    registration: &Mutex<usize>,
+    user_ptr: *mut c_void,
+) {
+    let mut registration = registration.lock().unwrap_or_else(PoisonError::into_inner);
+    if *registration == user_ptr as usize {
         unsafe {
-            embree4_sys::rtcSetDeviceMemoryMonitorFunction(self.device, None, null_mut());
+            embree4_sys::rtcSetDeviceMemoryMonitorFunction(device, None, null_mut());
+        }
+        *registration = 0;
+    }
+}
+
+// Creates a value with each of `configs` in turn, returning the first success
+fn first_created<T>(configs: &[&str], mut create: impl FnMut(&str) -> Result<T>) -> Result<T> {
+    let mut last_error = None;
+    for config in configs {
+        match create(config) {
+            Ok(value) => return Ok(value),
+            Err(error) => last_error = Some(error.context(format!("With {config}"))),
         }
     }
+    Err(last_error.unwrap_or_else(|| anyhow::anyhow!("No configuration to try")))
 }
 
 #[test]
@@ -236,8 +615,163 @@ fn try_new_invalid_config() {
     assert!(err_device.is_err());
 }
 
+#[test]
+fn try_new_reports_device_creation_errors() {
+    let Err(error) = Device::try_new(Some("verbose=bruh")) else {
+        panic!("device creation should fail");
+    };
+    assert!(matches!(error, EmbreeError::DeviceCreation(code) if code != RTCError::NONE));
+
+    let Err(error) = Device::try_new(Some("verbose=0\0")) else {
+        panic!("device creation should fail");
+    };
+    assert_eq!(
+        error,
+        EmbreeError::DeviceCreation(RTCError::INVALID_ARGUMENT)
+    );
+}
+
 #[test]
 fn try_new_no_config() {
     let ok_device = Device::try_new(None);
     assert!(ok_device.is_ok());
 }
+
+#[test]
+fn errors_are_per_thread() {
+    let device = Device::try_new(None).unwrap();
+    let _callback = device.register_error_callback(|_, _| {});
+    let barrier = std::sync::Barrier::new(2);
+
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            // REFIT is only valid for geometries
+            unsafe {
+                let scene = embree4_sys::rtcNewScene(device.handle);
+                embree4_sys::rtcSetSceneBuildQuality(scene, embree4_sys::RTCBuildQuality::REFIT);
+                embree4_sys::rtcReleaseScene(scene);
+            }
+            barrier.wait();
+            barrier.wait();
+            assert!(device.error().is_some());
+        });
+        s.spawn(|| {
+            barrier.wait();
+            assert!(device.error().is_none());
+            barrier.wait();
+        });
+    });
+
+    assert!(device.last_error().is_some());
+}
+
+#[test]
+fn set_and_read_back_property() {
+    let device = Device::try_new(None).unwrap();
+
+    let debug_property = RTCDeviceProperty(*WRITABLE_PROPERTIES.start());
+    device.set_property(debug_property, 42).unwrap();
+    assert_eq!(device.property(debug_property).unwrap(), 42);
+
+    let err = device
+        .set_property(RTCDeviceProperty::VERSION, 0)
+        .unwrap_err();
+    assert!(err.to_string().contains("read-only"));
+}
+
+#[test]
+fn dropping_a_clone_keeps_the_device_usable() {
+    use crate::scene::{Scene, SceneOptions};
+
+    let device = Device::try_new(None).unwrap();
+    let clone = device.clone();
+    {
+        let scene = Scene::try_new(&clone, SceneOptions::default()).unwrap();
+        scene.commit().unwrap();
+    }
+    drop(clone);
+
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    scene.commit().unwrap();
+    assert!(device.error().is_none());
+}
+
+#[test]
+fn best_device_falls_back_to_lower_isa() {
+    let mut attempts = Vec::new();
+    let isa = first_created(&FALLBACK_ISAS, |isa| {
+        attempts.push(isa.to_owned());
+        if isa == "avx512" {
+            bail!("Failed to create device: Some(UNSUPPORTED_CPU)");
+        }
+        Ok(isa.to_owned())
+    })
+    .unwrap();
+    assert_eq!(isa, "avx2");
+    assert_eq!(attempts, ["avx512", "avx2"]);
+
+    let error = first_created(&FALLBACK_ISAS, |_| -> Result<()> { bail!("no device") });
+    assert!(format!("{:#}", error.unwrap_err()).contains("sse2"));
+
+    Device::try_new_best().unwrap();
+}
+
+#[test]
+fn commit_past_memory_budget_fails() {
+    use crate::{
+        error::EmbreeError,
+        geometry::TriangleMeshGeometry,
+        scene::{Scene, SceneOptions},
+    };
+
+    let device = Device::try_new(None).unwrap();
+    let count = 100_000u32;
+    let vertices: Vec<_> = (0..3 * count)
+        .map(|i| (i as f32, (i % 3) as f32, (i / 3) as f32))
+        .collect();
+    let indices: Vec<_> = (0..count).map(|i| (3 * i, 3 * i + 1, 3 * i + 2)).collect();
+    let mesh = TriangleMeshGeometry::try_new(&device, &vertices, &indices).unwrap();
+
+    // Dropping a monitor replaced by the budget leaves the budget in place
+    let monitor = device.register_device_memory_monitor_callback(|_, _| true);
+    let budget = device.set_memory_budget(4096);
+    drop(monitor);
+
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    scene.attach_geometry(&mesh).unwrap();
+    let err = scene.commit().err().unwrap();
+    let error = err.downcast_ref::<EmbreeError>().unwrap();
+    assert_eq!(error.code(), RTCError::OUT_OF_MEMORY);
+    assert!(budget.allocated() <= 4096);
+
+    // Without the budget, the same scene builds
+    drop(budget);
+    assert!(scene.commit().is_ok());
+}
+
+#[test]
+fn error_callback_may_use_the_device() {
+    let device = Device::try_new(None).unwrap();
+    let messages = Mutex::new(Vec::new());
+    let _callback = device.register_error_callback(|_, message| {
+        messages.lock().unwrap().push(message.to_string());
+        // Not passed to the callback, which is still running
+        device.advise("nested");
+    });
+
+    device.advise("first");
+    device.advise("second");
+    assert_eq!(*messages.lock().unwrap(), ["first", "second"]);
+}
+
+#[test]
+fn dropping_an_older_error_callback_keeps_the_newer_one() {
+    let device = Device::try_new(None).unwrap();
+    let older = device.register_error_callback(|_, _| {});
+    let called = std::sync::atomic::AtomicBool::new(false);
+    let _newer = device.register_error_callback(|_, _| called.store(true, Ordering::SeqCst));
+    drop(older);
+
+    device.advise("still reported");
+    assert!(called.load(Ordering::SeqCst));
+}
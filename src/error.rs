@@ -0,0 +1,129 @@
+use std::{fmt, io};
+
+use embree4_sys::RTCError;
+
+/// An error reported by Embree.
+///
+/// The [Display](fmt::Display) implementation maps each error code to a fixed, human readable
+/// message that does not depend on Embree's internal formatting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmbreeError {
+    /// An error code reported by the device.
+    ///
+    /// See [rtcGetDeviceError](https://github.com/embree/embree/blob/master/doc/src/api/rtcGetDeviceError.md).
+    Device(RTCError),
+    /// The device could not be created, e.g. because of an invalid configuration string
+    /// (`INVALID_ARGUMENT`) or because the CPU lacks the required instruction sets
+    /// (`UNSUPPORTED_CPU`).
+    ///
+    /// See [rtcNewDevice](https://github.com/embree/embree/blob/master/doc/src/api/rtcNewDevice.md).
+    DeviceCreation(RTCError),
+    /// A scene commit was cancelled by its progress monitor returning `false`.
+    ///
+    /// The scene is left uncommitted and can be committed again.
+    ///
+    /// See [rtcSetSceneProgressMonitorFunction](https://github.com/embree/embree/blob/master/doc/src/api/rtcSetSceneProgressMonitorFunction.md).
+    Cancelled,
+}
+
+impl EmbreeError {
+    /// Returns the underlying Embree error code.
+    pub fn code(&self) -> RTCError {
+        match self {
+            EmbreeError::Device(code) | EmbreeError::DeviceCreation(code) => *code,
+            EmbreeError::Cancelled => RTCError::CANCELLED,
+        }
+    }
+}
+
+impl From<RTCError> for EmbreeError {
+    fn from(code: RTCError) -> Self {
+        EmbreeError::Device(code)
+    }
+}
+
+impl fmt::Display for EmbreeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmbreeError::Device(code) => f.write_str(error_message(*code)),
+            EmbreeError::DeviceCreation(code) => {
+                write!(f, "could not create device: {}", error_message(*code))
+            }
+            EmbreeError::Cancelled => f.write_str("cancelled by the progress monitor"),
+        }
+    }
+}
+
+impl std::error::Error for EmbreeError {}
+
+impl From<EmbreeError> for io::Error {
+    fn from(error: EmbreeError) -> Self {
+        let kind = match error.code() {
+            RTCError::INVALID_ARGUMENT => io::ErrorKind::InvalidInput,
+            RTCError::OUT_OF_MEMORY => io::ErrorKind::OutOfMemory,
+            RTCError::UNSUPPORTED_CPU => io::ErrorKind::Unsupported,
+            RTCError::CANCELLED => io::ErrorKind::Interrupted,
+            _ => io::ErrorKind::Other,
+        };
+        io::Error::new(kind, error)
+    }
+}
+
+fn error_message(code: RTCError) -> &'static str {
+    match code {
+        RTCError::NONE => "no error",
+        RTCError::UNKNOWN => "unknown error",
+        RTCError::INVALID_ARGUMENT => "invalid argument",
+        RTCError::INVALID_OPERATION => "invalid operation",
+        RTCError::OUT_OF_MEMORY => "out of memory",
+        RTCError::UNSUPPORTED_CPU => "unsupported cpu",
+        RTCError::CANCELLED => "cancelled",
+        RTCError::LEVEL_ZERO_RAISED_ERROR => "level zero raised error",
+        _ => "unrecognized error",
+    }
+}
+
+#[test]
+fn display_device_errors() {
+    let cases = [
+        (RTCError::NONE, "no error"),
+        (RTCError::UNKNOWN, "unknown error"),
+        (RTCError::INVALID_ARGUMENT, "invalid argument"),
+        (RTCError::INVALID_OPERATION, "invalid operation"),
+        (RTCError::OUT_OF_MEMORY, "out of memory"),
+        (RTCError::UNSUPPORTED_CPU, "unsupported cpu"),
+        (RTCError::CANCELLED, "cancelled"),
+        (RTCError::LEVEL_ZERO_RAISED_ERROR, "level zero raised error"),
+    ];
+
+    for (code, message) in cases {
+        assert_eq!(EmbreeError::from(code).to_string(), message);
+    }
+}
+
+#[test]
+fn display_device_creation_errors() {
+    let error = EmbreeError::DeviceCreation(RTCError::UNSUPPORTED_CPU);
+    assert_eq!(error.code(), RTCError::UNSUPPORTED_CPU);
+    assert_eq!(
+        error.to_string(),
+        "could not create device: unsupported cpu"
+    );
+}
+
+#[test]
+fn display_cancelled() {
+    let error = EmbreeError::Cancelled;
+    assert_eq!(error.code(), RTCError::CANCELLED);
+    assert_eq!(error.to_string(), "cancelled by the progress monitor");
+
+    let error: io::Error = error.into();
+    assert_eq!(error.kind(), io::ErrorKind::Interrupted);
+}
+
+#[test]
+fn into_io_error() {
+    let error: io::Error = EmbreeError::from(RTCError::OUT_OF_MEMORY).into();
+    assert_eq!(error.kind(), io::ErrorKind::OutOfMemory);
+    assert_eq!(error.to_string(), "out of memory");
+}
@@ -0,0 +1,257 @@
+use std::{ffi::c_void, marker::PhantomData, slice};
+
+use embree4_sys::RTCFilterFunctionNArguments;
+
+use crate::geometry::{
+    CurveGeometry, Geometry, SphereGeometry, SubdivGeometry, TriangleMeshGeometry,
+};
+
+type FilterFn<'f> = Box<dyn Fn(&mut RTCFilterFunctionNArguments) + Send + Sync + 'f>;
+
+/// A sequence of intersection filters run one after the other.
+///
+/// A filter rejects a hit by setting its `valid` entry to `0`, see
+/// [RTCFilterFunctionN](https://github.com/embree/embree/blob/master/doc/src/api/RTCFilterFunctionN.md).
+/// Once every hit of a call has been rejected, the remaining filters are skipped.
+///
+/// Embree runs filters concurrently from the threads tracing rays, so they must be `Sync`.
+///
+/// # Example
+/// ```
+/// use embree4_rs::{filter::*, prelude::*};
+///
+/// let device = Device::try_new(None).unwrap();
+/// let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 5.0), 1.0).unwrap();
+///
+/// let alpha_test = |args: &mut embree4_sys::RTCFilterFunctionNArguments| { /* ... */ };
+/// let self_intersection = |args: &mut embree4_sys::RTCFilterFunctionNArguments| { /* ... */ };
+/// let _filter = sphere.set_intersect_filter(FilterChain::new().then(alpha_test).then(self_intersection));
+/// ```
+#[derive(Default)]
+pub struct FilterChain<'f> {
+    filters: Vec<FilterFn<'f>>,
+}
+
+impl<'f> FilterChain<'f> {
+    /// Constructs an empty `FilterChain`, accepting every hit.
+    pub fn new() -> Self {
+        Self {
+            filters: Vec::new(),
+        }
+    }
+
+    /// Appends a filter to the chain.
+    pub fn then<F: Fn(&mut RTCFilterFunctionNArguments) + Send + Sync + 'f>(
+        mut self,
+        f: F,
+    ) -> Self {
+        self.filters.push(Box::new(f));
+        self
+    }
+
+    fn run(&self, args: &mut RTCFilterFunctionNArguments) {
+        for filter in self.filters.iter() {
+            filter(args);
+
+            let valid = unsafe { slice::from_raw_parts(args.valid, args.N as usize) };
+            if valid.iter().all(|&v| v == 0) {
+                break;
+            }
+        }
+    }
+}
+
+/// Geometries supporting intersection filters.
+///
+/// The filter state is passed to Embree through the geometry user data pointer, so this is not
+/// implemented for geometries that rely on it.
+pub trait FilterGeometry: Geometry {
+    /// Sets the intersection filter of the geometry and recommits it. Returns a structure that
+    /// will remove it on drop.
+    ///
+    /// The filter is only taken into account by scenes committed after this call.
+    ///
+    /// The user data pointer of the geometry is replaced while the filter is set. Filters still
+    /// see the previous pointer in `geometryUserPtr`, and it is restored when the filter is
+    /// removed.
+    ///
+    /// Scenes may run the filter until they are committed again, so it cannot borrow anything.
+    /// Forgetting the returned [FilterScope] with [std::mem::forget] leaks the chain and keeps the
+    /// filter set.
+    fn set_intersect_filter(&self, chain: FilterChain<'static>) -> FilterScope<'_, Self>
+    where
+        Self: Sized,
+    {
+        unsafe extern "C" fn trampoline(args: *const RTCFilterFunctionNArguments) {
+            let mut args = *args;
+            let state = &*(args.geometryUserPtr as *const FilterState);
+            args.geometryUserPtr = state.user_data;
+            state.chain.run(&mut args);
+        }
+
+        let handle = self.geometry();
+        // Boxed so that it stays at the same address until the scope is dropped
+        let state = Box::new(FilterState {
+            chain,
+            user_data: unsafe { embree4_sys::rtcGetGeometryUserData(handle) },
+        });
+        unsafe {
+            // Released by the scope, so that its drop never reaches a released geometry
+            embree4_sys::rtcRetainGeometry(handle);
+            embree4_sys::rtcSetGeometryUserData(handle, &*state as *const _ as *mut c_void);
+            embree4_sys::rtcSetGeometryIntersectFilterFunction(handle, Some(trampoline));
+            embree4_sys::rtcCommitGeometry(handle);
+        }
+
+        FilterScope {
+            handle,
+            state,
+            geometry: PhantomData,
+        }
+    }
+
+    /// Sets whether the filter function of the ray query context runs on the hits of the geometry
+    /// even when the context does not invoke it on every geometry, and recommits the geometry.
+    ///
+    /// See [RayQueryContext::invoke_argument_filter](crate::context::RayQueryContext::invoke_argument_filter).
+    /// The geometry is only taken into account by scenes committed after this call.
+    fn enable_argument_filter(&self, enable: bool) {
+        let handle = self.geometry();
+        unsafe {
+            embree4_sys::rtcSetGeometryEnableFilterFunctionFromArguments(handle, enable);
+            embree4_sys::rtcCommitGeometry(handle);
+        }
+    }
+}
+
+impl FilterGeometry for CurveGeometry {}
+impl FilterGeometry for SphereGeometry {}
+impl FilterGeometry for SubdivGeometry {}
+impl FilterGeometry for TriangleMeshGeometry {}
+
+// The filter chain of a geometry and the user data pointer it replaced
+struct FilterState {
+    chain: FilterChain<'static>,
+    user_data: *mut c_void,
+}
+
+/// A type that will remove the intersection filter of a geometry on drop
+///
+/// # Note:
+/// Scenes committed while the filter was set keep using it until they are committed again.
+pub struct FilterScope<'g, G> {
+    handle: embree4_sys::RTCGeometry,
+    state: Box<FilterState>,
+    geometry: PhantomData<&'g G>,
+}
+
+impl<G> Drop for FilterScope<'_, G> {
+    fn drop(&mut self) {
+        unsafe {
+            // A filter set after this one stays in place
+            let current = embree4_sys::rtcGetGeometryUserData(self.handle);
+            if std::ptr::eq(current as *const FilterState, &*self.state) {
+                embree4_sys::rtcSetGeometryIntersectFilterFunction(self.handle, None);
+                embree4_sys::rtcSetGeometryUserData(self.handle, self.state.user_data);
+                embree4_sys::rtcCommitGeometry(self.handle);
+            }
+            embree4_sys::rtcReleaseGeometry(self.handle);
+        }
+    }
+}
+
+#[test]
+fn chained_filters() {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use crate::{
+        device::Device,
+        ray::RayBuilder,
+        scene::{Scene, SceneOptions},
+    };
+
+    let device = Device::try_new(None).unwrap();
+    let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 5.0), 1.0).unwrap();
+    let ray = RayBuilder::new((0.0, 0.0, 0.0), (0.0, 0.0, 1.0)).build();
+
+    let counter = |count: &Arc<AtomicUsize>| {
+        let count = count.clone();
+        move |_: &mut RTCFilterFunctionNArguments| {
+            count.fetch_add(1, Ordering::Relaxed);
+        }
+    };
+    let reject = |args: &mut RTCFilterFunctionNArguments| {
+        let valid = unsafe { slice::from_raw_parts_mut(args.valid, args.N as usize) };
+        valid.fill(0);
+    };
+
+    let trace = |chain| {
+        let _filter = sphere.set_intersect_filter(chain);
+        let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+        scene.attach_geometry(&sphere).unwrap();
+        let scene = scene.commit().unwrap();
+        scene.intersect_1(ray).unwrap().is_some()
+    };
+
+    // Both filters run and accept the hit
+    let (first, second) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+    assert!(trace(
+        FilterChain::new()
+            .then(counter(&first))
+            .then(counter(&second))
+    ));
+    assert!(first.load(Ordering::Relaxed) > 0);
+    assert!(second.load(Ordering::Relaxed) > 0);
+
+    // The second filter rejects the hit
+    let first = Arc::new(AtomicUsize::new(0));
+    assert!(!trace(
+        FilterChain::new().then(counter(&first)).then(reject)
+    ));
+    assert!(first.load(Ordering::Relaxed) > 0);
+
+    // The first filter rejects the hit, the second one is skipped
+    let second = Arc::new(AtomicUsize::new(0));
+    assert!(!trace(
+        FilterChain::new().then(reject).then(counter(&second))
+    ));
+    assert_eq!(second.load(Ordering::Relaxed), 0);
+}
+
+#[test]
+fn dropping_the_scope_removes_the_filter() {
+    use crate::{
+        device::Device,
+        ray::RayBuilder,
+        scene::{Scene, SceneOptions},
+    };
+
+    let device = Device::try_new(None).unwrap();
+    let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 5.0), 1.0).unwrap();
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    scene.attach_geometry(&sphere).unwrap();
+    let ray = RayBuilder::new((0.0, 0.0, 0.0), (0.0, 0.0, 1.0)).build();
+
+    // Filters see the user data of the geometry, which is restored on drop
+    let user_data = 0x1234;
+    unsafe { embree4_sys::rtcSetGeometryUserData(sphere.geometry(), user_data as *mut c_void) };
+
+    let reject = move |args: &mut RTCFilterFunctionNArguments| {
+        assert_eq!(args.geometryUserPtr as usize, user_data);
+        let valid = unsafe { slice::from_raw_parts_mut(args.valid, args.N as usize) };
+        valid.fill(0);
+    };
+    let filter = sphere.set_intersect_filter(FilterChain::new().then(reject));
+    assert!(scene.commit().unwrap().intersect_1(ray).unwrap().is_none());
+
+    drop(filter);
+    assert!(scene.commit().unwrap().intersect_1(ray).unwrap().is_some());
+    assert_eq!(
+        unsafe { embree4_sys::rtcGetGeometryUserData(sphere.geometry()) } as usize,
+        user_data
+    );
+    assert!(device.error().is_none());
+}
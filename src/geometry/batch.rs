@@ -0,0 +1,235 @@
+use std::cell::Cell;
+
+use anyhow::{bail, Result};
+
+use crate::{aabb::Aabb, device::Device};
+
+use super::{Geometry, SharedData, TriangleMeshGeometry, Validation};
+
+/// Merges many triangle meshes into a single geometry, to avoid the per-geometry overhead of
+/// attaching them one by one to a scene.
+///
+/// Sub-meshes are pushed one after the other: their vertices are concatenated and their indices
+/// remapped, and the resulting [BatchedGeometry] maps the primitive ids of hits back to the
+/// sub-mesh they belong to.
+///
+/// # Example
+/// ```
+/// use embree4_rs::prelude::*;
+///
+/// let triangle = [(0.0, 0.0, 0.0), (1.0, 0.0, 0.0), (0.0, 1.0, 0.0)];
+/// let moved = triangle.map(|(x, y, z)| (x + 2.0, y, z));
+///
+/// let mut batch = GeometryBatch::new();
+/// batch.push(&triangle, &[(0, 1, 2)]).unwrap();
+/// batch.push(&moved, &[(0, 1, 2)]).unwrap();
+///
+/// let device = Device::try_new(None).unwrap();
+/// let geometry = batch.build(&device).unwrap();
+/// assert_eq!(geometry.resolve(1), Some((1, 0)));
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct GeometryBatch {
+    vertices: Vec<(f32, f32, f32)>,
+    indices: Vec<(u32, u32, u32)>,
+    // Index of the first primitive of each sub-mesh
+    primitive_offsets: Vec<u32>,
+}
+
+impl GeometryBatch {
+    /// Constructs an empty `GeometryBatch`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a triangle mesh to the batch.
+    ///
+    /// # Returns
+    /// A `Result` containing the index of the sub-mesh, or an error if an index is out of the
+    /// vertices of the mesh.
+    pub fn push(
+        &mut self,
+        vertices: &[(f32, f32, f32)],
+        indices: &[(u32, u32, u32)],
+    ) -> Result<usize> {
+        if let Some(index) = indices
+            .iter()
+            .flat_map(|&(a, b, c)| [a, b, c])
+            .find(|&i| i as usize >= vertices.len())
+        {
+            bail!(
+                "Sub-mesh index {index} is out of its {} vertices",
+                vertices.len()
+            );
+        }
+        if self.vertices.len() + vertices.len() > u32::MAX as usize {
+            bail!("Batched geometry exceeds {} vertices", u32::MAX);
+        }
+        let vertex_offset = self.vertices.len() as u32;
+
+        self.primitive_offsets.push(self.indices.len() as u32);
+        self.vertices.extend_from_slice(vertices);
+        self.indices.extend(
+            indices
+                .iter()
+                .map(|&(a, b, c)| (a + vertex_offset, b + vertex_offset, c + vertex_offset)),
+        );
+        Ok(self.primitive_offsets.len() - 1)
+    }
+
+    /// Returns the number of sub-meshes pushed to the batch.
+    pub fn len(&self) -> usize {
+        self.primitive_offsets.len()
+    }
+
+    /// Returns `true` if no sub-mesh was pushed to the batch.
+    pub fn is_empty(&self) -> bool {
+        self.primitive_offsets.is_empty()
+    }
+
+    /// Builds the combined triangle mesh of all sub-meshes.
+    pub fn build(self, device: &Device) -> Result<BatchedGeometry> {
+        let mesh = TriangleMeshGeometry::try_new(device, &self.vertices, &self.indices)?;
+        Ok(BatchedGeometry {
+            mesh,
+            primitive_offsets: self.primitive_offsets,
+        })
+    }
+}
+
+/// A triangle mesh combining the sub-meshes of a [GeometryBatch].
+pub struct BatchedGeometry {
+    mesh: TriangleMeshGeometry,
+    primitive_offsets: Vec<u32>,
+}
+
+impl BatchedGeometry {
+    /// Returns the combined triangle mesh.
+    pub fn mesh(&self) -> &TriangleMeshGeometry {
+        &self.mesh
+    }
+
+    /// Returns the number of sub-meshes.
+    pub fn sub_mesh_count(&self) -> usize {
+        self.primitive_offsets.len()
+    }
+
+    /// Maps the primitive id of a hit on the combined mesh back to its sub-mesh.
+    ///
+    /// # Returns
+    /// The index of the sub-mesh and the primitive id within it, or `None` if `prim_id` is out of
+    /// the combined mesh.
+    pub fn resolve(&self, prim_id: u32) -> Option<(usize, u32)> {
+        if prim_id as usize >= self.mesh.primitive_count() {
+            return None;
+        }
+        // Sub-meshes without primitives share their offset with the next one
+        let sub_mesh = self.primitive_offsets.partition_point(|&o| o <= prim_id) - 1;
+        Some((sub_mesh, prim_id - self.primitive_offsets[sub_mesh]))
+    }
+}
+
+impl Geometry for BatchedGeometry {
+    fn geometry(&self) -> embree4_sys::RTCGeometry {
+        self.mesh.geometry()
+    }
+
+    fn kind(&self) -> embree4_sys::RTCGeometryType {
+        self.mesh.kind()
+    }
+
+    fn time_step_count(&self) -> u32 {
+        self.mesh.time_step_count()
+    }
+
+    fn primitive_count(&self) -> usize {
+        self.mesh.primitive_count()
+    }
+
+    fn is_committed(&self) -> bool {
+        self.mesh.is_committed()
+    }
+
+    fn enabled_flag(&self) -> Option<&Cell<bool>> {
+        self.mesh.enabled_flag()
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        self.mesh.bounds()
+    }
+
+    fn validation(&self) -> Option<Validation> {
+        self.mesh.validation()
+    }
+
+    fn shared_data(&self) -> Option<SharedData> {
+        self.mesh.shared_data()
+    }
+}
+
+#[test]
+fn batch_intersects_like_separate_meshes() {
+    use crate::{
+        ray::RayBuilder,
+        scene::{Scene, SceneOptions},
+    };
+
+    let device = Device::try_new(None).unwrap();
+    let triangle = |dx: f32, z: f32| {
+        [(0.0, 0.0, z), (1.0, 0.0, z), (0.0, 1.0, z)].map(|(x, y, z)| (x + dx, y, z))
+    };
+    // The second sub-mesh holds two triangles, the first of them behind the second one
+    let meshes: [(Vec<_>, Vec<_>); 3] = [
+        (triangle(0.0, 1.0).to_vec(), vec![(0, 1, 2)]),
+        (
+            [triangle(2.0, 3.0), triangle(2.0, 2.0)].concat(),
+            vec![(0, 1, 2), (3, 4, 5)],
+        ),
+        (triangle(4.0, 1.5).to_vec(), vec![(0, 2, 1)]),
+    ];
+
+    let separate = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    let mut geometries = Vec::new();
+    for (vertices, indices) in &meshes {
+        let mesh = TriangleMeshGeometry::try_new(&device, vertices, indices).unwrap();
+        separate.attach_geometry(&mesh).unwrap();
+        geometries.push(mesh);
+    }
+    let separate = separate.commit().unwrap();
+
+    let mut batch = GeometryBatch::new();
+    for (vertices, indices) in &meshes {
+        batch.push(vertices, indices).unwrap();
+    }
+    assert_eq!(batch.len(), 3);
+    let batched_geometry = batch.build(&device).unwrap();
+    assert_eq!(batched_geometry.primitive_count(), 4);
+    let batched = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    batched.attach_geometry(&batched_geometry).unwrap();
+    let batched = batched.commit().unwrap();
+
+    for x in [0.25, 2.25, 4.25, 6.25] {
+        let ray = || RayBuilder::new((x, 0.25, 0.0), (0.0, 0.0, 1.0)).build();
+        let expected = separate.intersect_1(ray()).unwrap();
+        let hit = batched.intersect_1(ray()).unwrap();
+        assert_eq!(hit.is_some(), expected.is_some());
+        let (Some(hit), Some(expected)) = (hit, expected) else {
+            continue;
+        };
+
+        assert_eq!(hit.ray.tfar, expected.ray.tfar);
+        assert_eq!(
+            batched_geometry.resolve(hit.hit.primID),
+            Some((expected.hit.geomID as usize, expected.hit.primID))
+        );
+    }
+    assert_eq!(batched_geometry.resolve(4), None);
+}
+
+#[test]
+fn out_of_range_sub_mesh_index_is_rejected() {
+    let mut batch = GeometryBatch::new();
+    let triangle = [(0.0, 0.0, 0.0), (1.0, 0.0, 0.0), (0.0, 1.0, 0.0)];
+    assert!(batch.push(&triangle, &[(0, 1, 3)]).is_err());
+    assert!(batch.is_empty());
+}
@@ -0,0 +1,187 @@
+use std::cell::Cell;
+
+use anyhow::{bail, Result};
+
+use crate::{aabb::Aabb, device::Device};
+
+use super::{Geometry, SharedData, TriangleMeshGeometry, Validation};
+
+/// An axis-aligned box, built as a triangle mesh of 8 vertices and 12 triangles.
+///
+/// The triangles wind counter-clockwise seen from outside, so geometric normals point out of the
+/// box. Triangles `2 * f` and `2 * f + 1` make face `f`, in the order `-x`, `+x`, `-y`, `+y`,
+/// `-z`, `+z`, see [BoxGeometry::face_normal].
+pub struct BoxGeometry {
+    mesh: TriangleMeshGeometry,
+}
+
+// Corners of the faces in the order of `BoxGeometry::face_normal`. Bit `k` of a corner selects
+// the upper bound on axis `k`.
+const FACES: [[u32; 4]; 6] = [
+    [0, 4, 6, 2],
+    [1, 3, 7, 5],
+    [0, 1, 5, 4],
+    [2, 6, 7, 3],
+    [0, 2, 3, 1],
+    [4, 5, 7, 6],
+];
+
+impl BoxGeometry {
+    /// Constructs a new `BoxGeometry` from its lower and upper corners.
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::prelude::*;
+    ///
+    /// let device = Device::try_new(None).unwrap();
+    /// let cube = BoxGeometry::try_new(&device, (-1.0, -1.0, 4.0), (1.0, 1.0, 6.0)).unwrap();
+    /// let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    /// scene.attach_owned(cube).unwrap();
+    /// ```
+    pub fn try_new(device: &Device, min: (f32, f32, f32), max: (f32, f32, f32)) -> Result<Self> {
+        let (min, max) = ([min.0, min.1, min.2], [max.0, max.1, max.2]);
+        if !min.iter().chain(&max).all(|x| x.is_finite()) {
+            bail!("The corners of the box must be finite");
+        }
+        if (0..3).any(|k| min[k] > max[k]) {
+            bail!("The lower corner of the box must not be above its upper corner");
+        }
+
+        let vertices: Vec<_> = (0..8)
+            .map(|corner| {
+                let [x, y, z] = [0, 1, 2].map(|k| {
+                    if corner & (1 << k) == 0 {
+                        min[k]
+                    } else {
+                        max[k]
+                    }
+                });
+                (x, y, z)
+            })
+            .collect();
+        let indices: Vec<_> = FACES
+            .iter()
+            .flat_map(|&[a, b, c, d]| [(a, b, c), (a, c, d)])
+            .collect();
+
+        let mesh = TriangleMeshGeometry::try_new(device, &vertices, &indices)?;
+        Ok(Self { mesh })
+    }
+
+    /// Constructs a new `BoxGeometry` covering `aabb`.
+    pub fn from_aabb(device: &Device, aabb: Aabb) -> Result<Self> {
+        let (lower, upper) = (aabb.lower, aabb.upper);
+        Self::try_new(
+            device,
+            (lower[0], lower[1], lower[2]),
+            (upper[0], upper[1], upper[2]),
+        )
+    }
+
+    /// Returns the outward unit normal of the face holding triangle `prim_id`, or `None` if the
+    /// box has no such triangle.
+    pub fn face_normal(prim_id: u32) -> Option<[f32; 3]> {
+        let face = prim_id as usize / 2;
+        if face >= FACES.len() {
+            return None;
+        }
+        let mut normal = [0.0; 3];
+        normal[face / 2] = if face.is_multiple_of(2) { -1.0 } else { 1.0 };
+        Some(normal)
+    }
+
+    /// Returns the triangle mesh of the box.
+    pub fn mesh(&self) -> &TriangleMeshGeometry {
+        &self.mesh
+    }
+}
+
+impl Geometry for BoxGeometry {
+    fn geometry(&self) -> embree4_sys::RTCGeometry {
+        self.mesh.geometry()
+    }
+
+    fn kind(&self) -> embree4_sys::RTCGeometryType {
+        self.mesh.kind()
+    }
+
+    fn time_step_count(&self) -> u32 {
+        self.mesh.time_step_count()
+    }
+
+    fn primitive_count(&self) -> usize {
+        self.mesh.primitive_count()
+    }
+
+    fn is_committed(&self) -> bool {
+        self.mesh.is_committed()
+    }
+
+    fn enabled_flag(&self) -> Option<&Cell<bool>> {
+        self.mesh.enabled_flag()
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        self.mesh.bounds()
+    }
+
+    fn validation(&self) -> Option<Validation> {
+        self.mesh.validation()
+    }
+
+    fn shared_data(&self) -> Option<SharedData> {
+        self.mesh.shared_data()
+    }
+}
+
+#[test]
+fn rays_hit_the_facing_side_with_outward_normals() {
+    use crate::{
+        ray::RayBuilder,
+        scene::{Scene, SceneOptions},
+    };
+
+    let device = Device::try_new(None).unwrap();
+    let aabb = Aabb::new([-1.0, -2.0, -3.0], [1.0, 2.0, 3.0]);
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    scene
+        .attach_owned(BoxGeometry::from_aabb(&device, aabb).unwrap())
+        .unwrap();
+    let scene = scene.commit().unwrap();
+
+    for axis in 0..3 {
+        for sign in [-1.0, 1.0] {
+            // Start outside the box on the `sign` side of `axis`, slightly off center
+            let mut origin = [0.1, 0.2, 0.3];
+            origin[axis] = 10.0 * sign;
+            let mut direction = [0.0; 3];
+            direction[axis] = -sign;
+
+            let ray = RayBuilder::new(
+                (origin[0], origin[1], origin[2]),
+                (direction[0], direction[1], direction[2]),
+            )
+            .build();
+            let hit = scene.intersect_1(ray).unwrap().unwrap();
+
+            let mut expected = [0.0; 3];
+            expected[axis] = sign;
+            assert_eq!(BoxGeometry::face_normal(hit.hit.primID), Some(expected));
+
+            let bound = if sign > 0.0 {
+                aabb.upper[axis]
+            } else {
+                aabb.lower[axis]
+            };
+            assert!((hit.ray.tfar - (10.0 - bound.abs())).abs() < 1e-4);
+
+            let normal = [hit.hit.Ng_x, hit.hit.Ng_y, hit.hit.Ng_z];
+            assert!(normal[axis] * sign > 0.0);
+            assert!((0..3).filter(|&k| k != axis).all(|k| normal[k] == 0.0));
+        }
+    }
+
+    let inverted = Aabb::new([1.0, 0.0, 0.0], [0.0, 1.0, 1.0]);
+    assert!(BoxGeometry::from_aabb(&device, inverted).is_err());
+    assert_eq!(BoxGeometry::face_normal(12), None);
+}
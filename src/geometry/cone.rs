@@ -0,0 +1,353 @@
+use std::{cell::Cell, sync::Arc};
+
+use anyhow::{bail, Result};
+use embree4_sys::{RTCBounds, RTCRayHit, RTCRayQueryContext};
+
+use crate::{aabb::Aabb, device::Device};
+
+use super::{Geometry, SharedData, UserGeometry, UserGeometryImpl};
+
+/// A cone with a flat base, intersected analytically as a user geometry.
+///
+/// The shape read by its callbacks is shared with the scenes the geometry is attached to, so the
+/// geometry can be dropped once attached, see [Geometry::shared_data].
+///
+/// Hits report `u` as the position along the axis, from `0` at the base to `1` at the apex, and
+/// `v` as `0`.
+pub struct ConeGeometry {
+    geometry: UserGeometry<CappedCone>,
+    shape: Arc<CappedCone>,
+}
+
+/// A cylinder with flat caps, intersected analytically as a user geometry.
+///
+/// The shape read by its callbacks is shared with the scenes the geometry is attached to, so the
+/// geometry can be dropped once attached, see [Geometry::shared_data].
+///
+/// Hits report `u` as the position along the axis, from `0` at the first cap to `1` at the
+/// second one, and `v` as `0`.
+pub struct CylinderGeometry {
+    geometry: UserGeometry<CappedCone>,
+    shape: Arc<CappedCone>,
+}
+
+impl ConeGeometry {
+    /// Constructs a new `ConeGeometry` from the center of its base, its apex and the radius of
+    /// its base.
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::prelude::*;
+    ///
+    /// let device = Device::try_new(None).unwrap();
+    /// let cone = ConeGeometry::try_new(&device, (0.0, 0.0, 5.0), (0.0, 2.0, 5.0), 1.0).unwrap();
+    /// let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    /// scene.attach_owned(cone).unwrap();
+    /// ```
+    pub fn try_new(
+        device: &Device,
+        base: (f32, f32, f32),
+        apex: (f32, f32, f32),
+        radius: f32,
+    ) -> Result<Self> {
+        let shape = Arc::new(CappedCone::try_new(base, apex, radius, 0.0)?);
+        let geometry = UserGeometry::try_new(device, &*shape)?;
+        Ok(Self { geometry, shape })
+    }
+}
+
+impl CylinderGeometry {
+    /// Constructs a new `CylinderGeometry` from the centers of its caps and its radius.
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::prelude::*;
+    ///
+    /// let device = Device::try_new(None).unwrap();
+    /// let cylinder =
+    ///     CylinderGeometry::try_new(&device, (0.0, 0.0, 5.0), (0.0, 2.0, 5.0), 1.0).unwrap();
+    /// let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    /// scene.attach_owned(cylinder).unwrap();
+    /// ```
+    pub fn try_new(
+        device: &Device,
+        start: (f32, f32, f32),
+        end: (f32, f32, f32),
+        radius: f32,
+    ) -> Result<Self> {
+        let shape = Arc::new(CappedCone::try_new(start, end, radius, radius)?);
+        let geometry = UserGeometry::try_new(device, &*shape)?;
+        Ok(Self { geometry, shape })
+    }
+}
+
+impl Geometry for ConeGeometry {
+    fn geometry(&self) -> embree4_sys::RTCGeometry {
+        self.geometry.geometry()
+    }
+
+    fn kind(&self) -> embree4_sys::RTCGeometryType {
+        embree4_sys::RTCGeometryType::USER
+    }
+
+    fn time_step_count(&self) -> u32 {
+        1
+    }
+
+    fn primitive_count(&self) -> usize {
+        1
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(self.shape.bounds().into())
+    }
+
+    fn shared_data(&self) -> Option<SharedData> {
+        Some(self.shape.clone())
+    }
+
+    fn enabled_flag(&self) -> Option<&Cell<bool>> {
+        self.geometry.enabled_flag()
+    }
+}
+
+impl Geometry for CylinderGeometry {
+    fn geometry(&self) -> embree4_sys::RTCGeometry {
+        self.geometry.geometry()
+    }
+
+    fn kind(&self) -> embree4_sys::RTCGeometryType {
+        embree4_sys::RTCGeometryType::USER
+    }
+
+    fn time_step_count(&self) -> u32 {
+        1
+    }
+
+    fn primitive_count(&self) -> usize {
+        1
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(self.shape.bounds().into())
+    }
+
+    fn shared_data(&self) -> Option<SharedData> {
+        Some(self.shape.clone())
+    }
+
+    fn enabled_flag(&self) -> Option<&Cell<bool>> {
+        self.geometry.enabled_flag()
+    }
+}
+
+// A truncated cone closed by flat caps, going from radius `r0` at `p0` to radius `r1` at `p1`.
+// Cylinders have equal radii and cones a zero radius at the apex.
+#[derive(Debug, Clone, Copy)]
+struct CappedCone {
+    p0: [f32; 3],
+    p1: [f32; 3],
+    r0: f32,
+    r1: f32,
+    // Unit axis from `p0` to `p1`, and the length of the axis
+    axis: [f32; 3],
+    height: f32,
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+impl CappedCone {
+    fn try_new(p0: (f32, f32, f32), p1: (f32, f32, f32), r0: f32, r1: f32) -> Result<Self> {
+        let (p0, p1) = ([p0.0, p0.1, p0.2], [p1.0, p1.1, p1.2]);
+        let offset = [0, 1, 2].map(|k| p1[k] - p0[k]);
+        let height = dot(offset, offset).sqrt();
+        if !(height > 0.0 && height.is_finite()) {
+            bail!("The ends of the axis must be distinct and finite");
+        }
+        if !(r0 > 0.0 && r0.is_finite() && r1 >= 0.0 && r1.is_finite()) {
+            bail!("The radius must be positive and finite");
+        }
+
+        Ok(Self {
+            p0,
+            p1,
+            r0,
+            r1,
+            axis: offset.map(|x| x / height),
+            height,
+        })
+    }
+
+    // Returns the closest hit in `(tnear, tfar)`, as its distance, unnormalized geometric normal
+    // and position along the axis
+    fn intersect(
+        &self,
+        org: [f32; 3],
+        dir: [f32; 3],
+        tnear: f32,
+        tfar: f32,
+    ) -> Option<(f32, [f32; 3], f32)> {
+        let a = self.axis;
+        let w = [0, 1, 2].map(|k| org[k] - self.p0[k]);
+        let (yw, yd) = (dot(w, a), dot(dir, a));
+        // Components of the origin and direction orthogonal to the axis
+        let qw = [0, 1, 2].map(|k| w[k] - yw * a[k]);
+        let qd = [0, 1, 2].map(|k| dir[k] - yd * a[k]);
+
+        // The radius at `t` is `c0 + c1 * t`
+        let slope = (self.r1 - self.r0) / self.height;
+        let (c0, c1) = (self.r0 + slope * yw, slope * yd);
+
+        let mut closest: Option<(f32, [f32; 3], f32)> = None;
+        let mut consider = |t: f32, normal: [f32; 3], y: f32| {
+            if t > tnear && t < tfar && closest.is_none_or(|(best, _, _)| t < best) {
+                closest = Some((t, normal, y / self.height));
+            }
+        };
+
+        // Side: |q(t)|^2 = r(t)^2
+        let qa = dot(qd, qd) - c1 * c1;
+        let qb = 2.0 * (dot(qw, qd) - c0 * c1);
+        let qc = dot(qw, qw) - c0 * c0;
+        let roots = if qa.abs() > f32::EPSILON {
+            let discriminant = qb * qb - 4.0 * qa * qc;
+            if discriminant >= 0.0 {
+                let sqrt = discriminant.sqrt();
+                [(-qb - sqrt) / (2.0 * qa), (-qb + sqrt) / (2.0 * qa)]
+            } else {
+                [f32::NAN; 2]
+            }
+        } else if qb != 0.0 {
+            [-qc / qb, f32::NAN]
+        } else {
+            [f32::NAN; 2]
+        };
+        for t in roots.into_iter().filter(|t| t.is_finite()) {
+            let y = yw + t * yd;
+            // The second nappe of a cone is outside the axis segment
+            if !(0.0..=self.height).contains(&y) || c0 + c1 * t < 0.0 {
+                continue;
+            }
+
+            let q = [0, 1, 2].map(|k| qw[k] + t * qd[k]);
+            let len = dot(q, q).sqrt();
+            let normal = if len > 0.0 {
+                [0, 1, 2].map(|k| q[k] / len - slope * a[k])
+            } else {
+                a
+            };
+            consider(t, normal, y);
+        }
+
+        // Caps
+        if yd != 0.0 {
+            for (y, radius, normal) in [(0.0, self.r0, a.map(|x| -x)), (self.height, self.r1, a)] {
+                let t = (y - yw) / yd;
+                let q = [0, 1, 2].map(|k| qw[k] + t * qd[k]);
+                if radius > 0.0 && dot(q, q) <= radius * radius {
+                    consider(t, normal, y);
+                }
+            }
+        }
+
+        closest
+    }
+}
+
+impl UserGeometryImpl for CappedCone {
+    fn bounds(&self) -> RTCBounds {
+        // Each cap spans `r * sqrt(1 - a_k^2)` around its center along the axis `k`
+        let extent = self.axis.map(|a| (1.0 - a * a).max(0.0).sqrt());
+        let cap = |center: [f32; 3], radius: f32| {
+            Aabb::new(
+                [0, 1, 2].map(|k| center[k] - radius * extent[k]),
+                [0, 1, 2].map(|k| center[k] + radius * extent[k]),
+            )
+        };
+        let bounds = cap(self.p0, self.r0).union(&cap(self.p1, self.r1));
+
+        RTCBounds {
+            lower_x: bounds.lower[0],
+            lower_y: bounds.lower[1],
+            lower_z: bounds.lower[2],
+            upper_x: bounds.upper[0],
+            upper_y: bounds.upper[1],
+            upper_z: bounds.upper[2],
+            ..Default::default()
+        }
+    }
+
+    fn intersect(
+        &self,
+        geom_id: u32,
+        prim_id: u32,
+        ctx: &RTCRayQueryContext,
+        ray_hit: &mut RTCRayHit,
+    ) {
+        let ray = &mut ray_hit.ray;
+        let org = [ray.org_x, ray.org_y, ray.org_z];
+        let dir = [ray.dir_x, ray.dir_y, ray.dir_z];
+        let Some((t, normal, u)) = self.intersect(org, dir, ray.tnear, ray.tfar) else {
+            return;
+        };
+
+        ray.tfar = t;
+        let hit = &mut ray_hit.hit;
+        [hit.Ng_x, hit.Ng_y, hit.Ng_z] = normal;
+        hit.u = u;
+        hit.v = 0.0;
+        hit.geomID = geom_id;
+        hit.primID = prim_id;
+        hit.instID = ctx.instID;
+    }
+}
+
+#[test]
+fn cylinder_side_hit_and_cap_miss() {
+    use crate::{
+        ray::RayBuilder,
+        scene::{Scene, SceneOptions},
+    };
+
+    let device = Device::try_new(None).unwrap();
+    let cylinder =
+        CylinderGeometry::try_new(&device, (0.0, 0.0, 5.0), (0.0, 2.0, 5.0), 1.0).unwrap();
+    let cone = ConeGeometry::try_new(&device, (4.0, 0.0, 5.0), (4.0, 2.0, 5.0), 1.0).unwrap();
+    let bounds = cylinder.bounds().unwrap();
+    assert_eq!(bounds, Aabb::new([-1.0, 0.0, 4.0], [1.0, 2.0, 6.0]));
+
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    let cylinder_id = scene.attach_geometry(&cylinder).unwrap();
+    let cone_id = scene.attach_geometry(&cone).unwrap();
+    // The scene keeps the shapes read by the callbacks alive
+    drop((cylinder, cone));
+    let scene = scene.commit().unwrap();
+
+    let trace = |org, dir| {
+        scene
+            .intersect_1(RayBuilder::new(org, dir).build())
+            .unwrap()
+    };
+
+    // Side of the cylinder
+    let hit = trace((0.0, 1.0, 0.0), (0.0, 0.0, 1.0)).unwrap();
+    assert_eq!(hit.hit.geomID, cylinder_id);
+    assert!((hit.ray.tfar - 4.0).abs() < 1e-5);
+    assert!(hit.hit.Ng_z < 0.0 && hit.hit.Ng_x.abs() < 1e-5 && hit.hit.Ng_y.abs() < 1e-5);
+
+    // Past the cap of the cylinder
+    assert!(trace((0.0, 2.5, 0.0), (0.0, 0.0, 1.0)).is_none());
+
+    // Cap of the cylinder, from above
+    let hit = trace((0.0, 5.0, 5.0), (0.0, -1.0, 0.0)).unwrap();
+    assert!((hit.ray.tfar - 3.0).abs() < 1e-5);
+    assert!(hit.hit.Ng_y > 0.0);
+
+    // Side of the cone, halfway to the apex
+    let hit = trace((4.0, 1.0, 0.0), (0.0, 0.0, 1.0)).unwrap();
+    assert_eq!(hit.hit.geomID, cone_id);
+    assert!((hit.ray.tfar - 4.5).abs() < 1e-5);
+    assert!((hit.hit.u - 0.5).abs() < 1e-5);
+}
@@ -0,0 +1,465 @@
+use std::{
+    cell::Cell,
+    mem::size_of,
+    slice,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+};
+
+use anyhow::{bail, Result};
+use embree4_sys::RTCGeometryType;
+
+use crate::{aabb::Aabb, device::Device, device_handle_error_or};
+
+use super::{new_geometry_buffer, validation::Layout, vertex_buffer_bounds, Geometry, Validation};
+
+pub struct CurveGeometry {
+    handle: embree4_sys::RTCGeometry,
+    enabled: Cell<bool>,
+    device: embree4_sys::RTCDevice,
+    kind: RTCGeometryType,
+    vertex_count: usize,
+    primitive_count: usize,
+    // Shared with the validations, which read it when they run
+    time_step_count: Arc<AtomicU32>,
+    committed: Cell<bool>,
+    tessellation_rate: Cell<f32>,
+}
+
+impl CurveGeometry {
+    /// Constructs a new `CurveGeometry` instance from the given control vertices and segment
+    /// indices.
+    ///
+    /// # Arguments
+    /// * `device` - A reference to the `Device` instance.
+    /// * `kind` - The curve type, e.g. `ROUND_LINEAR_CURVE` or `ROUND_BEZIER_CURVE`. Normal
+    ///   oriented and hermite curves are not supported.
+    /// * `vertices` - The control vertices as `(x, y, z, radius)`.
+    /// * `indices` - The index of the first control vertex of each curve segment.
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::{geometry::*, prelude::*};
+    /// use embree4_sys::*;
+    ///
+    /// let vertices = [(-1.0, 0.0, 0.0, 0.1), (1.0, 0.0, 0.0, 0.1)];
+    /// let indices = [0];
+    ///
+    /// let device = Device::try_new(None).unwrap();
+    /// let geometry = CurveGeometry::try_new(
+    ///     &device,
+    ///     RTCGeometryType::ROUND_LINEAR_CURVE,
+    ///     &vertices,
+    ///     &indices,
+    /// )
+    /// .unwrap();
+    /// let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    /// scene.attach_geometry(&geometry).unwrap();
+    /// ```
+    pub fn try_new(
+        device: &Device,
+        kind: RTCGeometryType,
+        vertices: &[(f32, f32, f32, f32)],
+        indices: &[u32],
+    ) -> Result<Self> {
+        if !is_supported_curve_type(kind) {
+            bail!("Unsupported curve geometry type: {:?}", kind);
+        }
+
+        let geometry = device.new_geometry(kind)?.into_raw();
+
+        let curve = Self {
+            handle: geometry,
+            enabled: Cell::new(true),
+            device: device.handle,
+            kind,
+            vertex_count: vertices.len(),
+            primitive_count: indices.len(),
+            time_step_count: Arc::new(AtomicU32::new(1)),
+            committed: Cell::new(false),
+            tessellation_rate: Cell::new(4.0),
+        };
+
+        curve.set_vertex_buffer(0, vertices)?;
+
+        let index_buf_ptr = new_geometry_buffer(
+            device.handle,
+            geometry,
+            embree4_sys::RTCBufferType::INDEX,
+            0,
+            embree4_sys::RTCFormat::UINT,
+            size_of::<u32>(),
+            indices.len(),
+        )?;
+
+        let index_buf =
+            unsafe { slice::from_raw_parts_mut(index_buf_ptr as *mut u32, indices.len()) };
+        index_buf.copy_from_slice(indices);
+
+        curve.commit()?;
+        Ok(curve)
+    }
+
+    /// Sets the control vertices of each time step for motion blur and recommits the geometry.
+    ///
+    /// The time steps are evenly distributed over the `[0, 1]` time range of the ray.
+    ///
+    /// # Arguments
+    /// * `time_steps` - The control vertices of each time step. Each time step must have as many
+    ///   vertices as the geometry was created with.
+    pub fn set_time_steps(&self, time_steps: &[&[(f32, f32, f32, f32)]]) -> Result<()> {
+        if time_steps.is_empty() {
+            bail!("At least one time step is required");
+        }
+        if let Some(step) = time_steps.iter().find(|s| s.len() != self.vertex_count) {
+            bail!(
+                "Curve time step has {} vertices, expected {}",
+                step.len(),
+                self.vertex_count
+            );
+        }
+
+        Geometry::set_time_step_count(self, time_steps.len() as u32)?;
+        for (slot, vertices) in time_steps.iter().enumerate() {
+            self.set_vertex_buffer(slot as u32, vertices)?;
+        }
+
+        self.commit()
+    }
+
+    /// Sets the control vertices of a single time step and recommits the geometry.
+    ///
+    /// The number of time steps must have been set beforehand with
+    /// [Geometry::set_time_step_count].
+    ///
+    /// # Arguments
+    /// * `slot` - The time step, below the number of time steps.
+    /// * `vertices` - The control vertices of the time step, as many as the geometry was created
+    ///   with.
+    pub fn set_time_step(&self, slot: u32, vertices: &[(f32, f32, f32, f32)]) -> Result<()> {
+        if slot >= self.time_step_count() {
+            bail!(
+                "Time step {} is out of range, the curve has {} time steps",
+                slot,
+                self.time_step_count()
+            );
+        }
+        if vertices.len() != self.vertex_count {
+            bail!(
+                "Curve time step has {} vertices, expected {}",
+                vertices.len(),
+                self.vertex_count
+            );
+        }
+
+        self.set_vertex_buffer(slot, vertices)?;
+        self.commit()
+    }
+
+    /// Sets how much Embree's minimum width feature may enlarge the curve radius, and recommits
+    /// the geometry.
+    ///
+    /// With minimum width, Embree enlarges the radius of distant curves so that they cover a
+    /// minimum width, e.g. of a pixel, set per query by the `minWidthDistanceFactor` of the ray
+    /// query context. The radius grows by at most `scale`. The scale has no effect unless Embree
+    /// is built with `EMBREE_MIN_WIDTH`.
+    ///
+    /// See [rtcSetGeometryMaxRadiusScale](https://github.com/embree/embree/blob/master/doc/src/api/rtcSetGeometryMaxRadiusScale.md).
+    pub fn set_max_radius_scale(&self, scale: f32) -> Result<()> {
+        unsafe {
+            embree4_sys::rtcSetGeometryMaxRadiusScale(self.handle, scale);
+        }
+        device_handle_error_or(self.device, (), "Could not set curve max radius scale")?;
+
+        self.commit()
+    }
+
+    /// Sets the number of ray facing quads each segment of a flat curve is tessellated into and
+    /// recommits the geometry.
+    ///
+    /// Higher rates give smoother curves at the cost of build time and memory. Embree defaults
+    /// to `4`, and ignores the rate for the other curve types.
+    ///
+    /// See [rtcSetGeometryTessellationRate](https://github.com/embree/embree/blob/master/doc/src/api/rtcSetGeometryTessellationRate.md).
+    pub fn set_tessellation_rate(&self, rate: f32) -> Result<()> {
+        unsafe {
+            embree4_sys::rtcSetGeometryTessellationRate(self.handle, rate);
+        }
+        device_handle_error_or(self.device, (), "Could not set curve tessellation rate")?;
+        self.tessellation_rate.set(rate);
+
+        self.commit()
+    }
+
+    /// Returns the tessellation rate last set on the geometry, `4` by default.
+    ///
+    /// Embree does not report the rate: it is tracked by the wrapper.
+    pub fn tessellation_rate(&self) -> f32 {
+        self.tessellation_rate.get()
+    }
+
+    /// Estimates the number of quads the curve is tessellated into at the current tessellation
+    /// rate, e.g. to predict the memory used by a commit.
+    ///
+    /// Only flat curves with a cubic basis are tessellated, into `rate` quads per segment, with
+    /// the rate rounded down and clamped to `[1, 16]` as Embree does. Round, cone and linear
+    /// curves are intersected directly, their estimate is `0`.
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::{geometry::*, prelude::*};
+    /// use embree4_sys::*;
+    ///
+    /// let vertices = [
+    ///     (0.0, 0.0, 0.0, 0.1),
+    ///     (1.0, 1.0, 0.0, 0.1),
+    ///     (2.0, 1.0, 0.0, 0.1),
+    ///     (3.0, 0.0, 0.0, 0.1),
+    /// ];
+    ///
+    /// let device = Device::try_new(None).unwrap();
+    /// let geometry =
+    ///     CurveGeometry::try_new(&device, RTCGeometryType::FLAT_BEZIER_CURVE, &vertices, &[0])
+    ///         .unwrap();
+    /// geometry.set_tessellation_rate(8.0).unwrap();
+    /// assert_eq!(geometry.estimated_quad_count(), 8);
+    /// ```
+    pub fn estimated_quad_count(&self) -> usize {
+        let tessellated = matches!(
+            self.kind,
+            RTCGeometryType::FLAT_BEZIER_CURVE
+                | RTCGeometryType::FLAT_BSPLINE_CURVE
+                | RTCGeometryType::FLAT_CATMULL_ROM_CURVE
+        );
+        if !tessellated {
+            return 0;
+        }
+
+        let segments = (self.tessellation_rate() as usize).clamp(1, 16);
+        self.primitive_count * segments
+    }
+
+    fn set_vertex_buffer(&self, slot: u32, vertices: &[(f32, f32, f32, f32)]) -> Result<()> {
+        self.committed.set(false);
+        let vertex_buf_ptr = new_geometry_buffer(
+            self.device,
+            self.handle,
+            embree4_sys::RTCBufferType::VERTEX,
+            slot,
+            embree4_sys::RTCFormat::FLOAT4,
+            4 * size_of::<f32>(),
+            vertices.len(),
+        )?;
+
+        let vertex_buf =
+            unsafe { slice::from_raw_parts_mut(vertex_buf_ptr as *mut f32, 4 * vertices.len()) };
+
+        // copy vertices into buffer
+        for (i, v) in vertices.iter().enumerate() {
+            vertex_buf[4 * i] = v.0;
+            vertex_buf[4 * i + 1] = v.1;
+            vertex_buf[4 * i + 2] = v.2;
+            vertex_buf[4 * i + 3] = v.3;
+        }
+
+        Ok(())
+    }
+
+    fn commit(&self) -> Result<()> {
+        unsafe {
+            embree4_sys::rtcCommitGeometry(self.handle);
+        }
+        device_handle_error_or(self.device, (), "Failed to commit curve geometry")?;
+        self.committed.set(true);
+        Ok(())
+    }
+}
+
+fn is_supported_curve_type(kind: RTCGeometryType) -> bool {
+    matches!(
+        kind,
+        RTCGeometryType::CONE_LINEAR_CURVE
+            | RTCGeometryType::ROUND_LINEAR_CURVE
+            | RTCGeometryType::FLAT_LINEAR_CURVE
+            | RTCGeometryType::ROUND_BEZIER_CURVE
+            | RTCGeometryType::FLAT_BEZIER_CURVE
+            | RTCGeometryType::ROUND_BSPLINE_CURVE
+            | RTCGeometryType::FLAT_BSPLINE_CURVE
+            | RTCGeometryType::ROUND_CATMULL_ROM_CURVE
+            | RTCGeometryType::FLAT_CATMULL_ROM_CURVE
+    )
+}
+
+impl Drop for CurveGeometry {
+    fn drop(&mut self) {
+        unsafe {
+            embree4_sys::rtcReleaseGeometry(self.handle);
+        }
+    }
+}
+
+impl Geometry for CurveGeometry {
+    fn geometry(&self) -> embree4_sys::RTCGeometry {
+        self.handle
+    }
+
+    fn enabled_flag(&self) -> Option<&Cell<bool>> {
+        Some(&self.enabled)
+    }
+
+    fn kind(&self) -> embree4_sys::RTCGeometryType {
+        self.kind
+    }
+
+    fn time_step_count(&self) -> u32 {
+        self.time_step_count.load(Ordering::Relaxed)
+    }
+
+    fn set_time_step_count(&self, count: u32) -> Result<()> {
+        if count == 0 {
+            bail!("At least one time step is required");
+        }
+
+        unsafe {
+            embree4_sys::rtcSetGeometryTimeStepCount(self.handle, count);
+        }
+        device_handle_error_or(self.device, (), "Could not set curve time step count")?;
+        self.time_step_count.store(count, Ordering::Relaxed);
+        self.committed.set(false);
+        Ok(())
+    }
+
+    fn is_committed(&self) -> bool {
+        self.committed.get()
+    }
+
+    fn primitive_count(&self) -> usize {
+        self.primitive_count
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        vertex_buffer_bounds(self.handle, self.time_step_count(), self.vertex_count, 4)
+    }
+
+    fn validation(&self) -> Option<Validation> {
+        let linear = matches!(
+            self.kind,
+            RTCGeometryType::CONE_LINEAR_CURVE
+                | RTCGeometryType::ROUND_LINEAR_CURVE
+                | RTCGeometryType::FLAT_LINEAR_CURVE
+        );
+
+        Some(
+            Validation::new(
+                self.handle,
+                Layout::Curves {
+                    vertex_count: self.vertex_count,
+                    segment_count: self.primitive_count,
+                    control_points: if linear { 2 } else { 4 },
+                    time_step_count: self.time_step_count(),
+                },
+            )
+            .with_time_step_count(self.time_step_count.clone()),
+        )
+    }
+}
+
+#[test]
+fn max_radius_scale_keeps_hits_without_min_width() {
+    use crate::{
+        ray::RayBuilder,
+        scene::{CommittedScene, Scene, SceneOptions},
+    };
+
+    let device = Device::try_new(None).unwrap();
+    let vertices = [(-1.0, 0.0, 0.0, 0.1), (1.0, 0.0, 0.0, 0.1)];
+    let scene_with = |scale: Option<f32>| {
+        let curve = CurveGeometry::try_new(
+            &device,
+            RTCGeometryType::ROUND_LINEAR_CURVE,
+            &vertices,
+            &[0],
+        )
+        .unwrap();
+        if let Some(scale) = scale {
+            curve.set_max_radius_scale(scale).unwrap();
+        }
+        let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+        scene.attach_owned(curve).unwrap();
+        scene
+    };
+    let (unscaled_scene, scaled_scene) = (scene_with(None), scene_with(Some(10.0)));
+    let unscaled = unscaled_scene.commit().unwrap();
+    let scaled = scaled_scene.commit().unwrap();
+
+    // The queries leave the minimum width at zero, so the radius is never enlarged
+    for y in [0.0, 0.05, 0.5] {
+        let ray = RayBuilder::new((0.0, y, -5.0), (0.0, 0.0, 1.0)).build();
+        let tfar = |scene: &CommittedScene| scene.intersect_1(ray).unwrap().map(|hit| hit.ray.tfar);
+        assert_eq!(tfar(&scaled), tfar(&unscaled));
+        assert_eq!(tfar(&unscaled).is_some(), y < 0.1);
+    }
+}
+
+#[test]
+fn max_radius_scale_keeps_growing_curves_hittable_under_motion_blur() {
+    use crate::{
+        ray::RayBuilder,
+        scene::{Scene, SceneOptions},
+    };
+
+    let device = Device::try_new(None).unwrap();
+    let thin = [(-1.0, 0.0, 0.0, 0.1), (1.0, 0.0, 0.0, 0.1)];
+    let thick = [(-1.0, 0.0, 0.0, 0.5), (1.0, 0.0, 0.0, 0.5)];
+    let curve =
+        CurveGeometry::try_new(&device, RTCGeometryType::ROUND_LINEAR_CURVE, &thin, &[0]).unwrap();
+    curve.set_time_steps(&[&thin, &thick]).unwrap();
+    curve.set_max_radius_scale(2.0).unwrap();
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    scene.attach_geometry(&curve).unwrap();
+    let scene = scene.commit().unwrap();
+
+    // Just inside the radius at the end of the motion, outside it at the start
+    let ray = |time| {
+        RayBuilder::new((0.0, 0.45, -5.0), (0.0, 0.0, 1.0))
+            .time(time)
+            .build()
+    };
+    assert!(scene.intersect_1(ray(1.0)).unwrap().is_some());
+    assert!(scene.intersect_1(ray(0.0)).unwrap().is_none());
+}
+
+#[test]
+fn only_flat_curves_are_tessellated() {
+    let device = Device::try_new(None).unwrap();
+    let vertices = [
+        (0.0, 0.0, 0.0, 0.1),
+        (1.0, 1.0, 0.0, 0.1),
+        (2.0, 1.0, 0.0, 0.1),
+        (3.0, 0.0, 0.0, 0.1),
+        (4.0, -1.0, 0.0, 0.1),
+    ];
+    let curve = |kind| CurveGeometry::try_new(&device, kind, &vertices, &[0, 1]).unwrap();
+
+    let flat = curve(RTCGeometryType::FLAT_BSPLINE_CURVE);
+    assert_eq!(flat.tessellation_rate(), 4.0);
+    assert_eq!(flat.estimated_quad_count(), 2 * 4);
+    let mut previous = flat.estimated_quad_count();
+    for rate in [6.0, 8.5, 16.0] {
+        flat.set_tessellation_rate(rate).unwrap();
+        assert_eq!(flat.tessellation_rate(), rate);
+        let estimate = flat.estimated_quad_count();
+        assert!(estimate > previous);
+        previous = estimate;
+    }
+    assert_eq!(previous, 2 * 16);
+    flat.set_tessellation_rate(64.0).unwrap();
+    assert_eq!(flat.estimated_quad_count(), 2 * 16);
+    assert!(flat.is_committed());
+
+    let round = curve(RTCGeometryType::ROUND_BSPLINE_CURVE);
+    round.set_tessellation_rate(16.0).unwrap();
+    assert_eq!(round.estimated_quad_count(), 0);
+}
@@ -0,0 +1,744 @@
+use std::{
+    cell::Cell,
+    collections::HashMap,
+    ffi::c_void,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{bail, Result};
+use embree4_sys::{RTCFormat, RTCQuaternionDecomposition};
+
+use crate::{
+    device::Device, device_error_or, device_handle_error_or, point_query::QueryShapes,
+    scene::CommittedScene,
+};
+
+use super::Geometry;
+
+pub struct InstanceGeometry {
+    handle: embree4_sys::RTCGeometry,
+    enabled: Cell<bool>,
+    device: embree4_sys::RTCDevice,
+    time_step_count: Cell<u32>,
+    committed: Cell<bool>,
+    instanced: Arc<Mutex<Instanced>>,
+}
+
+/// The instances of a scene, by geometry ID.
+pub(crate) type InstanceLinks = Arc<HashMap<u32, InstanceLink>>;
+
+/// Links an instance to the instances of the scene it instances, so that the transforms along an
+/// instance stack can be looked up, see
+/// [CommittedScene::world_transform](crate::scene::CommittedScene::world_transform).
+///
+/// Obtained from [Geometry::instance_link]. Keeps the instance geometry alive.
+pub struct InstanceLink {
+    geometry: embree4_sys::RTCGeometry,
+    instanced: Arc<Mutex<Instanced>>,
+}
+
+// The scene currently instanced, kept alive by the instance geometry, its instances and the
+// shapes of its geometries
+struct Instanced {
+    scene: embree4_sys::RTCScene,
+    // The instance depth of the instance, shared with the scenes it is attached to
+    depth: u32,
+    instances: InstanceLinks,
+    shapes: QueryShapes,
+}
+
+unsafe impl Send for Instanced {}
+
+impl InstanceLink {
+    /// Returns the instances of the scene currently instanced.
+    pub(crate) fn children(&self) -> InstanceLinks {
+        self.instanced.lock().unwrap().instances.clone()
+    }
+
+    /// Returns the instance depth of the instance, for the scene currently instanced.
+    pub(crate) fn depth(&self) -> u32 {
+        self.instanced.lock().unwrap().depth
+    }
+
+    /// Returns the scene currently instanced.
+    pub(crate) fn scene(&self) -> embree4_sys::RTCScene {
+        self.instanced.lock().unwrap().scene
+    }
+
+    /// Returns the shapes of the geometries of the scene currently instanced.
+    pub(crate) fn shapes(&self) -> QueryShapes {
+        self.instanced.lock().unwrap().shapes.clone()
+    }
+
+    /// Returns the local to world transform of the instance at the given time, as a column-major
+    /// 4x4 matrix.
+    pub(crate) fn transform(&self, time: f32) -> [f32; 16] {
+        let mut xfm = [0.0; 16];
+        unsafe {
+            embree4_sys::rtcGetGeometryTransform(
+                self.geometry,
+                time,
+                embree4_sys::RTCFormat::FLOAT4X4_COLUMN_MAJOR,
+                xfm.as_mut_ptr() as *mut c_void,
+            );
+        }
+        xfm
+    }
+}
+
+impl Clone for InstanceLink {
+    fn clone(&self) -> Self {
+        unsafe {
+            embree4_sys::rtcRetainGeometry(self.geometry);
+        }
+        Self {
+            geometry: self.geometry,
+            instanced: self.instanced.clone(),
+        }
+    }
+}
+
+impl Drop for InstanceLink {
+    fn drop(&mut self) {
+        unsafe {
+            embree4_sys::rtcReleaseGeometry(self.geometry);
+        }
+    }
+}
+
+unsafe impl Send for InstanceLink {}
+unsafe impl Sync for InstanceLink {}
+
+impl InstanceGeometry {
+    /// Constructs a new `InstanceGeometry` instancing the given scene with a transform.
+    ///
+    /// # Arguments
+    /// * `device` - A reference to the `Device` instance.
+    /// * `scene` - The instanced scene.
+    /// * `transform` - The local to world transform as a column-major 3x4 matrix: the three
+    ///   columns of the linear part followed by the translation.
+    ///
+    /// # Errors
+    /// Embree silently misses instances nested deeper than
+    /// [Device::MAX_INSTANCE_LEVEL_COUNT] levels, so instancing a scene that would exceed it is
+    /// an error.
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::prelude::*;
+    ///
+    /// let device = Device::try_new(None).unwrap();
+    /// let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 0.0), 1.0).unwrap();
+    /// let object = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    /// object.attach_geometry(&sphere).unwrap();
+    /// let object = object.commit().unwrap();
+    ///
+    /// let translate_x = [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 5.0, 0.0, 0.0];
+    /// let instance = InstanceGeometry::try_new(&device, &object, &translate_x).unwrap();
+    /// let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    /// scene.attach_geometry(&instance).unwrap();
+    /// ```
+    pub fn try_new(device: &Device, scene: &CommittedScene, transform: &[f32; 12]) -> Result<Self> {
+        Self::try_new_with_format(device, scene, RTCFormat::FLOAT3X4_COLUMN_MAJOR, transform)
+    }
+
+    /// Constructs a new `InstanceGeometry` from a row-major 3x4 transform, as matrices are usually
+    /// written: the three rows of the linear part, each followed by a translation component.
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::prelude::*;
+    ///
+    /// let device = Device::try_new(None).unwrap();
+    /// let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 0.0), 1.0).unwrap();
+    /// let object = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    /// object.attach_geometry(&sphere).unwrap();
+    /// let object = object.commit().unwrap();
+    ///
+    /// #[rustfmt::skip]
+    /// let translate_x = [
+    ///     1.0, 0.0, 0.0, 5.0,
+    ///     0.0, 1.0, 0.0, 0.0,
+    ///     0.0, 0.0, 1.0, 0.0,
+    /// ];
+    /// let instance = InstanceGeometry::from_row_major(&device, &object, &translate_x).unwrap();
+    /// ```
+    pub fn from_row_major(
+        device: &Device,
+        scene: &CommittedScene,
+        transform: &[f32; 12],
+    ) -> Result<Self> {
+        Self::try_new_with_format(device, scene, RTCFormat::FLOAT3X4_ROW_MAJOR, transform)
+    }
+
+    /// Constructs a new `InstanceGeometry` from a column-major 4x4 transform, the layout of the
+    /// matrices of most math libraries: four columns, the last one holding the translation.
+    ///
+    /// Embree ignores the last row, which must be `[0, 0, 0, 1]`.
+    pub fn from_column_major(
+        device: &Device,
+        scene: &CommittedScene,
+        transform: &[f32; 16],
+    ) -> Result<Self> {
+        Self::try_new_with_format(device, scene, RTCFormat::FLOAT4X4_COLUMN_MAJOR, transform)
+    }
+
+    fn try_new_with_format(
+        device: &Device,
+        scene: &CommittedScene,
+        format: RTCFormat,
+        transform: &[f32],
+    ) -> Result<Self> {
+        let depth = checked_instance_depth(scene)?;
+
+        let geometry = device
+            .new_geometry(embree4_sys::RTCGeometryType::INSTANCE)?
+            .into_raw();
+
+        let instance = Self {
+            handle: geometry,
+            enabled: Cell::new(true),
+            device: device.handle,
+            time_step_count: Cell::new(1),
+            committed: Cell::new(false),
+            instanced: Arc::new(Mutex::new(Instanced {
+                scene: scene.handle,
+                depth,
+                instances: scene.instances.clone(),
+                shapes: scene.shapes.clone(),
+            })),
+        };
+
+        unsafe {
+            embree4_sys::rtcSetGeometryInstancedScene(geometry, scene.handle);
+        }
+        device_error_or(device, (), "Could not set instanced scene")?;
+
+        instance.set_transform(format, transform)?;
+
+        Ok(instance)
+    }
+
+    /// Replaces the instanced scene and recommits the geometry, e.g. to switch between levels of
+    /// detail of an object without recreating the instance.
+    ///
+    /// The scenes the instance is attached to have to be committed again for the change to take
+    /// effect.
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::prelude::*;
+    ///
+    /// let device = Device::try_new(None).unwrap();
+    /// let sphere_scene = |radius| {
+    ///     let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 0.0), radius).unwrap();
+    ///     let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    ///     scene.attach_owned(sphere).unwrap();
+    ///     scene
+    /// };
+    /// let (high, low) = (sphere_scene(1.0), sphere_scene(0.9));
+    /// let (high, low) = (high.commit().unwrap(), low.commit().unwrap());
+    ///
+    /// let identity = [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0];
+    /// let instance = InstanceGeometry::try_new(&device, &high, &identity).unwrap();
+    /// instance.set_scene(&low).unwrap();
+    /// ```
+    pub fn set_scene(&self, scene: &CommittedScene) -> Result<()> {
+        let depth = checked_instance_depth(scene)?;
+
+        unsafe {
+            embree4_sys::rtcSetGeometryInstancedScene(self.handle, scene.handle);
+        }
+        device_handle_error_or(self.device, (), "Could not set instanced scene")?;
+
+        *self.instanced.lock().unwrap() = Instanced {
+            scene: scene.handle,
+            depth,
+            instances: scene.instances.clone(),
+            shapes: scene.shapes.clone(),
+        };
+
+        self.commit()
+    }
+
+    /// Sets the local to world transform and recommits the geometry.
+    ///
+    /// # Arguments
+    /// * `format` - The layout of the transform: `FLOAT3X4_ROW_MAJOR`, `FLOAT3X4_COLUMN_MAJOR`
+    ///   or `FLOAT4X4_COLUMN_MAJOR`, the formats supported by Embree.
+    /// * `transform` - The transform, 12 values for 3x4 formats and 16 for 4x4 formats.
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::prelude::*;
+    /// use embree4_sys::RTCFormat;
+    ///
+    /// let device = Device::try_new(None).unwrap();
+    /// let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 0.0), 1.0).unwrap();
+    /// let object = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    /// object.attach_geometry(&sphere).unwrap();
+    /// let object = object.commit().unwrap();
+    ///
+    /// let identity = [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0];
+    /// let instance = InstanceGeometry::try_new(&device, &object, &identity).unwrap();
+    /// let translate_x = glam::Mat4::from_translation(glam::vec3(5.0, 0.0, 0.0));
+    /// instance
+    ///     .set_transform(RTCFormat::FLOAT4X4_COLUMN_MAJOR, &translate_x.to_cols_array())
+    ///     .unwrap();
+    /// assert!(instance
+    ///     .set_transform(RTCFormat::FLOAT4X4_COLUMN_MAJOR, &identity)
+    ///     .is_err());
+    /// ```
+    pub fn set_transform(&self, format: RTCFormat, transform: &[f32]) -> Result<()> {
+        let Some(&(_, len)) = TRANSFORM_FORMATS.iter().find(|(f, _)| *f == format) else {
+            bail!("Unsupported instance transform format {format:?}");
+        };
+        if transform.len() != len {
+            bail!(
+                "Instance transform in format {format:?} has {len} values, got {}",
+                transform.len()
+            );
+        }
+
+        unsafe {
+            embree4_sys::rtcSetGeometryTransform(
+                self.handle,
+                0,
+                format,
+                transform.as_ptr() as *const c_void,
+            );
+        }
+        device_handle_error_or(self.device, (), "Could not set instance transform")?;
+
+        self.commit()
+    }
+
+    /// Sets the number of time steps of the transform for motion blur.
+    ///
+    /// The time steps are evenly distributed over the `[0, 1]` time range of the ray. Each of them
+    /// must then be set with [Self::set_quaternion_transform].
+    pub fn set_time_step_count(&self, count: u32) -> Result<()> {
+        unsafe {
+            embree4_sys::rtcSetGeometryTimeStepCount(self.handle, count);
+        }
+        device_handle_error_or(self.device, (), "Could not set instance time step count")?;
+        self.time_step_count.set(count);
+        self.committed.set(false);
+        Ok(())
+    }
+
+    /// Sets the transform of a time step as a quaternion decomposition and recommits the geometry.
+    ///
+    /// Unlike matrices, which are interpolated linearly, quaternion decompositions are
+    /// interpolated with a slerp of the rotation. Rotating instances under motion blur thus do not
+    /// shrink or shear between time steps.
+    ///
+    /// See [rtcSetGeometryTransformQuaternion](https://github.com/embree/embree/blob/master/doc/src/api/rtcSetGeometryTransformQuaternion.md).
+    ///
+    /// # Arguments
+    /// * `slot` - The time step.
+    /// * `decomp` - The transform of the time step.
+    pub fn set_quaternion_transform(
+        &self,
+        slot: u32,
+        decomp: RTCQuaternionDecomposition,
+    ) -> Result<()> {
+        unsafe {
+            embree4_sys::rtcSetGeometryTransformQuaternion(self.handle, slot, &decomp);
+        }
+        device_handle_error_or(
+            self.device,
+            (),
+            "Could not set instance quaternion transform",
+        )?;
+
+        self.commit()
+    }
+
+    /// Sets a per-instance value, e.g. a material index, that can be resolved from hits with
+    /// [CommittedScene::resolve_instance_data].
+    ///
+    /// The value is stored in place of the geometry user data pointer.
+    pub fn set_user_data(&self, id: usize) -> Result<()> {
+        unsafe {
+            embree4_sys::rtcSetGeometryUserData(self.handle, id as *mut c_void);
+        }
+        device_handle_error_or(self.device, (), "Could not set instance user data")
+    }
+
+    fn commit(&self) -> Result<()> {
+        unsafe {
+            embree4_sys::rtcCommitGeometry(self.handle);
+        }
+        device_handle_error_or(self.device, (), "Failed to commit instance geometry")?;
+        self.committed.set(true);
+        Ok(())
+    }
+}
+
+/// The transform formats accepted by `rtcSetGeometryTransform`, with their number of values.
+const TRANSFORM_FORMATS: [(RTCFormat, usize); 3] = [
+    (RTCFormat::FLOAT3X4_ROW_MAJOR, 12),
+    (RTCFormat::FLOAT3X4_COLUMN_MAJOR, 12),
+    (RTCFormat::FLOAT4X4_COLUMN_MAJOR, 16),
+];
+
+/// Returns the instance depth of an instance of `scene`, if it is supported by Embree.
+pub(super) fn checked_instance_depth(scene: &CommittedScene) -> Result<u32> {
+    let depth = scene.instance_depth() + 1;
+    let max_depth = Device::MAX_INSTANCE_LEVEL_COUNT;
+    if depth > max_depth {
+        bail!(
+            "Instancing this scene nests {depth} levels of instances, Embree supports at most {max_depth}"
+        );
+    }
+    Ok(depth)
+}
+
+impl Drop for InstanceGeometry {
+    fn drop(&mut self) {
+        unsafe {
+            embree4_sys::rtcReleaseGeometry(self.handle);
+        }
+    }
+}
+
+impl Geometry for InstanceGeometry {
+    fn geometry(&self) -> embree4_sys::RTCGeometry {
+        self.handle
+    }
+
+    fn enabled_flag(&self) -> Option<&Cell<bool>> {
+        Some(&self.enabled)
+    }
+
+    fn kind(&self) -> embree4_sys::RTCGeometryType {
+        embree4_sys::RTCGeometryType::INSTANCE
+    }
+
+    fn time_step_count(&self) -> u32 {
+        self.time_step_count.get()
+    }
+
+    fn set_time_step_count(&self, count: u32) -> Result<()> {
+        InstanceGeometry::set_time_step_count(self, count)
+    }
+
+    fn is_committed(&self) -> bool {
+        self.committed.get()
+    }
+
+    fn primitive_count(&self) -> usize {
+        1
+    }
+
+    fn instance_depth(&self) -> u32 {
+        self.instanced.lock().unwrap().depth
+    }
+
+    fn instance_link(&self) -> Option<InstanceLink> {
+        unsafe {
+            embree4_sys::rtcRetainGeometry(self.handle);
+        }
+        Some(InstanceLink {
+            geometry: self.handle,
+            instanced: self.instanced.clone(),
+        })
+    }
+}
+
+#[test]
+fn resolve_per_instance_data() {
+    use crate::{
+        geometry::SphereGeometry,
+        ray::RayBuilder,
+        scene::{Scene, SceneOptions},
+    };
+
+    let device = Device::try_new(None).unwrap();
+    let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 0.0), 1.0).unwrap();
+    let object = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    object.attach_geometry(&sphere).unwrap();
+    let object = object.commit().unwrap();
+
+    let translate_x = |x| [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, x, 0.0, 0.0];
+    let left = InstanceGeometry::try_new(&device, &object, &translate_x(-2.0)).unwrap();
+    left.set_user_data(7).unwrap();
+    let right = InstanceGeometry::try_new(&device, &object, &translate_x(2.0)).unwrap();
+    right.set_user_data(9).unwrap();
+
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    scene.attach_geometry(&left).unwrap();
+    scene.attach_geometry(&right).unwrap();
+    let scene = scene.commit().unwrap();
+
+    let hit_at = |x| {
+        let ray = RayBuilder::new((x, 0.0, -5.0), (0.0, 0.0, 1.0)).build();
+        scene.intersect_1(ray).unwrap().unwrap()
+    };
+
+    assert_eq!(scene.resolve_instance_data(&hit_at(-2.0)), Some(7));
+    assert_eq!(scene.resolve_instance_data(&hit_at(2.0)), Some(9));
+}
+
+#[test]
+fn quaternion_motion_blur_is_slerp() {
+    use crate::{
+        geometry::SphereGeometry,
+        ray::RayBuilder,
+        scene::{Scene, SceneOptions},
+    };
+
+    let device = Device::try_new(None).unwrap();
+    let sphere = SphereGeometry::try_new(&device, (2.0, 0.0, 0.0), 0.2).unwrap();
+    let object = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    object.attach_geometry(&sphere).unwrap();
+    let object = object.commit().unwrap();
+
+    let rotation_z = |angle: f32| RTCQuaternionDecomposition {
+        scale_x: 1.0,
+        scale_y: 1.0,
+        scale_z: 1.0,
+        skew_xy: 0.0,
+        skew_xz: 0.0,
+        skew_yz: 0.0,
+        shift_x: 0.0,
+        shift_y: 0.0,
+        shift_z: 0.0,
+        quaternion_r: (0.5 * angle).cos(),
+        quaternion_i: 0.0,
+        quaternion_j: 0.0,
+        quaternion_k: (0.5 * angle).sin(),
+        translation_x: 0.0,
+        translation_y: 0.0,
+        translation_z: 0.0,
+    };
+
+    let identity = [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0];
+    let instance = InstanceGeometry::try_new(&device, &object, &identity).unwrap();
+    instance.set_time_step_count(2).unwrap();
+    instance
+        .set_quaternion_transform(0, rotation_z(0.0))
+        .unwrap();
+    instance
+        .set_quaternion_transform(1, rotation_z(std::f32::consts::FRAC_PI_2))
+        .unwrap();
+
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    scene.attach_geometry(&instance).unwrap();
+    let scene = scene.commit().unwrap();
+
+    let ray_at = |x, y| {
+        RayBuilder::new((x, y, -5.0), (0.0, 0.0, 1.0))
+            .time(0.5)
+            .build()
+    };
+
+    // At mid-time, the slerp rotates the sphere by 45 degrees around the origin, while a linear
+    // interpolation of the matrices would bring it to (1, 1)
+    let slerp = std::f32::consts::SQRT_2;
+    assert!(scene.intersect_1(ray_at(slerp, slerp)).unwrap().is_some());
+    assert!(scene.intersect_1(ray_at(1.0, 1.0)).unwrap().is_none());
+}
+
+#[test]
+fn nesting_past_max_instance_level_count_fails() {
+    use crate::{
+        geometry::SphereGeometry,
+        scene::{Scene, SceneOptions},
+    };
+
+    let device = Device::try_new(None).unwrap();
+    let identity = [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0];
+
+    let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 0.0), 1.0).unwrap();
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    scene.attach_geometry(&sphere).unwrap();
+    let mut scenes = vec![scene];
+    let mut committed = scenes[0].commit().unwrap();
+    assert_eq!(committed.instance_depth(), 0);
+
+    for level in 1..=Device::MAX_INSTANCE_LEVEL_COUNT {
+        let instance = InstanceGeometry::try_new(&device, &committed, &identity).unwrap();
+        assert_eq!(instance.instance_depth(), level);
+
+        let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+        scene.attach_owned(instance).unwrap();
+        committed = scene.commit().unwrap();
+        assert_eq!(committed.instance_depth(), level);
+        scenes.push(scene);
+    }
+
+    let err = InstanceGeometry::try_new(&device, &committed, &identity)
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("levels of instances"));
+}
+
+#[test]
+fn set_scene_updates_the_instance_depth_of_scenes() {
+    use crate::{
+        geometry::SphereGeometry,
+        scene::{Scene, SceneOptions},
+    };
+
+    let device = Device::try_new(None).unwrap();
+    let identity = [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0];
+
+    let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 0.0), 1.0).unwrap();
+    let flat = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    flat.attach_geometry(&sphere).unwrap();
+    let flat = flat.commit().unwrap();
+    let nested = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    nested
+        .attach_owned(InstanceGeometry::try_new(&device, &flat, &identity).unwrap())
+        .unwrap();
+    let nested = nested.commit().unwrap();
+
+    let instance = InstanceGeometry::try_new(&device, &flat, &identity).unwrap();
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    scene.attach_geometry(&instance).unwrap();
+    assert_eq!(scene.commit().unwrap().instance_depth(), 1);
+
+    instance.set_scene(&nested).unwrap();
+    assert_eq!(instance.instance_depth(), 2);
+    assert_eq!(scene.commit().unwrap().instance_depth(), 2);
+
+    instance.set_scene(&flat).unwrap();
+    assert_eq!(scene.commit().unwrap().instance_depth(), 1);
+}
+
+#[test]
+fn world_transform_of_nested_instances() {
+    use crate::{
+        geometry::TriangleMeshGeometry,
+        ray::RayBuilder,
+        scene::{Scene, SceneOptions},
+    };
+
+    let device = Device::try_new(None).unwrap();
+    let vertices = [(-1.0, -1.0, 0.0), (1.0, -1.0, 0.0), (0.0, 1.0, 0.0)];
+    let mesh = TriangleMeshGeometry::try_new(&device, &vertices, &[(0, 1, 2)]).unwrap();
+    let object = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    object.attach_geometry(&mesh).unwrap();
+    let object = object.commit().unwrap();
+
+    // Rotates +z onto -y, then moves the object to z = 5
+    let rotate_x = [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, -1.0, 0.0, 0.0, 0.0, 5.0];
+    let inner = InstanceGeometry::try_new(&device, &object, &rotate_x).unwrap();
+    let inner_scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    inner_scene.attach_geometry(&inner).unwrap();
+    let inner_scene = inner_scene.commit().unwrap();
+
+    // Local normal of the triangle, mapped to world space by the linear part of the transform
+    let world_normal = |xfm: &[f32; 16], hit: &embree4_sys::RTCRayHit| {
+        let n = [hit.hit.Ng_x, hit.hit.Ng_y, hit.hit.Ng_z];
+        let n = [0, 1, 2].map(|r| (0..3).map(|c| xfm[4 * c + r] * n[c]).sum::<f32>());
+        let length = n.iter().map(|x| x * x).sum::<f32>().sqrt();
+        n.map(|x| x / length)
+    };
+    let close = |a: &[f32], b: &[f32]| a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-4);
+
+    let ray = RayBuilder::new((0.0, 5.0, 5.0), (0.0, -1.0, 0.0)).build();
+    let hit = inner_scene.intersect_1(ray).unwrap().unwrap();
+    let xfm = inner_scene.world_transform(&hit).unwrap();
+    assert!(close(&xfm[12..15], &[0.0, 0.0, 5.0]));
+    assert!(close(&world_normal(&xfm, &hit), &[0.0, -1.0, 0.0]));
+
+    if Device::MAX_INSTANCE_LEVEL_COUNT < 2 {
+        return;
+    }
+
+    let translate_x = [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 10.0, 0.0, 0.0];
+    let outer = InstanceGeometry::try_new(&device, &inner_scene, &translate_x).unwrap();
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    scene.attach_geometry(&outer).unwrap();
+    let scene = scene.commit().unwrap();
+
+    let ray = RayBuilder::new((10.0, 5.0, 5.0), (0.0, -1.0, 0.0)).build();
+    let hit = scene.intersect_1(ray).unwrap().unwrap();
+    let xfm = scene.world_transform(&hit).unwrap();
+    assert!(close(&xfm[12..15], &[10.0, 0.0, 5.0]));
+    assert!(close(&world_normal(&xfm, &hit), &[0.0, -1.0, 0.0]));
+}
+
+#[test]
+fn switch_instanced_scene() {
+    use crate::{
+        geometry::SphereGeometry,
+        ray::RayBuilder,
+        scene::{Scene, SceneOptions},
+    };
+
+    let device = Device::try_new(None).unwrap();
+    let small = SphereGeometry::try_new(&device, (0.0, 0.0, 0.0), 0.5).unwrap();
+    let large = SphereGeometry::try_new(&device, (0.0, 0.0, 0.0), 2.0).unwrap();
+    let [small, large] = [small, large].map(|sphere| {
+        let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+        scene.attach_owned(sphere).unwrap();
+        scene
+    });
+    let (small_committed, large_committed) = (small.commit().unwrap(), large.commit().unwrap());
+
+    let identity = [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0];
+    let instance = InstanceGeometry::try_new(&device, &small_committed, &identity).unwrap();
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    scene.attach_geometry(&instance).unwrap();
+
+    let ray = RayBuilder::new((1.0, 0.0, -5.0), (0.0, 0.0, 1.0)).build();
+    assert!(scene.commit().unwrap().intersect_1(ray).unwrap().is_none());
+
+    instance.set_scene(&large_committed).unwrap();
+    let hit = scene.commit().unwrap().intersect_1(ray).unwrap().unwrap();
+    assert!((hit.ray.tfar - (5.0 - 3.0f32.sqrt())).abs() < 1e-3);
+
+    instance.set_scene(&small_committed).unwrap();
+    assert!(scene.commit().unwrap().intersect_1(ray).unwrap().is_none());
+}
+
+#[test]
+fn translation_is_the_same_in_every_format() {
+    use crate::{
+        geometry::SphereGeometry,
+        ray::RayBuilder,
+        scene::{Scene, SceneOptions},
+    };
+
+    let device = Device::try_new(None).unwrap();
+    let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 0.0), 1.0).unwrap();
+    let object = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    object.attach_geometry(&sphere).unwrap();
+    let object = object.commit().unwrap();
+
+    #[rustfmt::skip]
+    let (column_3x4, row_3x4, column_4x4) = (
+        [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 3.0, 0.0, 0.0],
+        [1.0, 0.0, 0.0, 3.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0],
+        [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 3.0, 0.0, 0.0, 1.0],
+    );
+    let instances = [
+        InstanceGeometry::try_new(&device, &object, &column_3x4).unwrap(),
+        InstanceGeometry::from_row_major(&device, &object, &row_3x4).unwrap(),
+        InstanceGeometry::from_column_major(&device, &object, &column_4x4).unwrap(),
+    ];
+
+    // The translation moves the sphere under the ray, a transposed one would not
+    let ray = RayBuilder::new((3.0, 0.0, -5.0), (0.0, 0.0, 1.0)).build();
+    for instance in &instances {
+        let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+        scene.attach_geometry(instance).unwrap();
+        let hit = scene.commit().unwrap().intersect_1(ray).unwrap().unwrap();
+        assert!((hit.ray.tfar - 4.0).abs() < 1e-4);
+    }
+
+    let instance = &instances[0];
+    assert!(instance
+        .set_transform(RTCFormat::FLOAT3X4_ROW_MAJOR, &column_4x4)
+        .is_err());
+    assert!(instance
+        .set_transform(RTCFormat::FLOAT4X4_ROW_MAJOR, &column_4x4)
+        .is_err());
+    instance
+        .set_transform(RTCFormat::FLOAT3X4_ROW_MAJOR, &row_3x4)
+        .unwrap();
+}
@@ -0,0 +1,199 @@
+use std::{cell::Cell, ffi::c_void, mem::size_of, slice};
+
+use anyhow::{bail, Result};
+
+use crate::{device::Device, device_error_or, scene::CommittedScene};
+
+use super::{instance::checked_instance_depth, new_geometry_buffer, Geometry};
+
+/// Many instances of the same scene in a single geometry, each with its own transform.
+///
+/// Instance arrays are cheaper to build and store than as many [InstanceGeometry](super::InstanceGeometry),
+/// e.g. for vegetation. Hits report the instance array geometry ID in `instID`, and the index of
+/// the instance in the array in `instPrimID`.
+///
+/// The transforms along the instance stack of a hit are not looked up by
+/// [CommittedScene::world_transform]: read them with [InstanceArrayGeometry::transform] instead.
+pub struct InstanceArrayGeometry {
+    handle: embree4_sys::RTCGeometry,
+    enabled: Cell<bool>,
+    instance_count: usize,
+    depth: u32,
+}
+
+impl InstanceArrayGeometry {
+    /// Constructs a new `InstanceArrayGeometry` instancing the given scene once per transform.
+    ///
+    /// # Arguments
+    /// * `device` - A reference to the `Device` instance.
+    /// * `scene` - The instanced scene.
+    /// * `transforms` - The local to world transform of each instance, as column-major 3x4
+    ///   matrices, see [InstanceGeometry::try_new](super::InstanceGeometry::try_new).
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::prelude::*;
+    ///
+    /// let device = Device::try_new(None).unwrap();
+    /// let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 0.0), 1.0).unwrap();
+    /// let object = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    /// object.attach_geometry(&sphere).unwrap();
+    /// let object = object.commit().unwrap();
+    ///
+    /// let translate_x = |x| [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, x, 0.0, 0.0];
+    /// let transforms: Vec<_> = (0..100).map(|i| translate_x(3.0 * i as f32)).collect();
+    /// let instances = InstanceArrayGeometry::try_new(&device, &object, &transforms).unwrap();
+    /// let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    /// scene.attach_geometry(&instances).unwrap();
+    /// ```
+    pub fn try_new(
+        device: &Device,
+        scene: &CommittedScene,
+        transforms: &[[f32; 12]],
+    ) -> Result<Self> {
+        let depth = checked_instance_depth(scene)?;
+
+        let raw = device.new_geometry(embree4_sys::RTCGeometryType::INSTANCE_ARRAY)?;
+        let geometry = raw.handle();
+
+        let mut scenes = [scene.handle];
+        unsafe {
+            embree4_sys::rtcSetGeometryInstancedScenes(geometry, scenes.as_mut_ptr(), 1);
+        }
+        device_error_or(device, (), "Could not set instanced scene")?;
+
+        let transform_buf_ptr = new_geometry_buffer(
+            device.handle,
+            geometry,
+            embree4_sys::RTCBufferType::TRANSFORM,
+            0,
+            embree4_sys::RTCFormat::FLOAT3X4_COLUMN_MAJOR,
+            12 * size_of::<f32>(),
+            transforms.len(),
+        )?;
+        let transform_buf = unsafe {
+            slice::from_raw_parts_mut(transform_buf_ptr as *mut [f32; 12], transforms.len())
+        };
+        transform_buf.copy_from_slice(transforms);
+
+        unsafe {
+            embree4_sys::rtcCommitGeometry(geometry);
+        }
+        device_error_or(device, (), "Failed to commit instance array geometry")?;
+
+        Ok(Self {
+            handle: raw.into_raw(),
+            enabled: Cell::new(true),
+            instance_count: transforms.len(),
+            depth,
+        })
+    }
+
+    /// Returns the local to world transform of an instance of the array at the given time, as a
+    /// column-major 4x4 matrix.
+    ///
+    /// See [rtcGetGeometryTransformEx](https://github.com/embree/embree/blob/master/doc/src/api/rtcGetGeometryTransformEx.md).
+    ///
+    /// # Arguments
+    /// * `inst_prim_id` - The index of the instance in the array, as reported by `instPrimID`.
+    /// * `time` - The time of the ray, for motion blur.
+    pub fn transform(&self, inst_prim_id: u32, time: f32) -> Result<[f32; 16]> {
+        if inst_prim_id as usize >= self.instance_count {
+            bail!(
+                "Instance {} is out of range, the instance array has {} instances",
+                inst_prim_id,
+                self.instance_count
+            );
+        }
+
+        let mut xfm = [0.0; 16];
+        unsafe {
+            embree4_sys::rtcGetGeometryTransformEx(
+                self.handle,
+                inst_prim_id,
+                time,
+                embree4_sys::RTCFormat::FLOAT4X4_COLUMN_MAJOR,
+                xfm.as_mut_ptr() as *mut c_void,
+            );
+        }
+        Ok(xfm)
+    }
+}
+
+impl Drop for InstanceArrayGeometry {
+    fn drop(&mut self) {
+        unsafe {
+            embree4_sys::rtcReleaseGeometry(self.handle);
+        }
+    }
+}
+
+impl Geometry for InstanceArrayGeometry {
+    fn geometry(&self) -> embree4_sys::RTCGeometry {
+        self.handle
+    }
+
+    fn enabled_flag(&self) -> Option<&Cell<bool>> {
+        Some(&self.enabled)
+    }
+
+    fn kind(&self) -> embree4_sys::RTCGeometryType {
+        embree4_sys::RTCGeometryType::INSTANCE_ARRAY
+    }
+
+    fn time_step_count(&self) -> u32 {
+        1
+    }
+
+    fn primitive_count(&self) -> usize {
+        self.instance_count
+    }
+
+    fn instance_depth(&self) -> u32 {
+        self.depth
+    }
+}
+
+#[test]
+fn transform_of_instance_array_element() {
+    use crate::{
+        geometry::SphereGeometry,
+        ray::RayBuilder,
+        scene::{Scene, SceneOptions},
+    };
+
+    let device = Device::try_new(None).unwrap();
+    let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 0.0), 1.0).unwrap();
+    let object = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    object.attach_geometry(&sphere).unwrap();
+    let object = object.commit().unwrap();
+
+    let translate = |x, y| [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, x, y, 0.0];
+    let transforms = [
+        translate(-4.0, 0.0),
+        translate(0.0, 2.0),
+        translate(4.0, 0.0),
+    ];
+    let instances = InstanceArrayGeometry::try_new(&device, &object, &transforms).unwrap();
+    assert_eq!(instances.primitive_count(), 3);
+
+    let xfm = instances.transform(1, 0.0).unwrap();
+    #[rustfmt::skip]
+    let expected = [
+        1.0, 0.0, 0.0, 0.0,
+        0.0, 1.0, 0.0, 0.0,
+        0.0, 0.0, 1.0, 0.0,
+        0.0, 2.0, 0.0, 1.0,
+    ];
+    assert_eq!(xfm, expected);
+    assert_eq!(instances.transform(2, 0.5).unwrap()[12], 4.0);
+    assert!(instances.transform(3, 0.0).is_err());
+
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    let geom_id = scene.attach_geometry(&instances).unwrap();
+    let scene = scene.commit().unwrap();
+    let ray = RayBuilder::new((0.0, 2.0, -5.0), (0.0, 0.0, 1.0)).build();
+    let hit = scene.intersect_1(ray).unwrap().unwrap();
+    assert_eq!(hit.hit.instID[0], geom_id);
+    assert!((hit.ray.tfar - 4.0).abs() < 1e-4);
+}
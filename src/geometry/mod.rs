@@ -1,10 +1,42 @@
+use std::{any::Any, cell::Cell, ffi::c_void, sync::Arc};
+
+use anyhow::{bail, Result};
+use embree4_sys::{RTCBufferType, RTCFormat, RTCGeometryType};
+
+use crate::{aabb::Aabb, clear_device_error, device_handle_error_or};
+
+mod batch;
+mod box_mesh;
+mod cone;
+mod curve;
+mod instance;
+mod instance_array;
+mod raw;
 mod sphere;
+mod subdiv;
+mod transformed;
 mod tri_mesh;
 mod user;
+mod validation;
 
+pub use batch::*;
+pub use box_mesh::*;
+pub use cone::*;
+pub use curve::*;
+pub use instance::*;
+pub use instance_array::*;
+pub use raw::*;
 pub use sphere::*;
+pub use subdiv::*;
+pub use transformed::*;
 pub use tri_mesh::*;
 pub use user::*;
+pub(crate) use validation::Layout;
+pub use validation::Validation;
+
+/// Data that Embree reads in place, kept alive by the scenes a geometry is attached to, see
+/// [Geometry::shared_data].
+pub type SharedData = Arc<dyn Any + Send + Sync>;
 
 /// A trait implemented by all geometry types.
 /// If you want to implement your own geometry type, you must implement this trait.
@@ -14,4 +46,335 @@ pub use user::*;
 pub trait Geometry {
     /// Returns the handle of the geometry.
     fn geometry(&self) -> embree4_sys::RTCGeometry;
+
+    /// Returns the type of the geometry, e.g. to shade curves and triangles differently.
+    ///
+    /// Instances and [TransformedGeometry] report `INSTANCE`, user geometries including
+    /// [ConeGeometry] and [CylinderGeometry] report `USER`.
+    ///
+    /// Returns `USER` by default, for custom geometries.
+    fn kind(&self) -> RTCGeometryType {
+        RTCGeometryType::USER
+    }
+
+    /// Returns the number of time steps of the geometry, `1` if it is not motion blurred.
+    ///
+    /// Returns `1` by default.
+    fn time_step_count(&self) -> u32 {
+        1
+    }
+
+    /// Returns the number of primitives of the geometry.
+    ///
+    /// Returns `0` by default, for geometries that do not report it. Scenes only use it for
+    /// statistics.
+    fn primitive_count(&self) -> usize {
+        0
+    }
+
+    /// Returns `true` if the geometry was committed with `rtcCommitGeometry` since its last
+    /// change.
+    ///
+    /// Embree does not report uncommitted geometries, which are silently traced with stale or
+    /// missing data, so [Scene::attach_geometry](crate::scene::Scene::attach_geometry) rejects
+    /// them. The geometries of this crate track it: they are committed by their constructors and
+    /// setters, and left uncommitted when buffers are replaced without being filled, e.g. by
+    /// [Geometry::set_time_step_count].
+    ///
+    /// Returns `true` by default; custom geometries should track their own state.
+    fn is_committed(&self) -> bool {
+        true
+    }
+
+    /// Sets the number of time steps of the geometry for motion blur, without setting their
+    /// buffers.
+    ///
+    /// This allows filling the time steps one by one, e.g. as they are loaded. The buffers of the
+    /// time steps below `count` must all be set before the geometry is attached to a committed
+    /// scene, and time steps from `count` on cannot be set.
+    ///
+    /// Returns an error by default, for geometries that do not support motion blur.
+    fn set_time_step_count(&self, count: u32) -> Result<()> {
+        let _ = count;
+        bail!("Geometry does not support motion blur")
+    }
+
+    /// Returns the bounds of the geometry over all of its time steps, computed from its buffers
+    /// rather than from a BVH.
+    ///
+    /// Returns `None` by default, for geometries whose bounds are unknown.
+    fn bounds(&self) -> Option<Aabb> {
+        None
+    }
+
+    /// Returns a check of the buffers of the geometry, run by
+    /// [Scene::validate](crate::scene::Scene::validate).
+    ///
+    /// Returns `None` by default, for geometries that cannot be checked.
+    fn validation(&self) -> Option<Validation> {
+        None
+    }
+
+    /// Returns the data Embree reads in place rather than from buffers it owns, e.g. the shared
+    /// buffers of [TriangleMeshGeometry::try_new_shared] or the shape read by the callbacks of
+    /// [ConeGeometry].
+    ///
+    /// Scenes keep this data alive for as long as they hold the geometry, so that the geometry can
+    /// be dropped once attached. Returns `None` by default, for geometries whose data is owned by
+    /// Embree.
+    fn shared_data(&self) -> Option<SharedData> {
+        None
+    }
+
+    /// Returns the number of instance levels traversed to reach the primitives of the geometry:
+    /// `0` by default, one more than the instanced scene for instances.
+    fn instance_depth(&self) -> u32 {
+        0
+    }
+
+    /// Returns the link used to look up the transforms of an instance stack, for instances.
+    ///
+    /// Returns `None` by default, for geometries that are not instances.
+    fn instance_link(&self) -> Option<InstanceLink> {
+        None
+    }
+
+    /// Enables the geometry, so that rays hit it again once the scenes it is attached to are
+    /// committed. Geometries are enabled when created.
+    ///
+    /// See [rtcEnableGeometry](https://github.com/embree/embree/blob/master/doc/src/api/rtcEnableGeometry.md).
+    fn enable(&self) {
+        unsafe { embree4_sys::rtcEnableGeometry(self.geometry()) };
+        if let Some(enabled) = self.enabled_flag() {
+            enabled.set(true);
+        }
+    }
+
+    /// Disables the geometry, so that rays ignore it once the scenes it is attached to are
+    /// committed.
+    ///
+    /// See [rtcDisableGeometry](https://github.com/embree/embree/blob/master/doc/src/api/rtcDisableGeometry.md).
+    fn disable(&self) {
+        unsafe { embree4_sys::rtcDisableGeometry(self.geometry()) };
+        if let Some(enabled) = self.enabled_flag() {
+            enabled.set(false);
+        }
+    }
+
+    /// Returns `false` if the geometry was disabled with [Geometry::disable] and not enabled
+    /// since.
+    ///
+    /// Embree does not report the enable state, so it is tracked by the geometry, see
+    /// [Geometry::enabled_flag].
+    fn is_enabled(&self) -> bool {
+        self.enabled_flag().is_none_or(Cell::get)
+    }
+
+    /// Returns the flag in which the geometry tracks its enable state for [Geometry::is_enabled].
+    ///
+    /// Returns `None` by default, for geometries that do not track it and are reported as enabled.
+    fn enabled_flag(&self) -> Option<&Cell<bool>> {
+        None
+    }
+}
+
+/// Allocates a new buffer of `item_count` items of `byte_stride` bytes and binds it to the
+/// geometry.
+///
+/// Errors report the size of the requested allocation.
+pub(crate) fn new_geometry_buffer(
+    device: embree4_sys::RTCDevice,
+    geometry: embree4_sys::RTCGeometry,
+    buffer_type: RTCBufferType,
+    slot: u32,
+    format: RTCFormat,
+    byte_stride: usize,
+    item_count: usize,
+) -> Result<*mut c_void> {
+    clear_device_error(device);
+    let buf_ptr = unsafe {
+        embree4_sys::rtcSetNewGeometryBuffer(
+            geometry,
+            buffer_type,
+            slot,
+            format,
+            byte_stride,
+            item_count,
+        )
+    };
+
+    let message = format!(
+        "Failed to allocate {}-byte {} buffer",
+        byte_stride as u128 * item_count as u128,
+        buffer_type_name(buffer_type)
+    );
+    device_handle_error_or(device, (), &message)?;
+    if buf_ptr.is_null() {
+        bail!(message);
+    }
+
+    Ok(buf_ptr)
+}
+
+/// Bounds of the first `vertex_count` vertices of the vertex buffers of each time step.
+///
+/// Vertices are made of `components` floats: the position, followed by the radius when there are
+/// 4 of them.
+pub(crate) fn vertex_buffer_bounds(
+    geometry: embree4_sys::RTCGeometry,
+    time_step_count: u32,
+    vertex_count: usize,
+    components: usize,
+) -> Option<Aabb> {
+    let mut bounds: Option<Aabb> = None;
+    for slot in 0..time_step_count {
+        let buf_ptr =
+            unsafe { embree4_sys::rtcGetGeometryBufferData(geometry, RTCBufferType::VERTEX, slot) };
+        if buf_ptr.is_null() {
+            return None;
+        }
+
+        let buf =
+            unsafe { std::slice::from_raw_parts(buf_ptr as *const f32, components * vertex_count) };
+        for vertex in buf.chunks_exact(components) {
+            let radius = if components == 4 { vertex[3] } else { 0.0 };
+            let point = Aabb::new(
+                [vertex[0] - radius, vertex[1] - radius, vertex[2] - radius],
+                [vertex[0] + radius, vertex[1] + radius, vertex[2] + radius],
+            );
+            bounds = Some(bounds.map_or(point, |b| b.union(&point)));
+        }
+    }
+
+    bounds
+}
+
+fn buffer_type_name(buffer_type: RTCBufferType) -> String {
+    let name = match buffer_type {
+        RTCBufferType::INDEX => "INDEX",
+        RTCBufferType::VERTEX => "VERTEX",
+        RTCBufferType::VERTEX_ATTRIBUTE => "VERTEX_ATTRIBUTE",
+        RTCBufferType::NORMAL => "NORMAL",
+        RTCBufferType::TANGENT => "TANGENT",
+        RTCBufferType::NORMAL_DERIVATIVE => "NORMAL_DERIVATIVE",
+        RTCBufferType::GRID => "GRID",
+        RTCBufferType::FACE => "FACE",
+        RTCBufferType::LEVEL => "LEVEL",
+        RTCBufferType::EDGE_CREASE_INDEX => "EDGE_CREASE_INDEX",
+        RTCBufferType::EDGE_CREASE_WEIGHT => "EDGE_CREASE_WEIGHT",
+        RTCBufferType::VERTEX_CREASE_INDEX => "VERTEX_CREASE_INDEX",
+        RTCBufferType::VERTEX_CREASE_WEIGHT => "VERTEX_CREASE_WEIGHT",
+        RTCBufferType::HOLE => "HOLE",
+        RTCBufferType::TRANSFORM => "TRANSFORM",
+        RTCBufferType::FLAGS => "FLAGS",
+        _ => return format!("{:?}", buffer_type),
+    };
+    name.to_owned()
+}
+
+#[test]
+fn buffer_allocation_error_reports_size() {
+    use crate::device::Device;
+
+    let device = Device::try_new(None).unwrap();
+    let _budget = device.set_memory_budget(4096);
+
+    // The vertex buffer is allocated first, and is past the budget
+    let vertices = vec![(0.0, 0.0, 0.0); 1024];
+    let err = TriangleMeshGeometry::try_new(&device, &vertices, &[(0, 1, 2)])
+        .err()
+        .unwrap();
+    assert!(err
+        .to_string()
+        .starts_with("Failed to allocate 12288-byte VERTEX buffer"));
+}
+
+#[test]
+fn geometry_creation_error_is_not_stale() {
+    use crate::{device::Device, error::EmbreeError};
+
+    let device = Device::try_new(None).unwrap();
+
+    // Leave an INVALID_ARGUMENT error behind, REFIT is only valid for geometries
+    unsafe {
+        let scene = embree4_sys::rtcNewScene(device.handle);
+        embree4_sys::rtcSetSceneBuildQuality(scene, embree4_sys::RTCBuildQuality::REFIT);
+        embree4_sys::rtcReleaseScene(scene);
+    }
+
+    let err = device.new_geometry(RTCGeometryType(u32::MAX)).unwrap_err();
+    let error = err.downcast_ref::<EmbreeError>().unwrap();
+    assert_eq!(error.code(), embree4_sys::RTCError::UNKNOWN);
+    assert!(device.error().is_none());
+}
+
+#[test]
+fn geometries_report_their_kind() {
+    use crate::{
+        device::Device,
+        scene::{Scene, SceneOptions},
+    };
+
+    let device = Device::try_new(None).unwrap();
+    let vertices = [(0.0, 0.0, 0.0), (1.0, 0.0, 0.0), (0.0, 1.0, 0.0)];
+    let mesh = TriangleMeshGeometry::try_new(&device, &vertices, &[(0, 1, 2)]).unwrap();
+    assert_eq!(mesh.kind(), RTCGeometryType::TRIANGLE);
+
+    let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 0.0), 1.0).unwrap();
+    assert_eq!(sphere.kind(), RTCGeometryType::SPHERE_POINT);
+
+    let curve_vertices = [(-1.0, 0.0, 0.0, 0.1), (1.0, 0.0, 0.0, 0.1)];
+    let curve = CurveGeometry::try_new(
+        &device,
+        RTCGeometryType::FLAT_LINEAR_CURVE,
+        &curve_vertices,
+        &[0],
+    )
+    .unwrap();
+    assert_eq!(curve.kind(), RTCGeometryType::FLAT_LINEAR_CURVE);
+
+    let cylinder =
+        CylinderGeometry::try_new(&device, (0.0, 0.0, 0.0), (0.0, 1.0, 0.0), 1.0).unwrap();
+    assert_eq!(cylinder.kind(), RTCGeometryType::USER);
+
+    let identity = [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0];
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    scene.attach_geometry(&mesh).unwrap();
+    let scene = scene.commit().unwrap();
+    let instance = InstanceGeometry::try_new(&device, &scene, &identity).unwrap();
+    assert_eq!(instance.kind(), RTCGeometryType::INSTANCE);
+
+    let transformed = TransformedGeometry::try_new(&device, sphere, &identity).unwrap();
+    assert_eq!(transformed.kind(), RTCGeometryType::INSTANCE);
+}
+
+#[test]
+fn enable_state_follows_toggles() {
+    use crate::{
+        device::Device,
+        ray::RayBuilder,
+        scene::{Scene, SceneOptions},
+    };
+
+    let device = Device::try_new(None).unwrap();
+    let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 5.0), 1.0).unwrap();
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    scene.attach_geometry(&sphere).unwrap();
+    let ray = RayBuilder::new((0.0, 0.0, 0.0), (0.0, 0.0, 1.0)).build();
+    assert!(sphere.is_enabled());
+
+    sphere.disable();
+    assert!(!sphere.is_enabled());
+    assert!(scene.commit().unwrap().intersect_1(ray).unwrap().is_none());
+
+    sphere.enable();
+    assert!(sphere.is_enabled());
+    assert!(scene.commit().unwrap().intersect_1(ray).unwrap().is_some());
+
+    // A new geometry reusing the handle of a disabled one starts enabled
+    sphere.disable();
+    drop(scene);
+    drop(sphere);
+    let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 5.0), 1.0).unwrap();
+    assert!(sphere.is_enabled());
 }
@@ -0,0 +1,85 @@
+/// A geometry handle released on drop, from which geometry types are built.
+///
+/// Created by [Device::new_geometry](crate::device::Device::new_geometry). Constructors set up
+/// the buffers of the handle, and take it over with [RawGeometry::into_raw] once they succeed, so
+/// that the geometry is released on every early return.
+///
+/// # Example
+/// ```
+/// use embree4_rs::prelude::*;
+/// use embree4_sys::*;
+///
+/// let device = Device::try_new(None).unwrap();
+/// let geometry = device.new_geometry(RTCGeometryType::TRIANGLE).unwrap();
+/// assert_eq!(geometry.kind(), RTCGeometryType::TRIANGLE);
+/// ```
+#[derive(Debug)]
+pub struct RawGeometry {
+    handle: embree4_sys::RTCGeometry,
+    kind: embree4_sys::RTCGeometryType,
+}
+
+impl RawGeometry {
+    // The handle must be a valid geometry, whose reference is owned by the new `RawGeometry`
+    pub(crate) fn from_raw(
+        handle: embree4_sys::RTCGeometry,
+        kind: embree4_sys::RTCGeometryType,
+    ) -> Self {
+        Self { handle, kind }
+    }
+
+    /// Returns the handle of the geometry.
+    pub fn handle(&self) -> embree4_sys::RTCGeometry {
+        self.handle
+    }
+
+    /// Returns the type the geometry was created with.
+    pub fn kind(&self) -> embree4_sys::RTCGeometryType {
+        self.kind
+    }
+
+    /// Returns the handle of the geometry without releasing it. The caller becomes responsible
+    /// for releasing it with `rtcReleaseGeometry`.
+    pub fn into_raw(self) -> embree4_sys::RTCGeometry {
+        let handle = self.handle;
+        std::mem::forget(self);
+        handle
+    }
+}
+
+impl Drop for RawGeometry {
+    fn drop(&mut self) {
+        unsafe {
+            embree4_sys::rtcReleaseGeometry(self.handle);
+        }
+    }
+}
+
+unsafe impl Send for RawGeometry {}
+unsafe impl Sync for RawGeometry {}
+
+#[test]
+fn raw_triangle_geometry_is_released() {
+    use crate::device::Device;
+
+    let device = Device::try_new(None).unwrap();
+    let geometry = device
+        .new_geometry(embree4_sys::RTCGeometryType::TRIANGLE)
+        .unwrap();
+    assert!(!geometry.handle().is_null());
+    assert_eq!(geometry.kind(), embree4_sys::RTCGeometryType::TRIANGLE);
+
+    // The drop releases a single reference, the one taken here keeps the geometry alive
+    let handle = geometry.handle();
+    unsafe { embree4_sys::rtcRetainGeometry(handle) };
+    drop(geometry);
+    unsafe { embree4_sys::rtcReleaseGeometry(handle) };
+    assert!(device.error().is_none());
+
+    let handle = device
+        .new_geometry(embree4_sys::RTCGeometryType::TRIANGLE)
+        .unwrap()
+        .into_raw();
+    unsafe { embree4_sys::rtcReleaseGeometry(handle) };
+    assert!(device.error().is_none());
+}
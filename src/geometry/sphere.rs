@@ -1,13 +1,14 @@
-use std::{mem::size_of, slice};
+use std::{cell::Cell, mem::size_of, slice};
 
-use anyhow::{bail, Result};
+use anyhow::Result;
 
-use crate::{device::Device, device_error_or};
+use crate::{aabb::Aabb, device::Device, device_error_or};
 
-use super::Geometry;
+use super::{new_geometry_buffer, validation::Layout, vertex_buffer_bounds, Geometry, Validation};
 
 pub struct SphereGeometry {
     handle: embree4_sys::RTCGeometry,
+    enabled: Cell<bool>,
 }
 
 impl SphereGeometry {
@@ -24,30 +25,18 @@ impl SphereGeometry {
     /// scene.attach_geometry(&geometry);
     /// ```
     pub fn try_new(device: &Device, origin: (f32, f32, f32), radius: f32) -> Result<Self> {
-        let geometry = unsafe {
-            embree4_sys::rtcNewGeometry(device.handle, embree4_sys::RTCGeometryType::SPHERE_POINT)
-        };
-        if geometry.is_null() {
-            bail!("Failed to create geometry: {:?}", device.error());
-        }
+        let raw = device.new_geometry(embree4_sys::RTCGeometryType::SPHERE_POINT)?;
+        let geometry = raw.handle();
 
-        let vertex_buf_ptr = unsafe {
-            embree4_sys::rtcSetNewGeometryBuffer(
-                geometry,
-                embree4_sys::RTCBufferType::VERTEX,
-                0,
-                embree4_sys::RTCFormat::FLOAT4,
-                4 * size_of::<f32>(),
-                1,
-            )
-        };
-        if vertex_buf_ptr.is_null() {
-            bail!(
-                "Failed to create sphere vertex buffer: {:?}",
-                device.error()
-            );
-        }
-        device_error_or(device, (), "Failed not create sphere vertex buffer")?;
+        let vertex_buf_ptr = new_geometry_buffer(
+            device.handle,
+            geometry,
+            embree4_sys::RTCBufferType::VERTEX,
+            0,
+            embree4_sys::RTCFormat::FLOAT4,
+            4 * size_of::<f32>(),
+            1,
+        )?;
 
         let vertex_buf = unsafe { slice::from_raw_parts_mut(vertex_buf_ptr as *mut f32, 4) };
         vertex_buf.copy_from_slice(&[origin.0, origin.1, origin.2, radius]);
@@ -57,7 +46,10 @@ impl SphereGeometry {
         }
         device_error_or(device, (), "Failed to commit sphere geometry")?;
 
-        Ok(Self { handle: geometry })
+        Ok(Self {
+            handle: raw.into_raw(),
+            enabled: Cell::new(true),
+        })
     }
 }
 
@@ -73,4 +65,28 @@ impl Geometry for SphereGeometry {
     fn geometry(&self) -> embree4_sys::RTCGeometry {
         self.handle
     }
+
+    fn enabled_flag(&self) -> Option<&Cell<bool>> {
+        Some(&self.enabled)
+    }
+
+    fn kind(&self) -> embree4_sys::RTCGeometryType {
+        embree4_sys::RTCGeometryType::SPHERE_POINT
+    }
+
+    fn time_step_count(&self) -> u32 {
+        1
+    }
+
+    fn primitive_count(&self) -> usize {
+        1
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        vertex_buffer_bounds(self.handle, 1, 1, 4)
+    }
+
+    fn validation(&self) -> Option<Validation> {
+        Some(Validation::new(self.handle, Layout::Sphere))
+    }
 }
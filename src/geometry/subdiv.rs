@@ -0,0 +1,434 @@
+use std::{cell::Cell, mem::size_of, slice};
+
+use anyhow::{bail, Result};
+
+use crate::{aabb::Aabb, device::Device, device_error_or, device_handle_error_or};
+
+use super::{new_geometry_buffer, validation::Layout, vertex_buffer_bounds, Geometry, Validation};
+
+/// The lowest tessellation rate picked by [SubdivGeometry::set_tessellation_from_distance].
+pub const MIN_TESSELLATION_RATE: f32 = 1.0;
+
+/// The highest tessellation rate picked by [SubdivGeometry::set_tessellation_from_distance].
+pub const MAX_TESSELLATION_RATE: f32 = 64.0;
+
+/// Segments per edge for a geometry as large as its distance to the camera.
+const TESSELLATION_RATE_SCALE: f32 = 16.0;
+
+/// Embree's tessellation rate for subdivision geometries it was not set on.
+const DEFAULT_TESSELLATION_RATE: f32 = 2.0;
+
+pub struct SubdivGeometry {
+    handle: embree4_sys::RTCGeometry,
+    enabled: Cell<bool>,
+    device: embree4_sys::RTCDevice,
+    face_count: usize,
+    vertex_count: usize,
+    tessellation_rate: Cell<f32>,
+}
+
+impl SubdivGeometry {
+    /// Constructs a new Catmull-Clark `SubdivGeometry` instance from the given control mesh.
+    ///
+    /// # Arguments
+    /// * `device` - A reference to the `Device` instance.
+    /// * `vertices` - The control vertices.
+    /// * `face_vertex_counts` - The number of vertices of each face.
+    /// * `indices` - The vertex indices of all faces, one after the other.
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::prelude::*;
+    ///
+    /// let vertices = [
+    ///   (-1.0, -1.0, 0.0),
+    ///   (1.0, -1.0, 0.0),
+    ///   (1.0, 1.0, 0.0),
+    ///   (-1.0, 1.0, 0.0),
+    /// ];
+    ///
+    /// let device = Device::try_new(None).unwrap();
+    /// let geometry = SubdivGeometry::try_new(&device, &vertices, &[4], &[0, 1, 2, 3]).unwrap();
+    /// let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    /// scene.attach_geometry(&geometry).unwrap();
+    /// ```
+    pub fn try_new(
+        device: &Device,
+        vertices: &[(f32, f32, f32)],
+        face_vertex_counts: &[u32],
+        indices: &[u32],
+    ) -> Result<Self> {
+        let index_count: usize = face_vertex_counts.iter().map(|&n| n as usize).sum();
+        if index_count != indices.len() {
+            bail!(
+                "Subdivision faces have {} vertices in total, got {} indices",
+                index_count,
+                indices.len()
+            );
+        }
+
+        let geometry = device
+            .new_geometry(embree4_sys::RTCGeometryType::SUBDIVISION)?
+            .into_raw();
+
+        let subdiv = Self {
+            handle: geometry,
+            enabled: Cell::new(true),
+            device: device.handle,
+            face_count: face_vertex_counts.len(),
+            vertex_count: vertices.len(),
+            tessellation_rate: Cell::new(DEFAULT_TESSELLATION_RATE),
+        };
+
+        let vertex_buf_ptr = new_geometry_buffer(
+            device.handle,
+            geometry,
+            embree4_sys::RTCBufferType::VERTEX,
+            0,
+            embree4_sys::RTCFormat::FLOAT3,
+            3 * size_of::<f32>(),
+            vertices.len(),
+        )?;
+
+        let vertex_buf =
+            unsafe { slice::from_raw_parts_mut(vertex_buf_ptr as *mut f32, 3 * vertices.len()) };
+
+        // copy vertices into buffer
+        for (i, v) in vertices.iter().enumerate() {
+            vertex_buf[3 * i] = v.0;
+            vertex_buf[3 * i + 1] = v.1;
+            vertex_buf[3 * i + 2] = v.2;
+        }
+
+        for (buffer_type, data) in [
+            (embree4_sys::RTCBufferType::FACE, face_vertex_counts),
+            (embree4_sys::RTCBufferType::INDEX, indices),
+        ] {
+            let buf_ptr = new_geometry_buffer(
+                device.handle,
+                geometry,
+                buffer_type,
+                0,
+                embree4_sys::RTCFormat::UINT,
+                size_of::<u32>(),
+                data.len(),
+            )?;
+
+            let buf = unsafe { slice::from_raw_parts_mut(buf_ptr as *mut u32, data.len()) };
+            buf.copy_from_slice(data);
+        }
+
+        unsafe {
+            embree4_sys::rtcCommitGeometry(geometry);
+        }
+        device_error_or(device, (), "Failed to commit subdivision geometry")?;
+
+        Ok(subdiv)
+    }
+
+    /// Sets the number of segments each edge of the control mesh is tessellated into and recommits
+    /// the geometry.
+    ///
+    /// Higher rates give smoother surfaces at the cost of build time and memory. Embree defaults
+    /// to `2`.
+    ///
+    /// See [rtcSetGeometryTessellationRate](https://github.com/embree/embree/blob/master/doc/src/api/rtcSetGeometryTessellationRate.md).
+    pub fn set_tessellation_rate(&self, rate: f32) -> Result<()> {
+        unsafe {
+            embree4_sys::rtcSetGeometryTessellationRate(self.handle, rate);
+            embree4_sys::rtcCommitGeometry(self.handle);
+        }
+        device_handle_error_or(
+            self.device,
+            (),
+            "Could not set subdivision tessellation rate",
+        )?;
+        self.tessellation_rate.set(rate);
+        Ok(())
+    }
+
+    /// Returns the tessellation rate last set on the geometry, `2` by default.
+    ///
+    /// Embree does not report the rate: it is tracked by the wrapper.
+    pub fn tessellation_rate(&self) -> f32 {
+        self.tessellation_rate.get()
+    }
+
+    /// Estimates the number of quads the control mesh is tessellated into at the current
+    /// tessellation rate, e.g. to predict the memory used by a commit.
+    ///
+    /// Embree does not expose the tessellation it performs, so this follows its scheme: quads are
+    /// tessellated into a grid of `rate` segments per edge, and other faces are first split into
+    /// one quad per vertex, each with half as many segments per edge. Flat curves also take a
+    /// tessellation rate, see [super::CurveGeometry::estimated_quad_count].
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::prelude::*;
+    ///
+    /// let vertices = [(0.0, 0.0, 0.0), (1.0, 0.0, 0.0), (1.0, 1.0, 0.0), (0.0, 1.0, 0.0)];
+    ///
+    /// let device = Device::try_new(None).unwrap();
+    /// let geometry = SubdivGeometry::try_new(&device, &vertices, &[4], &[0, 1, 2, 3]).unwrap();
+    /// geometry.set_tessellation_rate(8.0).unwrap();
+    /// assert_eq!(geometry.estimated_quad_count(), 64);
+    /// ```
+    pub fn estimated_quad_count(&self) -> usize {
+        let faces = unsafe {
+            embree4_sys::rtcGetGeometryBufferData(self.handle, embree4_sys::RTCBufferType::FACE, 0)
+        } as *const u32;
+        if faces.is_null() {
+            return 0;
+        }
+
+        let rate = self.tessellation_rate().max(1.0);
+        let quad_segments = rate.ceil() as usize;
+        let split_segments = (0.5 * rate).ceil().max(1.0) as usize;
+
+        let faces = unsafe { slice::from_raw_parts(faces, self.face_count) };
+        faces
+            .iter()
+            .map(|&vertex_count| match vertex_count {
+                4 => quad_segments * quad_segments,
+                n => n as usize * split_segments * split_segments,
+            })
+            .sum()
+    }
+
+    /// Sets a tessellation rate matching the size of the geometry seen from `camera_pos` and
+    /// recommits the geometry.
+    ///
+    /// The rate is proportional to the angular size of the bounds of the control mesh, from
+    /// [MIN_TESSELLATION_RATE] for distant geometries up to [MAX_TESSELLATION_RATE] for close ones.
+    ///
+    /// # Returns
+    /// A `Result` containing the rate that was set.
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::{geometry::*, prelude::*};
+    ///
+    /// let vertices = [
+    ///   (-1.0, -1.0, 0.0),
+    ///   (1.0, -1.0, 0.0),
+    ///   (1.0, 1.0, 0.0),
+    ///   (-1.0, 1.0, 0.0),
+    /// ];
+    ///
+    /// let device = Device::try_new(None).unwrap();
+    /// let geometry = SubdivGeometry::try_new(&device, &vertices, &[4], &[0, 1, 2, 3]).unwrap();
+    /// let rate = geometry.set_tessellation_from_distance((0.0, 0.0, -10.0)).unwrap();
+    /// assert!((MIN_TESSELLATION_RATE..=MAX_TESSELLATION_RATE).contains(&rate));
+    /// ```
+    pub fn set_tessellation_from_distance(&self, camera_pos: (f32, f32, f32)) -> Result<f32> {
+        let rate = match self.bounds() {
+            Some(bounds) => {
+                let camera = [camera_pos.0, camera_pos.1, camera_pos.2];
+                let center = [0, 1, 2].map(|i| 0.5 * (bounds.lower[i] + bounds.upper[i]));
+                let extent = [0, 1, 2].map(|i| bounds.upper[i] - bounds.lower[i]);
+                let dist = (0..3).map(|i| (center[i] - camera[i]).powi(2)).sum::<f32>();
+                let size = extent.iter().map(|x| x * x).sum::<f32>();
+
+                let rate = TESSELLATION_RATE_SCALE * (size / dist.max(f32::EPSILON)).sqrt();
+                rate.clamp(MIN_TESSELLATION_RATE, MAX_TESSELLATION_RATE)
+            }
+            None => MIN_TESSELLATION_RATE,
+        };
+
+        self.set_tessellation_rate(rate)?;
+        Ok(rate)
+    }
+
+    /// Returns the number of faces of the control mesh.
+    pub fn face_count(&self) -> usize {
+        self.face_count
+    }
+
+    /// Returns an iterator over the half-edges of a face, in order.
+    ///
+    /// See [rtcGetGeometryFirstHalfEdge](https://github.com/embree/embree/blob/master/doc/src/api/rtcGetGeometryFirstHalfEdge.md).
+    ///
+    /// # Returns
+    /// `None` if the face does not exist.
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::prelude::*;
+    ///
+    /// let vertices = [(0.0, 0.0, 0.0), (1.0, 0.0, 0.0), (1.0, 1.0, 0.0), (0.0, 1.0, 0.0)];
+    ///
+    /// let device = Device::try_new(None).unwrap();
+    /// let geometry = SubdivGeometry::try_new(&device, &vertices, &[4], &[0, 1, 2, 3]).unwrap();
+    /// assert_eq!(geometry.face_half_edges(0).unwrap().count(), 4);
+    /// ```
+    pub fn face_half_edges(&self, face: u32) -> Option<FaceHalfEdges<'_>> {
+        if face as usize >= self.face_count {
+            return None;
+        }
+
+        let first = unsafe { embree4_sys::rtcGetGeometryFirstHalfEdge(self.handle, face) };
+        Some(FaceHalfEdges {
+            geometry: self,
+            first,
+            next: Some(first),
+        })
+    }
+
+    /// Returns the face a half-edge belongs to.
+    pub fn half_edge_face(&self, edge: u32) -> u32 {
+        unsafe { embree4_sys::rtcGetGeometryFace(self.handle, edge) }
+    }
+
+    /// Returns the next half-edge of the same face.
+    pub fn next_half_edge(&self, edge: u32) -> u32 {
+        unsafe { embree4_sys::rtcGetGeometryNextHalfEdge(self.handle, edge) }
+    }
+
+    /// Returns the previous half-edge of the same face.
+    pub fn previous_half_edge(&self, edge: u32) -> u32 {
+        unsafe { embree4_sys::rtcGetGeometryPreviousHalfEdge(self.handle, edge) }
+    }
+
+    /// Returns the half-edge of the adjacent face sharing this edge.
+    ///
+    /// For border edges, the half-edge itself is returned.
+    pub fn opposite_half_edge(&self, edge: u32) -> u32 {
+        unsafe { embree4_sys::rtcGetGeometryOppositeHalfEdge(self.handle, 0, edge) }
+    }
+}
+
+/// An iterator over the half-edges of a face, see [SubdivGeometry::face_half_edges].
+pub struct FaceHalfEdges<'g> {
+    geometry: &'g SubdivGeometry,
+    first: u32,
+    next: Option<u32>,
+}
+
+impl Iterator for FaceHalfEdges<'_> {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<u32> {
+        let edge = self.next?;
+        let next = self.geometry.next_half_edge(edge);
+        self.next = (next != self.first).then_some(next);
+        Some(edge)
+    }
+}
+
+impl Drop for SubdivGeometry {
+    fn drop(&mut self) {
+        unsafe {
+            embree4_sys::rtcReleaseGeometry(self.handle);
+        }
+    }
+}
+
+impl Geometry for SubdivGeometry {
+    fn geometry(&self) -> embree4_sys::RTCGeometry {
+        self.handle
+    }
+
+    fn enabled_flag(&self) -> Option<&Cell<bool>> {
+        Some(&self.enabled)
+    }
+
+    fn kind(&self) -> embree4_sys::RTCGeometryType {
+        embree4_sys::RTCGeometryType::SUBDIVISION
+    }
+
+    fn time_step_count(&self) -> u32 {
+        1
+    }
+
+    fn primitive_count(&self) -> usize {
+        self.face_count
+    }
+
+    /// The limit surface lies within the convex hull of the control vertices, so their bounds
+    /// enclose it.
+    fn bounds(&self) -> Option<Aabb> {
+        vertex_buffer_bounds(self.handle, 1, self.vertex_count, 3)
+    }
+
+    fn validation(&self) -> Option<Validation> {
+        Some(Validation::new(
+            self.handle,
+            Layout::Subdivision {
+                vertex_count: self.vertex_count,
+                face_count: self.face_count,
+            },
+        ))
+    }
+}
+
+#[test]
+fn quad_half_edge_cycle() {
+    let device = Device::try_new(None).unwrap();
+    let vertices = [
+        (0.0, 0.0, 0.0),
+        (1.0, 0.0, 0.0),
+        (1.0, 1.0, 0.0),
+        (0.0, 1.0, 0.0),
+    ];
+    let geometry = SubdivGeometry::try_new(&device, &vertices, &[4], &[0, 1, 2, 3]).unwrap();
+
+    let edges: Vec<_> = geometry.face_half_edges(0).unwrap().collect();
+    assert_eq!(edges.len(), 4);
+    assert!(edges.iter().all(|&e| geometry.half_edge_face(e) == 0));
+    assert_eq!(geometry.next_half_edge(edges[3]), edges[0]);
+    assert_eq!(geometry.previous_half_edge(edges[0]), edges[3]);
+    assert!(geometry.face_half_edges(1).is_none());
+}
+
+#[test]
+fn closer_geometry_is_tessellated_more() {
+    let device = Device::try_new(None).unwrap();
+    let vertices = [
+        (0.0, 0.0, 0.0),
+        (1.0, 0.0, 0.0),
+        (1.0, 1.0, 0.0),
+        (0.0, 1.0, 0.0),
+    ];
+    let geometry = SubdivGeometry::try_new(&device, &vertices, &[4], &[0, 1, 2, 3]).unwrap();
+
+    let near = geometry
+        .set_tessellation_from_distance((0.5, 0.5, -2.0))
+        .unwrap();
+    let far = geometry
+        .set_tessellation_from_distance((0.5, 0.5, -50.0))
+        .unwrap();
+    assert!(near > far);
+    assert_eq!(
+        geometry
+            .set_tessellation_from_distance((0.5, 0.5, -1e6))
+            .unwrap(),
+        MIN_TESSELLATION_RATE
+    );
+}
+
+#[test]
+fn higher_tessellation_rate_yields_more_quads() {
+    let device = Device::try_new(None).unwrap();
+    // A quad and a triangle sharing an edge
+    let vertices = [
+        (0.0, 0.0, 0.0),
+        (1.0, 0.0, 0.0),
+        (1.0, 1.0, 0.0),
+        (0.0, 1.0, 0.0),
+        (2.0, 0.5, 0.0),
+    ];
+    let geometry =
+        SubdivGeometry::try_new(&device, &vertices, &[4, 3], &[0, 1, 2, 3, 1, 4, 2]).unwrap();
+    assert_eq!(geometry.tessellation_rate(), 2.0);
+    assert_eq!(geometry.estimated_quad_count(), 4 + 3);
+
+    let mut previous = geometry.estimated_quad_count();
+    for rate in [4.0, 8.0, 16.0] {
+        geometry.set_tessellation_rate(rate).unwrap();
+        assert_eq!(geometry.tessellation_rate(), rate);
+        let estimate = geometry.estimated_quad_count();
+        assert!(estimate > previous);
+        previous = estimate;
+    }
+    assert_eq!(previous, 16 * 16 + 3 * 8 * 8);
+}
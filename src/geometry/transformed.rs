@@ -0,0 +1,129 @@
+use std::cell::Cell;
+
+use anyhow::Result;
+
+use crate::{
+    device::Device,
+    scene::{Scene, SceneOptions},
+};
+
+use super::{Geometry, InstanceGeometry};
+
+/// A geometry placed in the world with a transform.
+///
+/// Embree only transforms geometries through instances: this wraps the geometry in a scene of its
+/// own, instanced once with the given transform. Hits on it report the instance geometry ID in
+/// `instID[0]`, while `geomID` and `primID` refer to the wrapped geometry.
+pub struct TransformedGeometry<G: Geometry> {
+    geometry: G,
+    instance: InstanceGeometry,
+}
+
+impl<G: Geometry> TransformedGeometry<G> {
+    /// Constructs a new `TransformedGeometry` placing `geometry` with the given transform.
+    ///
+    /// # Arguments
+    /// * `device` - A reference to the `Device` instance.
+    /// * `geometry` - The geometry to place.
+    /// * `transform` - The local to world transform as a column-major 3x4 matrix, see
+    ///   [InstanceGeometry::try_new].
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::prelude::*;
+    ///
+    /// let device = Device::try_new(None).unwrap();
+    /// let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 0.0), 1.0).unwrap();
+    ///
+    /// let translate_x = [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 5.0, 0.0, 0.0];
+    /// let sphere = TransformedGeometry::try_new(&device, sphere, &translate_x).unwrap();
+    /// let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    /// scene.attach_geometry(&sphere).unwrap();
+    /// ```
+    pub fn try_new(device: &Device, geometry: G, transform: &[f32; 12]) -> Result<Self> {
+        let scene = Scene::try_new(device, SceneOptions::default())?;
+        scene.attach_geometry(&geometry)?;
+        let scene = scene.commit()?;
+
+        // The instance keeps a reference to the scene, which can be released here
+        let instance = InstanceGeometry::try_new(device, &scene, transform)?;
+
+        Ok(Self { geometry, instance })
+    }
+
+    /// Sets the local to world transform and recommits the geometry.
+    ///
+    /// # Arguments
+    /// * `transform` - The transform as a column-major 3x4 matrix.
+    pub fn set_transform(&self, transform: &[f32; 12]) -> Result<()> {
+        self.instance
+            .set_transform(embree4_sys::RTCFormat::FLOAT3X4_COLUMN_MAJOR, transform)
+    }
+
+    /// Returns the wrapped geometry.
+    pub fn inner(&self) -> &G {
+        &self.geometry
+    }
+}
+
+impl<G: Geometry> Geometry for TransformedGeometry<G> {
+    fn geometry(&self) -> embree4_sys::RTCGeometry {
+        self.instance.geometry()
+    }
+
+    fn kind(&self) -> embree4_sys::RTCGeometryType {
+        embree4_sys::RTCGeometryType::INSTANCE
+    }
+
+    fn time_step_count(&self) -> u32 {
+        self.instance.time_step_count()
+    }
+
+    fn primitive_count(&self) -> usize {
+        self.geometry.primitive_count()
+    }
+
+    fn is_committed(&self) -> bool {
+        self.instance.is_committed()
+    }
+
+    fn enabled_flag(&self) -> Option<&Cell<bool>> {
+        self.instance.enabled_flag()
+    }
+
+    fn instance_depth(&self) -> u32 {
+        self.instance.instance_depth()
+    }
+
+    fn instance_link(&self) -> Option<super::InstanceLink> {
+        self.instance.instance_link()
+    }
+
+    fn shared_data(&self) -> Option<super::SharedData> {
+        self.geometry.shared_data()
+    }
+}
+
+#[test]
+fn translated_triangle_mesh() {
+    use crate::{geometry::TriangleMeshGeometry, ray::RayBuilder};
+
+    let device = Device::try_new(None).unwrap();
+    let vertices = [(-1.0, -1.0, 0.0), (1.0, -1.0, 0.0), (0.0, 1.0, 0.0)];
+    let mesh = TriangleMeshGeometry::try_new(&device, &vertices, &[(0, 1, 2)]).unwrap();
+
+    let translate_x = [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 5.0, 0.0, 0.0];
+    let mesh = TransformedGeometry::try_new(&device, mesh, &translate_x).unwrap();
+    assert_eq!(mesh.primitive_count(), 1);
+
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    let inst_id = scene.attach_geometry(&mesh).unwrap();
+    let scene = scene.commit().unwrap();
+
+    let ray_at = |x| RayBuilder::new((x, 0.0, -5.0), (0.0, 0.0, 1.0)).build();
+    assert!(scene.intersect_1(ray_at(0.0)).unwrap().is_none());
+
+    let hit = scene.intersect_1(ray_at(5.0)).unwrap().unwrap();
+    assert!((hit.ray.tfar - 5.0).abs() < 1e-4);
+    assert_eq!(hit.hit.instID[0], inst_id);
+}
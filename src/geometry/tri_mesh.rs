@@ -1,13 +1,41 @@
-use std::{mem::size_of, slice};
+use std::{
+    cell::Cell,
+    mem::size_of,
+    ptr::null_mut,
+    slice,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+};
 
 use anyhow::{bail, Result};
+use embree4_sys::RTCFormat;
 
-use crate::{device::Device, device_error_or};
+use crate::{
+    aabb::Aabb, buffer::AlignedVec, device::Device, device_error_or, device_error_raw,
+    device_handle_error_or,
+};
 
-use super::Geometry;
+use super::{
+    new_geometry_buffer, validation::Layout, vertex_buffer_bounds, Geometry, SharedData, Validation,
+};
+
+/// An oriented bounding box: its center, its orthonormal axes and its half extents along them.
+pub type Obb = ([f32; 3], [[f32; 3]; 3], [f32; 3]);
 
 pub struct TriangleMeshGeometry {
     handle: embree4_sys::RTCGeometry,
+    enabled: Cell<bool>,
+    device: embree4_sys::RTCDevice,
+    vertex_count: usize,
+    primitive_count: usize,
+    // Shared with the validations, which read it when they run
+    time_step_count: Arc<AtomicU32>,
+    committed: Cell<bool>,
+    vertex_attribute_components: Vec<Option<usize>>,
+    // Vertex and index data Embree reads in place, for meshes built with `try_new_shared`
+    shared_buffers: Option<Arc<(AlignedVec<f32>, AlignedVec<u32>)>>,
 }
 
 impl TriangleMeshGeometry {
@@ -36,75 +64,708 @@ impl TriangleMeshGeometry {
         vertices: &[(f32, f32, f32)],
         indices: &[(u32, u32, u32)],
     ) -> Result<Self> {
-        let geometry = unsafe {
-            embree4_sys::rtcNewGeometry(device.handle, embree4_sys::RTCGeometryType::TRIANGLE)
-        };
-        if geometry.is_null() {
-            bail!("Failed to create geometry: {:?}", device.error());
+        Self::try_new_with_indices(device, vertices, indices)
+    }
+
+    /// Constructs a new `TriangleMeshGeometry` instance from 16-bit indices, for meshes of up to
+    /// 65536 vertices.
+    ///
+    /// Embree only accepts 32-bit indices, so the indices are widened while being copied into the
+    /// geometry's index buffer. This saves memory on the caller's side only.
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::prelude::*;
+    ///
+    /// let vertices = [(0.0, 0.0, 0.0), (1.0, 0.0, 0.0), (0.0, 1.0, 0.0)];
+    /// let indices: [(u16, u16, u16); 1] = [(0, 1, 2)];
+    ///
+    /// let device = Device::try_new(None).unwrap();
+    /// let geometry = TriangleMeshGeometry::try_new_u16(&device, &vertices, &indices).unwrap();
+    /// ```
+    pub fn try_new_u16(
+        device: &Device,
+        vertices: &[(f32, f32, f32)],
+        indices: &[(u16, u16, u16)],
+    ) -> Result<Self> {
+        Self::try_new_with_indices(device, vertices, indices)
+    }
+
+    /// Constructs a new `TriangleMeshGeometry` instance with the given per-vertex normals, set
+    /// as a `FLOAT3` vertex attribute at slot 0.
+    ///
+    /// Smooth shading normals are then obtained with [Self::interpolate] on slot 0. The normals
+    /// are stored as given, so they should be normalized, and interpolated normals have to be
+    /// normalized again.
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::prelude::*;
+    ///
+    /// let vertices = [(0.0, 0.0, 0.0), (1.0, 0.0, 0.0), (0.0, 1.0, 0.0)];
+    /// let normals = [(0.0, 0.0, 1.0); 3];
+    ///
+    /// let device = Device::try_new(None).unwrap();
+    /// let geometry =
+    ///     TriangleMeshGeometry::try_new_with_normals(&device, &vertices, &[(0, 1, 2)], &normals)
+    ///         .unwrap();
+    /// let normal = geometry.interpolate(0, 0, 0.25, 0.5).unwrap();
+    /// assert_eq!(normal, vec![0.0, 0.0, 1.0]);
+    /// ```
+    pub fn try_new_with_normals(
+        device: &Device,
+        vertices: &[(f32, f32, f32)],
+        indices: &[(u32, u32, u32)],
+        normals: &[(f32, f32, f32)],
+    ) -> Result<Self> {
+        if normals.len() != vertices.len() {
+            bail!(
+                "Triangle mesh has {} vertices, got {} normals",
+                vertices.len(),
+                normals.len()
+            );
         }
 
-        let vertex_buf_ptr = unsafe {
-            embree4_sys::rtcSetNewGeometryBuffer(
+        let mut mesh = Self::try_new(device, vertices, indices)?;
+        let normals = normals
+            .iter()
+            .flat_map(|&(x, y, z)| [x, y, z])
+            .collect::<Vec<_>>();
+        mesh.set_vertex_attribute(0, RTCFormat::FLOAT3, &normals)?;
+        Ok(mesh)
+    }
+
+    fn try_new_with_indices<I: Copy + Into<u32>>(
+        device: &Device,
+        vertices: &[(f32, f32, f32)],
+        indices: &[(I, I, I)],
+    ) -> Result<Self> {
+        let raw = device.new_geometry(embree4_sys::RTCGeometryType::TRIANGLE)?;
+        let geometry = raw.handle();
+
+        let vertex_buf_ptr = new_geometry_buffer(
+            device.handle,
+            geometry,
+            embree4_sys::RTCBufferType::VERTEX,
+            0,
+            embree4_sys::RTCFormat::FLOAT3,
+            3 * size_of::<f32>(),
+            vertices.len(),
+        )?;
+
+        let vertex_buf =
+            unsafe { slice::from_raw_parts_mut(vertex_buf_ptr as *mut f32, 3 * vertices.len()) };
+
+        // copy vertices into buffer
+        for (i, v) in vertices.iter().enumerate() {
+            vertex_buf[3 * i] = v.0;
+            vertex_buf[3 * i + 1] = v.1;
+            vertex_buf[3 * i + 2] = v.2;
+        }
+
+        let index_buf_ptr = new_geometry_buffer(
+            device.handle,
+            geometry,
+            embree4_sys::RTCBufferType::INDEX,
+            0,
+            embree4_sys::RTCFormat::UINT3,
+            3 * size_of::<u32>(),
+            indices.len(),
+        )?;
+
+        let index_buf =
+            unsafe { slice::from_raw_parts_mut(index_buf_ptr as *mut u32, 3 * indices.len()) };
+
+        // copy indices into buffer
+        for (i, idx) in indices.iter().enumerate() {
+            index_buf[3 * i] = idx.0.into();
+            index_buf[3 * i + 1] = idx.1.into();
+            index_buf[3 * i + 2] = idx.2.into();
+        }
+
+        unsafe {
+            embree4_sys::rtcCommitGeometry(geometry);
+        }
+        device_error_or(device, (), "Failed to commit triangle mesh geometry")?;
+
+        Ok(Self {
+            handle: raw.into_raw(),
+            enabled: Cell::new(true),
+            device: device.handle,
+            vertex_count: vertices.len(),
+            primitive_count: indices.len(),
+            time_step_count: Arc::new(AtomicU32::new(1)),
+            committed: Cell::new(true),
+            vertex_attribute_components: Vec::new(),
+            shared_buffers: None,
+        })
+    }
+
+    /// Constructs a new `TriangleMeshGeometry` instance that reads its vertices and indices in
+    /// place, without copying them into buffers owned by Embree.
+    ///
+    /// Embree requires shared buffers to be 16-byte aligned and padded, which `AlignedVec`
+    /// guarantees. Data that is not aligned, e.g. a sub-slice of a larger array, is copied once
+    /// into an aligned buffer by `AlignedVec::from_slice`; `AlignedVec::is_aligned` tells whether
+    /// the data could have been used as is.
+    ///
+    /// The buffers are shared with the scenes the geometry is attached to, which keep them alive
+    /// for as long as they need them, see [Geometry::shared_data].
+    ///
+    /// # Arguments
+    /// * `vertices` - The vertex positions, 3 floats per vertex.
+    /// * `indices` - The vertex indices, 3 per triangle.
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::{buffer::AlignedVec, prelude::*};
+    ///
+    /// let vertices = [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0];
+    /// let indices = [0, 1, 2];
+    ///
+    /// let device = Device::try_new(None).unwrap();
+    /// let geometry = TriangleMeshGeometry::try_new_shared(
+    ///     &device,
+    ///     AlignedVec::from_slice(&vertices),
+    ///     AlignedVec::from_slice(&indices),
+    /// )
+    /// .unwrap();
+    /// ```
+    pub fn try_new_shared(
+        device: &Device,
+        vertices: AlignedVec<f32>,
+        indices: AlignedVec<u32>,
+    ) -> Result<Self> {
+        if !vertices.len().is_multiple_of(3) || !indices.len().is_multiple_of(3) {
+            bail!(
+                "Shared triangle mesh buffers must hold 3 values per item, got {} vertex and {} index values",
+                vertices.len(),
+                indices.len()
+            );
+        }
+        let (vertex_count, primitive_count) = (vertices.len() / 3, indices.len() / 3);
+
+        let raw = device.new_geometry(embree4_sys::RTCGeometryType::TRIANGLE)?;
+        let geometry = raw.handle();
+        unsafe {
+            embree4_sys::rtcSetSharedGeometryBuffer(
                 geometry,
                 embree4_sys::RTCBufferType::VERTEX,
                 0,
                 embree4_sys::RTCFormat::FLOAT3,
+                vertices.as_ptr() as *const _,
+                0,
                 3 * size_of::<f32>(),
-                vertices.len(),
+                vertex_count,
+            );
+            embree4_sys::rtcSetSharedGeometryBuffer(
+                geometry,
+                embree4_sys::RTCBufferType::INDEX,
+                0,
+                embree4_sys::RTCFormat::UINT3,
+                indices.as_ptr() as *const _,
+                0,
+                3 * size_of::<u32>(),
+                primitive_count,
+            );
+            embree4_sys::rtcCommitGeometry(geometry);
+        }
+
+        // Owned from here on, so the geometry is released on error
+        let mesh = Self {
+            handle: raw.into_raw(),
+            enabled: Cell::new(true),
+            device: device.handle,
+            vertex_count,
+            primitive_count,
+            time_step_count: Arc::new(AtomicU32::new(1)),
+            committed: Cell::new(true),
+            vertex_attribute_components: Vec::new(),
+            shared_buffers: Some(Arc::new((vertices, indices))),
+        };
+        device_error_or(
+            device,
+            mesh,
+            "Failed to commit shared triangle mesh geometry",
+        )
+    }
+
+    /// Overwrites the vertex positions and recommits the geometry.
+    ///
+    /// The topology is unchanged, so the number of vertices must match the one the geometry was
+    /// created with.
+    pub fn set_vertices(&self, vertices: &[(f32, f32, f32)]) -> Result<()> {
+        if vertices.len() != self.vertex_count {
+            bail!(
+                "Triangle mesh has {} vertices, got {}",
+                self.vertex_count,
+                vertices.len()
+            );
+        }
+
+        let vertex_buf_ptr = unsafe {
+            embree4_sys::rtcGetGeometryBufferData(
+                self.handle,
+                embree4_sys::RTCBufferType::VERTEX,
+                0,
             )
         };
         if vertex_buf_ptr.is_null() {
             bail!(
-                "Failed to create triangle mesh vertex buffer: {:?}",
-                device.error()
+                "Failed to get triangle mesh vertex buffer: {:?}",
+                device_error_raw(self.device)
             );
         }
-        device_error_or(device, (), "Failed not create triangle mesh vertex buffer")?;
 
         let vertex_buf =
             unsafe { slice::from_raw_parts_mut(vertex_buf_ptr as *mut f32, 3 * vertices.len()) };
+        for (i, v) in vertices.iter().enumerate() {
+            vertex_buf[3 * i] = v.0;
+            vertex_buf[3 * i + 1] = v.1;
+            vertex_buf[3 * i + 2] = v.2;
+        }
 
-        // copy vertices into buffer
+        self.committed.set(false);
+        unsafe {
+            embree4_sys::rtcUpdateGeometryBuffer(
+                self.handle,
+                embree4_sys::RTCBufferType::VERTEX,
+                0,
+            );
+        }
+        self.commit()
+    }
+
+    /// Sets the vertex positions of each time step for motion blur and recommits the geometry.
+    ///
+    /// The time steps are evenly distributed over the `[0, 1]` time range of the ray.
+    ///
+    /// # Arguments
+    /// * `time_steps` - The vertices of each time step. Each time step must have as many vertices as
+    ///   the geometry was created with.
+    pub fn set_time_steps(&self, time_steps: &[&[(f32, f32, f32)]]) -> Result<()> {
+        if time_steps.is_empty() {
+            bail!("At least one time step is required");
+        }
+        if let Some(step) = time_steps.iter().find(|s| s.len() != self.vertex_count) {
+            bail!(
+                "Triangle mesh time step has {} vertices, expected {}",
+                step.len(),
+                self.vertex_count
+            );
+        }
+
+        Geometry::set_time_step_count(self, time_steps.len() as u32)?;
+        for (slot, vertices) in time_steps.iter().enumerate() {
+            self.set_vertex_buffer(slot as u32, vertices)?;
+        }
+
+        self.commit()
+    }
+
+    /// Sets the vertex positions of a single time step and recommits the geometry.
+    ///
+    /// The number of time steps must have been set beforehand with
+    /// [Geometry::set_time_step_count].
+    ///
+    /// # Arguments
+    /// * `slot` - The time step, below the number of time steps.
+    /// * `vertices` - The vertices of the time step, as many as the geometry was created with.
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::{geometry::*, prelude::*};
+    ///
+    /// let start = [(0.0, 0.0, 0.0), (1.0, 0.0, 0.0), (0.0, 1.0, 0.0)];
+    /// let end = start.map(|(x, y, z)| (x, y, z + 1.0));
+    ///
+    /// let device = Device::try_new(None).unwrap();
+    /// let mesh = TriangleMeshGeometry::try_new(&device, &start, &[(0, 1, 2)]).unwrap();
+    /// mesh.set_time_step_count(2).unwrap();
+    /// mesh.set_time_step(1, &end).unwrap();
+    /// ```
+    pub fn set_time_step(&self, slot: u32, vertices: &[(f32, f32, f32)]) -> Result<()> {
+        if slot >= self.time_step_count() {
+            bail!(
+                "Time step {} is out of range, the triangle mesh has {} time steps",
+                slot,
+                self.time_step_count()
+            );
+        }
+        if vertices.len() != self.vertex_count {
+            bail!(
+                "Triangle mesh time step has {} vertices, expected {}",
+                vertices.len(),
+                self.vertex_count
+            );
+        }
+
+        self.set_vertex_buffer(slot, vertices)?;
+
+        self.commit()
+    }
+
+    fn set_vertex_buffer(&self, slot: u32, vertices: &[(f32, f32, f32)]) -> Result<()> {
+        self.committed.set(false);
+        let vertex_buf_ptr = new_geometry_buffer(
+            self.device,
+            self.handle,
+            embree4_sys::RTCBufferType::VERTEX,
+            slot,
+            embree4_sys::RTCFormat::FLOAT3,
+            3 * size_of::<f32>(),
+            vertices.len(),
+        )?;
+
+        let vertex_buf =
+            unsafe { slice::from_raw_parts_mut(vertex_buf_ptr as *mut f32, 3 * vertices.len()) };
         for (i, v) in vertices.iter().enumerate() {
             vertex_buf[3 * i] = v.0;
             vertex_buf[3 * i + 1] = v.1;
             vertex_buf[3 * i + 2] = v.2;
         }
 
+        Ok(())
+    }
+
+    fn commit(&self) -> Result<()> {
+        unsafe {
+            embree4_sys::rtcCommitGeometry(self.handle);
+        }
+        device_handle_error_or(self.device, (), "Failed to commit triangle mesh geometry")?;
+        self.committed.set(true);
+        Ok(())
+    }
+
+    /// Sets the vertex attribute buffer at the given slot and recommits the geometry.
+    ///
+    /// # Arguments
+    /// * `slot` - The vertex attribute slot. The vertex attribute count is grown as needed.
+    /// * `format` - One of `FLOAT`, `FLOAT2`, `FLOAT3` or `FLOAT4`.
+    /// * `data` - The attribute values, tightly packed, one item per vertex.
+    ///
+    /// # Returns
+    /// A `Result` indicating success or failure.
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::{geometry::*, prelude::*};
+    /// use embree4_sys::*;
+    ///
+    /// let vertices = [(0.0, 0.0, 0.0), (1.0, 0.0, 0.0), (0.0, 1.0, 0.0)];
+    /// let uvs = [0.0, 0.0, 1.0, 0.0, 0.0, 1.0];
+    ///
+    /// let device = Device::try_new(None).unwrap();
+    /// let mut geometry = TriangleMeshGeometry::try_new(&device, &vertices, &[(0, 1, 2)]).unwrap();
+    /// geometry.set_vertex_attribute(0, RTCFormat::FLOAT2, &uvs).unwrap();
+    /// let uv = geometry.interpolate(0, 0, 0.25, 0.5).unwrap();
+    /// assert_eq!(uv.len(), 2);
+    /// ```
+    pub fn set_vertex_attribute(
+        &mut self,
+        slot: u32,
+        format: RTCFormat,
+        data: &[f32],
+    ) -> Result<()> {
+        let Some(components) = float_format_components(format) else {
+            bail!("Unsupported vertex attribute format: {:?}", format);
+        };
+        if data.len() != components * self.vertex_count {
+            bail!(
+                "Vertex attribute data has {} floats, expected {} ({} per vertex for {:?})",
+                data.len(),
+                components * self.vertex_count,
+                components,
+                format
+            );
+        }
+
+        let slot_index = slot as usize;
+        if slot_index >= self.vertex_attribute_components.len() {
+            unsafe {
+                embree4_sys::rtcSetGeometryVertexAttributeCount(self.handle, slot + 1);
+            }
+            device_handle_error_or(self.device, (), "Could not set vertex attribute count")?;
+            self.vertex_attribute_components
+                .resize(slot_index + 1, None);
+        }
+
+        self.committed.set(false);
+        let buf_ptr = new_geometry_buffer(
+            self.device,
+            self.handle,
+            embree4_sys::RTCBufferType::VERTEX_ATTRIBUTE,
+            slot,
+            format,
+            components * size_of::<f32>(),
+            self.vertex_count,
+        )?;
+
+        let buf = unsafe { slice::from_raw_parts_mut(buf_ptr as *mut f32, data.len()) };
+        buf.copy_from_slice(data);
+        self.vertex_attribute_components[slot_index] = Some(components);
+
+        self.commit()
+    }
+
+    /// Interpolates the vertex attribute at the given slot over a triangle.
+    ///
+    /// # Arguments
+    /// * `slot` - The vertex attribute slot, as set by [Self::set_vertex_attribute].
+    /// * `prim_id` - The index of the triangle.
+    /// * `u`, `v` - The barycentric coordinates, as reported in a hit.
+    ///
+    /// # Returns
+    /// A `Result` containing one value per component of the attribute format, i.e. exactly 2
+    /// values for a `FLOAT2` attribute.
+    pub fn interpolate(&self, slot: u32, prim_id: u32, u: f32, v: f32) -> Result<Vec<f32>> {
+        let Some(&Some(components)) = self.vertex_attribute_components.get(slot as usize) else {
+            bail!("Vertex attribute slot {} is not set", slot);
+        };
+
+        let mut values = vec![0.0; components];
+        let args = embree4_sys::RTCInterpolateArguments {
+            geometry: self.handle,
+            primID: prim_id,
+            u,
+            v,
+            bufferType: embree4_sys::RTCBufferType::VERTEX_ATTRIBUTE,
+            bufferSlot: slot,
+            P: values.as_mut_ptr(),
+            dPdu: null_mut(),
+            dPdv: null_mut(),
+            ddPdudu: null_mut(),
+            ddPdvdv: null_mut(),
+            ddPdudv: null_mut(),
+            valueCount: components as u32,
+        };
+        unsafe {
+            embree4_sys::rtcInterpolate(&args);
+        }
+        device_handle_error_or(
+            self.device,
+            values,
+            "Could not interpolate vertex attribute",
+        )
+    }
+
+    /// Computes smooth per-vertex normals and sets them as a `FLOAT3` vertex attribute at the
+    /// given slot, for shading with [Self::interpolate].
+    ///
+    /// The normal of each vertex is the sum of the normals of the triangles around it, weighted by
+    /// their area, normalized. Triangles are assumed to be wound counter-clockwise when seen from
+    /// the front. Degenerate triangles are skipped, and vertices without any other triangle get a
+    /// zero normal.
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::prelude::*;
+    ///
+    /// let vertices = [(0.0, 0.0, 0.0), (1.0, 0.0, 0.0), (0.0, 1.0, 0.0)];
+    ///
+    /// let device = Device::try_new(None).unwrap();
+    /// let mut geometry = TriangleMeshGeometry::try_new(&device, &vertices, &[(0, 1, 2)]).unwrap();
+    /// geometry.compute_normals(0).unwrap();
+    /// let normal = geometry.interpolate(0, 0, 0.25, 0.5).unwrap();
+    /// assert_eq!(normal, vec![0.0, 0.0, 1.0]);
+    /// ```
+    pub fn compute_normals(&mut self, slot: u32) -> Result<()> {
+        let vertex_buf_ptr = unsafe {
+            embree4_sys::rtcGetGeometryBufferData(
+                self.handle,
+                embree4_sys::RTCBufferType::VERTEX,
+                0,
+            )
+        };
         let index_buf_ptr = unsafe {
-            embree4_sys::rtcSetNewGeometryBuffer(
-                geometry,
-                embree4_sys::RTCBufferType::INDEX,
+            embree4_sys::rtcGetGeometryBufferData(self.handle, embree4_sys::RTCBufferType::INDEX, 0)
+        };
+        if vertex_buf_ptr.is_null() || index_buf_ptr.is_null() {
+            bail!("Triangle mesh has no vertex or index buffer");
+        }
+
+        let vertices =
+            unsafe { slice::from_raw_parts(vertex_buf_ptr as *const f32, 3 * self.vertex_count) };
+        let indices =
+            unsafe { slice::from_raw_parts(index_buf_ptr as *const u32, 3 * self.primitive_count) };
+
+        let mut normals = vec![0.0f32; 3 * self.vertex_count];
+        for triangle in indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|k| triangle[k] as usize);
+            if [a, b, c].iter().any(|&i| i >= self.vertex_count) {
+                bail!("Triangle mesh index out of range: {:?}", triangle);
+            }
+
+            let p = |i: usize| [vertices[3 * i], vertices[3 * i + 1], vertices[3 * i + 2]];
+            let (pa, pb, pc) = (p(a), p(b), p(c));
+            let e1 = [0, 1, 2].map(|k| pb[k] - pa[k]);
+            let e2 = [0, 1, 2].map(|k| pc[k] - pa[k]);
+
+            // The cross product is twice the area of the triangle
+            let face_normal = [
+                e1[1] * e2[2] - e1[2] * e2[1],
+                e1[2] * e2[0] - e1[0] * e2[2],
+                e1[0] * e2[1] - e1[1] * e2[0],
+            ];
+            if !face_normal.iter().all(|x| x.is_finite()) || face_normal == [0.0; 3] {
+                continue;
+            }
+
+            for i in [a, b, c] {
+                for k in 0..3 {
+                    normals[3 * i + k] += face_normal[k];
+                }
+            }
+        }
+
+        for normal in normals.chunks_exact_mut(3) {
+            let len = normal.iter().map(|x| x * x).sum::<f32>().sqrt();
+            if len > 0.0 {
+                normal.iter_mut().for_each(|x| *x /= len);
+            }
+        }
+
+        self.set_vertex_attribute(slot, RTCFormat::FLOAT3, &normals)
+    }
+
+    /// Fits an oriented bounding box to the vertices of the first time step, whose axes are the
+    /// principal components of the vertices.
+    ///
+    /// This is tighter than [Geometry::bounds] for elongated meshes that are not aligned with the
+    /// coordinate axes. Planar and collinear vertex sets get a zero extent along the missing
+    /// dimensions.
+    ///
+    /// # Returns
+    /// The center of the box, its orthonormal axes sorted from the one of largest variance to the
+    /// one of smallest variance, and its half extents along them, or `None` if the mesh has no
+    /// vertex.
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::prelude::*;
+    ///
+    /// let vertices = [(0.0, 0.0, 0.0), (4.0, 4.0, 0.0), (4.0, 4.0, 1.0), (0.0, 0.0, 1.0)];
+    /// let indices = [(0, 1, 2), (2, 3, 0)];
+    ///
+    /// let device = Device::try_new(None).unwrap();
+    /// let geometry = TriangleMeshGeometry::try_new(&device, &vertices, &indices).unwrap();
+    /// let (_, _, extents) = geometry.obb().unwrap();
+    /// assert!((extents[0] - 8f32.sqrt()).abs() < 1e-4);
+    /// assert!(extents[2].abs() < 1e-4);
+    /// ```
+    pub fn obb(&self) -> Option<Obb> {
+        let vertex_buf_ptr = unsafe {
+            embree4_sys::rtcGetGeometryBufferData(
+                self.handle,
+                embree4_sys::RTCBufferType::VERTEX,
                 0,
-                embree4_sys::RTCFormat::UINT3,
-                3 * size_of::<u32>(),
-                indices.len(),
             )
         };
-        if index_buf_ptr.is_null() {
-            bail!(
-                "Failed to create triangle mesh index buffer: {:?}",
-                device.error()
-            );
+        if vertex_buf_ptr.is_null() || self.vertex_count == 0 {
+            return None;
         }
-        device_error_or(device, (), "Failed to create triangle mesh index buffer")?;
 
-        let index_buf =
-            unsafe { slice::from_raw_parts_mut(index_buf_ptr as *mut u32, 3 * indices.len()) };
+        let vertices =
+            unsafe { slice::from_raw_parts(vertex_buf_ptr as *const [f32; 3], self.vertex_count) };
+        Some(fit_obb(vertices))
+    }
+}
 
-        // copy indices into buffer
-        for (i, idx) in indices.iter().enumerate() {
-            index_buf[3 * i] = idx.0;
-            index_buf[3 * i + 1] = idx.1;
-            index_buf[3 * i + 2] = idx.2;
+/// Fits an oriented bounding box to a non-empty set of points, see
+/// [TriangleMeshGeometry::obb].
+fn fit_obb(points: &[[f32; 3]]) -> Obb {
+    let n = points.len() as f64;
+    let mean = [0, 1, 2].map(|k| points.iter().map(|p| p[k] as f64).sum::<f64>() / n);
+
+    let mut covariance = [[0.0f64; 3]; 3];
+    for p in points {
+        let d = [0, 1, 2].map(|k| p[k] as f64 - mean[k]);
+        for i in 0..3 {
+            for j in 0..3 {
+                covariance[i][j] += d[i] * d[j] / n;
+            }
         }
+    }
 
-        unsafe {
-            embree4_sys::rtcCommitGeometry(geometry);
+    let (eigenvalues, eigenvectors) = symmetric_eigen(covariance);
+    let mut order = [0, 1, 2];
+    order.sort_by(|&a, &b| eigenvalues[b].total_cmp(&eigenvalues[a]));
+    let axis = |i: usize| [0, 1, 2].map(|k| eigenvectors[k][order[i]]);
+    let (a0, a1) = (axis(0), axis(1));
+    // Right-handed, and orthogonal even when eigenvalues are repeated
+    let a2 = [
+        a0[1] * a1[2] - a0[2] * a1[1],
+        a0[2] * a1[0] - a0[0] * a1[2],
+        a0[0] * a1[1] - a0[1] * a1[0],
+    ];
+    let axes = [a0, a1, a2];
+
+    let mut lower = [f64::INFINITY; 3];
+    let mut upper = [f64::NEG_INFINITY; 3];
+    for p in points {
+        let d = [0, 1, 2].map(|k| p[k] as f64 - mean[k]);
+        for (i, axis) in axes.iter().enumerate() {
+            let t = (0..3).map(|k| d[k] * axis[k]).sum::<f64>();
+            lower[i] = lower[i].min(t);
+            upper[i] = upper[i].max(t);
         }
-        device_error_or(device, (), "Failed to commit triangle mesh geometry")?;
+    }
+
+    let mid = [0, 1, 2].map(|i| 0.5 * (lower[i] + upper[i]));
+    let center =
+        [0, 1, 2].map(|k| (mean[k] + (0..3).map(|i| mid[i] * axes[i][k]).sum::<f64>()) as f32);
+    let extents = [0, 1, 2].map(|i| (0.5 * (upper[i] - lower[i])) as f32);
+    (center, axes.map(|a| a.map(|x| x as f32)), extents)
+}
+
+/// Diagonalizes a symmetric matrix with cyclic Jacobi rotations.
+///
+/// Returns the eigenvalues, and the matrix whose columns are the matching orthonormal
+/// eigenvectors.
+fn symmetric_eigen(mut a: [[f64; 3]; 3]) -> ([f64; 3], [[f64; 3]; 3]) {
+    let mut v = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+    for _ in 0..32 {
+        let off_diagonal = a[0][1].abs() + a[0][2].abs() + a[1][2].abs();
+        if off_diagonal <= f64::EPSILON * (a[0][0].abs() + a[1][1].abs() + a[2][2].abs()) {
+            break;
+        }
+
+        for (p, q) in [(0, 1), (0, 2), (1, 2)] {
+            if a[p][q] == 0.0 {
+                continue;
+            }
+            let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+            let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+            let c = 1.0 / (t * t + 1.0).sqrt();
+            let s = t * c;
 
-        Ok(Self { handle: geometry })
+            // a = Jᵀ a J, v = v J with the rotation J in the (p, q) plane
+            for row in &mut a {
+                let (ap, aq) = (row[p], row[q]);
+                row[p] = c * ap - s * aq;
+                row[q] = s * ap + c * aq;
+            }
+            let (ap, aq) = (a[p], a[q]);
+            a[p] = [0, 1, 2].map(|k| c * ap[k] - s * aq[k]);
+            a[q] = [0, 1, 2].map(|k| s * ap[k] + c * aq[k]);
+            for row in &mut v {
+                let (vp, vq) = (row[p], row[q]);
+                row[p] = c * vp - s * vq;
+                row[q] = s * vp + c * vq;
+            }
+        }
+    }
+    ([a[0][0], a[1][1], a[2][2]], v)
+}
+
+/// Returns the number of `f32` components of a float vertex format.
+fn float_format_components(format: RTCFormat) -> Option<usize> {
+    match format {
+        RTCFormat::FLOAT => Some(1),
+        RTCFormat::FLOAT2 => Some(2),
+        RTCFormat::FLOAT3 => Some(3),
+        RTCFormat::FLOAT4 => Some(4),
+        _ => None,
     }
 }
 
@@ -120,4 +781,397 @@ impl Geometry for TriangleMeshGeometry {
     fn geometry(&self) -> embree4_sys::RTCGeometry {
         self.handle
     }
+
+    fn enabled_flag(&self) -> Option<&Cell<bool>> {
+        Some(&self.enabled)
+    }
+
+    fn kind(&self) -> embree4_sys::RTCGeometryType {
+        embree4_sys::RTCGeometryType::TRIANGLE
+    }
+
+    fn time_step_count(&self) -> u32 {
+        self.time_step_count.load(Ordering::Relaxed)
+    }
+
+    fn set_time_step_count(&self, count: u32) -> Result<()> {
+        if count == 0 {
+            bail!("At least one time step is required");
+        }
+
+        unsafe {
+            embree4_sys::rtcSetGeometryTimeStepCount(self.handle, count);
+        }
+        device_handle_error_or(
+            self.device,
+            (),
+            "Could not set triangle mesh time step count",
+        )?;
+        self.time_step_count.store(count, Ordering::Relaxed);
+        self.committed.set(false);
+        Ok(())
+    }
+
+    fn is_committed(&self) -> bool {
+        self.committed.get()
+    }
+
+    fn primitive_count(&self) -> usize {
+        self.primitive_count
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        vertex_buffer_bounds(self.handle, self.time_step_count(), self.vertex_count, 3)
+    }
+
+    fn shared_data(&self) -> Option<SharedData> {
+        self.shared_buffers
+            .clone()
+            .map(|buffers| buffers as SharedData)
+    }
+
+    fn validation(&self) -> Option<Validation> {
+        Some(
+            Validation::new(
+                self.handle,
+                Layout::Triangles {
+                    vertex_count: self.vertex_count,
+                    triangle_count: self.primitive_count,
+                    time_step_count: self.time_step_count(),
+                },
+            )
+            .with_time_step_count(self.time_step_count.clone()),
+        )
+    }
+}
+
+#[test]
+fn interpolate_float2_uv() {
+    let device = Device::try_new(None).unwrap();
+    let vertices = [(0.0, 0.0, 0.0), (1.0, 0.0, 0.0), (0.0, 1.0, 0.0)];
+    let mut geometry = TriangleMeshGeometry::try_new(&device, &vertices, &[(0, 1, 2)]).unwrap();
+
+    let uvs = [0.0, 0.0, 1.0, 0.0, 0.0, 1.0];
+    geometry
+        .set_vertex_attribute(0, RTCFormat::FLOAT2, &uvs)
+        .unwrap();
+
+    let uv = geometry.interpolate(0, 0, 0.25, 0.5).unwrap();
+    assert_eq!(uv.len(), 2);
+    assert!((uv[0] - 0.25).abs() < 1e-6);
+    assert!((uv[1] - 0.5).abs() < 1e-6);
+}
+
+#[test]
+fn vertex_attribute_size_mismatch() {
+    let device = Device::try_new(None).unwrap();
+    let vertices = [(0.0, 0.0, 0.0), (1.0, 0.0, 0.0), (0.0, 1.0, 0.0)];
+    let mut geometry = TriangleMeshGeometry::try_new(&device, &vertices, &[(0, 1, 2)]).unwrap();
+
+    let padded_uvs = [0.0; 9];
+    assert!(geometry
+        .set_vertex_attribute(0, RTCFormat::FLOAT2, &padded_uvs)
+        .is_err());
+}
+
+#[test]
+fn translating_triangle_under_motion_blur() {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use crate::{
+        ray::RayBuilder,
+        scene::{Scene, SceneOptions},
+    };
+
+    let device = Device::try_new(None).unwrap();
+    let triangle_at = |z| [(-1.0, -1.0, z), (1.0, -1.0, z), (0.0, 1.0, z)];
+    let (start, end) = (triangle_at(2.0), triangle_at(6.0));
+
+    let mesh = TriangleMeshGeometry::try_new(&device, &start, &[(0, 1, 2)]).unwrap();
+    mesh.set_time_steps(&[&start, &end]).unwrap();
+
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    scene.attach_geometry(&mesh).unwrap();
+    let scene = scene.commit().unwrap();
+
+    let mut rng = StdRng::seed_from_u64(0);
+    for _ in 0..64 {
+        let ray = RayBuilder::new((0.0, 0.0, 0.0), (0.0, 0.0, 1.0))
+            .time_jitter(|| rng.gen())
+            .build();
+        let hit = scene.intersect_1(ray).unwrap().unwrap();
+        assert!((hit.ray.tfar - (2.0 + 4.0 * ray.time)).abs() < 1e-4);
+    }
+}
+
+#[test]
+fn time_step_and_primitive_counts() {
+    let device = Device::try_new(None).unwrap();
+    let vertices = [
+        (-1.0, -1.0, 0.0),
+        (1.0, -1.0, 0.0),
+        (1.0, 1.0, 0.0),
+        (-1.0, 1.0, 0.0),
+    ];
+    let moved = vertices.map(|(x, y, z)| (x, y, z + 1.0));
+
+    let mesh = TriangleMeshGeometry::try_new(&device, &vertices, &[(0, 1, 2), (2, 3, 0)]).unwrap();
+    assert_eq!(mesh.time_step_count(), 1);
+    assert_eq!(mesh.primitive_count(), 2);
+
+    mesh.set_time_steps(&[&vertices, &moved]).unwrap();
+    assert_eq!(mesh.time_step_count(), 2);
+    assert_eq!(mesh.primitive_count(), 2);
+}
+
+#[test]
+fn time_steps_filled_one_by_one() {
+    use crate::{
+        ray::RayBuilder,
+        scene::{Scene, SceneOptions},
+    };
+
+    let device = Device::try_new(None).unwrap();
+    let triangle_at = |z| [(-1.0, -1.0, z), (1.0, -1.0, z), (0.0, 1.0, z)];
+
+    let mesh = TriangleMeshGeometry::try_new(&device, &triangle_at(2.0), &[(0, 1, 2)]).unwrap();
+    assert!(mesh.is_committed());
+    mesh.set_time_step_count(3).unwrap();
+    assert_eq!(mesh.time_step_count(), 3);
+    assert!(!mesh.is_committed());
+
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    assert!(scene.attach_geometry(&mesh).is_err());
+
+    for (slot, z) in [(0, 2.0), (1, 4.0), (2, 6.0)] {
+        mesh.set_time_step(slot, &triangle_at(z)).unwrap();
+    }
+    assert!(mesh.set_time_step(3, &triangle_at(8.0)).is_err());
+    assert!(mesh.set_time_step(1, &triangle_at(4.0)[..2]).is_err());
+    assert!(mesh.is_committed());
+
+    scene.attach_geometry(&mesh).unwrap();
+    let scene = scene.commit().unwrap();
+
+    for (time, depth) in [(0.0, 2.0), (0.25, 3.0), (0.5, 4.0), (1.0, 6.0)] {
+        let ray = RayBuilder::new((0.0, 0.0, 0.0), (0.0, 0.0, 1.0))
+            .time(time)
+            .build();
+        let hit = scene.intersect_1(ray).unwrap().unwrap();
+        assert!((hit.ray.tfar - depth).abs() < 1e-4);
+    }
+}
+
+#[test]
+fn computed_normals_point_outward() {
+    let device = Device::try_new(None).unwrap();
+
+    // Octahedron, with counter-clockwise faces seen from the outside
+    let vertices = [
+        (1.0, 0.0, 0.0),
+        (-1.0, 0.0, 0.0),
+        (0.0, 1.0, 0.0),
+        (0.0, -1.0, 0.0),
+        (0.0, 0.0, 1.0),
+        (0.0, 0.0, -1.0),
+    ];
+    let mut indices = Vec::new();
+    for (sx, x) in [(1.0, 0), (-1.0, 1)] {
+        for (sy, y) in [(1.0, 2), (-1.0, 3)] {
+            for (sz, z) in [(1.0, 4), (-1.0, 5)] {
+                indices.push(if sx * sy * sz > 0.0 {
+                    (x, y, z)
+                } else {
+                    (x, z, y)
+                });
+            }
+        }
+    }
+
+    let mut mesh = TriangleMeshGeometry::try_new(&device, &vertices, &indices).unwrap();
+    mesh.compute_normals(0).unwrap();
+
+    for (prim_id, &(a, b, c)) in indices.iter().enumerate() {
+        for (vertex, (u, v)) in [(a, (0.0, 0.0)), (b, (1.0, 0.0)), (c, (0.0, 1.0))] {
+            let normal = mesh.interpolate(0, prim_id as u32, u, v).unwrap();
+            let p = vertices[vertex as usize];
+            let cos = normal[0] * p.0 + normal[1] * p.1 + normal[2] * p.2;
+            assert!(cos > 0.99);
+        }
+    }
+}
+
+#[test]
+fn u16_indices_match_u32_indices() {
+    use crate::{
+        ray::RayBuilder,
+        scene::{Scene, SceneOptions},
+    };
+
+    let device = Device::try_new(None).unwrap();
+    let vertices = [
+        (-1.0, -1.0, 5.0),
+        (1.0, -1.0, 5.0),
+        (1.0, 1.0, 6.0),
+        (-1.0, 1.0, 6.0),
+    ];
+    let wide = TriangleMeshGeometry::try_new(&device, &vertices, &[(0, 1, 2), (2, 3, 0)]).unwrap();
+    let narrow =
+        TriangleMeshGeometry::try_new_u16(&device, &vertices, &[(0, 1, 2), (2, 3, 0)]).unwrap();
+
+    let hits = [wide, narrow].map(|mesh| {
+        let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+        scene.attach_geometry(&mesh).unwrap();
+        let scene = scene.commit().unwrap();
+
+        [(0.5, -0.5), (-0.5, 0.5), (0.0, 0.0)].map(|(x, y)| {
+            let ray = RayBuilder::new((x, y, 0.0), (0.0, 0.0, 1.0)).build();
+            let hit = scene.intersect_1(ray).unwrap().unwrap();
+            (hit.ray.tfar, hit.hit.primID, hit.hit.u, hit.hit.v)
+        })
+    });
+    assert_eq!(hits[0], hits[1]);
+}
+
+#[test]
+fn misaligned_shared_buffers_are_copied() {
+    use crate::{
+        ray::RayBuilder,
+        scene::{Scene, SceneOptions},
+    };
+
+    let device = Device::try_new(None).unwrap();
+
+    // A quad at z = 5, placed one float into the storage if needed to misalign it
+    let quad = [
+        -1.0, -1.0, 5.0, 1.0, -1.0, 5.0, 1.0, 1.0, 5.0, -1.0, 1.0, 5.0,
+    ];
+    let mut storage = vec![0.0f32; quad.len() + 1];
+    let offset = if AlignedVec::is_aligned(&storage) {
+        1
+    } else {
+        0
+    };
+    storage[offset..offset + quad.len()].copy_from_slice(&quad);
+    let misaligned = &storage[offset..offset + quad.len()];
+    assert!(!AlignedVec::is_aligned(misaligned));
+
+    let vertices = AlignedVec::from_slice(misaligned);
+    assert!(AlignedVec::is_aligned(&vertices));
+
+    let indices = AlignedVec::from_slice(&[0, 1, 2, 2, 3, 0]);
+    let mesh = TriangleMeshGeometry::try_new_shared(&device, vertices, indices).unwrap();
+    assert_eq!(mesh.primitive_count(), 2);
+
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    scene.attach_geometry(&mesh).unwrap();
+    let scene = scene.commit().unwrap();
+
+    for (x, y, prim_id) in [(0.5, -0.5, 0), (-0.5, 0.5, 1)] {
+        let ray = RayBuilder::new((x, y, 0.0), (0.0, 0.0, 1.0)).build();
+        let hit = scene.intersect_1(ray).unwrap().unwrap();
+        assert!((hit.ray.tfar - 5.0).abs() < 1e-5);
+        assert_eq!(hit.hit.primID, prim_id);
+    }
+}
+
+#[test]
+fn shared_buffers_outlive_the_mesh() {
+    use crate::{
+        ray::RayBuilder,
+        scene::{Scene, SceneOptions},
+    };
+
+    let device = Device::try_new(None).unwrap();
+    let vertices = AlignedVec::from_slice(&[-1.0, -1.0, 5.0, 1.0, -1.0, 5.0, 0.0, 1.0, 5.0]);
+    let indices = AlignedVec::from_slice(&[0, 1, 2]);
+    let mesh = TriangleMeshGeometry::try_new_shared(&device, vertices, indices).unwrap();
+
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    scene.attach_geometry(&mesh).unwrap();
+    drop(mesh);
+    let scene = scene.commit().unwrap();
+
+    let ray = RayBuilder::new((0.0, 0.0, 0.0), (0.0, 0.0, 1.0)).build();
+    let hit = scene.intersect_1(ray).unwrap().unwrap();
+    assert!((hit.ray.tfar - 5.0).abs() < 1e-5);
+}
+
+#[test]
+fn supplied_normals_are_interpolated() {
+    let device = Device::try_new(None).unwrap();
+    let vertices = [(0.0, 0.0, 0.0), (1.0, 0.0, 0.0), (0.0, 1.0, 0.0)];
+    let normals = [(1.0, 0.0, 0.0), (0.0, 1.0, 0.0), (0.0, 0.0, 1.0)];
+
+    let mesh =
+        TriangleMeshGeometry::try_new_with_normals(&device, &vertices, &[(0, 1, 2)], &normals)
+            .unwrap();
+    let normal = mesh.interpolate(0, 0, 1.0 / 3.0, 1.0 / 3.0).unwrap();
+    for component in normal {
+        assert!((component - 1.0 / 3.0).abs() < 1e-6);
+    }
+
+    assert!(TriangleMeshGeometry::try_new_with_normals(
+        &device,
+        &vertices,
+        &[(0, 1, 2)],
+        &normals[..2]
+    )
+    .is_err());
+}
+
+#[test]
+fn obb_follows_long_axis() {
+    let device = Device::try_new(None).unwrap();
+
+    // A 10 x 2 x 0.5 box, rotated by 30 degrees around z and moved
+    let (sin, cos) = 30f32.to_radians().sin_cos();
+    let mut vertices = Vec::new();
+    for x in [-5.0, 5.0] {
+        for y in [-1.0, 1.0] {
+            for z in [-0.25, 0.25] {
+                vertices.push((cos * x - sin * y + 1.0, sin * x + cos * y + 2.0, z + 3.0));
+            }
+        }
+    }
+    let indices = [(0, 1, 2), (5, 6, 7), (0, 3, 7)];
+    let geometry = TriangleMeshGeometry::try_new(&device, &vertices, &indices).unwrap();
+
+    let (center, axes, extents) = geometry.obb().unwrap();
+    let close = |a: f32, b: f32| (a - b).abs() < 1e-4;
+    assert!(center
+        .iter()
+        .zip([1.0, 2.0, 3.0])
+        .all(|(&a, b)| close(a, b)));
+    assert!(extents
+        .iter()
+        .zip([5.0, 1.0, 0.25])
+        .all(|(&a, b)| close(a, b)));
+    let along = |axis: [f32; 3], dir: [f32; 3]| (0..3).map(|k| axis[k] * dir[k]).sum::<f32>().abs();
+    assert!(close(along(axes[0], [cos, sin, 0.0]), 1.0));
+    assert!(close(along(axes[1], [-sin, cos, 0.0]), 1.0));
+    assert!(close(along(axes[2], [0.0, 0.0, 1.0]), 1.0));
+}
+
+#[test]
+fn obb_of_degenerate_vertices_is_finite() {
+    let device = Device::try_new(None).unwrap();
+
+    let collinear = [(0.0, 0.0, 0.0), (1.0, 1.0, 1.0), (2.0, 2.0, 2.0)];
+    let geometry = TriangleMeshGeometry::try_new(&device, &collinear, &[(0, 1, 2)]).unwrap();
+    let (center, axes, extents) = geometry.obb().unwrap();
+    assert!(center
+        .iter()
+        .chain(axes.iter().flatten())
+        .chain(&extents)
+        .all(|x| x.is_finite()));
+    assert!((extents[0] - 3f32.sqrt()).abs() < 1e-4);
+    assert!(extents[1].abs() < 1e-4 && extents[2].abs() < 1e-4);
+
+    let coincident = [(1.0, 2.0, 3.0); 3];
+    let geometry = TriangleMeshGeometry::try_new(&device, &coincident, &[(0, 1, 2)]).unwrap();
+    let (center, axes, extents) = geometry.obb().unwrap();
+    assert_eq!(center, [1.0, 2.0, 3.0]);
+    assert!(axes.iter().flatten().all(|x| x.is_finite()));
+    assert_eq!(extents, [0.0; 3]);
 }
@@ -1,9 +1,9 @@
-use std::{marker::PhantomData, ptr};
+use std::{cell::Cell, marker::PhantomData, ptr};
 
-use crate::{device::Device, device_error_or};
+use crate::{aabb::Aabb, device::Device, device_error_or, scene::CommittedScene};
 
-use anyhow::Result;
-use embree4_sys::{RTCRayHit, RTC_INVALID_GEOMETRY_ID};
+use anyhow::{bail, Result};
+use embree4_sys::{RTCOccludedFunctionNArguments, RTCRay, RTCRayHit, RTC_INVALID_GEOMETRY_ID};
 
 use super::Geometry;
 
@@ -33,10 +33,80 @@ pub trait UserGeometryImpl {
         ctx: &embree4_sys::RTCRayQueryContext,
         ray_hit: &mut embree4_sys::RTCRayHit,
     );
+
+    /// Checks whether the given ray hits the geometry between its `tnear` and `tfar`, for
+    /// occlusion queries such as [CommittedScene::occluded_1].
+    ///
+    /// `forward` continues the query into a child scene, e.g. for geometries implementing custom
+    /// instancing or level of detail, see [OcclusionForward].
+    ///
+    /// The default implementation reports whether [UserGeometryImpl::intersect] finds a hit.
+    fn occluded(
+        &self,
+        geom_id: u32,
+        prim_id: u32,
+        ctx: &embree4_sys::RTCRayQueryContext,
+        ray: &RTCRay,
+        forward: &OcclusionForward<'_>,
+    ) -> bool {
+        let _ = forward;
+        let mut ray_hit = RTCRayHit {
+            ray: *ray,
+            hit: embree4_sys::RTCHit {
+                Ng_x: 0.0,
+                Ng_y: 0.0,
+                Ng_z: 0.0,
+                u: 0.0,
+                v: 0.0,
+                primID: RTC_INVALID_GEOMETRY_ID,
+                geomID: RTC_INVALID_GEOMETRY_ID,
+                instID: [RTC_INVALID_GEOMETRY_ID],
+            },
+        };
+        self.intersect(geom_id, prim_id, ctx, &mut ray_hit);
+        ray_hit.hit.geomID != RTC_INVALID_GEOMETRY_ID
+    }
+}
+
+/// Continues an occlusion query into a child scene from [UserGeometryImpl::occluded].
+///
+/// Wraps [rtcForwardOccluded1](https://github.com/embree/embree/blob/master/doc/src/api/rtcForwardOccluded1.md).
+/// It is only valid inside the callback it is passed to, and only for rays traced one at a time
+/// such as with [CommittedScene::occluded_1]: Embree can not forward the rays of a packet.
+pub struct OcclusionForward<'a> {
+    args: &'a RTCOccludedFunctionNArguments,
+}
+
+impl OcclusionForward<'_> {
+    /// Traces `ray` through `scene` as instance `inst_id`, usually the `geom_id` of the user
+    /// geometry.
+    ///
+    /// `ray` is given in the space of `scene`, e.g. transformed by the inverse transform of a
+    /// custom instance. An occluder found in `scene` occludes the original ray, so the callback
+    /// does not need to report it again.
+    ///
+    /// # Returns
+    /// A `Result` containing `true` if `scene` occludes the ray, or an error if the query holds
+    /// several rays.
+    pub fn occluded(&self, scene: &CommittedScene, ray: &RTCRay, inst_id: u32) -> Result<bool> {
+        if self.args.N != 1 {
+            bail!("Could not forward occlusion ray: only single ray queries can be forwarded");
+        }
+
+        let mut ray = *ray;
+        unsafe {
+            embree4_sys::rtcForwardOccluded1(self.args, scene.handle, &mut ray, inst_id);
+        }
+
+        // Embree marks occlusion with a tfar of -inf, on the original ray or the forwarded one
+        let original_tfar = unsafe { *(self.args.ray as *const f32).add(8) };
+        Ok(ray.tfar == f32::NEG_INFINITY || original_tfar == f32::NEG_INFINITY)
+    }
 }
 
 pub struct UserGeometry<T: UserGeometryImpl> {
     handle: embree4_sys::RTCGeometry,
+    enabled: Cell<bool>,
     data: PhantomData<T>,
 }
 
@@ -56,10 +126,8 @@ impl<T: UserGeometryImpl> UserGeometry<T> {
     ///
     /// A `Result` containing the `UserGeometry` object if successful, or an `anyhow::Error` if an error occurred.
     pub fn try_new(device: &Device, data: &T) -> Result<Self> {
-        let handle = unsafe {
-            embree4_sys::rtcNewGeometry(device.handle, embree4_sys::RTCGeometryType::USER)
-        };
-        device_error_or(device, (), "Could not create user geometry")?;
+        let raw = device.new_geometry(embree4_sys::RTCGeometryType::USER)?;
+        let handle = raw.handle();
 
         unsafe {
             embree4_sys::rtcSetGeometryUserPrimitiveCount(handle, 1);
@@ -87,10 +155,10 @@ impl<T: UserGeometryImpl> UserGeometry<T> {
         }
         device_error_or(device, (), "Could not set user geometry intersect function")?;
 
-        // unsafe {
-        //     embree4_sys::rtcSetGeometryOccludedFunction(handle, Some(occluded_fn));
-        // }
-        // device_error_or(device, (), "Could not set user geometry occluded function")?;
+        unsafe {
+            embree4_sys::rtcSetGeometryOccludedFunction(handle, Some(internal_occluded_fn::<T>));
+        }
+        device_error_or(device, (), "Could not set user geometry occluded function")?;
 
         // unsafe {
         //     embree4_sys::rtcSetGeometryPointQueryFunction(
@@ -105,7 +173,8 @@ impl<T: UserGeometryImpl> UserGeometry<T> {
         device_error_or(device, (), "Could not commit user geometry")?;
 
         Ok(Self {
-            handle,
+            handle: raw.into_raw(),
+            enabled: Cell::new(true),
             data: PhantomData,
         })
     }
@@ -115,6 +184,28 @@ impl<T: UserGeometryImpl> Geometry for UserGeometry<T> {
     fn geometry(&self) -> embree4_sys::RTCGeometry {
         self.handle
     }
+
+    fn enabled_flag(&self) -> Option<&Cell<bool>> {
+        Some(&self.enabled)
+    }
+
+    fn kind(&self) -> embree4_sys::RTCGeometryType {
+        embree4_sys::RTCGeometryType::USER
+    }
+
+    fn time_step_count(&self) -> u32 {
+        1
+    }
+
+    fn primitive_count(&self) -> usize {
+        1
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let data = unsafe { embree4_sys::rtcGetGeometryUserData(self.handle) as *const T };
+        let data = unsafe { data.as_ref()? };
+        Some(data.bounds().into())
+    }
 }
 
 impl<T: UserGeometryImpl> Drop for UserGeometry<T> {
@@ -230,7 +321,109 @@ unsafe extern "C" fn internal_intersect_fn<T: UserGeometryImpl>(
     }
 }
 
+unsafe extern "C" fn internal_occluded_fn<T: UserGeometryImpl>(
+    args: *const RTCOccludedFunctionNArguments,
+) {
+    let args = &*args;
+    let geom = &*(args.geometryUserPtr as *const T);
+    let forward = OcclusionForward { args };
+
+    let ray_n = args.ray as *mut f32;
+
+    let n = args.N as usize;
+    let valid = std::slice::from_raw_parts(args.valid as *const u32, n);
+
+    let context = &*(args.context as *const embree4_sys::RTCRayQueryContext);
+
+    for (i, valid) in valid.iter().enumerate() {
+        if *valid == 0 {
+            continue;
+        }
+
+        let field = |k: usize| ray_n.add(offset(k, n, i));
+        let ray = RTCRay {
+            org_x: *field(0),
+            org_y: *field(1),
+            org_z: *field(2),
+            tnear: *field(3),
+            dir_x: *field(4),
+            dir_y: *field(5),
+            dir_z: *field(6),
+            time: *field(7),
+            tfar: *field(8),
+            mask: *(field(9) as *const u32),
+            id: *(field(10) as *const u32),
+            flags: *(field(11) as *const u32),
+        };
+
+        if geom.occluded(args.geomID, args.primID, context, &ray, &forward) {
+            *field(8) = f32::NEG_INFINITY;
+        }
+    }
+}
+
 #[inline(always)]
 fn offset(offset: usize, n: usize, i: usize) -> usize {
     offset * n + i
 }
+
+#[test]
+fn forwarded_occlusion_follows_the_child_scene() {
+    use crate::{
+        geometry::TriangleMeshGeometry,
+        ray::RayBuilder,
+        scene::{Scene, SceneOptions},
+    };
+
+    // Forwards occlusion rays unchanged into its child scene, like an identity instance
+    struct Forwarder<'s> {
+        child: &'s CommittedScene<'s>,
+    }
+
+    impl UserGeometryImpl for Forwarder<'_> {
+        fn bounds(&self) -> embree4_sys::RTCBounds {
+            self.child.bounds().unwrap()
+        }
+
+        fn intersect(
+            &self,
+            _geom_id: u32,
+            _prim_id: u32,
+            _ctx: &embree4_sys::RTCRayQueryContext,
+            _ray_hit: &mut RTCRayHit,
+        ) {
+        }
+
+        fn occluded(
+            &self,
+            geom_id: u32,
+            _prim_id: u32,
+            _ctx: &embree4_sys::RTCRayQueryContext,
+            ray: &RTCRay,
+            forward: &OcclusionForward<'_>,
+        ) -> bool {
+            forward.occluded(self.child, ray, geom_id).unwrap()
+        }
+    }
+
+    let device = Device::try_new(None).unwrap();
+    let vertices = [(-1.0, -1.0, 5.0), (1.0, -1.0, 5.0), (0.0, 1.0, 5.0)];
+    let mesh = TriangleMeshGeometry::try_new(&device, &vertices, &[(0, 1, 2)]).unwrap();
+    let child = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    child.attach_geometry(&mesh).unwrap();
+    let child = child.commit().unwrap();
+
+    let forwarder = Forwarder { child: &child };
+    let user = UserGeometry::try_new(&device, &forwarder).unwrap();
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    scene.attach_geometry(&user).unwrap();
+    let scene = scene.commit().unwrap();
+
+    let towards = |x: f32, y: f32| RayBuilder::new((x, y, 0.0), (0.0, 0.0, 1.0));
+    assert!(scene.occluded_1(towards(0.0, 0.0).build()).unwrap());
+    // Within the bounds of the child scene, but beside its triangle
+    assert!(!scene.occluded_1(towards(0.9, 0.9).build()).unwrap());
+    assert!(!scene
+        .occluded_1(towards(0.0, 0.0).tfar(4.0).build())
+        .unwrap());
+}
@@ -0,0 +1,284 @@
+use std::{
+    slice,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+};
+
+use anyhow::{bail, Result};
+use embree4_sys::RTCBufferType;
+
+/// A check of the buffers of a built-in geometry, see [Geometry::validation](super::Geometry::validation).
+///
+/// It looks for mistakes Embree does not report but that cause silent misses or crashes: indices
+/// out of range, vertices that are not finite and degenerate primitives. It is `O(n)` in the size
+/// of the buffers and only runs when explicitly called.
+///
+/// The check keeps a reference to the geometry, so it can outlive it.
+#[derive(Debug)]
+pub struct Validation {
+    geometry: embree4_sys::RTCGeometry,
+    layout: Layout,
+    // The time step count of the geometry, when it can change after the check is made
+    time_step_count: Option<Arc<AtomicU32>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Layout {
+    Triangles {
+        vertex_count: usize,
+        triangle_count: usize,
+        time_step_count: u32,
+    },
+    Curves {
+        vertex_count: usize,
+        segment_count: usize,
+        control_points: usize,
+        time_step_count: u32,
+    },
+    Sphere,
+    Subdivision {
+        vertex_count: usize,
+        face_count: usize,
+    },
+}
+
+// Reports are capped so that a mesh with broken buffers doesn't produce millions of lines
+const MAX_ISSUES: usize = 16;
+
+impl Validation {
+    pub(crate) fn new(geometry: embree4_sys::RTCGeometry, layout: Layout) -> Self {
+        unsafe {
+            embree4_sys::rtcRetainGeometry(geometry);
+        }
+        Self {
+            geometry,
+            layout,
+            time_step_count: None,
+        }
+    }
+
+    /// Reads the time step count of the layout from `count` when the check runs, for geometries
+    /// whose time step count can change after the check is made.
+    pub(crate) fn with_time_step_count(mut self, count: Arc<AtomicU32>) -> Self {
+        self.time_step_count = Some(count);
+        self
+    }
+
+    /// Runs the check.
+    ///
+    /// # Returns
+    /// An error listing the issues found, if any.
+    pub fn run(&self) -> Result<()> {
+        let issues = self.issues();
+        if issues.is_empty() {
+            return Ok(());
+        }
+
+        bail!(issues.join("\n"))
+    }
+
+    pub(crate) fn layout(&self) -> Layout {
+        let mut layout = self.layout;
+        if let Some(count) = &self.time_step_count {
+            match &mut layout {
+                Layout::Triangles {
+                    time_step_count, ..
+                }
+                | Layout::Curves {
+                    time_step_count, ..
+                } => *time_step_count = count.load(Ordering::Relaxed),
+                Layout::Sphere | Layout::Subdivision { .. } => {}
+            }
+        }
+        layout
+    }
+
+    pub(crate) fn geometry(&self) -> embree4_sys::RTCGeometry {
+        self.geometry
+    }
+
+    pub(crate) fn issues(&self) -> Vec<String> {
+        let mut issues = Vec::new();
+        match self.layout() {
+            Layout::Triangles {
+                vertex_count,
+                triangle_count,
+                time_step_count,
+            } => {
+                let indices = self.buffer::<u32>(RTCBufferType::INDEX, 0, 3 * triangle_count);
+                check_finite(self, 3, vertex_count, time_step_count, &mut issues);
+
+                let vertices = self.buffer::<f32>(RTCBufferType::VERTEX, 0, 3 * vertex_count);
+                for (prim_id, triangle) in indices.chunks_exact(3).enumerate() {
+                    if let Some(&index) = triangle.iter().find(|&&i| i as usize >= vertex_count) {
+                        issues.push(format!(
+                            "triangle {} has out-of-range index {} ({} vertices)",
+                            prim_id, index, vertex_count
+                        ));
+                        continue;
+                    }
+
+                    if vertices.is_empty() {
+                        continue;
+                    }
+                    let p: [&[f32]; 3] = std::array::from_fn(|k| {
+                        let i = triangle[k] as usize;
+                        &vertices[3 * i..3 * i + 3]
+                    });
+                    let e1 = [0, 1, 2].map(|k| p[1][k] - p[0][k]);
+                    let e2 = [0, 1, 2].map(|k| p[2][k] - p[0][k]);
+                    let normal = [
+                        e1[1] * e2[2] - e1[2] * e2[1],
+                        e1[2] * e2[0] - e1[0] * e2[2],
+                        e1[0] * e2[1] - e1[1] * e2[0],
+                    ];
+                    if normal == [0.0; 3] {
+                        issues.push(format!("triangle {} is degenerate", prim_id));
+                    }
+                }
+            }
+            Layout::Curves {
+                vertex_count,
+                segment_count,
+                control_points,
+                time_step_count,
+            } => {
+                let indices = self.buffer::<u32>(RTCBufferType::INDEX, 0, segment_count);
+                check_finite(self, 4, vertex_count, time_step_count, &mut issues);
+
+                for (prim_id, &index) in indices.iter().enumerate() {
+                    if index as usize + control_points > vertex_count {
+                        issues.push(format!(
+                            "curve segment {} starts at index {} but needs {} control points ({} vertices)",
+                            prim_id, index, control_points, vertex_count
+                        ));
+                    }
+                }
+            }
+            Layout::Sphere => {
+                check_finite(self, 4, 1, 1, &mut issues);
+            }
+            Layout::Subdivision {
+                vertex_count,
+                face_count,
+            } => {
+                let faces = self.buffer::<u32>(RTCBufferType::FACE, 0, face_count);
+                let index_count = faces.iter().map(|&n| n as usize).sum();
+                let indices = self.buffer::<u32>(RTCBufferType::INDEX, 0, index_count);
+                check_finite(self, 3, vertex_count, 1, &mut issues);
+
+                let mut first = 0;
+                for (face, &n) in faces.iter().enumerate() {
+                    let face_indices = &indices[first..first + n as usize];
+                    if let Some(&index) = face_indices.iter().find(|&&i| i as usize >= vertex_count)
+                    {
+                        issues.push(format!(
+                            "face {} has out-of-range index {} ({} vertices)",
+                            face, index, vertex_count
+                        ));
+                    }
+                    if n < 3 {
+                        issues.push(format!("face {} is degenerate ({} vertices)", face, n));
+                    }
+                    first += n as usize;
+                }
+            }
+        }
+
+        if issues.len() > MAX_ISSUES {
+            let more = issues.len() - MAX_ISSUES;
+            issues.truncate(MAX_ISSUES);
+            issues.push(format!("... and {} more", more));
+        }
+        issues
+    }
+
+    /// Returns the raw bytes of all buffers covered by the layout, in a fixed order.
+    pub(crate) fn buffer_bytes(&self) -> Vec<&[u8]> {
+        let bytes = |buffer_type, slot, len| self.buffer::<u8>(buffer_type, slot, 4 * len);
+        match self.layout() {
+            Layout::Triangles {
+                vertex_count,
+                triangle_count,
+                time_step_count,
+            } => (0..time_step_count)
+                .map(|slot| bytes(RTCBufferType::VERTEX, slot, 3 * vertex_count))
+                .chain([bytes(RTCBufferType::INDEX, 0, 3 * triangle_count)])
+                .collect(),
+            Layout::Curves {
+                vertex_count,
+                segment_count,
+                time_step_count,
+                ..
+            } => (0..time_step_count)
+                .map(|slot| bytes(RTCBufferType::VERTEX, slot, 4 * vertex_count))
+                .chain([bytes(RTCBufferType::INDEX, 0, segment_count)])
+                .collect(),
+            Layout::Sphere => vec![bytes(RTCBufferType::VERTEX, 0, 4)],
+            Layout::Subdivision {
+                vertex_count,
+                face_count,
+            } => {
+                let faces = self.buffer::<u32>(RTCBufferType::FACE, 0, face_count);
+                let index_count = faces.iter().map(|&n| n as usize).sum();
+                vec![
+                    bytes(RTCBufferType::VERTEX, 0, 3 * vertex_count),
+                    bytes(RTCBufferType::FACE, 0, face_count),
+                    bytes(RTCBufferType::INDEX, 0, index_count),
+                ]
+            }
+        }
+    }
+
+    pub(crate) fn buffer<T>(&self, buffer_type: RTCBufferType, slot: u32, len: usize) -> &[T] {
+        let ptr =
+            unsafe { embree4_sys::rtcGetGeometryBufferData(self.geometry, buffer_type, slot) };
+        if ptr.is_null() {
+            return &[];
+        }
+        unsafe { slice::from_raw_parts(ptr as *const T, len) }
+    }
+}
+
+impl Clone for Validation {
+    fn clone(&self) -> Self {
+        Self {
+            time_step_count: self.time_step_count.clone(),
+            ..Self::new(self.geometry, self.layout)
+        }
+    }
+}
+
+impl Drop for Validation {
+    fn drop(&mut self) {
+        unsafe {
+            embree4_sys::rtcReleaseGeometry(self.geometry);
+        }
+    }
+}
+
+/// Checks vertices of `components` floats are finite, and radii non-negative when there are 4.
+fn check_finite(
+    validation: &Validation,
+    components: usize,
+    vertex_count: usize,
+    time_step_count: u32,
+    issues: &mut Vec<String>,
+) {
+    for slot in 0..time_step_count {
+        let vertices =
+            validation.buffer::<f32>(RTCBufferType::VERTEX, slot, components * vertex_count);
+        for (i, vertex) in vertices.chunks_exact(components).enumerate() {
+            if vertex.iter().any(|x| !x.is_finite()) {
+                issues.push(format!("vertex {} of time step {} is not finite", i, slot));
+            } else if components == 4 && vertex[3] < 0.0 {
+                issues.push(format!(
+                    "vertex {} of time step {} has a negative radius",
+                    i, slot
+                ));
+            }
+        }
+    }
+}
@@ -16,16 +16,56 @@
 //! See the [examples/](https://github.com/psytrx/embree4-rs/tree/main/examples) for a quick start
 //! on how to use this crate.
 
+pub mod aabb;
+#[cfg(feature = "bench")]
+pub mod bench;
+pub mod buffer;
+pub mod bvh;
+pub mod camera;
+pub mod context;
 pub mod device;
+pub mod error;
+pub mod filter;
 pub mod geometry;
+pub mod point_query;
+pub mod ray;
+pub mod rebase;
+mod render;
 pub mod scene;
+mod snapshot;
+#[cfg(feature = "sycl")]
+pub mod sycl;
 
 use std::arch::asm;
 
-use anyhow::{bail, Result};
+use anyhow::Result;
 
+/// Re-exports of the commonly used types.
+///
+/// # Example
+/// ```
+/// use embree4_rs::prelude::*;
+///
+/// let device = Device::try_new(None).unwrap();
+/// let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+/// let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 5.0), 1.0).unwrap();
+/// scene.attach_geometry(&sphere).unwrap();
+/// let scene: CommittedScene = scene.commit().unwrap();
+/// ```
 pub mod prelude {
+    pub use crate::aabb::Aabb;
+    pub use crate::camera::Camera;
+    pub use crate::context::RayQueryContext;
     pub use crate::device::Device;
+    pub use crate::error::EmbreeError;
+    pub use crate::filter::{FilterChain, FilterGeometry};
+    pub use crate::geometry::{
+        BatchedGeometry, BoxGeometry, ConeGeometry, CurveGeometry, CylinderGeometry, Geometry,
+        GeometryBatch, InstanceArrayGeometry, InstanceGeometry, OcclusionForward, SphereGeometry,
+        SubdivGeometry, TransformedGeometry, TriangleMeshGeometry, UserGeometry, UserGeometryImpl,
+    };
+    pub use crate::ray::{Intersection, RayBuilder, RayHit};
+    pub use crate::rebase::RebasedScene;
     pub use crate::scene::{CommittedScene, Scene, SceneOptions};
 }
 
@@ -42,18 +82,102 @@ fn device_error_raw(device: embree4_sys::RTCDevice) -> Option<embree4_sys::RTCEr
     }
 }
 
+/// Clears the calling thread's device error, so that a stale error left by an unrelated earlier
+/// call is not attributed to the next operation.
+fn clear_device_error(device: embree4_sys::RTCDevice) {
+    let _ = device_error_raw(device);
+}
+
 fn device_error_or<T>(device: &device::Device, ok_value: T, message: &str) -> Result<T> {
-    device_error_raw(device.handle)
-        .map(|error| bail!("{}: {:?}", message, error))
-        .unwrap_or(Ok(ok_value))
+    device_handle_error_or(device.handle, ok_value, message)
+}
+
+fn device_handle_error_or<T>(
+    device: embree4_sys::RTCDevice,
+    ok_value: T,
+    message: &str,
+) -> Result<T> {
+    match device_error_raw(device) {
+        Some(error) => {
+            #[cfg(feature = "log")]
+            log::error!("{message}: {error:?}");
+
+            // The context alone is displayed, it names the error so that it is not lost
+            let error = error::EmbreeError::from(error);
+            Err(anyhow::Error::new(error).context(format!("{message}: {error}")))
+        }
+        None => Ok(ok_value),
+    }
+}
+
+// Installs a logger capturing the records of all tests, which share the global logger
+#[cfg(all(test, feature = "log"))]
+fn captured_log_records() -> &'static std::sync::Mutex<Vec<(log::Level, String)>> {
+    use std::sync::{Mutex, Once};
+
+    static RECORDS: Mutex<Vec<(log::Level, String)>> = Mutex::new(Vec::new());
+    static INSTALL: Once = Once::new();
+
+    struct CapturingLogger;
+
+    impl log::Log for CapturingLogger {
+        fn enabled(&self, _metadata: &log::Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record) {
+            let message = record.args().to_string();
+            RECORDS.lock().unwrap().push((record.level(), message));
+        }
+
+        fn flush(&self) {}
+    }
+
+    static LOGGER: CapturingLogger = CapturingLogger;
+    INSTALL.call_once(|| {
+        log::set_logger(&LOGGER).unwrap();
+        log::set_max_level(log::LevelFilter::Debug);
+    });
+    &RECORDS
+}
+
+/// Runs `f` with "Flush to Zero" and "Denormals are Zero" enabled on the calling thread, as
+/// Embree requires for performance, and restores the previous floating point control state
+/// afterwards.
+///
+/// The queries of this crate already do this around each Embree call, so that code running
+/// between them keeps full IEEE denormals. This is meant for Embree functions called directly
+/// through [sys].
+///
+/// # Example
+/// ```
+/// use std::hint::black_box;
+///
+/// use embree4_rs::with_denormals_flushed;
+///
+/// let denormal = || black_box(f32::MIN_POSITIVE) / black_box(2.0);
+/// let flushed = with_denormals_flushed(denormal);
+/// # #[cfg(all(target_arch = "x86_64", target_feature = "sse"))]
+/// assert_eq!(flushed, 0.0);
+/// assert_ne!(denormal(), 0.0);
+/// ```
+pub fn with_denormals_flushed<R>(f: impl FnOnce() -> R) -> R {
+    let _mxcsr = unsafe { Mxcsr::setup() };
+    f()
 }
 
 // Ensure that "Flush to Zero" and "Denormals are Zero" are enabled and restore old flags when
-// needed
+// needed. On aarch64, the FZ bit of the FPCR register flushes both inputs and outputs.
 pub(crate) struct Mxcsr {
+    #[cfg(not(target_arch = "aarch64"))]
     old: u32,
+    #[cfg(target_arch = "aarch64")]
+    old: u64,
 }
 
+#[cfg(target_arch = "aarch64")]
+const FPCR_FZ: u64 = 1 << 24;
+
 impl Mxcsr {
     pub(crate) unsafe fn setup() -> Self {
         let mut this = Self { old: 0 };
@@ -66,15 +190,79 @@ impl Mxcsr {
             asm!("ldmxcsr [{}]", in(reg) &new);
         }
 
+        #[cfg(target_arch = "aarch64")]
+        {
+            asm!("mrs {}, fpcr", out(reg) this.old);
+            asm!("msr fpcr, {}", in(reg) this.old | FPCR_FZ);
+        }
+
         this
     }
 }
 
-#[cfg(all(target_arch = "x86_64", target_feature = "sse"))]
+#[cfg(any(
+    all(target_arch = "x86_64", target_feature = "sse"),
+    target_arch = "aarch64"
+))]
 impl Drop for Mxcsr {
     fn drop(&mut self) {
+        #[cfg(target_arch = "x86_64")]
         unsafe {
             asm!("ldmxcsr [{}]", in(reg) &self.old);
         }
+
+        #[cfg(target_arch = "aarch64")]
+        unsafe {
+            asm!("msr fpcr, {}", in(reg) self.old);
+        }
+    }
+}
+
+#[cfg(all(target_arch = "x86_64", target_feature = "sse"))]
+#[test]
+fn mxcsr_flush_to_zero_inside_guard() {
+    let mxcsr = || {
+        let mut mxcsr = 0u32;
+        unsafe { asm!("stmxcsr [{}]", in(reg) std::ptr::addr_of_mut!(mxcsr)) };
+        mxcsr
+    };
+
+    let before = mxcsr();
+    with_denormals_flushed(|| assert_eq!(mxcsr() & 0x8040, 0x8040));
+    assert_eq!(mxcsr(), before);
+}
+
+#[cfg(target_arch = "aarch64")]
+#[test]
+fn fpcr_flush_to_zero_inside_guard() {
+    let fpcr = || {
+        let fpcr: u64;
+        unsafe { asm!("mrs {}, fpcr", out(reg) fpcr) };
+        fpcr
+    };
+
+    let before = fpcr();
+    {
+        let _guard = unsafe { Mxcsr::setup() };
+        assert_ne!(fpcr() & FPCR_FZ, 0);
     }
+    assert_eq!(fpcr(), before);
+}
+
+#[test]
+fn device_errors_name_the_error() {
+    let device = device::Device::try_new(None).unwrap();
+    // REFIT is only valid for geometries
+    unsafe {
+        let scene = embree4_sys::rtcNewScene(device.handle);
+        embree4_sys::rtcSetSceneBuildQuality(scene, embree4_sys::RTCBuildQuality::REFIT);
+        embree4_sys::rtcReleaseScene(scene);
+    }
+
+    let err = device_error_or(&device, (), "Could not set quality").unwrap_err();
+    assert_eq!(err.to_string(), "Could not set quality: invalid argument");
+    assert_eq!(
+        err.downcast_ref::<error::EmbreeError>().unwrap().code(),
+        embree4_sys::RTCError::INVALID_ARGUMENT
+    );
 }
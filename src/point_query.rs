@@ -0,0 +1,259 @@
+use std::{collections::HashMap, slice, sync::Arc};
+
+use embree4_sys::{RTCPointQueryContext, RTCPointQueryFunctionArguments, RTC_INVALID_GEOMETRY_ID};
+
+use crate::geometry::{InstanceLinks, Layout};
+
+/// The closest point of a scene to a query point, see
+/// [CommittedScene::closest_point](crate::scene::CommittedScene::closest_point).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClosestPoint {
+    /// The closest point, in world space.
+    pub point: [f32; 3],
+    /// The distance from the query point to the closest point, in world space.
+    pub distance: f32,
+    /// The ID of the geometry the closest point lies on, in the innermost instanced scene.
+    pub geom_id: u32,
+    /// The ID of the primitive the closest point lies on.
+    pub prim_id: u32,
+    /// The IDs of the instances the closest point was found through, from the outermost to the
+    /// innermost one.
+    pub inst_ids: Vec<u32>,
+}
+
+/// The geometries of a scene taking part in closest point queries, by geometry ID.
+pub(crate) type QueryShapes = Arc<HashMap<u32, QueryShape>>;
+
+/// A geometry and the layout of its buffers, as described by its [Validation](crate::geometry::Validation).
+///
+/// The handle is not retained: the scene holding the geometry does.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct QueryShape {
+    pub(crate) geometry: embree4_sys::RTCGeometry,
+    pub(crate) layout: Layout,
+}
+
+unsafe impl Send for QueryShape {}
+unsafe impl Sync for QueryShape {}
+
+// The state of a closest point query, passed to `closest_point_query`
+pub(crate) struct ClosestPointQuery {
+    pub(crate) point: [f32; 3],
+    pub(crate) scene: embree4_sys::RTCScene,
+    pub(crate) shapes: QueryShapes,
+    pub(crate) instances: InstanceLinks,
+    pub(crate) closest: Option<ClosestPoint>,
+}
+
+impl ClosestPointQuery {
+    // Looks up the scene holding the geometries reached through the instance stack, and the
+    // shapes of its geometries
+    fn leaf_scene(
+        &self,
+        context: &RTCPointQueryContext,
+    ) -> Option<(embree4_sys::RTCScene, QueryShapes)> {
+        let mut scene = self.scene;
+        let mut shapes = self.shapes.clone();
+        let mut instances = self.instances.clone();
+        for inst_id in instance_stack(context) {
+            let link = instances.get(inst_id)?;
+            scene = link.scene();
+            shapes = link.shapes();
+            instances = link.children();
+        }
+        Some((scene, shapes))
+    }
+}
+
+pub(crate) fn new_point_query_context() -> RTCPointQueryContext {
+    RTCPointQueryContext {
+        world2inst: Default::default(),
+        inst2world: Default::default(),
+        instID: [RTC_INVALID_GEOMETRY_ID; embree4_sys::RTC_MAX_INSTANCE_LEVEL_COUNT as usize],
+        instStackSize: 0,
+    }
+}
+
+fn instance_stack(context: &RTCPointQueryContext) -> &[u32] {
+    &context.instID[..context.instStackSize as usize]
+}
+
+// Applies a column-major 4x4 affine transform to a point
+fn transform_point(xfm: &[f32; 16], p: [f32; 3]) -> [f32; 3] {
+    [0, 1, 2].map(|row| xfm[row] * p[0] + xfm[4 + row] * p[1] + xfm[8 + row] * p[2] + xfm[12 + row])
+}
+
+/// Point query function of [CommittedScene::closest_point](crate::scene::CommittedScene::closest_point),
+/// given to `rtcPointQuery` and called for every primitive within the query radius.
+///
+/// Runs the closest point computation of the primitive in the space of its innermost instance,
+/// and records the result if it is closer than the previous ones. Geometries without a known
+/// shape are skipped.
+pub(crate) unsafe extern "C" fn closest_point_query(
+    args: *mut RTCPointQueryFunctionArguments,
+) -> bool {
+    let args = &mut *args;
+    let state = &mut *(args.userPtr as *mut ClosestPointQuery);
+    let context = &*args.context;
+
+    let Some((scene, shapes)) = state.leaf_scene(context) else {
+        return false;
+    };
+    let Some(shape) = shapes.get(&args.geomID) else {
+        return false;
+    };
+    if embree4_sys::rtcGetGeometry(scene, args.geomID) != shape.geometry {
+        return false;
+    }
+
+    // The query of the arguments is only transformed for similarity transforms, so the point is
+    // transformed with the instance stack of the context instead
+    let depth = context.instStackSize as usize;
+    let local = match depth {
+        0 => state.point,
+        _ => transform_point(&context.world2inst[depth - 1], state.point),
+    };
+    let Some(closest) = closest_in_primitive(shape, args.primID, local) else {
+        return false;
+    };
+    let closest = match depth {
+        0 => closest,
+        _ => transform_point(&context.inst2world[depth - 1], closest),
+    };
+
+    let distance = (0..3)
+        .map(|k| (closest[k] - state.point[k]).powi(2))
+        .sum::<f32>()
+        .sqrt();
+    if state
+        .closest
+        .as_ref()
+        .is_some_and(|best| best.distance <= distance)
+    {
+        return false;
+    }
+
+    state.closest = Some(ClosestPoint {
+        point: closest,
+        distance,
+        geom_id: args.geomID,
+        prim_id: args.primID,
+        inst_ids: instance_stack(context).to_vec(),
+    });
+
+    // Shrink the query radius, in the space of the query
+    let query = &mut *args.query;
+    query.radius = if args.similarityScale > 0.0 {
+        distance * args.similarityScale
+    } else {
+        distance
+    };
+    true
+}
+
+// Finds the closest point of a primitive of a triangle mesh or a sphere, at the first time step
+unsafe fn closest_in_primitive(shape: &QueryShape, prim_id: u32, p: [f32; 3]) -> Option<[f32; 3]> {
+    let buffer = |kind| embree4_sys::rtcGetGeometryBufferData(shape.geometry, kind, 0);
+    match shape.layout {
+        Layout::Triangles {
+            vertex_count,
+            triangle_count,
+            ..
+        } => {
+            let vertices = buffer(embree4_sys::RTCBufferType::VERTEX) as *const f32;
+            let indices = buffer(embree4_sys::RTCBufferType::INDEX) as *const u32;
+            if vertices.is_null() || indices.is_null() || prim_id as usize >= triangle_count {
+                return None;
+            }
+
+            let triangle = slice::from_raw_parts(indices.add(3 * prim_id as usize), 3);
+            if triangle.iter().any(|&i| i as usize >= vertex_count) {
+                return None;
+            }
+            let [a, b, c] = [0, 1, 2].map(|k| {
+                let vertex = slice::from_raw_parts(vertices.add(3 * triangle[k] as usize), 3);
+                [vertex[0], vertex[1], vertex[2]]
+            });
+            Some(closest_point_on_triangle(p, a, b, c))
+        }
+        Layout::Sphere => {
+            let vertices = buffer(embree4_sys::RTCBufferType::VERTEX) as *const f32;
+            if vertices.is_null() || prim_id != 0 {
+                return None;
+            }
+
+            let sphere = slice::from_raw_parts(vertices, 4);
+            let offset = [0, 1, 2].map(|k| p[k] - sphere[k]);
+            let len = offset.iter().map(|x| x * x).sum::<f32>().sqrt();
+            if len == 0.0 {
+                // Every point of the surface is as close
+                return Some([sphere[0] + sphere[3], sphere[1], sphere[2]]);
+            }
+            Some([0, 1, 2].map(|k| sphere[k] + sphere[3] * offset[k] / len))
+        }
+        Layout::Curves { .. } | Layout::Subdivision { .. } => None,
+    }
+}
+
+// From Real-Time Collision Detection, Christer Ericson, 5.1.5
+fn closest_point_on_triangle(p: [f32; 3], a: [f32; 3], b: [f32; 3], c: [f32; 3]) -> [f32; 3] {
+    let sub = |u: [f32; 3], v: [f32; 3]| [u[0] - v[0], u[1] - v[1], u[2] - v[2]];
+    let dot = |u: [f32; 3], v: [f32; 3]| u[0] * v[0] + u[1] * v[1] + u[2] * v[2];
+    let lerp = |u: [f32; 3], d: [f32; 3], t: f32| [0, 1, 2].map(|k| u[k] + t * d[k]);
+
+    let (ab, ac, ap) = (sub(b, a), sub(c, a), sub(p, a));
+    let (d1, d2) = (dot(ab, ap), dot(ac, ap));
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return a;
+    }
+
+    let bp = sub(p, b);
+    let (d3, d4) = (dot(ab, bp), dot(ac, bp));
+    if d3 >= 0.0 && d4 <= d3 {
+        return b;
+    }
+
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        return lerp(a, ab, d1 / (d1 - d3));
+    }
+
+    let cp = sub(p, c);
+    let (d5, d6) = (dot(ab, cp), dot(ac, cp));
+    if d6 >= 0.0 && d5 <= d6 {
+        return c;
+    }
+
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        return lerp(a, ac, d2 / (d2 - d6));
+    }
+
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && d4 - d3 >= 0.0 && d5 - d6 >= 0.0 {
+        return lerp(b, sub(c, b), (d4 - d3) / ((d4 - d3) + (d5 - d6)));
+    }
+
+    let denom = 1.0 / (va + vb + vc);
+    let (v, w) = (vb * denom, vc * denom);
+    [0, 1, 2].map(|k| a[k] + ab[k] * v + ac[k] * w)
+}
+
+#[test]
+fn closest_point_on_triangle_regions() {
+    let (a, b, c) = ([0.0, 0.0, 0.0], [2.0, 0.0, 0.0], [0.0, 2.0, 0.0]);
+
+    assert_eq!(closest_point_on_triangle([-1.0, -1.0, 0.0], a, b, c), a);
+    assert_eq!(
+        closest_point_on_triangle([1.0, -1.0, 3.0], a, b, c),
+        [1.0, 0.0, 0.0]
+    );
+    assert_eq!(
+        closest_point_on_triangle([2.0, 2.0, 0.0], a, b, c),
+        [1.0, 1.0, 0.0]
+    );
+    assert_eq!(
+        closest_point_on_triangle([0.5, 0.5, -4.0], a, b, c),
+        [0.5, 0.5, 0.0]
+    );
+}
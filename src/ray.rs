@@ -0,0 +1,786 @@
+use std::ops::{Deref, DerefMut};
+
+use embree4_sys::{
+    RTCHit16, RTCHit4, RTCHit8, RTCRay, RTCRay16, RTCRay4, RTCRay8, RTCRayHit, RTCRayHit16,
+    RTCRayHit4, RTCRayHit8, RTC_INVALID_GEOMETRY_ID,
+};
+
+/// A builder for [RTCRay].
+///
+/// Fields that are not set keep the values of `RTCRay::default()`.
+///
+/// # Example
+/// ```
+/// use embree4_rs::prelude::*;
+///
+/// let ray = RayBuilder::new((0.0, 0.0, 0.0), (0.0, 0.0, 1.0))
+///     .tnear(0.001)
+///     .tfar(100.0)
+///     .build();
+/// assert_eq!(ray.dir_z, 1.0);
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct RayBuilder {
+    ray: RTCRay,
+}
+
+impl RayBuilder {
+    /// Constructs a new `RayBuilder` for a ray with the given origin and direction.
+    ///
+    /// The direction does not need to be normalized. Hit distances are expressed in multiples of
+    /// its length.
+    pub fn new(origin: (f32, f32, f32), direction: (f32, f32, f32)) -> Self {
+        Self {
+            ray: RTCRay {
+                org_x: origin.0,
+                org_y: origin.1,
+                org_z: origin.2,
+                dir_x: direction.0,
+                dir_y: direction.1,
+                dir_z: direction.2,
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Constructs a new `RayBuilder` for a ray leaving `origin` in a direction sampled from the
+    /// hemisphere around `normal` with a cosine-weighted density, e.g. for diffuse bounces or
+    /// ambient occlusion.
+    ///
+    /// The direction is normalized, and `u1` and `u2` are uniform random numbers in `[0, 1)`. The
+    /// density of the direction is `cos(theta) / pi`, with `theta` the angle to the normal.
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::prelude::*;
+    ///
+    /// let ray = RayBuilder::cosine_hemisphere((0.0, 0.0, 0.0), (0.0, 1.0, 0.0), 0.3, 0.7)
+    ///     .tnear(1e-3)
+    ///     .build();
+    /// assert!(ray.dir_y > 0.0);
+    /// ```
+    pub fn cosine_hemisphere(
+        origin: (f32, f32, f32),
+        normal: (f32, f32, f32),
+        u1: f32,
+        u2: f32,
+    ) -> Self {
+        // Malley's method: project a uniform sample of the unit disk onto the hemisphere
+        let r = u1.sqrt();
+        let phi = 2.0 * std::f32::consts::PI * u2;
+        let local = [r * phi.cos(), r * phi.sin(), (1.0 - u1).max(0.0).sqrt()];
+
+        let [tangent, bitangent, normal] = orthonormal_basis([normal.0, normal.1, normal.2]);
+        let [x, y, z] = [0, 1, 2]
+            .map(|k| local[0] * tangent[k] + local[1] * bitangent[k] + local[2] * normal[k]);
+        Self::new(origin, (x, y, z))
+    }
+
+    /// Constructs a new `RayBuilder` for a ray leaving `origin` in a direction sampled uniformly
+    /// on the unit sphere, e.g. for environment sampling or point lights.
+    ///
+    /// The direction is normalized, and `u1` and `u2` are uniform random numbers in `[0, 1)`. The
+    /// density of the direction is `1 / (4 * pi)`.
+    pub fn uniform_sphere(origin: (f32, f32, f32), u1: f32, u2: f32) -> Self {
+        let z = 1.0 - 2.0 * u1;
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * std::f32::consts::PI * u2;
+        Self::new(origin, (r * phi.cos(), r * phi.sin(), z))
+    }
+
+    /// Sets the start of the ray segment.
+    pub fn tnear(mut self, tnear: f32) -> Self {
+        self.ray.tnear = tnear;
+        self
+    }
+
+    /// Sets the end of the ray segment.
+    pub fn tfar(mut self, tfar: f32) -> Self {
+        self.ray.tfar = tfar;
+        self
+    }
+
+    /// Sets the time of the ray for motion blur.
+    ///
+    /// Embree only supports times in `[0, 1]`, the range covered by the time steps of motion
+    /// blurred geometries, and silently clamps other values. The time is clamped here instead,
+    /// and a warning is logged in debug builds with the `log` feature, as an out-of-range time
+    /// usually reveals a bug in the sampling of the caller. `NaN` is replaced by `0`.
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::prelude::*;
+    ///
+    /// let ray = RayBuilder::new((0.0, 0.0, 0.0), (0.0, 0.0, 1.0)).time(1.5).build();
+    /// assert_eq!(ray.time, 1.0);
+    /// ```
+    pub fn time(mut self, time: f32) -> Self {
+        let clamped = if time.is_nan() {
+            0.0
+        } else {
+            time.clamp(0.0, 1.0)
+        };
+
+        #[cfg(all(debug_assertions, feature = "log"))]
+        if clamped != time {
+            log::warn!("Ray time {time} is outside of [0, 1], clamped to {clamped}");
+        }
+
+        self.ray.time = clamped;
+        self
+    }
+
+    /// Sets the time of the ray to a sample drawn from `sampler`, for motion blur.
+    ///
+    /// The sampler is expected to return values uniformly distributed in `[0, 1]`, the time range
+    /// covered by the time steps of motion blurred geometries.
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::prelude::*;
+    /// use rand::Rng;
+    ///
+    /// let mut rng = rand::thread_rng();
+    /// let ray = RayBuilder::new((0.0, 0.0, 0.0), (0.0, 0.0, 1.0))
+    ///     .time_jitter(|| rng.gen())
+    ///     .build();
+    /// assert!((0.0..=1.0).contains(&ray.time));
+    /// ```
+    pub fn time_jitter<F: FnOnce() -> f32>(self, sampler: F) -> Self {
+        self.time(sampler())
+    }
+
+    /// Sets the ray mask, matched against the geometry masks.
+    pub fn mask(mut self, mask: u32) -> Self {
+        self.ray.mask = mask;
+        self
+    }
+
+    /// Sets the ray ID, a user tag preserved through queries and readable from the returned
+    /// [RTCRayHit](embree4_sys::RTCRayHit).
+    pub fn id(mut self, id: u32) -> Self {
+        self.ray.id = id;
+        self
+    }
+
+    /// Sets the ray flags, user bits preserved through queries like the ray ID.
+    pub fn flags(mut self, flags: u32) -> Self {
+        self.ray.flags = flags;
+        self
+    }
+
+    /// Returns the built ray.
+    pub fn build(self) -> RTCRay {
+        self.ray
+    }
+}
+
+// Returns two unit vectors completing the direction of `normal` into an orthonormal basis,
+// followed by the normalized normal. From Building an Orthonormal Basis, Revisited, Duff et al.
+fn orthonormal_basis(normal: [f32; 3]) -> [[f32; 3]; 3] {
+    let length = normal.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let [x, y, z] = if length > 0.0 {
+        normal.map(|c| c / length)
+    } else {
+        [0.0, 0.0, 1.0]
+    };
+
+    let sign = 1.0f32.copysign(z);
+    let a = -1.0 / (sign + z);
+    let b = x * y * a;
+    [
+        [1.0 + sign * x * x * a, sign * b, -sign * x],
+        [b, sign + y * y * a, -y],
+        [x, y, z],
+    ]
+}
+
+/// Transforms a ray by an affine transform.
+///
+/// The transform is a column-major 4x4 matrix whose last row is `[0, 0, 0, 1]`. The origin is
+/// transformed as a point and the direction as a vector. The direction is not normalized, so that
+/// `tnear`, `tfar` and hit distances keep the same meaning in both spaces.
+///
+/// # Example
+/// ```
+/// use embree4_rs::{prelude::*, ray::transform_ray};
+///
+/// let translate_x = [
+///     1.0, 0.0, 0.0, 0.0,
+///     0.0, 1.0, 0.0, 0.0,
+///     0.0, 0.0, 1.0, 0.0,
+///     5.0, 0.0, 0.0, 1.0,
+/// ];
+/// let ray = RayBuilder::new((0.0, 0.0, 0.0), (0.0, 0.0, 1.0)).build();
+/// let ray = transform_ray(&translate_x, &ray);
+/// assert_eq!((ray.org_x, ray.dir_x), (5.0, 0.0));
+/// ```
+pub fn transform_ray(xfm: &[f32; 16], ray: &RTCRay) -> RTCRay {
+    let m = |row: usize, col: usize| xfm[4 * col + row];
+    let [org_x, org_y, org_z] = [0, 1, 2]
+        .map(|r| m(r, 0) * ray.org_x + m(r, 1) * ray.org_y + m(r, 2) * ray.org_z + m(r, 3));
+    let [dir_x, dir_y, dir_z] =
+        [0, 1, 2].map(|r| m(r, 0) * ray.dir_x + m(r, 1) * ray.dir_y + m(r, 2) * ray.dir_z);
+
+    RTCRay {
+        org_x,
+        org_y,
+        org_z,
+        dir_x,
+        dir_y,
+        dir_z,
+        ..*ray
+    }
+}
+
+/// Transforms a ray by the inverse of an affine transform, e.g. from world space to the object
+/// space of a geometry placed with `xfm`.
+///
+/// See [transform_ray] for the layout of the transform.
+///
+/// # Returns
+/// The transformed ray, or `None` if the transform is not invertible.
+pub fn inverse_transform_ray(xfm: &[f32; 16], ray: &RTCRay) -> Option<RTCRay> {
+    let m = |row: usize, col: usize| xfm[4 * col + row];
+
+    // Inverse of the linear part from its cofactors
+    let cofactor = |r: usize, c: usize| {
+        let (r0, r1) = ((r + 1) % 3, (r + 2) % 3);
+        let (c0, c1) = ((c + 1) % 3, (c + 2) % 3);
+        m(r0, c0) * m(r1, c1) - m(r0, c1) * m(r1, c0)
+    };
+    let det = (0..3).map(|c| m(0, c) * cofactor(0, c)).sum::<f32>();
+    if det == 0.0 || !det.is_finite() {
+        return None;
+    }
+
+    let mut inverse = [0.0; 16];
+    for r in 0..3 {
+        for c in 0..3 {
+            inverse[4 * c + r] = cofactor(c, r) / det;
+        }
+    }
+    for r in 0..3 {
+        inverse[12 + r] = -(0..3).map(|c| inverse[4 * c + r] * m(c, 3)).sum::<f32>();
+    }
+    inverse[15] = 1.0;
+
+    Some(transform_ray(&inverse, ray))
+}
+
+/// A ray hit, in world space.
+///
+/// Produced by [CommittedScene::intersect](crate::scene::CommittedScene::intersect), or converted
+/// from a raw [RTCRayHit].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Intersection {
+    /// Distance along the ray, in multiples of the length of its direction.
+    pub t: f32,
+    /// Hit point, `org + t * dir`.
+    pub point: [f32; 3],
+    /// Normalized geometric normal. Its orientation follows the winding of the primitive and does
+    /// not necessarily face the ray.
+    ///
+    /// Embree reports the normal in the space of the innermost instance, see
+    /// [world_normal](Self::world_normal) and
+    /// [CommittedScene::intersect_world](crate::scene::CommittedScene::intersect_world).
+    pub normal: [f32; 3],
+    /// Hit coordinates in the primitive, whose meaning depends on the geometry type:
+    ///
+    /// * triangles: the barycentric coordinates of the hit;
+    /// * curves: `u` is the curve parameter of the hit along the segment, see
+    ///   [curve_param](Self::curve_param);
+    /// * spheres: Embree reports zero, use [sphere_uv](Self::sphere_uv) instead.
+    pub uv: [f32; 2],
+    pub geom_id: u32,
+    pub prim_id: u32,
+    /// Ids of the instances traversed to reach the geometry, outermost first. Empty when the
+    /// geometry was hit directly.
+    pub inst_ids: Vec<u32>,
+}
+
+impl Intersection {
+    /// Returns the spherical coordinates of a hit on a sphere, both in `[0, 1]`.
+    ///
+    /// They are derived from the normal with `+y` as the pole axis: `u` is the longitude, growing
+    /// from `-x` through `-z`, `+x` and `+z`, and `v` the colatitude, from `0` at the `+y` pole
+    /// to `1` at the `-y` pole. Only meaningful for hits on a
+    /// [SphereGeometry](crate::geometry::SphereGeometry).
+    pub fn sphere_uv(&self) -> [f32; 2] {
+        let [x, y, z] = self.normal;
+        let u = 0.5 + z.atan2(x) / (2.0 * std::f32::consts::PI);
+        let v = y.clamp(-1.0, 1.0).acos() / std::f32::consts::PI;
+        [u, v]
+    }
+
+    /// Returns the normal transformed to world space by the local to world transform of the hit,
+    /// as returned by [CommittedScene::world_transform](crate::scene::CommittedScene::world_transform).
+    ///
+    /// Normals are transformed by the inverse transpose of the linear part of the transform, so
+    /// that they stay orthogonal to the surface under non-uniform scaling. The result is
+    /// normalized, and the normal is returned unchanged if the transform is not invertible.
+    pub fn world_normal(&self, world_transform: &[f32; 16]) -> [f32; 3] {
+        let m = |row: usize, col: usize| world_transform[4 * col + row];
+        let cofactor = |r: usize, c: usize| {
+            let (r0, r1) = ((r + 1) % 3, (r + 2) % 3);
+            let (c0, c1) = ((c + 1) % 3, (c + 2) % 3);
+            m(r0, c0) * m(r1, c1) - m(r0, c1) * m(r1, c0)
+        };
+        let det = (0..3).map(|c| m(0, c) * cofactor(0, c)).sum::<f32>();
+        if det == 0.0 || !det.is_finite() {
+            return self.normal;
+        }
+
+        // The inverse transpose is the cofactor matrix divided by the determinant, only its sign
+        // matters before normalizing
+        let n = self.normal;
+        let normal =
+            [0, 1, 2].map(|r| det.signum() * (0..3).map(|c| cofactor(r, c) * n[c]).sum::<f32>());
+        let length = normal.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if length > 0.0 {
+            normal.map(|x| x / length)
+        } else {
+            normal
+        }
+    }
+
+    /// Returns the curve parameter of a hit on a curve segment, in `[0, 1]` from the start to the
+    /// end of the segment. Only meaningful for hits on a
+    /// [CurveGeometry](crate::geometry::CurveGeometry).
+    pub fn curve_param(&self) -> f32 {
+        self.uv[0]
+    }
+}
+
+impl From<&RTCRayHit> for Intersection {
+    fn from(ray_hit: &RTCRayHit) -> Self {
+        let RTCRayHit { ray, hit } = ray_hit;
+
+        let t = ray.tfar;
+        let normal = [hit.Ng_x, hit.Ng_y, hit.Ng_z];
+        let length = normal.iter().map(|x| x * x).sum::<f32>().sqrt();
+        let normal = if length > 0.0 {
+            normal.map(|x| x / length)
+        } else {
+            normal
+        };
+
+        Self {
+            t,
+            point: [
+                ray.org_x + t * ray.dir_x,
+                ray.org_y + t * ray.dir_y,
+                ray.org_z + t * ray.dir_z,
+            ],
+            normal,
+            uv: [hit.u, hit.v],
+            geom_id: hit.geomID,
+            prim_id: hit.primID,
+            inst_ids: hit
+                .instID
+                .iter()
+                .copied()
+                .take_while(|&id| id != RTC_INVALID_GEOMETRY_ID)
+                .collect(),
+        }
+    }
+}
+
+impl From<RTCRayHit> for Intersection {
+    fn from(ray_hit: RTCRayHit) -> Self {
+        Self::from(&ray_hit)
+    }
+}
+
+/// Comparison of the results of several queries of the same ray, e.g. against split scenes, to
+/// keep the nearest hit.
+///
+/// Implemented by [RTCRayHit], which misses when its `geomID` is `RTC_INVALID_GEOMETRY_ID`, and
+/// by the `Option<RTCRayHit>` returned by
+/// [CommittedScene::intersect_1](crate::scene::CommittedScene::intersect_1), which misses when it
+/// is `None`.
+///
+/// # Example
+/// ```
+/// use embree4_rs::prelude::*;
+///
+/// let device = Device::try_new(None).unwrap();
+/// let near = SphereGeometry::try_new(&device, (0.0, 0.0, 5.0), 1.0).unwrap();
+/// let far = SphereGeometry::try_new(&device, (0.0, 0.0, 10.0), 1.0).unwrap();
+/// let scenes = [&near, &far].map(|sphere| {
+///     let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+///     scene.attach_geometry(sphere).unwrap();
+///     scene
+/// });
+///
+/// let ray = RayBuilder::new((0.0, 0.0, 0.0), (0.0, 0.0, 1.0)).build();
+/// let mut nearest = None;
+/// for scene in scenes.iter().rev() {
+///     let scene = scene.commit().unwrap();
+///     nearest.merge_into(scene.intersect_1(ray).unwrap());
+/// }
+/// assert!((nearest.distance() - 4.0).abs() < 1e-4);
+/// ```
+pub trait RayHit: Sized {
+    /// Returns `true` if the ray hit something.
+    fn is_hit(&self) -> bool;
+
+    /// Returns the distance of the hit along the ray, `tfar`, or infinity for a miss.
+    fn distance(&self) -> f32;
+
+    /// Returns the nearer of two hits of the same ray, misses being infinitely far. `a` is kept
+    /// on ties.
+    fn min(a: Self, b: Self) -> Self {
+        if b.distance() < a.distance() {
+            b
+        } else {
+            a
+        }
+    }
+
+    /// Replaces `self` with `other` if `other` is nearer.
+    fn merge_into(&mut self, other: Self) {
+        if other.distance() < self.distance() {
+            *self = other;
+        }
+    }
+}
+
+impl RayHit for RTCRayHit {
+    fn is_hit(&self) -> bool {
+        self.hit.geomID != RTC_INVALID_GEOMETRY_ID
+    }
+
+    fn distance(&self) -> f32 {
+        if self.is_hit() {
+            self.ray.tfar
+        } else {
+            f32::INFINITY
+        }
+    }
+}
+
+impl RayHit for Option<RTCRayHit> {
+    fn is_hit(&self) -> bool {
+        self.as_ref().is_some_and(RayHit::is_hit)
+    }
+
+    fn distance(&self) -> f32 {
+        self.as_ref().map_or(f32::INFINITY, RayHit::distance)
+    }
+}
+
+/// Composes two column-major 4x4 transforms, applying `inner` first.
+pub(crate) fn compose_transforms(outer: &[f32; 16], inner: &[f32; 16]) -> [f32; 16] {
+    std::array::from_fn(|i| {
+        let (c, r) = (i / 4, i % 4);
+        (0..4).map(|k| outer[4 * k + r] * inner[4 * c + k]).sum()
+    })
+}
+
+macro_rules! aligned_ray_hit {
+    ($(#[$doc:meta])* $name:ident, $ray_hit:ident, $ray:ident, $hit:ident, $align:literal) => {
+        $(#[$doc])*
+        #[repr(C, align($align))]
+        #[derive(Debug, Clone, Copy)]
+        pub struct $name(pub $ray_hit);
+
+        impl $name {
+            /// Packs the given rays, with every lane of the hit reset to no hit.
+            pub fn new(ray: $ray) -> Self {
+                let mut hit = $hit::default();
+                hit.primID.fill(RTC_INVALID_GEOMETRY_ID);
+                hit.geomID.fill(RTC_INVALID_GEOMETRY_ID);
+                for inst_id in hit.instID.iter_mut() {
+                    inst_id.fill(RTC_INVALID_GEOMETRY_ID);
+                }
+                Self($ray_hit { ray, hit })
+            }
+
+            /// Returns the pointer to pass to the packet query functions.
+            pub fn as_mut_ptr(&mut self) -> *mut $ray_hit {
+                &mut self.0
+            }
+        }
+
+        /// Rays are zeroed, and thus have an empty `[0, 0]` range and a zero mask.
+        impl Default for $name {
+            fn default() -> Self {
+                Self::new($ray::default())
+            }
+        }
+
+        impl Deref for $name {
+            type Target = $ray_hit;
+
+            fn deref(&self) -> &$ray_hit {
+                &self.0
+            }
+        }
+
+        impl DerefMut for $name {
+            fn deref_mut(&mut self) -> &mut $ray_hit {
+                &mut self.0
+            }
+        }
+    };
+}
+
+aligned_ray_hit!(
+    /// A packet of 4 rays and hits, aligned to 16 bytes as required by
+    /// [rtcIntersect4](embree4_sys::rtcIntersect4), including when stored on the heap.
+    AlignedRayHit4,
+    RTCRayHit4,
+    RTCRay4,
+    RTCHit4,
+    16
+);
+aligned_ray_hit!(
+    /// A packet of 8 rays and hits, aligned to 32 bytes as required by
+    /// [rtcIntersect8](embree4_sys::rtcIntersect8), including when stored on the heap.
+    AlignedRayHit8,
+    RTCRayHit8,
+    RTCRay8,
+    RTCHit8,
+    32
+);
+aligned_ray_hit!(
+    /// A packet of 16 rays and hits, aligned to 64 bytes as required by
+    /// [rtcIntersect16](embree4_sys::rtcIntersect16), including when stored on the heap.
+    AlignedRayHit16,
+    RTCRayHit16,
+    RTCRay16,
+    RTCHit16,
+    64
+);
+
+impl From<RayBuilder> for RTCRay {
+    fn from(builder: RayBuilder) -> Self {
+        builder.build()
+    }
+}
+
+#[test]
+fn intersect_child_in_object_space() {
+    use crate::{
+        device::Device,
+        geometry::SphereGeometry,
+        scene::{Scene, SceneOptions},
+    };
+
+    let device = Device::try_new(None).unwrap();
+    let child = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 0.0), 1.0).unwrap();
+    child.attach_geometry(&sphere).unwrap();
+    let child = child.commit().unwrap();
+
+    #[rustfmt::skip]
+    let translate_x = [
+        1.0, 0.0, 0.0, 0.0,
+        0.0, 1.0, 0.0, 0.0,
+        0.0, 0.0, 1.0, 0.0,
+        5.0, 0.0, 0.0, 1.0,
+    ];
+    #[rustfmt::skip]
+    let scale_translate_x = [
+        2.0, 0.0, 0.0, 0.0,
+        0.0, 2.0, 0.0, 0.0,
+        0.0, 0.0, 2.0, 0.0,
+        5.0, 0.0, 0.0, 1.0,
+    ];
+
+    let world_ray = RayBuilder::new((5.0, 0.0, -5.0), (0.0, 0.0, 1.0)).build();
+    for (xfm, world_t) in [(translate_x, 4.0), (scale_translate_x, 3.0)] {
+        let object_ray = inverse_transform_ray(&xfm, &world_ray).unwrap();
+        let hit = child.intersect_1(object_ray).unwrap().unwrap();
+        assert!((hit.ray.tfar - world_t).abs() < 1e-4);
+
+        let round_trip = transform_ray(&xfm, &object_ray);
+        assert!((round_trip.org_x - world_ray.org_x).abs() < 1e-5);
+        assert!((round_trip.dir_z - world_ray.dir_z).abs() < 1e-5);
+    }
+
+    assert!(inverse_transform_ray(&[0.0; 16], &world_ray).is_none());
+}
+
+#[test]
+fn sphere_uv_on_equator() {
+    use crate::{
+        device::Device,
+        geometry::SphereGeometry,
+        scene::{Scene, SceneOptions},
+    };
+
+    let device = Device::try_new(None).unwrap();
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 0.0), 1.0).unwrap();
+    scene.attach_geometry(&sphere).unwrap();
+    let scene = scene.commit().unwrap();
+
+    // Hits on the equator facing -z, then -x
+    for (origin, direction, expected_u) in [
+        ((0.0, 0.0, -5.0), (0.0, 0.0, 1.0), 0.25),
+        ((-5.0, 0.0, 0.0), (1.0, 0.0, 0.0), 0.0),
+    ] {
+        let ray = RayBuilder::new(origin, direction).build();
+        let [u, v] = scene.intersect(ray).unwrap().unwrap().sphere_uv();
+        assert!((u - expected_u).abs() < 1e-4 || (u - expected_u - 1.0).abs() < 1e-4);
+        assert!((v - 0.5).abs() < 1e-4);
+    }
+}
+
+#[test]
+fn aligned_packets_in_a_vec() {
+    use crate::{
+        camera::pack_rays,
+        device::Device,
+        geometry::SphereGeometry,
+        scene::{Scene, SceneOptions},
+    };
+
+    let device = Device::try_new(None).unwrap();
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 5.0), 1.0).unwrap();
+    scene.attach_geometry(&sphere).unwrap();
+    let scene = scene.commit().unwrap();
+
+    // Lanes 0 and 2 hit the sphere, lanes 1 and 3 miss it
+    let rays: [RTCRay; 4] = std::array::from_fn(|i| {
+        RayBuilder::new((3.0 * (i % 2) as f32, 0.0, 0.0), (0.0, 0.0, 1.0)).build()
+    });
+    let mut packets = vec![AlignedRayHit4::new(pack_rays!(RTCRay4, rays)); 3];
+    packets.push(AlignedRayHit4::default());
+
+    for packet in packets.iter_mut() {
+        assert_eq!(packet.as_mut_ptr() as usize % 16, 0);
+    }
+    assert_eq!(AlignedRayHit8::default().as_mut_ptr() as usize % 32, 0);
+    assert_eq!(AlignedRayHit16::default().as_mut_ptr() as usize % 64, 0);
+
+    let valid = [-1i32; 4];
+    let packet = &mut packets[1];
+    unsafe {
+        embree4_sys::rtcIntersect4(
+            valid.as_ptr(),
+            scene.handle,
+            packet.as_mut_ptr(),
+            std::ptr::null_mut(),
+        );
+    }
+    assert_eq!(packet.hit.geomID[0], 0);
+    assert_eq!(packet.hit.geomID[1], RTC_INVALID_GEOMETRY_ID);
+    assert!((packet.ray.tfar[2] - 4.0).abs() < 1e-4);
+    assert_eq!(packet.hit.geomID[3], RTC_INVALID_GEOMETRY_ID);
+}
+
+#[test]
+fn out_of_range_time_is_clamped() {
+    let ray = |time| {
+        RayBuilder::new((0.0, 0.0, 0.0), (0.0, 0.0, 1.0))
+            .time(time)
+            .build()
+    };
+    assert_eq!(ray(0.25).time, 0.25);
+    assert_eq!(ray(-0.5).time, 0.0);
+    assert_eq!(ray(2.0).time, 1.0);
+    assert_eq!(ray(f32::NAN).time, 0.0);
+
+    #[cfg(all(debug_assertions, feature = "log"))]
+    {
+        let records = crate::captured_log_records();
+        let _ = ray(2.0);
+        assert!(records
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|(level, message)| *level == log::Level::Warn
+                && message == "Ray time 2 is outside of [0, 1], clamped to 1"));
+    }
+}
+
+#[test]
+fn cosine_hemisphere_samples_face_the_normal() {
+    let dot = |ray: &RTCRay, n: [f32; 3]| ray.dir_x * n[0] + ray.dir_y * n[1] + ray.dir_z * n[2];
+    let length = |ray: &RTCRay| (ray.dir_x.powi(2) + ray.dir_y.powi(2) + ray.dir_z.powi(2)).sqrt();
+
+    let normals = [
+        [0.0, 0.0, 1.0],
+        [0.0, 0.0, -1.0],
+        [1.0, 0.0, 0.0],
+        [0.0, -1.0, 0.0],
+        [0.6, -0.48, 0.64],
+    ];
+    for n in normals {
+        let mut mean_cos = 0.0;
+        let steps = 64;
+        for i in 0..steps {
+            for j in 0..steps {
+                let (u1, u2) = (i as f32 / steps as f32, (j as f32 + 0.5) / steps as f32);
+                let ray =
+                    RayBuilder::cosine_hemisphere((1.0, 2.0, 3.0), (n[0], n[1], n[2]), u1, u2)
+                        .build();
+                assert_eq!((ray.org_x, ray.org_y, ray.org_z), (1.0, 2.0, 3.0));
+                assert!((length(&ray) - 1.0).abs() < 1e-5);
+                assert!(dot(&ray, n) > 0.0);
+                mean_cos += dot(&ray, n) / (steps * steps) as f32;
+            }
+        }
+        // E[cos] = 2 / 3 for a cosine-weighted hemisphere
+        assert!((mean_cos - 2.0 / 3.0).abs() < 1e-2);
+    }
+
+    let mut mean = [0.0f32; 3];
+    for i in 0..64 {
+        for j in 0..64 {
+            let (u1, u2) = ((i as f32 + 0.5) / 64.0, (j as f32 + 0.5) / 64.0);
+            let ray = RayBuilder::uniform_sphere((0.0, 0.0, 0.0), u1, u2).build();
+            assert!((length(&ray) - 1.0).abs() < 1e-5);
+            mean = [
+                mean[0] + ray.dir_x,
+                mean[1] + ray.dir_y,
+                mean[2] + ray.dir_z,
+            ];
+        }
+    }
+    assert!(mean.iter().all(|m| (m / 4096.0).abs() < 1e-3));
+}
+
+#[test]
+fn merging_keeps_the_nearest_hit() {
+    let hit_at = |tfar: f32, geom_id: u32| {
+        let mut ray_hit = RTCRayHit {
+            ray: RayBuilder::new((0.0, 0.0, 0.0), (0.0, 0.0, 1.0))
+                .tfar(tfar)
+                .build(),
+            hit: Default::default(),
+        };
+        ray_hit.hit.geomID = geom_id;
+        ray_hit
+    };
+    let miss = hit_at(f32::INFINITY, RTC_INVALID_GEOMETRY_ID);
+    let (near, far) = (hit_at(2.0, 0), hit_at(5.0, 1));
+    assert!(!miss.is_hit());
+    assert_eq!(miss.distance(), f32::INFINITY);
+
+    // A hit and a miss, in both orders
+    assert_eq!(RayHit::min(miss, far).hit.geomID, 1);
+    assert_eq!(RayHit::min(far, miss).hit.geomID, 1);
+    // Two hits at different distances
+    assert_eq!(RayHit::min(far, near).hit.geomID, 0);
+    assert_eq!(RayHit::min(near, far).hit.geomID, 0);
+
+    let mut merged = miss;
+    merged.merge_into(far);
+    assert_eq!(merged.distance(), 5.0);
+    merged.merge_into(near);
+    assert_eq!(merged.distance(), 2.0);
+    merged.merge_into(far);
+    merged.merge_into(miss);
+    assert_eq!(merged.hit.geomID, 0);
+
+    let mut merged: Option<RTCRayHit> = None;
+    merged.merge_into(Some(far));
+    merged.merge_into(None);
+    assert_eq!(merged.distance(), 5.0);
+    assert!(RayHit::min(Some(near), merged).is_some_and(|hit| hit.hit.geomID == 0));
+}
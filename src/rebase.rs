@@ -0,0 +1,164 @@
+use anyhow::Result;
+use embree4_sys::RTCRayHit;
+
+use crate::{ray::RayBuilder, scene::CommittedScene};
+
+/// A committed scene placed at a double precision world origin.
+///
+/// Embree works in single precision, which only resolves about 8 units at a distance of `1e8`
+/// from the origin. Large worlds keep their positions in `f64` and author the geometry of the
+/// scene relative to a nearby origin with [RebasedScene::local_point]. Rays given in world
+/// coordinates are rebased to that origin before being traced, so that only the small local
+/// offsets are rounded to `f32`.
+///
+/// # Example
+/// ```
+/// use embree4_rs::prelude::*;
+///
+/// let origin = [6.4e6, 0.0, 0.0];
+/// let device = Device::try_new(None).unwrap();
+/// let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+/// let center = RebasedScene::local_point(origin, [6.4e6 + 1.5, 0.0, 10.0]);
+/// let sphere = SphereGeometry::try_new(&device, center, 0.5).unwrap();
+/// scene.attach_geometry(&sphere).unwrap();
+///
+/// let scene = RebasedScene::new(scene.commit().unwrap(), origin);
+/// let hit = scene
+///     .intersect_1([6.4e6 + 1.5, 0.0, 0.0], (0.0, 0.0, 1.0))
+///     .unwrap()
+///     .unwrap();
+/// let point = scene.hit_point(&hit);
+/// ```
+pub struct RebasedScene<'a> {
+    scene: CommittedScene<'a>,
+    origin: [f64; 3],
+}
+
+impl<'a> RebasedScene<'a> {
+    /// Places `scene` at `origin`, in world coordinates.
+    pub fn new(scene: CommittedScene<'a>, origin: [f64; 3]) -> Self {
+        Self { scene, origin }
+    }
+
+    /// Returns the world origin of the scene.
+    pub fn origin(&self) -> [f64; 3] {
+        self.origin
+    }
+
+    /// Returns the underlying scene, which uses local coordinates.
+    pub fn scene(&self) -> &CommittedScene<'a> {
+        &self.scene
+    }
+
+    /// Returns the underlying scene, which uses local coordinates.
+    pub fn into_inner(self) -> CommittedScene<'a> {
+        self.scene
+    }
+
+    /// Converts a world position into the local coordinates of a scene placed at `origin`.
+    ///
+    /// Use this to build the geometry of the scene before it is committed.
+    pub fn local_point(origin: [f64; 3], point: [f64; 3]) -> (f32, f32, f32) {
+        (
+            (point[0] - origin[0]) as f32,
+            (point[1] - origin[1]) as f32,
+            (point[2] - origin[2]) as f32,
+        )
+    }
+
+    /// Converts a world position into the local coordinates of the scene.
+    pub fn to_local(&self, point: [f64; 3]) -> (f32, f32, f32) {
+        Self::local_point(self.origin, point)
+    }
+
+    /// Converts a position in the local coordinates of the scene into world coordinates.
+    pub fn to_world(&self, point: (f32, f32, f32)) -> [f64; 3] {
+        [
+            self.origin[0] + point.0 as f64,
+            self.origin[1] + point.1 as f64,
+            self.origin[2] + point.2 as f64,
+        ]
+    }
+
+    /// Finds the closest hit of a ray starting at `origin`, in world coordinates.
+    ///
+    /// The returned hit is in the local coordinates of the scene, see [RebasedScene::hit_point].
+    ///
+    /// # Returns
+    /// A `Result` containing an `Option` with the hit if one was found, or an error if an error
+    /// occurred.
+    pub fn intersect_1(
+        &self,
+        origin: [f64; 3],
+        direction: (f32, f32, f32),
+    ) -> Result<Option<RTCRayHit>> {
+        let ray = RayBuilder::new(self.to_local(origin), direction).build();
+        self.scene.intersect_1(ray)
+    }
+
+    /// Checks whether a ray starting at `origin`, in world coordinates, hits anything before
+    /// `max_dist`.
+    pub fn occluded_1(
+        &self,
+        origin: [f64; 3],
+        direction: (f32, f32, f32),
+        max_dist: f32,
+    ) -> Result<bool> {
+        let ray = RayBuilder::new(self.to_local(origin), direction)
+            .tfar(max_dist)
+            .build();
+        self.scene.occluded_1(ray)
+    }
+
+    /// Returns the world position of a hit returned by [RebasedScene::intersect_1].
+    pub fn hit_point(&self, hit: &RTCRayHit) -> [f64; 3] {
+        let ray = &hit.ray;
+        let t = ray.tfar as f64;
+        [
+            self.origin[0] + ray.org_x as f64 + t * ray.dir_x as f64,
+            self.origin[1] + ray.org_y as f64 + t * ray.dir_y as f64,
+            self.origin[2] + ray.org_z as f64 + t * ray.dir_z as f64,
+        ]
+    }
+}
+
+#[test]
+fn rebasing_keeps_far_geometry_hittable() {
+    use crate::{
+        device::Device,
+        geometry::TriangleMeshGeometry,
+        scene::{Scene, SceneOptions},
+    };
+
+    // f32 only represents multiples of 8 around 1e8: without rebasing, the apex of the triangle
+    // and the ray both snap to y = 1e8, where the ray passes next to the apex
+    let origin = [0.0, 1e8, 0.0];
+    let world_vertices = [
+        [0.0, 1e8 + 3.0, 0.0],
+        [-1.0, 1e8 + 5.0, 0.0],
+        [1.0, 1e8 + 5.0, 0.0],
+    ];
+    let ray_origin = [0.3, 1e8 + 3.9, -10.0];
+    let direction = (0.0, 0.0, 1.0);
+
+    let device = Device::try_new(None).unwrap();
+    let scene_of = |origin: [f64; 3]| {
+        let vertices = world_vertices.map(|v| RebasedScene::local_point(origin, v));
+        let mesh = TriangleMeshGeometry::try_new(&device, &vertices, &[(0, 1, 2)]).unwrap();
+        let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+        scene.attach_owned(mesh).unwrap();
+        scene
+    };
+
+    let single_scene = scene_of([0.0; 3]);
+    let single = RebasedScene::new(single_scene.commit().unwrap(), [0.0; 3]);
+    assert!(single.intersect_1(ray_origin, direction).unwrap().is_none());
+
+    let rebased_scene = scene_of(origin);
+    let rebased = RebasedScene::new(rebased_scene.commit().unwrap(), origin);
+    let hit = rebased.intersect_1(ray_origin, direction).unwrap().unwrap();
+    let point = rebased.hit_point(&hit);
+    assert!((point[0] - 0.3).abs() < 1e-3);
+    assert!((point[1] - (1e8 + 3.9)).abs() < 1e-3);
+    assert!(point[2].abs() < 1e-3);
+}
@@ -0,0 +1,231 @@
+use anyhow::{bail, Result};
+use embree4_sys::RTCRayHit;
+
+use crate::{camera::Camera, scene::CommittedScene};
+
+impl CommittedScene<'_> {
+    /// Renders an image of the scene seen from `camera`.
+    ///
+    /// One primary ray is traced through the center of each pixel, and `shade` computes the color
+    /// of the pixel from its closest hit, or `None` if the ray missed the scene. Rows are
+    /// parallelized with the `rayon` feature.
+    ///
+    /// # Returns
+    /// A `Result` containing the `width * height` pixels in row-major order, starting from the top
+    /// left corner of the image.
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::prelude::*;
+    ///
+    /// let device = Device::try_new(None).unwrap();
+    /// let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    /// let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 5.0), 1.0).unwrap();
+    /// scene.attach_geometry(&sphere).unwrap();
+    /// let scene = scene.commit().unwrap();
+    ///
+    /// let (width, height) = (320, 240);
+    /// let camera = Camera::look_at(
+    ///     (0.0, 0.0, 0.0),
+    ///     (0.0, 0.0, 1.0),
+    ///     (0.0, 1.0, 0.0),
+    ///     60f32.to_radians(),
+    ///     width as f32 / height as f32,
+    /// );
+    /// let normals = scene
+    ///     .render_to_buffer(&camera, |hit| match hit {
+    ///         Some(hit) => [hit.hit.Ng_x, hit.hit.Ng_y, hit.hit.Ng_z],
+    ///         None => [0.0; 3],
+    ///     }, width, height)
+    ///     .unwrap();
+    /// assert_eq!(normals.len(), width * height);
+    /// ```
+    pub fn render_to_buffer<F>(
+        &self,
+        camera: &Camera,
+        shade: F,
+        width: usize,
+        height: usize,
+    ) -> Result<Vec<[f32; 3]>>
+    where
+        F: Fn(Option<&RTCRayHit>) -> [f32; 3] + Sync,
+    {
+        let render_row = |y: usize| -> Result<Vec<[f32; 3]>> {
+            (0..width)
+                .map(|x| {
+                    let hit = self.intersect_1(camera.pixel_ray(x, y, width, height))?;
+                    Ok(shade(hit.as_ref()))
+                })
+                .collect()
+        };
+
+        render_rows(height, render_row)
+    }
+
+    /// Renders an anti-aliased image of the scene seen from `camera`.
+    ///
+    /// Like [CommittedScene::render_to_buffer], but `samples` rays are traced through each pixel,
+    /// jittered over its area, and the colors computed by `shade` are averaged. The jitter follows
+    /// a fixed low-discrepancy sequence, so that renders are reproducible.
+    ///
+    /// # Returns
+    /// A `Result` containing the `width * height` pixels in row-major order, starting from the top
+    /// left corner of the image, or an error if `samples` is zero.
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::prelude::*;
+    ///
+    /// let device = Device::try_new(None).unwrap();
+    /// let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    /// let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 5.0), 1.0).unwrap();
+    /// scene.attach_geometry(&sphere).unwrap();
+    /// let scene = scene.commit().unwrap();
+    ///
+    /// let camera = Camera::look_at(
+    ///     (0.0, 0.0, 0.0),
+    ///     (0.0, 0.0, 1.0),
+    ///     (0.0, 1.0, 0.0),
+    ///     60f32.to_radians(),
+    ///     1.0,
+    /// );
+    /// let coverage = scene
+    ///     .render_msaa(&camera, |hit| [hit.map_or(0.0, |_| 1.0); 3], 64, 64, 8)
+    ///     .unwrap();
+    /// assert_eq!(coverage.len(), 64 * 64);
+    /// ```
+    pub fn render_msaa<F>(
+        &self,
+        camera: &Camera,
+        shade: F,
+        width: usize,
+        height: usize,
+        samples: usize,
+    ) -> Result<Vec<[f32; 3]>>
+    where
+        F: Fn(Option<&RTCRayHit>) -> [f32; 3] + Sync,
+    {
+        if samples == 0 {
+            bail!("Could not render the scene: at least one sample per pixel is required");
+        }
+
+        let offsets: Vec<(f32, f32)> = (0..samples).map(sample_offset).collect();
+        let render_row = |y: usize| -> Result<Vec<[f32; 3]>> {
+            (0..width)
+                .map(|x| {
+                    let mut color = [0.0; 3];
+                    for &(dx, dy) in &offsets {
+                        let ray = camera.ray(
+                            (x as f32 + dx) / width as f32,
+                            (y as f32 + dy) / height as f32,
+                        );
+                        let hit = self.intersect_1(ray)?;
+                        let sample = shade(hit.as_ref());
+                        (0..3).for_each(|i| color[i] += sample[i]);
+                    }
+                    Ok(color.map(|c| c / samples as f32))
+                })
+                .collect()
+        };
+
+        render_rows(height, render_row)
+    }
+}
+
+/// Renders the `height` rows of an image, in parallel with the `rayon` feature, and concatenates
+/// them.
+fn render_rows<F>(height: usize, render_row: F) -> Result<Vec<[f32; 3]>>
+where
+    F: Fn(usize) -> Result<Vec<[f32; 3]>> + Sync + Send,
+{
+    #[cfg(feature = "rayon")]
+    let rows: Result<Vec<_>> = {
+        use rayon::prelude::*;
+        (0..height).into_par_iter().map(render_row).collect()
+    };
+    #[cfg(not(feature = "rayon"))]
+    let rows: Result<Vec<_>> = (0..height).map(render_row).collect();
+
+    Ok(rows?.concat())
+}
+
+/// Returns the position within a pixel of its `index`-th sample, following the R2 sequence
+/// (Roberts, 2018) so that any number of samples covers the pixel evenly. The first sample is the
+/// center of the pixel.
+fn sample_offset(index: usize) -> (f32, f32) {
+    // Inverses of the plastic number and of its square
+    const A1: f64 = 0.754_877_666_246_692_8;
+    const A2: f64 = 0.569_840_290_998_053_3;
+    let i = index as f64;
+    ((0.5 + A1 * i).fract() as f32, (0.5 + A2 * i).fract() as f32)
+}
+
+#[test]
+fn render_sphere_on_background() {
+    use crate::{
+        device::Device,
+        geometry::SphereGeometry,
+        scene::{Scene, SceneOptions},
+    };
+
+    let device = Device::try_new(None).unwrap();
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 5.0), 1.0).unwrap();
+    scene.attach_geometry(&sphere).unwrap();
+    let scene = scene.commit().unwrap();
+
+    let camera = Camera::look_at(
+        (0.0, 0.0, 0.0),
+        (0.0, 0.0, 1.0),
+        (0.0, 1.0, 0.0),
+        60f32.to_radians(),
+        1.0,
+    );
+    let (red, blue) = ([1.0, 0.0, 0.0], [0.0, 0.0, 1.0]);
+    let image = scene
+        .render_to_buffer(
+            &camera,
+            |hit| if hit.is_some() { red } else { blue },
+            17,
+            17,
+        )
+        .unwrap();
+
+    assert_eq!(image.len(), 17 * 17);
+    assert_eq!(image[8 * 17 + 8], red);
+    assert_eq!(image[0], blue);
+    assert_eq!(image[17 * 17 - 1], blue);
+}
+
+#[test]
+fn covered_pixel_is_surface_color() {
+    use crate::{
+        device::Device,
+        geometry::SphereGeometry,
+        scene::{Scene, SceneOptions},
+    };
+
+    let device = Device::try_new(None).unwrap();
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 5.0), 1.0).unwrap();
+    scene.attach_geometry(&sphere).unwrap();
+    let scene = scene.commit().unwrap();
+
+    let camera = Camera::look_at(
+        (0.0, 0.0, 0.0),
+        (0.0, 0.0, 1.0),
+        (0.0, 1.0, 0.0),
+        60f32.to_radians(),
+        1.0,
+    );
+    let surface = [0.25, 0.5, 1.0];
+    let shade = |hit: Option<&RTCRayHit>| if hit.is_some() { surface } else { [0.0; 3] };
+
+    for samples in [1, 2, 5, 16] {
+        let image = scene.render_msaa(&camera, shade, 17, 17, samples).unwrap();
+        assert_eq!(image.len(), 17 * 17);
+        assert_eq!(image[8 * 17 + 8], surface);
+        assert_eq!(image[0], [0.0; 3]);
+    }
+    assert!(scene.render_msaa(&camera, shade, 17, 17, 0).is_err());
+}
@@ -1,13 +1,58 @@
-use std::{ffi::c_void, marker::PhantomData, ptr::null_mut};
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    ffi::c_void,
+    ops::Deref,
+    ptr::null_mut,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{channel, Receiver, Sender, TryRecvError},
+        Arc, OnceLock,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
 
-use anyhow::{bail, Result};
-use embree4_sys::RTCBounds;
+use anyhow::{anyhow, bail, Result};
+use embree4_sys::{RTCBounds, RTCError, RTCSceneFlags, RTC_INVALID_GEOMETRY_ID};
 
-use crate::{device::Device, device_error_or, device_error_raw, geometry::Geometry, Mxcsr};
+use crate::{
+    aabb::Aabb,
+    clear_device_error,
+    context::RayQueryContext,
+    device::Device,
+    device_error_or, device_error_raw,
+    error::EmbreeError,
+    geometry::{Geometry, InstanceLink, InstanceLinks, Layout, SharedData, Validation},
+    point_query::{
+        closest_point_query, new_point_query_context, ClosestPoint, ClosestPointQuery, QueryShape,
+        QueryShapes,
+    },
+    ray::{compose_transforms, Intersection, RayBuilder},
+    snapshot, Mxcsr,
+};
 
 pub struct Scene<'a> {
-    device: &'a Device,
+    device: SceneDevice<'a>,
     handle: embree4_sys::RTCScene,
+    dirty: RefCell<Vec<u32>>,
+    // Refitted by the last incremental commit, restored before the next build
+    refitted: RefCell<Vec<u32>>,
+    attached: RefCell<Vec<embree4_sys::RTCGeometry>>,
+    kinds: RefCell<Vec<(u32, embree4_sys::RTCGeometryType)>>,
+    owned: RefCell<Vec<Box<dyn Geometry + 'a>>>,
+    // Dropped after the scene is released, see `Geometry::shared_data`
+    shared_data: RefCell<Vec<SharedData>>,
+    bounds_estimate: Cell<Option<Aabb>>,
+    primitive_count: Cell<usize>,
+    // Of the geometries without an instance link, see `Scene::instance_depth`
+    instance_depth: Cell<u32>,
+    instances: RefCell<HashMap<u32, InstanceLink>>,
+    shapes: RefCell<HashMap<u32, QueryShape>>,
+    validations: RefCell<Vec<(u32, Validation)>>,
+    flags: Cell<embree4_sys::RTCSceneFlags>,
+    build_quality: Cell<embree4_sys::RTCBuildQuality>,
+    committing: AtomicBool,
 }
 
 impl<'a> Scene<'a> {
@@ -33,6 +78,11 @@ impl<'a> Scene<'a> {
     /// let scene = Scene::try_new(&device, options).unwrap();
     /// ```
     pub fn try_new(device: &'a Device, options: SceneOptions) -> Result<Self> {
+        Self::try_new_with_device(SceneDevice::Borrowed(device), options)
+    }
+
+    fn try_new_with_device(device: SceneDevice<'a>, options: SceneOptions) -> Result<Self> {
+        clear_device_error(device.handle);
         let handle = unsafe { embree4_sys::rtcNewScene(device.handle) };
 
         if handle.is_null() {
@@ -40,9 +90,27 @@ impl<'a> Scene<'a> {
             bail!("Could not create scene: {:?}", error);
         }
 
-        let scene = Scene { device, handle };
+        let scene = Scene {
+            device,
+            handle,
+            dirty: Default::default(),
+            refitted: Default::default(),
+            attached: Default::default(),
+            kinds: Default::default(),
+            owned: Default::default(),
+            shared_data: Default::default(),
+            bounds_estimate: Default::default(),
+            primitive_count: Default::default(),
+            instance_depth: Default::default(),
+            instances: Default::default(),
+            shapes: Default::default(),
+            validations: Default::default(),
+            flags: Default::default(),
+            build_quality: Cell::new(embree4_sys::RTCBuildQuality::MEDIUM),
+            committing: AtomicBool::new(false),
+        };
 
-        if options.build_quality != Default::default() {
+        if options.build_quality != scene.build_quality() {
             scene.set_build_quality(options.build_quality)?;
         }
 
@@ -53,6 +121,26 @@ impl<'a> Scene<'a> {
         Ok(scene)
     }
 
+    /// Constructs a new `Scene` instance sharing ownership of the device.
+    ///
+    /// Unlike [Scene::try_new], the scene does not borrow the device and can be stored and moved
+    /// freely.
+    ///
+    /// # Example
+    /// ```
+    /// use std::sync::Arc;
+    ///
+    /// use embree4_rs::prelude::*;
+    ///
+    /// let device = Arc::new(Device::try_new(None).unwrap());
+    /// let scenes: Vec<Scene<'static>> = (0..4)
+    ///     .map(|_| Scene::try_new_arc(device.clone(), SceneOptions::default()).unwrap())
+    ///     .collect();
+    /// ```
+    pub fn try_new_arc(device: Arc<Device>, options: SceneOptions) -> Result<Scene<'static>> {
+        Scene::try_new_with_device(SceneDevice::Shared(device), options)
+    }
+
     /// Sets the build quality of the scene.
     ///
     /// # Arguments
@@ -61,43 +149,291 @@ impl<'a> Scene<'a> {
     /// # Returns
     /// A `Result` indicating success or failure.
     pub fn set_build_quality(&self, quality: embree4_sys::RTCBuildQuality) -> Result<()> {
+        self.ensure_not_committing("Could not set scene build quality")?;
+        clear_device_error(self.device.handle);
         unsafe {
             embree4_sys::rtcSetSceneBuildQuality(self.handle, quality);
         }
-        device_error_or(self.device, (), "Could not set scene build quality")
+        device_error_or(&self.device, (), "Could not set scene build quality")?;
+        self.build_quality.set(quality);
+        Ok(())
+    }
+
+    /// Returns the build quality last set on the scene.
+    ///
+    /// Embree has no getter for it, so this is tracked by the wrapper.
+    pub fn build_quality(&self) -> embree4_sys::RTCBuildQuality {
+        self.build_quality.get()
     }
 
     /// Sets the flags of the scene.
     ///
+    /// Combining `COMPACT` with `DYNAMIC` is reported as an advisory to the device error callback,
+    /// as compact layouts cannot be refitted efficiently. The flags are set regardless.
+    ///
     /// # Arguments
     /// * `flags` - The flags to set.
     ///
     /// # Returns
     /// A `Result` indicating success or failure.
     pub fn set_flags(&self, flags: embree4_sys::RTCSceneFlags) -> Result<()> {
+        self.ensure_not_committing("Could not set scene flags")?;
+        let compact_dynamic = RTCSceneFlags::COMPACT | RTCSceneFlags::DYNAMIC;
+        if flags & compact_dynamic == compact_dynamic {
+            self.device.advise(
+                "Scene flags combine COMPACT with DYNAMIC: compact layouts cannot be refitted efficiently",
+            );
+        }
+
+        clear_device_error(self.device.handle);
         unsafe {
             embree4_sys::rtcSetSceneFlags(self.handle, flags);
         }
-        device_error_or(self.device, (), "Could not set scene flags")
+        device_error_or(&self.device, (), "Could not set scene flags")?;
+        self.flags.set(flags);
+        Ok(())
+    }
+
+    /// Returns the flags last set on the scene.
+    ///
+    /// This is tracked by the wrapper rather than queried from Embree.
+    pub fn flags(&self) -> embree4_sys::RTCSceneFlags {
+        self.flags.get()
     }
 
     /// Attaches the given geometry to the scene.
     ///
+    /// The scene retains the geometry handle and its [Geometry::shared_data] until it is dropped,
+    /// so the geometry can be dropped once attached, and one geometry can be attached to several
+    /// scenes. Data borrowed by the geometry, such as the [UserGeometry] data, must still outlive
+    /// the scenes.
+    ///
+    /// Fails if the geometry is not committed, see [Geometry::is_committed].
+    ///
     /// # Arguments
     /// * `geometry` - A reference to the `Geometry` instance to attach.
     ///
     /// # Returns
     /// * A `Result` containing the geometry ID if successful, or an error if an error occurred.
+    ///
+    /// [UserGeometry]: crate::geometry::UserGeometry
     pub fn attach_geometry(&self, geometry: &impl Geometry) -> Result<u32> {
-        let geom_id = unsafe { embree4_sys::rtcAttachGeometry(self.handle, geometry.geometry()) };
-        device_error_or(self.device, geom_id, "Could not attach geometry")
+        self.ensure_not_committing("Could not attach geometry")?;
+        if !geometry.is_committed() {
+            bail!("Could not attach geometry: the geometry was not committed");
+        }
+
+        clear_device_error(self.device.handle);
+        let handle = geometry.geometry();
+        let geom_id = unsafe { embree4_sys::rtcAttachGeometry(self.handle, handle) };
+        device_error_or(&self.device, (), "Could not attach geometry")?;
+
+        unsafe {
+            embree4_sys::rtcRetainGeometry(handle);
+        }
+        self.attached.borrow_mut().push(handle);
+        self.kinds.borrow_mut().push((geom_id, geometry.kind()));
+        self.shared_data.borrow_mut().extend(geometry.shared_data());
+
+        if let Some(validation) = geometry.validation() {
+            let layout = validation.layout();
+            if validation.geometry() == handle
+                && matches!(layout, Layout::Triangles { .. } | Layout::Sphere)
+            {
+                let shape = QueryShape {
+                    geometry: handle,
+                    layout,
+                };
+                self.shapes.borrow_mut().insert(geom_id, shape);
+            }
+            self.validations.borrow_mut().push((geom_id, validation));
+        }
+
+        self.primitive_count
+            .set(self.primitive_count.get() + geometry.primitive_count());
+        // The depth of instances is read from their link, as it changes with the instanced scene
+        if let Some(link) = geometry.instance_link() {
+            self.instances.borrow_mut().insert(geom_id, link);
+        } else {
+            self.instance_depth
+                .set(self.instance_depth.get().max(geometry.instance_depth()));
+        }
+
+        if let Some(bounds) = geometry.bounds() {
+            let estimate = self.bounds_estimate.get();
+            self.bounds_estimate
+                .set(Some(estimate.map_or(bounds, |e| e.union(&bounds))));
+        }
+
+        Ok(geom_id)
+    }
+
+    /// Attaches the given geometry to the scene, which takes ownership of it.
+    ///
+    /// The geometry lives as long as the scene, along with anything it borrows. Use
+    /// [Scene::attach_geometry] to share a geometry between scenes.
+    ///
+    /// # Returns
+    /// * A `Result` containing the geometry ID if successful, or an error if an error occurred.
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::prelude::*;
+    ///
+    /// fn build_scene(device: &Device) -> Scene<'_> {
+    ///     let scene = Scene::try_new(device, SceneOptions::default()).unwrap();
+    ///     for x in 0..4 {
+    ///         let sphere = SphereGeometry::try_new(device, (x as f32, 0.0, 5.0), 0.5).unwrap();
+    ///         scene.attach_owned(sphere).unwrap();
+    ///     }
+    ///     scene
+    /// }
+    ///
+    /// let device = Device::try_new(None).unwrap();
+    /// let scene = build_scene(&device).commit().unwrap();
+    /// ```
+    pub fn attach_owned(&self, geometry: impl Geometry + 'a) -> Result<u32> {
+        let geom_id = self.attach_geometry(&geometry)?;
+        self.owned.borrow_mut().push(Box::new(geometry));
+        Ok(geom_id)
+    }
+
+    /// Serializes the options of the scene and the buffers of its geometries into a blob, from
+    /// which [Scene::from_snapshot] rebuilds an identical scene.
+    ///
+    /// This makes bugs in nondeterministic renders reproducible from the exact geometry. The
+    /// buffers are read as they currently are, including the time steps of motion blur. Only
+    /// triangle meshes and spheres are supported, and vertex attributes are not saved.
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::prelude::*;
+    ///
+    /// let device = Device::try_new(None).unwrap();
+    /// let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    /// let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 5.0), 1.0).unwrap();
+    /// scene.attach_geometry(&sphere).unwrap();
+    ///
+    /// let blob = scene.snapshot().unwrap();
+    /// let replay = Scene::from_snapshot(&device, &blob).unwrap();
+    /// ```
+    pub fn snapshot(&self) -> Result<Vec<u8>> {
+        let validations = self.validations.borrow();
+        if validations.len() != self.attached.borrow().len() {
+            bail!("Only triangle meshes and spheres can be snapshotted");
+        }
+
+        let options = SceneOptions {
+            build_quality: self.build_quality(),
+            flags: self.flags(),
+        };
+        snapshot::write(&options, &validations)
+    }
+
+    /// Returns a hash of the options of the scene and of the buffers of its geometries, e.g. to key
+    /// a cache of built scenes.
+    ///
+    /// The hash is deterministic across runs and platforms of the same endianness: it is FNV-1a
+    /// over the kinds of the geometries and the raw bytes of their buffers, as they are when the
+    /// hash is computed. Instances contribute their transforms, and the triangle meshes, spheres
+    /// and instances of the scene they instance. Other geometries without checkable buffers,
+    /// such as user geometries, only contribute their kind, see [Geometry::validation]. The
+    /// enable state and the user data of the geometries are not hashed.
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::prelude::*;
+    ///
+    /// let device = Device::try_new(None).unwrap();
+    /// let sphere_scene = |radius| {
+    ///     let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    ///     let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 5.0), radius).unwrap();
+    ///     scene.attach_owned(sphere).unwrap();
+    ///     scene
+    /// };
+    /// assert_eq!(sphere_scene(1.0).content_hash(), sphere_scene(1.0).content_hash());
+    /// assert_ne!(sphere_scene(1.0).content_hash(), sphere_scene(2.0).content_hash());
+    /// ```
+    pub fn content_hash(&self) -> u64 {
+        let mut hash = Fnv1a::new();
+        hash.write(&self.build_quality().0.to_le_bytes());
+        hash.write(&self.flags().0.to_le_bytes());
+        hash.write(&(self.attached.borrow().len() as u64).to_le_bytes());
+        for (geom_id, kind) in self.kinds.borrow().iter() {
+            hash.write(&geom_id.to_le_bytes());
+            hash.write(&kind.0.to_le_bytes());
+        }
+        for (geom_id, validation) in self.validations.borrow().iter() {
+            hash.write(&geom_id.to_le_bytes());
+            hash_buffers(&mut hash, validation);
+        }
+        hash_instances(&mut hash, &self.instances.borrow());
+        hash.finish()
+    }
+
+    /// Rebuilds a scene from a blob made by [Scene::snapshot]. The geometries get the same IDs
+    /// as in the original scene.
+    pub fn from_snapshot(device: &'a Device, blob: &[u8]) -> Result<Self> {
+        snapshot::read(device, blob)
+    }
+
+    /// Returns an estimate of the scene bounds, available before the scene is committed.
+    ///
+    /// The estimate is accumulated from the [Geometry::bounds] of the geometries, as they were
+    /// when attached. Geometries with unknown bounds, such as instances, are not taken into
+    /// account, and geometries modified after being attached are not updated. Use
+    /// [CommittedScene::bounds] for exact bounds.
+    ///
+    /// Returns `None` if no geometry with known bounds was attached.
+    pub fn bounds_estimate(&self) -> Option<Aabb> {
+        self.bounds_estimate.get()
+    }
+
+    /// Returns the number of primitives of the attached geometries, as they were when attached.
+    pub fn primitive_count(&self) -> usize {
+        self.primitive_count.get()
+    }
+
+    /// Checks the buffers of the attached built-in geometries for common mistakes: indices out of
+    /// range, vertices that are not finite and degenerate primitives.
+    ///
+    /// Embree does not report these, and they cause silent misses or crashes. This is `O(n)` in
+    /// the size of the scene and only runs when called, see [Geometry::validation].
+    ///
+    /// # Returns
+    /// An error listing the issues found in each geometry, if any.
+    pub fn validate(&self) -> Result<()> {
+        let report: Vec<_> = self
+            .validations
+            .borrow()
+            .iter()
+            .flat_map(|(geom_id, validation)| {
+                validation
+                    .issues()
+                    .into_iter()
+                    .map(move |issue| format!("geometry {}: {}", geom_id, issue))
+            })
+            .collect();
+
+        if !report.is_empty() {
+            bail!("Scene validation failed:\n{}", report.join("\n"));
+        }
+        Ok(())
     }
 
     /// Commits the scene.
     ///
+    /// Embree does not support committing a scene while it is already being built. Such a commit,
+    /// made from a progress monitor callback or while a [Scene::commit_with_progress] build is
+    /// pending, fails instead of corrupting the build.
+    ///
     /// # Returns
     /// A `Result` containing the `CommittedScene` instance if successful, or an error if an error occurred.
     ///
+    /// If a progress monitor registered with [Scene::register_scene_progress_monitor_callback]
+    /// returns `false`, the build is cancelled and the error holds [EmbreeError::Cancelled]. No
+    /// `CommittedScene` is handed out for the partial build, and the scene can be committed again.
+    ///
     /// # Example
     /// ```
     /// use embree4_rs::*;
@@ -109,28 +445,281 @@ impl<'a> Scene<'a> {
     /// let scene = scene.commit().unwrap();
     /// ```
     pub fn commit(&self) -> Result<CommittedScene<'a>> {
+        let _guard = CommitGuard::acquire(&self.committing)?;
+        self.restore_refitted();
+        self.commit_locked()
+    }
+
+    // Commits the scene while the caller holds its `CommitGuard`
+    fn commit_locked(&self) -> Result<CommittedScene<'a>> {
+        #[cfg(feature = "log")]
+        let start = std::time::Instant::now();
+
+        clear_device_error(self.device.handle);
         unsafe {
             let _mxcsr = Mxcsr::setup();
             embree4_sys::rtcCommitScene(self.handle);
         }
-        device_error_or(
-            self.device,
-            CommittedScene {
-                device: self.device,
-                handle: self.handle,
+
+        #[cfg(feature = "log")]
+        log::debug!(
+            "Scene commit of {} geometries took {:?}",
+            self.attached.borrow().len(),
+            start.elapsed()
+        );
+
+        commit_result(device_error_raw(self.device.handle), self.committed())
+    }
+
+    // The committed state of the scene, which holds its own reference to the scene handle
+    fn committed(&self) -> CommittedScene<'a> {
+        unsafe { embree4_sys::rtcRetainScene(self.handle) };
+        CommittedScene {
+            device: self.device.clone(),
+            handle: self.handle,
+            aabb: OnceLock::new(),
+            build_quality: self.build_quality(),
+            instance_depth: self.instance_depth(),
+            instances: Arc::new(self.instances.borrow().clone()),
+            shapes: Arc::new(self.shapes.borrow().clone()),
+            geometry_count: self.attached.borrow().len(),
+            ray_epsilon: DEFAULT_RAY_EPSILON,
+        }
+    }
+
+    /// Commits the scene with the given build quality, then restores the build quality of the
+    /// scene.
+    ///
+    /// This supports building a scene at `LOW` quality while it is edited and baking it at `HIGH`
+    /// quality once done, without reconfiguring it. The returned scene reports `quality` as its
+    /// [CommittedScene::build_quality]. The build quality is restored even if the commit fails, in
+    /// which case the error of the commit is returned.
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::prelude::*;
+    /// use embree4_sys::RTCBuildQuality;
+    ///
+    /// let device = Device::try_new(None).unwrap();
+    /// let options = SceneOptions {
+    ///     build_quality: RTCBuildQuality::LOW,
+    ///     ..Default::default()
+    /// };
+    /// let scene = Scene::try_new(&device, options).unwrap();
+    /// let preview = scene.commit().unwrap();
+    /// let baked = scene.commit_with_quality(RTCBuildQuality::HIGH).unwrap();
+    /// ```
+    pub fn commit_with_quality(
+        &self,
+        quality: embree4_sys::RTCBuildQuality,
+    ) -> Result<CommittedScene<'a>> {
+        let previous = self.build_quality();
+        self.set_build_quality(quality)?;
+        let committed = self.commit();
+        let restored = self.set_build_quality(previous);
+        // The error of the commit takes precedence over the one of the restore
+        let committed = committed?;
+        restored?;
+        Ok(committed)
+    }
+
+    /// Commits the scene, measuring how long the BVH build took.
+    ///
+    /// # Returns
+    /// A `Result` containing the `CommittedScene` instance and the duration of the commit if
+    /// successful, or an error if an error occurred.
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::prelude::*;
+    ///
+    /// let device = Device::try_new(None).unwrap();
+    /// let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    /// let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 5.0), 1.0).unwrap();
+    /// scene.attach_geometry(&sphere).unwrap();
+    ///
+    /// let start = std::time::Instant::now();
+    /// let (committed, elapsed) = scene.commit_timed().unwrap();
+    /// assert!(elapsed <= start.elapsed());
+    /// assert_eq!(scene.primitive_count(), 1);
+    ///
+    /// let ray = RayBuilder::new((0.0, 0.0, 0.0), (0.0, 0.0, 1.0)).build();
+    /// assert!(committed.intersect_1(ray).unwrap().is_some());
+    /// ```
+    pub fn commit_timed(&self) -> Result<(CommittedScene<'a>, Duration)> {
+        let start = Instant::now();
+        let scene = self.commit()?;
+        Ok((scene, start.elapsed()))
+    }
+
+    /// Marks an attached geometry as moved since the last commit, see [Scene::commit_incremental].
+    ///
+    /// # Arguments
+    /// * `geom_id` - The ID returned by [Scene::attach_geometry].
+    pub fn set_dirty(&self, geom_id: u32) {
+        let mut dirty = self.dirty.borrow_mut();
+        if !dirty.contains(&geom_id) {
+            dirty.push(geom_id);
+        }
+    }
+
+    /// Commits the scene, refitting the BVH of the geometries marked with [Scene::set_dirty]
+    /// instead of rebuilding it.
+    ///
+    /// The dirty geometries are committed with the `REFIT` build quality before the scene, so
+    /// that the returned scene reflects their changes. Refitting keeps the BVH topology, which is
+    /// fast and works well when a geometry moved or deformed slightly. The dirty geometries get
+    /// back Embree's default `MEDIUM` build quality at the next commit, so that it rebuilds them.
+    /// Geometries that changed without being marked dirty are rebuilt as with [Scene::commit].
+    ///
+    /// The geometries stay marked dirty if the commit fails.
+    ///
+    /// # Returns
+    /// A `Result` containing the `CommittedScene` instance if successful, or an error if an error occurred.
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::prelude::*;
+    ///
+    /// let device = Device::try_new(None).unwrap();
+    /// let vertices = [(0.0, 0.0, 0.0), (1.0, 0.0, 0.0), (0.0, 1.0, 0.0)];
+    /// let mesh = TriangleMeshGeometry::try_new(&device, &vertices, &[(0, 1, 2)]).unwrap();
+    /// let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    /// let geom_id = scene.attach_geometry(&mesh).unwrap();
+    /// scene.commit().unwrap();
+    ///
+    /// let moved = [(0.0, 0.0, 1.0), (1.0, 0.0, 1.0), (0.0, 1.0, 1.0)];
+    /// mesh.set_vertices(&moved).unwrap();
+    /// scene.set_dirty(geom_id);
+    /// let scene = scene.commit_incremental().unwrap();
+    /// ```
+    pub fn commit_incremental(&self) -> Result<CommittedScene<'a>> {
+        let _guard = CommitGuard::acquire(&self.committing)?;
+        self.restore_refitted();
+        clear_device_error(self.device.handle);
+        let dirty = self.dirty.borrow().clone();
+        let result = (|| {
+            for geom_id in dirty {
+                let geometry = unsafe { embree4_sys::rtcGetGeometry(self.handle, geom_id) };
+                if geometry.is_null() {
+                    bail!("Could not refit geometry {}: not attached", geom_id);
+                }
+
+                self.refitted.borrow_mut().push(geom_id);
+                unsafe {
+                    embree4_sys::rtcSetGeometryBuildQuality(
+                        geometry,
+                        embree4_sys::RTCBuildQuality::REFIT,
+                    );
+                    embree4_sys::rtcCommitGeometry(geometry);
+                }
+                device_error_or(&self.device, (), "Could not refit geometry")?;
+            }
+
+            self.commit_locked()
+        })();
+
+        if result.is_ok() {
+            self.dirty.borrow_mut().clear();
+        }
+        result
+    }
+
+    // Gives the geometries refitted by the last incremental commit back Embree's default build
+    // quality, the crate never changes it otherwise. This is done before the next build rather
+    // than after the incremental one, so that the scene it returned is left untouched.
+    fn restore_refitted(&self) {
+        for geom_id in self.refitted.take() {
+            let geometry = unsafe { embree4_sys::rtcGetGeometry(self.handle, geom_id) };
+            if geometry.is_null() {
+                continue;
+            }
+            unsafe {
+                embree4_sys::rtcSetGeometryBuildQuality(
+                    geometry,
+                    embree4_sys::RTCBuildQuality::MEDIUM,
+                );
+                embree4_sys::rtcCommitGeometry(geometry);
+            }
+        }
+    }
+
+    // The deepest nesting of instances, with the instanced scenes as they are now
+    fn instance_depth(&self) -> u32 {
+        self.instances
+            .borrow()
+            .values()
+            .map(InstanceLink::depth)
+            .fold(self.instance_depth.get(), u32::max)
+    }
+
+    // Embree does not support changing a scene while it is being built
+    fn ensure_not_committing(&self, message: &str) -> Result<()> {
+        if self.committing.load(Ordering::Acquire) {
+            bail!("{message}: the scene is being committed");
+        }
+        Ok(())
+    }
+
+    /// Commits the scene on a background thread, forwarding the build progress over a channel.
+    ///
+    /// Increasing progress values in `[0, 1]` are sent on the returned [Receiver] while the build
+    /// runs, ending with `1` if it succeeds. The channel is closed once the build is done. Call
+    /// [PendingCommit::join] to retrieve the [CommittedScene].
+    ///
+    /// The scene cannot be committed again, nor can its geometries, flags or build quality
+    /// change, until the build is done. If it is already being committed, no build is started and
+    /// [PendingCommit::join] fails.
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::prelude::*;
+    ///
+    /// let device = Device::try_new(None).unwrap();
+    /// let scene = Scene::try_new(&device, Default::default()).unwrap();
+    /// let (commit, progress) = scene.commit_with_progress();
+    /// for p in progress {
+    ///     println!("Building BVH: {:.0}%", 100.0 * p);
+    /// }
+    /// let scene = commit.join().unwrap();
+    /// ```
+    pub fn commit_with_progress(&self) -> (PendingCommit<'_, 'a>, Receiver<f64>) {
+        let (sender, receiver) = channel();
+        let Ok(guard) = CommitGuard::acquire(&self.committing) else {
+            let pending = PendingCommit {
+                scene: self,
+                thread: None,
+                guard: None,
+            };
+            return (pending, receiver);
+        };
+
+        self.restore_refitted();
+        let handles = RawCommitHandles::retain(self);
+        let thread = std::thread::spawn(move || handles.commit(sender));
+
+        (
+            PendingCommit {
+                scene: self,
+                thread: Some(thread),
+                guard: Some(guard),
             },
-            "Could not commit scene",
+            receiver,
         )
     }
 
     /// Setup a callback that is called on progress and returns a structure that will remove is on drop.
     ///
     /// For semantic see the reference for [rtcSetSceneProgressMonitorFunction](https://github.com/RenderKit/embree/blob/master/doc/src/api/rtcSetSceneProgressMonitorFunction.md).
+    /// Returning `false` from the callback cancels the build, see [Scene::commit].
+    ///
+    /// [Scene::commit_with_progress] replaces the callback for the duration of its build and
+    /// removes it afterwards.
     ///
     /// To setup a permanent callback, use [std::mem::forget] on the returned [SceneProgressCallbackScope] but this will force the callback to have a `'static` lifetime.
     pub fn register_scene_progress_monitor_callback<'scope, F: FnMut(f64) -> bool + 'scope>(
         &mut self,
-        mut f: F,
+        f: F,
     ) -> SceneProgressCallbackScope<'scope> {
         unsafe extern "C" fn trampoline<'scope, F: FnMut(f64) -> bool + 'scope>(
             user_ptr: *mut ::std::os::raw::c_void,
@@ -139,17 +728,19 @@ impl<'a> Scene<'a> {
             let f = &mut *(user_ptr as *mut F);
             f(progress)
         }
+        // Boxed so that the callback outlives this call, the scope keeps it alive
+        let mut f = Box::new(f);
         unsafe {
             embree4_sys::rtcSetSceneProgressMonitorFunction(
                 self.handle,
                 Some(trampoline::<F>),
-                &mut f as &mut _ as *mut _ as *mut c_void,
+                &mut *f as *mut F as *mut c_void,
             )
         }
 
         SceneProgressCallbackScope {
             handle: self.handle,
-            lifetime: Default::default(),
+            _callback: f,
         }
     }
 
@@ -165,13 +756,17 @@ impl<'a> Drop for Scene<'a> {
     fn drop(&mut self) {
         unsafe {
             embree4_sys::rtcReleaseScene(self.handle);
+            for &geometry in self.attached.get_mut().iter() {
+                embree4_sys::rtcReleaseGeometry(geometry);
+            }
         }
     }
 }
 
 pub struct SceneProgressCallbackScope<'a> {
     handle: embree4_sys::RTCScene,
-    lifetime: PhantomData<&'a ()>,
+    // Dropped after the callback is removed from the scene
+    _callback: Box<dyn FnMut(f64) -> bool + 'a>,
 }
 
 impl Drop for SceneProgressCallbackScope<'_> {
@@ -180,48 +775,2675 @@ impl Drop for SceneProgressCallbackScope<'_> {
     }
 }
 
-#[derive(Default)]
-pub struct SceneOptions {
-    pub build_quality: embree4_sys::RTCBuildQuality,
-    pub flags: embree4_sys::RTCSceneFlags,
+/// A scene commit running on a background thread, see [Scene::commit_with_progress].
+///
+/// Dropping a `PendingCommit` waits for the build to finish. The build holds its own references
+/// to the scene, its device and its [Geometry::shared_data], so forgetting a `PendingCommit` with
+/// [std::mem::forget] is safe, but leaves the scene marked as being committed.
+pub struct PendingCommit<'s, 'a> {
+    scene: &'s Scene<'a>,
+    thread: Option<JoinHandle<Option<RTCError>>>,
+    // Released once the thread is joined, `None` if the scene was already being committed
+    guard: Option<CommitGuard<'s>>,
 }
 
-pub struct CommittedScene<'a> {
-    device: &'a Device,
-    handle: embree4_sys::RTCScene,
+impl<'s, 'a> PendingCommit<'s, 'a> {
+    /// Waits for the build to finish.
+    ///
+    /// # Returns
+    /// A `Result` containing the `CommittedScene` instance if successful, or an error if an error occurred.
+    pub fn join(mut self) -> Result<CommittedScene<'a>> {
+        if self.guard.is_none() {
+            bail!("Could not commit scene: the scene is already being committed");
+        }
+
+        let error = match self.thread.take() {
+            Some(thread) => thread
+                .join()
+                .map_err(|_| anyhow!("Scene commit thread panicked"))?,
+            None => None,
+        };
+
+        commit_result(error, self.scene.committed())
+    }
 }
 
-unsafe impl<'a> Sync for CommittedScene<'a> {}
+impl Drop for PendingCommit<'_, '_> {
+    fn drop(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
 
-impl<'a> CommittedScene<'a> {
-    pub fn intersect_1(&self, ray: embree4_sys::RTCRay) -> Result<Option<embree4_sys::RTCRayHit>> {
-        let mut ray_hit = embree4_sys::RTCRayHit {
-            ray,
-            hit: Default::default(),
-        };
+/// Combines the progress of several builds started with [Scene::commit_with_progress], e.g. to
+/// show a single progress bar while the BVHs of many objects are built in parallel.
+///
+/// Each build weighs the same in the combined fraction. A build counts as done once its progress
+/// channel is closed.
+///
+/// # Example
+/// ```
+/// use embree4_rs::{prelude::*, scene::ProgressAggregator};
+///
+/// let device = Device::try_new(None).unwrap();
+/// let scenes: Vec<_> = (0..4)
+///     .map(|_| Scene::try_new(&device, SceneOptions::default()).unwrap())
+///     .collect();
+///
+/// let mut aggregator = ProgressAggregator::new();
+/// let commits: Vec<_> = scenes
+///     .iter()
+///     .map(|scene| {
+///         let (commit, progress) = scene.commit_with_progress();
+///         aggregator.add(progress);
+///         commit
+///     })
+///     .collect();
+/// while !aggregator.is_done() {
+///     println!("Building BVHs: {:.0}%", 100.0 * aggregator.poll());
+///     std::thread::sleep(std::time::Duration::from_millis(10));
+/// }
+/// let scenes: Vec<_> = commits.into_iter().map(|c| c.join().unwrap()).collect();
+/// ```
+#[derive(Debug, Default)]
+pub struct ProgressAggregator {
+    // The progress channel of each build, `None` once it is closed
+    receivers: Vec<Option<Receiver<f64>>>,
+    progress: Vec<f64>,
+}
+
+impl ProgressAggregator {
+    /// Constructs a `ProgressAggregator` without any build.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the progress channel of a build.
+    pub fn add(&mut self, progress: Receiver<f64>) {
+        self.receivers.push(Some(progress));
+        self.progress.push(0.0);
+    }
+
+    /// Reads the progress reported since the last call without blocking.
+    ///
+    /// # Returns
+    /// The combined progress of all builds in `[0, 1]`, `1` once they are all done.
+    pub fn poll(&mut self) -> f64 {
+        for (receiver, progress) in self.receivers.iter_mut().zip(&mut self.progress) {
+            while let Some(channel) = receiver {
+                match channel.try_recv() {
+                    Ok(p) => *progress = p.clamp(*progress, 1.0),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        *receiver = None;
+                        *progress = 1.0;
+                    }
+                }
+            }
+        }
+        self.fraction()
+    }
+
+    /// Returns the combined progress read by the last [Self::poll], `1` without any build.
+    pub fn fraction(&self) -> f64 {
+        if self.progress.is_empty() {
+            return 1.0;
+        }
+        self.progress.iter().sum::<f64>() / self.progress.len() as f64
+    }
+
+    /// Returns `true` once [Self::poll] has seen all builds finish.
+    pub fn is_done(&self) -> bool {
+        self.receivers.iter().all(Option::is_none)
+    }
+}
+
+// Marks a scene as being committed, so that re-entrant or concurrent commits are rejected
+struct CommitGuard<'s> {
+    committing: &'s AtomicBool,
+}
+
+impl<'s> CommitGuard<'s> {
+    fn acquire(committing: &'s AtomicBool) -> Result<Self> {
+        if committing
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            bail!("Could not commit scene: the scene is already being committed");
+        }
+        Ok(Self { committing })
+    }
+}
+
+impl Drop for CommitGuard<'_> {
+    fn drop(&mut self) {
+        self.committing.store(false, Ordering::Release);
+    }
+}
+
+// Maps the device error left by `rtcCommitScene`, reporting a build cancelled by the progress
+// monitor as `EmbreeError::Cancelled`
+fn commit_result<T>(error: Option<RTCError>, ok_value: T) -> Result<T> {
+    let error = match error {
+        None => return Ok(ok_value),
+        Some(error) if error == RTCError::CANCELLED => EmbreeError::Cancelled,
+        Some(error) => EmbreeError::from(error),
+    };
+
+    #[cfg(feature = "log")]
+    log::error!("Could not commit scene: {error:?}");
+
+    Err(anyhow::Error::new(error).context(format!("Could not commit scene: {error}")))
+}
 
+// Handles moved to the commit thread, retained until the build is done so that it does not
+// depend on `PendingCommit` being joined
+struct RawCommitHandles {
+    device: embree4_sys::RTCDevice,
+    scene: embree4_sys::RTCScene,
+    _shared_data: Vec<SharedData>,
+}
+
+unsafe impl Send for RawCommitHandles {}
+
+impl RawCommitHandles {
+    fn retain(scene: &Scene) -> Self {
+        unsafe {
+            embree4_sys::rtcRetainDevice(scene.device.handle);
+            embree4_sys::rtcRetainScene(scene.handle);
+        }
+        Self {
+            device: scene.device.handle,
+            scene: scene.handle,
+            _shared_data: scene.shared_data.borrow().clone(),
+        }
+    }
+
+    fn commit(self, sender: Sender<f64>) -> Option<RTCError> {
+        unsafe extern "C" fn trampoline(
+            user_ptr: *mut ::std::os::raw::c_void,
+            progress: f64,
+        ) -> bool {
+            let sender = &*(user_ptr as *const Sender<f64>);
+            // The receiver may have been dropped, the build goes on regardless
+            let _ = sender.send(progress);
+            true
+        }
+
+        clear_device_error(self.device);
         unsafe {
             let _mxcsr = Mxcsr::setup();
-            embree4_sys::rtcIntersect1(self.handle, &mut ray_hit, std::ptr::null_mut());
+            embree4_sys::rtcSetSceneProgressMonitorFunction(
+                self.scene,
+                Some(trampoline),
+                &sender as *const _ as *mut c_void,
+            );
+            embree4_sys::rtcCommitScene(self.scene);
+            embree4_sys::rtcSetSceneProgressMonitorFunction(self.scene, None, null_mut());
         }
-        device_error_or(self.device, (), "Could not intersect ray")?;
 
-        Ok(
-            if ray_hit.hit.geomID != embree4_sys::RTC_INVALID_GEOMETRY_ID {
-                Some(ray_hit)
-            } else {
-                None
-            },
-        )
+        // Device errors are per-thread, so they have to be read here
+        let error = device_error_raw(self.device);
+        if error.is_none() {
+            // Embree does not always report the end of the build
+            let _ = sender.send(1.0);
+        }
+        error
     }
+}
 
-    /// Returns the axis-aligned bounding box og the scene
-    pub fn bounds(&self) -> Result<embree4_sys::RTCBounds> {
-        let mut bounds = embree4_sys::RTCBounds::default();
+impl Drop for RawCommitHandles {
+    fn drop(&mut self) {
         unsafe {
-            let _mxcsr = Mxcsr::setup();
-            embree4_sys::rtcGetSceneBounds(self.handle, &mut bounds as *mut RTCBounds);
-        };
-        device_error_or(self.device, bounds, "Could not get bounds")
+            embree4_sys::rtcReleaseScene(self.scene);
+            embree4_sys::rtcReleaseDevice(self.device);
+        }
     }
 }
+
+// The device of a scene, either borrowed or shared with `Arc`
+#[derive(Clone)]
+enum SceneDevice<'a> {
+    Borrowed(&'a Device),
+    Shared(Arc<Device>),
+}
+
+impl Deref for SceneDevice<'_> {
+    type Target = Device;
+
+    fn deref(&self) -> &Device {
+        match self {
+            SceneDevice::Borrowed(device) => device,
+            SceneDevice::Shared(device) => device,
+        }
+    }
+}
+
+pub struct SceneOptions {
+    pub build_quality: embree4_sys::RTCBuildQuality,
+    pub flags: embree4_sys::RTCSceneFlags,
+}
+
+impl Default for SceneOptions {
+    /// The defaults of Embree: `MEDIUM` build quality and no flags.
+    fn default() -> Self {
+        Self {
+            build_quality: embree4_sys::RTCBuildQuality::MEDIUM,
+            flags: Default::default(),
+        }
+    }
+}
+
+impl SceneOptions {
+    /// Options for scenes only ever queried with shadow rays.
+    ///
+    /// Embree has no dedicated flag for occlusion-only scenes, so this selects a `LOW` build
+    /// quality: the BVH builds faster at the cost of slightly slower traversal, which shadow rays
+    /// largely amortize by stopping at the first hit. Such scenes are meant to be queried through
+    /// [CommittedScene::occluded_1]; closest-hit queries still work but are not tuned for.
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::prelude::*;
+    ///
+    /// let device = Device::try_new(None).unwrap();
+    /// let shadow_scene = Scene::try_new(&device, SceneOptions::occlusion_only()).unwrap();
+    /// ```
+    pub fn occlusion_only() -> Self {
+        Self {
+            build_quality: embree4_sys::RTCBuildQuality::LOW,
+            flags: Default::default(),
+        }
+    }
+}
+
+/// The default [CommittedScene::ray_epsilon].
+pub const DEFAULT_RAY_EPSILON: f32 = 1e-4;
+
+/// A committed scene, ready to be traced.
+///
+/// A `CommittedScene` holds its own reference to the scene, so it stays valid after the [Scene]
+/// it was committed from is dropped.
+pub struct CommittedScene<'a> {
+    device: SceneDevice<'a>,
+    pub(crate) handle: embree4_sys::RTCScene,
+    aabb: OnceLock<Aabb>,
+    build_quality: embree4_sys::RTCBuildQuality,
+    instance_depth: u32,
+    pub(crate) instances: InstanceLinks,
+    pub(crate) shapes: QueryShapes,
+    geometry_count: usize,
+    ray_epsilon: f32,
+}
+
+unsafe impl<'a> Sync for CommittedScene<'a> {}
+
+impl Drop for CommittedScene<'_> {
+    fn drop(&mut self) {
+        unsafe { embree4_sys::rtcReleaseScene(self.handle) }
+    }
+}
+
+impl<'a> CommittedScene<'a> {
+    /// Returns the flags the scene was committed with, as reported by Embree.
+    ///
+    /// See [rtcGetSceneFlags](https://github.com/embree/embree/blob/master/doc/src/api/rtcGetSceneFlags.md).
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::prelude::*;
+    /// use embree4_sys::RTCSceneFlags;
+    ///
+    /// let device = Device::try_new(None).unwrap();
+    /// let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    /// scene.set_flags(RTCSceneFlags::ROBUST).unwrap();
+    /// let scene = scene.commit().unwrap();
+    /// assert_eq!(scene.flags(), RTCSceneFlags::ROBUST);
+    /// ```
+    pub fn flags(&self) -> embree4_sys::RTCSceneFlags {
+        unsafe { embree4_sys::rtcGetSceneFlags(self.handle) }
+    }
+
+    /// Sets the distance skipped by [Self::continue_from] and [Self::intersect_all] before
+    /// looking for the next hit, so that rays do not hit again the surface they start from because
+    /// of rounding errors. Negative or NaN values are replaced by `0`.
+    ///
+    /// The epsilon is measured in multiples of the length of the ray direction, and defaults to
+    /// [DEFAULT_RAY_EPSILON].
+    pub fn with_ray_epsilon(mut self, epsilon: f32) -> Self {
+        self.ray_epsilon = epsilon.max(0.0);
+        self
+    }
+
+    /// Returns the epsilon set with [Self::with_ray_epsilon].
+    pub fn ray_epsilon(&self) -> f32 {
+        self.ray_epsilon
+    }
+
+    /// Returns the build quality the scene was committed with.
+    ///
+    /// Embree does not report the build quality, so this is tracked by the wrapper.
+    pub fn build_quality(&self) -> embree4_sys::RTCBuildQuality {
+        self.build_quality
+    }
+
+    /// Returns the deepest nesting of instances in the scene, `0` if it contains no instance.
+    pub fn instance_depth(&self) -> u32 {
+        self.instance_depth
+    }
+
+    /// Finds the closest hit along the ray.
+    ///
+    /// # Returns
+    /// A `Result` containing the hit, if any, or an error if an error occurred. Use
+    /// [intersect_1](Self::intersect_1) to get the raw [RTCRayHit](embree4_sys::RTCRayHit).
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::prelude::*;
+    ///
+    /// let device = Device::try_new(None).unwrap();
+    /// let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    /// let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 5.0), 1.0).unwrap();
+    /// scene.attach_geometry(&sphere).unwrap();
+    /// let scene = scene.commit().unwrap();
+    ///
+    /// let ray = RayBuilder::new((0.0, 0.0, 0.0), (0.0, 0.0, 1.0)).build();
+    /// let hit = scene.intersect(ray).unwrap().unwrap();
+    /// assert!((hit.point[2] - 4.0).abs() < 1e-4);
+    /// ```
+    pub fn intersect(&self, ray: embree4_sys::RTCRay) -> Result<Option<Intersection>> {
+        Ok(self.intersect_1(ray)?.map(Intersection::from))
+    }
+
+    /// Finds the closest hit along the ray, with its normal transformed to world space.
+    ///
+    /// Embree reports the hit point along the world space ray, but the normal in the space of
+    /// the innermost instance. This composes the transforms of the instance stack, see
+    /// [world_transform](Self::world_transform), so that both are in world space and can be used
+    /// for shading directly.
+    ///
+    /// # Returns
+    /// A `Result` containing the hit, if any, or an error if an error occurred or if an instance
+    /// of the hit was not attached through [Scene::attach_geometry].
+    pub fn intersect_world(&self, ray: embree4_sys::RTCRay) -> Result<Option<Intersection>> {
+        let Some(ray_hit) = self.intersect_1(ray)? else {
+            return Ok(None);
+        };
+
+        let mut intersection = Intersection::from(&ray_hit);
+        if !intersection.inst_ids.is_empty() {
+            intersection.normal = intersection.world_normal(&self.world_transform(&ray_hit)?);
+        }
+        Ok(Some(intersection))
+    }
+
+    /// Finds the closest hit along the ray, and passes it to `hit_fn`, or the ray to `miss_fn` if
+    /// it misses the scene.
+    ///
+    /// This saves matching on the result of [intersect_1](Self::intersect_1) in shading loops,
+    /// where misses are typically shaded with the environment.
+    ///
+    /// # Returns
+    /// A `Result` containing the value returned by the called function, or an error if an error
+    /// occurred.
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::prelude::*;
+    ///
+    /// let device = Device::try_new(None).unwrap();
+    /// let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    /// let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 5.0), 1.0).unwrap();
+    /// scene.attach_geometry(&sphere).unwrap();
+    /// let scene = scene.commit().unwrap();
+    ///
+    /// let sky = |ray: &embree4_sys::RTCRay| [0.5, 0.7, 1.0f32.min(ray.dir_y + 0.5)];
+    /// let ray = RayBuilder::new((0.0, 0.0, 0.0), (0.0, 0.0, 1.0)).build();
+    /// let color = scene
+    ///     .intersect_shade(ray, |hit| [hit.hit.Ng_z.abs(); 3], sky)
+    ///     .unwrap();
+    /// ```
+    pub fn intersect_shade<T>(
+        &self,
+        ray: embree4_sys::RTCRay,
+        hit_fn: impl FnOnce(embree4_sys::RTCRayHit) -> T,
+        miss_fn: impl FnOnce(&embree4_sys::RTCRay) -> T,
+    ) -> Result<T> {
+        Ok(match self.intersect_1(ray)? {
+            Some(ray_hit) => hit_fn(ray_hit),
+            None => miss_fn(&ray),
+        })
+    }
+
+    /// Finds the closest hit along the ray, converted into `T`, or returns the value of
+    /// `on_miss` if the ray misses the scene.
+    ///
+    /// See [intersect_shade](Self::intersect_shade) to handle hits with a function instead.
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::prelude::*;
+    ///
+    /// struct Radiance([f32; 3]);
+    ///
+    /// impl From<embree4_sys::RTCRayHit> for Radiance {
+    ///     fn from(hit: embree4_sys::RTCRayHit) -> Self {
+    ///         Radiance([hit.hit.Ng_z.abs(); 3])
+    ///     }
+    /// }
+    ///
+    /// let device = Device::try_new(None).unwrap();
+    /// let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    /// let scene = scene.commit().unwrap();
+    ///
+    /// let ray = RayBuilder::new((0.0, 0.0, 0.0), (0.0, 0.0, 1.0)).build();
+    /// let Radiance(color) = scene.intersect_or(ray, |_| Radiance([0.5, 0.7, 1.0])).unwrap();
+    /// assert_eq!(color, [0.5, 0.7, 1.0]);
+    /// ```
+    pub fn intersect_or<T: From<embree4_sys::RTCRayHit>>(
+        &self,
+        ray: embree4_sys::RTCRay,
+        on_miss: impl FnOnce(&embree4_sys::RTCRay) -> T,
+    ) -> Result<T> {
+        self.intersect_shade(ray, T::from, on_miss)
+    }
+
+    pub fn intersect_1(&self, ray: embree4_sys::RTCRay) -> Result<Option<embree4_sys::RTCRayHit>> {
+        let mut ray_hit = embree4_sys::RTCRayHit {
+            ray,
+            hit: Default::default(),
+        };
+
+        clear_device_error(self.device.handle);
+        unsafe {
+            let _mxcsr = Mxcsr::setup();
+            embree4_sys::rtcIntersect1(self.handle, &mut ray_hit, std::ptr::null_mut());
+        }
+        device_error_or(&self.device, (), "Could not intersect ray")?;
+
+        Ok(
+            if ray_hit.hit.geomID != embree4_sys::RTC_INVALID_GEOMETRY_ID {
+                Some(ray_hit)
+            } else {
+                None
+            },
+        )
+    }
+
+    /// Lazily finds the closest hit along each ray, see [CommittedScene::intersect_1].
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::prelude::*;
+    ///
+    /// let device = Device::try_new(None).unwrap();
+    /// let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    /// let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 5.0), 1.0).unwrap();
+    /// scene.attach_geometry(&sphere).unwrap();
+    /// let scene = scene.commit().unwrap();
+    ///
+    /// let rays = (0..8).map(|i| RayBuilder::new((0.75 * i as f32, 0.0, 0.0), (0.0, 0.0, 1.0)).build());
+    /// let hit_count = scene
+    ///     .intersect_iter(rays)
+    ///     .filter(|hit| matches!(hit, Ok(Some(_))))
+    ///     .count();
+    /// assert_eq!(hit_count, 2);
+    /// ```
+    pub fn intersect_iter<'s, I: IntoIterator<Item = embree4_sys::RTCRay>>(
+        &'s self,
+        rays: I,
+    ) -> impl Iterator<Item = Result<Option<embree4_sys::RTCRayHit>>> + 's
+    where
+        I::IntoIter: 's,
+    {
+        rays.into_iter().map(|ray| self.intersect_1(ray))
+    }
+
+    /// Finds the closest hit along each ray in parallel, see [CommittedScene::intersect_iter].
+    #[cfg(feature = "rayon")]
+    pub fn par_intersect_iter<
+        's,
+        I: rayon::iter::IntoParallelIterator<Item = embree4_sys::RTCRay>,
+    >(
+        &'s self,
+        rays: I,
+    ) -> impl rayon::iter::ParallelIterator<Item = Result<Option<embree4_sys::RTCRayHit>>> + 's
+    where
+        I::Iter: 's,
+    {
+        use rayon::iter::ParallelIterator;
+
+        rays.into_par_iter().map(|ray| self.intersect_1(ray))
+    }
+
+    /// Finds the closest hit along the ray using the given query context.
+    ///
+    /// # Returns
+    /// A `Result` containing the hit, if any, or an error if an error occurred.
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::prelude::*;
+    /// use embree4_sys::*;
+    ///
+    /// let device = Device::try_new(None).unwrap();
+    /// let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    /// let scene = scene.commit().unwrap();
+    ///
+    /// let context = RayQueryContext::new().feature_mask(RTCFeatureFlags::TRIANGLE);
+    /// let ray = RayBuilder::new((0.0, 0.0, 0.0), (0.0, 0.0, 1.0)).build();
+    /// let hit = scene.intersect_1_with_context(ray, &context).unwrap();
+    /// ```
+    pub fn intersect_1_with_context(
+        &self,
+        ray: embree4_sys::RTCRay,
+        context: &RayQueryContext,
+    ) -> Result<Option<embree4_sys::RTCRayHit>> {
+        let mut context = *context;
+        self.intersect_1_reuse(ray, &mut context)
+    }
+
+    /// Finds the closest hit of a single ray, using `context` in place.
+    ///
+    /// Unlike [Self::intersect_1_with_context], the context is not copied, so that a thread can
+    /// set up a context once and reuse it for all of its rays. Embree leaves the instance stack
+    /// of the context as it found it once the query returns.
+    ///
+    /// # Returns
+    /// A `Result` containing the closest hit, if any, or an error if an error occurred.
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::prelude::*;
+    ///
+    /// let device = Device::try_new(None).unwrap();
+    /// let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    /// let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 5.0), 1.0).unwrap();
+    /// scene.attach_geometry(&sphere).unwrap();
+    /// let scene = scene.commit().unwrap();
+    ///
+    /// let mut context = RayQueryContext::new();
+    /// for x in [-0.5, 0.0, 0.5] {
+    ///     let ray = RayBuilder::new((x, 0.0, 0.0), (0.0, 0.0, 1.0)).build();
+    ///     assert!(scene.intersect_1_reuse(ray, &mut context).unwrap().is_some());
+    /// }
+    /// ```
+    pub fn intersect_1_reuse(
+        &self,
+        ray: embree4_sys::RTCRay,
+        context: &mut RayQueryContext,
+    ) -> Result<Option<embree4_sys::RTCRayHit>> {
+        self.check_context(context)?;
+        let mut args = context.intersect_arguments();
+        let mut ray_hit = embree4_sys::RTCRayHit {
+            ray,
+            hit: Default::default(),
+        };
+
+        clear_device_error(self.device.handle);
+        unsafe {
+            let _mxcsr = Mxcsr::setup();
+            embree4_sys::rtcIntersect1(self.handle, &mut ray_hit, &mut args);
+        }
+        device_error_or(&self.device, (), "Could not intersect ray")?;
+
+        Ok(
+            if ray_hit.hit.geomID != embree4_sys::RTC_INVALID_GEOMETRY_ID {
+                Some(ray_hit)
+            } else {
+                None
+            },
+        )
+    }
+
+    /// Checks whether anything occludes the given ray segment using the given query context.
+    ///
+    /// # Returns
+    /// A `Result` containing `true` if a hit was found between `tnear` and `tfar`, or an error if an
+    /// error occurred.
+    pub fn occluded_1_with_context(
+        &self,
+        ray: embree4_sys::RTCRay,
+        context: &RayQueryContext,
+    ) -> Result<bool> {
+        self.check_context(context)?;
+        let mut context = *context;
+        let mut args = context.occluded_arguments();
+        let mut ray = ray;
+
+        clear_device_error(self.device.handle);
+        unsafe {
+            let _mxcsr = Mxcsr::setup();
+            embree4_sys::rtcOccluded1(self.handle, &mut ray, &mut args);
+        }
+
+        device_error_or(
+            &self.device,
+            ray.tfar == f32::NEG_INFINITY,
+            "Could not intersect occlusion ray",
+        )
+    }
+
+    // Embree silently ignores the filter of the arguments on scenes without the matching flag
+    fn check_context(&self, context: &RayQueryContext) -> Result<()> {
+        if context.has_filter() {
+            self.check_argument_filters()?;
+        }
+        Ok(())
+    }
+
+    fn check_argument_filters(&self) -> Result<()> {
+        let flag = RTCSceneFlags::FILTER_FUNCTION_IN_ARGUMENTS;
+        if self.flags() & flag != flag {
+            bail!(
+                "Scenes queried with a context filter need the FILTER_FUNCTION_IN_ARGUMENTS flag"
+            );
+        }
+        Ok(())
+    }
+
+    /// Finds the closest hit along the ray among the primitives accepted by `predicate`, called
+    /// with the geometry and primitive IDs of each candidate hit.
+    ///
+    /// The predicate runs as a filter function passed through the query arguments, so the scene
+    /// must be created with `RTCSceneFlags::FILTER_FUNCTION_IN_ARGUMENTS`. This restricts the
+    /// query to some objects, e.g. for debugging, without rebuilding the scene.
+    ///
+    /// # Returns
+    /// A `Result` containing the hit, if any, or an error if an error occurred.
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::prelude::*;
+    /// use embree4_sys::RTCSceneFlags;
+    ///
+    /// let device = Device::try_new(None).unwrap();
+    /// let options = SceneOptions {
+    ///     flags: RTCSceneFlags::FILTER_FUNCTION_IN_ARGUMENTS,
+    ///     ..Default::default()
+    /// };
+    /// let scene = Scene::try_new(&device, options).unwrap();
+    /// let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 5.0), 1.0).unwrap();
+    /// let geom_id = scene.attach_geometry(&sphere).unwrap();
+    /// let scene = scene.commit().unwrap();
+    ///
+    /// let ray = RayBuilder::new((0.0, 0.0, 0.0), (0.0, 0.0, 1.0)).build();
+    /// let hit = scene.intersect_1_where(ray, |id, _| id != geom_id).unwrap();
+    /// assert!(hit.is_none());
+    /// ```
+    pub fn intersect_1_where(
+        &self,
+        ray: embree4_sys::RTCRay,
+        mut predicate: impl FnMut(u32, u32) -> bool,
+    ) -> Result<Option<embree4_sys::RTCRayHit>> {
+        // Embree passes the context to the filter, which finds the predicate right after it
+        #[repr(C)]
+        struct PredicateContext<'p> {
+            context: embree4_sys::RTCRayQueryContext,
+            predicate: &'p mut dyn FnMut(u32, u32) -> bool,
+        }
+
+        unsafe extern "C" fn filter(args: *const embree4_sys::RTCFilterFunctionNArguments) {
+            let args = &*args;
+            let context = &mut *(args.context as *mut PredicateContext);
+            let n = args.N as usize;
+            let valid = std::slice::from_raw_parts_mut(args.valid, n);
+            // RTCHitN stores each field for the N hits: Ng, u, v, primID, geomID, instID
+            let hit = args.hit as *const u32;
+            for (i, valid) in valid.iter_mut().enumerate() {
+                let (prim_id, geom_id) = (*hit.add(5 * n + i), *hit.add(6 * n + i));
+                if *valid != 0 && !(context.predicate)(geom_id, prim_id) {
+                    *valid = 0;
+                }
+            }
+        }
+
+        self.check_argument_filters()?;
+
+        let mut context = PredicateContext {
+            context: embree4_sys::RTCRayQueryContext {
+                instID: [RTC_INVALID_GEOMETRY_ID;
+                    embree4_sys::RTC_MAX_INSTANCE_LEVEL_COUNT as usize],
+            },
+            predicate: &mut predicate,
+        };
+        let mut args = embree4_sys::RTCIntersectArguments {
+            flags: embree4_sys::RTCRayQueryFlags::INVOKE_ARGUMENT_FILTER,
+            feature_mask: embree4_sys::RTCFeatureFlags::ALL,
+            context: &mut context.context,
+            filter: Some(filter),
+            intersect: None,
+        };
+        let mut ray_hit = embree4_sys::RTCRayHit {
+            ray,
+            hit: Default::default(),
+        };
+
+        clear_device_error(self.device.handle);
+        unsafe {
+            let _mxcsr = Mxcsr::setup();
+            embree4_sys::rtcIntersect1(self.handle, &mut ray_hit, &mut args);
+        }
+        device_error_or(&self.device, (), "Could not intersect ray")?;
+
+        Ok(
+            if ray_hit.hit.geomID != embree4_sys::RTC_INVALID_GEOMETRY_ID {
+                Some(ray_hit)
+            } else {
+                None
+            },
+        )
+    }
+
+    /// Finds the closest hit along the ray, skipping the traversal for rays that miss the scene
+    /// bounds.
+    ///
+    /// The bounds are computed on the first call and cached. This is faster than
+    /// [CommittedScene::intersect_1] when many rays leave the scene without hitting anything, e.g.
+    /// for environment-dominated renders, and returns the same results.
+    ///
+    /// # Returns
+    /// A `Result` containing the hit, if any, or an error if an error occurred.
+    pub fn intersect_1_bounded(
+        &self,
+        ray: embree4_sys::RTCRay,
+    ) -> Result<Option<embree4_sys::RTCRayHit>> {
+        if self.cached_aabb()?.intersect_ray(&ray).is_none() {
+            return Ok(None);
+        }
+        self.intersect_1(ray)
+    }
+
+    fn cached_aabb(&self) -> Result<Aabb> {
+        if let Some(aabb) = self.aabb.get() {
+            return Ok(*aabb);
+        }
+
+        let aabb = Aabb::from(self.bounds()?);
+        // Grow the bounds so that the slab test never rejects a ray grazing the scene that
+        // Embree's traversal would report as a hit
+        let extent = aabb
+            .lower
+            .iter()
+            .chain(aabb.upper.iter())
+            .filter(|x| x.is_finite())
+            .fold(0.0f32, |acc, x| acc.max(x.abs()));
+        let aabb = aabb.expand(1e-5 * extent + f32::EPSILON);
+
+        Ok(*self.aabb.get_or_init(|| aabb))
+    }
+
+    /// Checks whether anything occludes the given ray segment.
+    ///
+    /// # Returns
+    /// A `Result` containing `true` if a hit was found between `tnear` and `tfar`, or an error if an
+    /// error occurred.
+    pub fn occluded_1(&self, ray: embree4_sys::RTCRay) -> Result<bool> {
+        let mut ray = ray;
+
+        clear_device_error(self.device.handle);
+        unsafe {
+            let _mxcsr = Mxcsr::setup();
+            embree4_sys::rtcOccluded1(self.handle, &mut ray, std::ptr::null_mut());
+        }
+
+        // Embree sets tfar to -inf when an occluder is found
+        device_error_or(
+            &self.device,
+            ray.tfar == f32::NEG_INFINITY,
+            "Could not intersect occlusion ray",
+        )
+    }
+
+    /// Checks whether the ray hits anything at all between `tnear` and `tfar`.
+    ///
+    /// This is [CommittedScene::occluded_1] under the name of the question it answers. Unlike
+    /// [CommittedScene::intersect], it stops at the first hit found rather than looking for the
+    /// closest one, and reports neither where nor what was hit. Prefer it whenever a yes or no is
+    /// enough, e.g. for shadow rays.
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::prelude::*;
+    ///
+    /// let device = Device::try_new(None).unwrap();
+    /// let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    /// let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 5.0), 1.0).unwrap();
+    /// scene.attach_geometry(&sphere).unwrap();
+    /// let scene = scene.commit().unwrap();
+    ///
+    /// let ray = RayBuilder::new((0.0, 0.0, 0.0), (0.0, 0.0, 1.0)).build();
+    /// assert!(scene.any_hit(ray).unwrap());
+    /// ```
+    pub fn any_hit(&self, ray: embree4_sys::RTCRay) -> Result<bool> {
+        self.occluded_1(ray)
+    }
+
+    /// Finds the closest hit within `max_dist` of the origin.
+    ///
+    /// `max_dist` is measured in multiples of the length of `direction`.
+    ///
+    /// # Returns
+    /// A `Result` containing the hit, if any, or an error if an error occurred.
+    pub fn intersect_within(
+        &self,
+        origin: (f32, f32, f32),
+        direction: (f32, f32, f32),
+        max_dist: f32,
+    ) -> Result<Option<embree4_sys::RTCRayHit>> {
+        self.intersect_1(RayBuilder::new(origin, direction).tfar(max_dist).build())
+    }
+
+    /// Traces a ray leaving the point of `hit` in `direction`, e.g. a ray bounced off a surface.
+    ///
+    /// The ray starts at the [ray epsilon](Self::with_ray_epsilon), so that it does not hit
+    /// again the surface it leaves.
+    ///
+    /// # Returns
+    /// A `Result` containing the closest hit of the new ray, if any, or an error if an error
+    /// occurred.
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::prelude::*;
+    ///
+    /// let device = Device::try_new(None).unwrap();
+    /// let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    /// let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 5.0), 1.0).unwrap();
+    /// scene.attach_geometry(&sphere).unwrap();
+    /// let scene = scene.commit().unwrap().with_ray_epsilon(1e-3);
+    ///
+    /// let ray = RayBuilder::new((0.0, 0.0, 0.0), (0.0, 0.0, 1.0)).build();
+    /// let hit = scene.intersect_1(ray).unwrap().unwrap();
+    /// // Reflected back towards the origin, away from the sphere
+    /// assert!(scene.continue_from(&hit, (0.0, 0.0, -1.0)).unwrap().is_none());
+    /// ```
+    pub fn continue_from(
+        &self,
+        hit: &embree4_sys::RTCRayHit,
+        direction: (f32, f32, f32),
+    ) -> Result<Option<embree4_sys::RTCRayHit>> {
+        let ray = &hit.ray;
+        let origin = (
+            ray.org_x + ray.tfar * ray.dir_x,
+            ray.org_y + ray.tfar * ray.dir_y,
+            ray.org_z + ray.tfar * ray.dir_z,
+        );
+        self.intersect_1(
+            RayBuilder::new(origin, direction)
+                .tnear(self.ray_epsilon)
+                .time(ray.time)
+                .mask(ray.mask)
+                .build(),
+        )
+    }
+
+    /// Finds all hits along the ray, from the closest to the farthest.
+    ///
+    /// After each hit, the search resumes past it by the [ray epsilon](Self::with_ray_epsilon),
+    /// so that a surface is reported once. Surfaces closer than the epsilon to a previous hit are
+    /// skipped. The search also stops if the epsilon is too small to move past a hit.
+    ///
+    /// # Returns
+    /// A `Result` containing the hits, or an error if an error occurred.
+    pub fn intersect_all(&self, ray: embree4_sys::RTCRay) -> Result<Vec<embree4_sys::RTCRayHit>> {
+        let mut hits = Vec::new();
+        let mut ray = ray;
+        while let Some(hit) = self.intersect_1(ray)? {
+            let tnear = hit.ray.tfar + self.ray_epsilon;
+            if tnear <= ray.tnear {
+                break;
+            }
+            hits.push(hit);
+            ray.tnear = tnear;
+        }
+        Ok(hits)
+    }
+
+    /// Checks whether anything lies within `max_dist` of the origin along the ray.
+    ///
+    /// `max_dist` is measured in multiples of the length of `direction`.
+    ///
+    /// # Returns
+    /// A `Result` containing `true` if an occluder was found, or an error if an error occurred.
+    pub fn occluded_within(
+        &self,
+        origin: (f32, f32, f32),
+        direction: (f32, f32, f32),
+        max_dist: f32,
+    ) -> Result<bool> {
+        self.occluded_1(RayBuilder::new(origin, direction).tfar(max_dist).build())
+    }
+
+    /// [Self::occluded_within] with `max_dist` in world space, as done for ambient occlusion.
+    ///
+    /// `max_dist` does not depend on the length of `direction`, which does not need to be
+    /// normalized.
+    ///
+    /// # Returns
+    /// A `Result` containing `true` if an occluder was found, or an error if an error occurred.
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::prelude::*;
+    ///
+    /// let device = Device::try_new(None).unwrap();
+    /// let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    /// let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 5.0), 1.0).unwrap();
+    /// scene.attach_geometry(&sphere).unwrap();
+    /// let scene = scene.commit().unwrap();
+    ///
+    /// let ao_radius = 2.0;
+    /// assert!(!scene.occluded_1_max((0.0, 0.0, 0.0), (0.0, 0.0, 10.0), ao_radius).unwrap());
+    /// ```
+    pub fn occluded_1_max(
+        &self,
+        origin: (f32, f32, f32),
+        direction: (f32, f32, f32),
+        max_dist: f32,
+    ) -> Result<bool> {
+        let len =
+            (direction.0 * direction.0 + direction.1 * direction.1 + direction.2 * direction.2)
+                .sqrt();
+        if len == 0.0 || max_dist <= 0.0 {
+            bail!("Occlusion ray needs a non-zero direction and a positive distance");
+        }
+
+        self.occluded_within(origin, direction, max_dist / len)
+    }
+
+    /// Resolves the per-instance value of the instance that was hit.
+    ///
+    /// The value is read from the instance at the top of the hit's instance stack, i.e. the
+    /// instance attached to this scene, as set by
+    /// [InstanceGeometry::set_user_data](crate::geometry::InstanceGeometry::set_user_data).
+    ///
+    /// # Returns
+    /// `Some(value)` if an instance was hit, `None` otherwise. Instances without a value
+    /// resolve to `Some(0)`.
+    pub fn resolve_instance_data(&self, hit: &embree4_sys::RTCRayHit) -> Option<usize> {
+        let inst_id = hit.hit.instID[0];
+        if inst_id == embree4_sys::RTC_INVALID_GEOMETRY_ID {
+            return None;
+        }
+
+        self.geometry_user_data(inst_id).map(|data| data as usize)
+    }
+
+    /// Returns the user data pointer of an attached geometry, e.g. the data of a
+    /// [UserGeometry](crate::geometry::UserGeometry), straight from the committed scene.
+    ///
+    /// This is the fast path Embree provides for hit-time lookups: it takes no lock and can be
+    /// called concurrently from any thread, including from filter and user geometry callbacks.
+    /// The pointer is only valid while the data it was set from lives, which for borrowed data
+    /// ends with the borrow, not with the scene.
+    ///
+    /// # Returns
+    /// `None` if no geometry with this ID was attached through [Scene::attach_geometry], whose
+    /// IDs are allocated sequentially from `0`.
+    pub fn geometry_user_data(&self, geom_id: u32) -> Option<*mut c_void> {
+        // Embree does not check the ID on this path
+        if geom_id as usize >= self.geometry_count {
+            return None;
+        }
+
+        Some(unsafe { embree4_sys::rtcGetGeometryUserDataFromScene(self.handle, geom_id) })
+    }
+
+    /// Returns the local to world transform of the hit geometry, as a column-major 4x4 matrix.
+    ///
+    /// Embree does not store the transforms in the hit: they are looked up from the instances
+    /// attached to the scene, along the `instID` stack of the hit, and composed at the time of
+    /// the ray. Hits on geometries that are not instanced have the identity transform.
+    ///
+    /// # Returns
+    /// An error if an instance of the stack was not attached through [Scene::attach_geometry].
+    pub fn world_transform(&self, hit: &embree4_sys::RTCRayHit) -> Result<[f32; 16]> {
+        #[rustfmt::skip]
+        let mut xfm = [
+            1.0, 0.0, 0.0, 0.0,
+            0.0, 1.0, 0.0, 0.0,
+            0.0, 0.0, 1.0, 0.0,
+            0.0, 0.0, 0.0, 1.0,
+        ];
+
+        let mut instances = self.instances.clone();
+        for &inst_id in hit
+            .hit
+            .instID
+            .iter()
+            .take_while(|&&id| id != RTC_INVALID_GEOMETRY_ID)
+        {
+            let Some(link) = instances.get(&inst_id) else {
+                bail!("Instance {inst_id} of the hit is not tracked by the scene");
+            };
+            xfm = compose_transforms(&xfm, &link.transform(hit.ray.time));
+            instances = link.children();
+        }
+
+        Ok(xfm)
+    }
+
+    /// Finds the closest point of the scene to `point`, within `radius`.
+    ///
+    /// Only triangle meshes and spheres take part in the query. Instances are traversed, and the
+    /// closest point and distance are reported in world space. Motion blur is not taken into
+    /// account: geometries are queried at their first time step.
+    ///
+    /// # Returns
+    /// A `Result` containing the closest point, or `None` if no geometry lies within `radius`, or
+    /// an error if an error occurred.
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::prelude::*;
+    ///
+    /// let device = Device::try_new(None).unwrap();
+    /// let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    /// let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 5.0), 1.0).unwrap();
+    /// scene.attach_geometry(&sphere).unwrap();
+    /// let scene = scene.commit().unwrap();
+    ///
+    /// let closest = scene.closest_point([0.0, 0.0, 0.0], f32::INFINITY).unwrap().unwrap();
+    /// assert_eq!(closest.point, [0.0, 0.0, 4.0]);
+    /// ```
+    pub fn closest_point(&self, point: [f32; 3], radius: f32) -> Result<Option<ClosestPoint>> {
+        let mut query = embree4_sys::RTCPointQuery {
+            x: point[0],
+            y: point[1],
+            z: point[2],
+            time: 0.0,
+            radius,
+        };
+        let mut context = new_point_query_context();
+        let mut state = ClosestPointQuery {
+            point,
+            scene: self.handle,
+            shapes: self.shapes.clone(),
+            instances: self.instances.clone(),
+            closest: None,
+        };
+
+        clear_device_error(self.device.handle);
+        unsafe {
+            let _mxcsr = Mxcsr::setup();
+            embree4_sys::rtcPointQuery(
+                self.handle,
+                &mut query,
+                &mut context,
+                Some(closest_point_query),
+                &mut state as *mut _ as *mut c_void,
+            );
+        }
+        device_error_or(&self.device, state.closest, "Could not query closest point")
+    }
+
+    /// Returns the axis-aligned bounding box og the scene
+    pub fn bounds(&self) -> Result<embree4_sys::RTCBounds> {
+        let mut bounds = embree4_sys::RTCBounds::default();
+        clear_device_error(self.device.handle);
+        unsafe {
+            let _mxcsr = Mxcsr::setup();
+            embree4_sys::rtcGetSceneBounds(self.handle, &mut bounds as *mut RTCBounds);
+        };
+        device_error_or(&self.device, bounds, "Could not get bounds")
+    }
+
+    /// Returns a sphere enclosing the scene, as its center and radius, circumscribed to the
+    /// bounds of the scene.
+    ///
+    /// Useful to give background rays a finite `tfar`, e.g. for environment lighting. Empty scenes
+    /// return a sphere of radius `0` at the origin.
+    ///
+    /// # Example
+    /// ```
+    /// use embree4_rs::prelude::*;
+    ///
+    /// let device = Device::try_new(None).unwrap();
+    /// let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    /// let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 5.0), 1.0).unwrap();
+    /// scene.attach_geometry(&sphere).unwrap();
+    /// let scene = scene.commit().unwrap();
+    ///
+    /// let (center, radius) = scene.bounding_sphere().unwrap();
+    /// assert!(radius >= 1.0);
+    /// ```
+    pub fn bounding_sphere(&self) -> Result<([f32; 3], f32)> {
+        let aabb = Aabb::from(self.bounds()?);
+        if aabb.is_empty() {
+            return Ok(([0.0; 3], 0.0));
+        }
+
+        let center = [0, 1, 2].map(|axis| 0.5 * (aabb.lower[axis] + aabb.upper[axis]));
+        let radius = (0..3)
+            .map(|axis| 0.5 * (aabb.upper[axis] - aabb.lower[axis]))
+            .map(|half_extent| half_extent * half_extent)
+            .sum::<f32>()
+            .sqrt();
+        Ok((center, radius))
+    }
+}
+
+fn hash_buffers(hash: &mut Fnv1a, validation: &Validation) {
+    for bytes in validation.buffer_bytes() {
+        hash.write(&(bytes.len() as u64).to_le_bytes());
+        hash.write(bytes);
+    }
+}
+
+// Hashes the transforms of the instances and the content of the scenes they instance, by
+// increasing geometry ID so that the hash does not depend on the order of the map
+fn hash_instances(hash: &mut Fnv1a, instances: &HashMap<u32, InstanceLink>) {
+    let mut geom_ids: Vec<_> = instances.keys().copied().collect();
+    geom_ids.sort_unstable();
+    for geom_id in geom_ids {
+        let link = &instances[&geom_id];
+        hash.write(&geom_id.to_le_bytes());
+        // The ends of the time range, which differ for instances with motion blur
+        for time in [0.0, 1.0] {
+            for x in link.transform(time) {
+                hash.write(&x.to_le_bytes());
+            }
+        }
+
+        let shapes = link.shapes();
+        let mut shape_ids: Vec<_> = shapes.keys().copied().collect();
+        shape_ids.sort_unstable();
+        for shape_id in shape_ids {
+            let shape = &shapes[&shape_id];
+            hash.write(&shape_id.to_le_bytes());
+            hash_buffers(hash, &Validation::new(shape.geometry, shape.layout));
+        }
+        hash_instances(hash, &link.children());
+    }
+}
+
+/// The 64-bit FNV-1a hash, see [Scene::content_hash].
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Finds the nearest hit of a ray among several scenes, e.g. the terrain and the props of a
+/// layered world.
+///
+/// The scenes are tested in turn, and the `tfar` of the ray is lowered to each hit, so that
+/// later scenes only look for nearer hits.
+///
+/// # Returns
+/// A `Result` containing the index of the scene of the nearest hit along with the hit, if any,
+/// or an error if an error occurred.
+///
+/// # Example
+/// ```
+/// use embree4_rs::{prelude::*, scene::intersect_nearest};
+///
+/// let device = Device::try_new(None).unwrap();
+/// let scenes = [10.0, 5.0].map(|z| {
+///     let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, z), 1.0).unwrap();
+///     let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+///     scene.attach_geometry(&sphere).unwrap();
+///     scene.commit().unwrap()
+/// });
+///
+/// let ray = RayBuilder::new((0.0, 0.0, 0.0), (0.0, 0.0, 1.0)).build();
+/// let (index, _) = intersect_nearest(&[&scenes[0], &scenes[1]], ray).unwrap().unwrap();
+/// assert_eq!(index, 1);
+/// ```
+pub fn intersect_nearest(
+    scenes: &[&CommittedScene],
+    ray: embree4_sys::RTCRay,
+) -> Result<Option<(usize, embree4_sys::RTCRayHit)>> {
+    let mut ray = ray;
+    let mut nearest = None;
+    for (index, scene) in scenes.iter().enumerate() {
+        if let Some(hit) = scene.intersect_1(ray)? {
+            ray.tfar = hit.ray.tfar;
+            nearest = Some((index, hit));
+        }
+    }
+    Ok(nearest)
+}
+
+#[test]
+fn one_geometry_in_two_scenes() {
+    use crate::geometry::SphereGeometry;
+
+    let device = Device::try_new(None).unwrap();
+    let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 5.0), 1.0).unwrap();
+    let other = SphereGeometry::try_new(&device, (3.0, 0.0, 5.0), 1.0).unwrap();
+
+    let first = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    first.attach_geometry(&sphere).unwrap();
+    let second = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    second.attach_geometry(&sphere).unwrap();
+    second.attach_owned(other).unwrap();
+    drop(sphere);
+    let first = first.commit().unwrap();
+    let second = second.commit().unwrap();
+
+    let center = RayBuilder::new((0.0, 0.0, 0.0), (0.0, 0.0, 1.0)).build();
+    let hits_sphere = |scene: &CommittedScene| {
+        let hit = scene.intersect_1(center).unwrap();
+        hit.is_some_and(|hit| (hit.ray.tfar - 4.0).abs() < 1e-4)
+    };
+    let side = RayBuilder::new((3.0, 0.0, 0.0), (0.0, 0.0, 1.0)).build();
+    assert!(hits_sphere(&first));
+    assert!(first.intersect_1(side).unwrap().is_none());
+    assert!(hits_sphere(&second));
+    assert!(second.intersect_1(side).unwrap().is_some());
+
+    // Each scene holds its own reference
+    drop(first);
+    assert!(hits_sphere(&second));
+    assert!(device.error().is_none());
+}
+
+#[test]
+fn commit_with_progress_is_monotonic() {
+    use crate::geometry::TriangleMeshGeometry;
+
+    let device = Device::try_new(None).unwrap();
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+
+    let num_tris = 100_000;
+    let vertices: Vec<_> = (0..3 * num_tris)
+        .map(|i| {
+            let f = i as f32;
+            (f.sin(), f.cos(), 0.001 * f)
+        })
+        .collect();
+    let indices: Vec<_> = (0..num_tris as u32)
+        .map(|i| (3 * i, 3 * i + 1, 3 * i + 2))
+        .collect();
+    let mesh = TriangleMeshGeometry::try_new(&device, &vertices, &indices).unwrap();
+    scene.attach_geometry(&mesh).unwrap();
+
+    let (commit, progress) = scene.commit_with_progress();
+    let progress: Vec<f64> = progress.iter().collect();
+    commit.join().unwrap();
+
+    assert!(!progress.is_empty());
+    assert!(progress.windows(2).all(|w| w[0] <= w[1]));
+    assert!(progress.iter().all(|p| (0.0..=1.0).contains(p)));
+    assert_eq!(progress.last(), Some(&1.0));
+}
+
+#[test]
+fn forgotten_commit_outlives_the_scene() {
+    use crate::geometry::SphereGeometry;
+
+    let device = Device::try_new(None).unwrap();
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 5.0), 1.0).unwrap();
+    scene.attach_geometry(&sphere).unwrap();
+
+    // The build holds its own references, so it runs to completion after the scene is dropped
+    let (commit, progress) = scene.commit_with_progress();
+    std::mem::forget(commit);
+    drop(scene);
+    assert_eq!(progress.iter().last(), Some(1.0));
+}
+
+#[test]
+fn device_error_not_reported_twice() {
+    let device = Device::try_new(None).unwrap();
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+
+    // REFIT is only valid for geometries
+    assert!(scene
+        .set_build_quality(embree4_sys::RTCBuildQuality::REFIT)
+        .is_err());
+    assert!(device.take_error().is_none());
+    assert!(scene.set_flags(embree4_sys::RTCSceneFlags::ROBUST).is_ok());
+}
+
+#[test]
+fn distance_limited_queries() {
+    use crate::geometry::SphereGeometry;
+
+    let device = Device::try_new(None).unwrap();
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 5.0), 1.0).unwrap();
+    scene.attach_geometry(&sphere).unwrap();
+    let scene = scene.commit().unwrap();
+
+    let origin = (0.0, 0.0, 0.0);
+    let direction = (0.0, 0.0, 1.0);
+
+    assert!(!scene.occluded_within(origin, direction, 2.0).unwrap());
+    assert!(scene.occluded_within(origin, direction, 10.0).unwrap());
+    assert!(scene
+        .intersect_within(origin, direction, 2.0)
+        .unwrap()
+        .is_none());
+    let hit = scene.intersect_within(origin, direction, 10.0).unwrap();
+    assert!((hit.unwrap().ray.tfar - 4.0).abs() < 1e-4);
+}
+
+#[test]
+fn compact_dynamic_flags_advisory() {
+    use std::sync::{Arc, Mutex};
+
+    let device = Device::try_new(None).unwrap();
+    let messages = Arc::new(Mutex::new(Vec::new()));
+    let _callback = device.register_error_callback({
+        let messages = messages.clone();
+        move |_, message| messages.lock().unwrap().push(message.to_owned())
+    });
+
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    scene.set_flags(RTCSceneFlags::ROBUST).unwrap();
+    assert!(messages.lock().unwrap().is_empty());
+
+    scene
+        .set_flags(RTCSceneFlags::COMPACT | RTCSceneFlags::DYNAMIC)
+        .unwrap();
+    assert_eq!(messages.lock().unwrap().len(), 1);
+    assert!(device.last_error().is_none());
+}
+
+#[test]
+fn committed_scene_reads_back_flags() {
+    let device = Device::try_new(None).unwrap();
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    assert_eq!(scene.commit().unwrap().flags(), RTCSceneFlags::NONE);
+
+    let flags = RTCSceneFlags::ROBUST | RTCSceneFlags::COMPACT;
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    scene.set_flags(flags).unwrap();
+    let scene = scene.commit().unwrap();
+    assert_eq!(scene.flags(), flags);
+
+    let scene = Scene::try_new(
+        &device,
+        SceneOptions {
+            flags,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(scene.commit().unwrap().flags(), flags);
+}
+
+#[test]
+fn commit_incremental_moves_geometry() {
+    use crate::geometry::TriangleMeshGeometry;
+
+    let device = Device::try_new(None).unwrap();
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+
+    let triangle_at = |x: f32, z: f32| [(x - 1.0, -1.0, z), (x + 1.0, -1.0, z), (x, 1.0, z)];
+    let meshes: Vec<_> = (0..16)
+        .map(|i| {
+            TriangleMeshGeometry::try_new(&device, &triangle_at(3.0 * i as f32, 0.0), &[(0, 1, 2)])
+                .unwrap()
+        })
+        .collect();
+    let geom_ids: Vec<_> = meshes
+        .iter()
+        .map(|mesh| scene.attach_geometry(mesh).unwrap())
+        .collect();
+
+    let ray_at = |x| RayBuilder::new((x, 0.0, -5.0), (0.0, 0.0, 1.0)).build();
+    let committed = scene.commit().unwrap();
+    assert!(
+        (committed
+            .intersect_1(ray_at(3.0))
+            .unwrap()
+            .unwrap()
+            .ray
+            .tfar
+            - 5.0)
+            .abs()
+            < 1e-4
+    );
+
+    meshes[1].set_vertices(&triangle_at(3.0, 10.0)).unwrap();
+    scene.set_dirty(geom_ids[1]);
+    let committed = scene.commit_incremental().unwrap();
+
+    let hit = committed.intersect_1(ray_at(3.0)).unwrap().unwrap();
+    assert_eq!(hit.hit.geomID, geom_ids[1]);
+    assert!((hit.ray.tfar - 15.0).abs() < 1e-4);
+    let hit = committed.intersect_1(ray_at(6.0)).unwrap().unwrap();
+    assert!((hit.ray.tfar - 5.0).abs() < 1e-4);
+}
+
+#[test]
+fn commit_incremental_reflects_moved_vertices() {
+    use crate::geometry::TriangleMeshGeometry;
+
+    let device = Device::try_new(None).unwrap();
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    let triangle = [(-1.0, -1.0, 0.0), (1.0, -1.0, 0.0), (0.0, 1.0, 0.0)];
+    let mesh = TriangleMeshGeometry::try_new(&device, &triangle, &[(0, 1, 2)]).unwrap();
+    let geom_id = scene.attach_geometry(&mesh).unwrap();
+    let ray = RayBuilder::new((0.0, 0.0, -5.0), (0.0, 0.0, 1.0)).build();
+    let tfar = |scene: &CommittedScene| scene.intersect_1(ray).unwrap().unwrap().ray.tfar;
+    assert!((tfar(&scene.commit().unwrap()) - 5.0).abs() < 1e-4);
+
+    mesh.set_vertices(&triangle.map(|(x, y, z)| (x, y, z + 2.0)))
+        .unwrap();
+    scene.set_dirty(geom_id);
+    assert!((tfar(&scene.commit_incremental().unwrap()) - 7.0).abs() < 1e-4);
+
+    // The next commit rebuilds the geometry
+    mesh.set_vertices(&triangle.map(|(x, y, z)| (x, y, z + 4.0)))
+        .unwrap();
+    assert!((tfar(&scene.commit().unwrap()) - 9.0).abs() < 1e-4);
+}
+
+#[test]
+fn failed_commit_incremental_keeps_geometries_dirty() {
+    use crate::geometry::TriangleMeshGeometry;
+
+    let device = Device::try_new(None).unwrap();
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    let triangle = [(-1.0, -1.0, 0.0), (1.0, -1.0, 0.0), (0.0, 1.0, 0.0)];
+    let first = TriangleMeshGeometry::try_new(&device, &triangle, &[(0, 1, 2)]).unwrap();
+    let second = TriangleMeshGeometry::try_new(&device, &triangle, &[(0, 1, 2)]).unwrap();
+    let first_id = scene.attach_geometry(&first).unwrap();
+    scene.commit().unwrap();
+
+    // The second geometry is not attached yet
+    scene.set_dirty(first_id);
+    scene.set_dirty(first_id + 1);
+    assert!(scene.commit_incremental().is_err());
+
+    let second_id = scene.attach_geometry(&second).unwrap();
+    assert_eq!(second_id, first_id + 1);
+    let moved = triangle.map(|(x, y, z)| (x, y, z + 10.0));
+    second.set_vertices(&moved).unwrap();
+    let committed = scene.commit_incremental().unwrap();
+
+    let ray = RayBuilder::new((0.0, 0.0, 5.0), (0.0, 0.0, 1.0)).build();
+    let hit = committed.intersect_1(ray).unwrap().unwrap();
+    assert_eq!(hit.hit.geomID, second_id);
+    assert!((hit.ray.tfar - 5.0).abs() < 1e-4);
+}
+
+#[test]
+fn intersect_1_bounded_matches_intersect_1() {
+    use crate::geometry::SphereGeometry;
+
+    let device = Device::try_new(None).unwrap();
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 5.0), 1.0).unwrap();
+    scene.attach_geometry(&sphere).unwrap();
+    let scene = scene.commit().unwrap();
+
+    for i in 0..64 {
+        let x = -1.5 + 3.0 * i as f32 / 63.0;
+        for direction in [(0.0, 0.0, 1.0), (0.0, 0.0, -1.0), (1.0, 0.0, 0.0)] {
+            let ray = RayBuilder::new((x, 0.25, 0.0), direction).build();
+            let expected = scene.intersect_1(ray).unwrap().map(|hit| hit.ray.tfar);
+            let bounded = scene
+                .intersect_1_bounded(ray)
+                .unwrap()
+                .map(|hit| hit.ray.tfar);
+            assert_eq!(expected, bounded);
+        }
+    }
+}
+
+#[test]
+fn triangle_feature_mask_hits_triangles() {
+    use crate::geometry::{SphereGeometry, TriangleMeshGeometry};
+
+    let device = Device::try_new(None).unwrap();
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    let vertices = [(-1.0, -1.0, 5.0), (1.0, -1.0, 5.0), (0.0, 1.0, 5.0)];
+    let mesh = TriangleMeshGeometry::try_new(&device, &vertices, &[(0, 1, 2)]).unwrap();
+    scene.attach_geometry(&mesh).unwrap();
+    let sphere = SphereGeometry::try_new(&device, (10.0, 0.0, 5.0), 1.0).unwrap();
+    scene.attach_geometry(&sphere).unwrap();
+    let scene = scene.commit().unwrap();
+
+    let context = RayQueryContext::new().feature_mask(embree4_sys::RTCFeatureFlags::TRIANGLE);
+    let ray = RayBuilder::new((0.0, 0.0, 0.0), (0.0, 0.0, 1.0)).build();
+
+    let hit = scene.intersect_1_with_context(ray, &context).unwrap();
+    assert!((hit.unwrap().ray.tfar - 5.0).abs() < 1e-4);
+    assert!(scene.occluded_1_with_context(ray, &context).unwrap());
+
+    // The sphere is not part of the mask: it may be skipped, but the queries still succeed and
+    // report it correctly if it is not
+    let ray = RayBuilder::new((10.0, 0.0, 0.0), (0.0, 0.0, 1.0)).build();
+    let hit = scene.intersect_1_with_context(ray, &context).unwrap();
+    if let Some(hit) = hit {
+        assert!((hit.ray.tfar - 4.0).abs() < 1e-4);
+    }
+    scene.occluded_1_with_context(ray, &context).unwrap();
+}
+
+#[test]
+fn store_scenes_sharing_a_device() {
+    let device = Arc::new(Device::try_new(None).unwrap());
+
+    let mut scenes = Vec::new();
+    for _ in 0..8 {
+        scenes.push(Scene::try_new_arc(device.clone(), SceneOptions::default()).unwrap());
+    }
+    drop(device);
+
+    for scene in &scenes {
+        scene.commit().unwrap();
+    }
+}
+
+#[test]
+fn occlusion_only_scene() {
+    use crate::geometry::SphereGeometry;
+
+    let device = Device::try_new(None).unwrap();
+    let scene = Scene::try_new(&device, SceneOptions::occlusion_only()).unwrap();
+    let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 5.0), 1.0).unwrap();
+    scene.attach_geometry(&sphere).unwrap();
+    let scene = scene.commit().unwrap();
+
+    let towards = RayBuilder::new((0.0, 0.0, 0.0), (0.0, 0.0, 1.0)).build();
+    let away = RayBuilder::new((0.0, 0.0, 0.0), (0.0, 0.0, -1.0)).build();
+    let short = RayBuilder::new((0.0, 0.0, 0.0), (0.0, 0.0, 1.0))
+        .tfar(2.0)
+        .build();
+
+    assert!(scene.occluded_1(towards).unwrap());
+    assert!(!scene.occluded_1(away).unwrap());
+    assert!(!scene.occluded_1(short).unwrap());
+}
+
+#[test]
+fn read_back_scene_configuration() {
+    use embree4_sys::RTCBuildQuality;
+
+    let device = Device::try_new(None).unwrap();
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    assert_eq!(scene.flags(), RTCSceneFlags::NONE);
+    assert_eq!(scene.build_quality(), RTCBuildQuality::MEDIUM);
+
+    let options = SceneOptions {
+        build_quality: RTCBuildQuality::HIGH,
+        flags: RTCSceneFlags::ROBUST,
+    };
+    let scene = Scene::try_new(&device, options).unwrap();
+    assert_eq!(scene.flags(), RTCSceneFlags::ROBUST);
+    assert_eq!(scene.build_quality(), RTCBuildQuality::HIGH);
+
+    scene
+        .set_flags(RTCSceneFlags::ROBUST | RTCSceneFlags::DYNAMIC)
+        .unwrap();
+    scene.set_build_quality(RTCBuildQuality::LOW).unwrap();
+
+    let scene = scene.commit().unwrap();
+    assert_eq!(
+        scene.flags(),
+        RTCSceneFlags::ROBUST | RTCSceneFlags::DYNAMIC
+    );
+    assert_eq!(scene.build_quality(), RTCBuildQuality::LOW);
+
+    let scene = Scene::try_new(&device, SceneOptions::occlusion_only()).unwrap();
+    assert_eq!(scene.build_quality(), RTCBuildQuality::LOW);
+}
+
+#[test]
+fn drop_geometry_after_attaching_it() {
+    use crate::geometry::SphereGeometry;
+
+    let device = Device::try_new(None).unwrap();
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    {
+        let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 5.0), 1.0).unwrap();
+        scene.attach_geometry(&sphere).unwrap();
+    }
+    let scene = scene.commit().unwrap();
+
+    let ray = RayBuilder::new((0.0, 0.0, 0.0), (0.0, 0.0, 1.0)).build();
+    let hit = scene.intersect_1(ray).unwrap().unwrap();
+    assert!((hit.ray.tfar - 4.0).abs() < 1e-4);
+}
+
+#[test]
+fn scene_owns_its_geometries() {
+    use crate::geometry::{SphereGeometry, TriangleMeshGeometry};
+
+    fn build_scene(device: &Device) -> Scene<'_> {
+        let scene = Scene::try_new(device, SceneOptions::default()).unwrap();
+        let sphere = SphereGeometry::try_new(device, (0.0, 0.0, 5.0), 1.0).unwrap();
+        let vertices = [(2.0, -1.0, 5.0), (4.0, -1.0, 5.0), (3.0, 1.0, 5.0)];
+        let mesh = TriangleMeshGeometry::try_new(device, &vertices, &[(0, 1, 2)]).unwrap();
+
+        assert_eq!(scene.attach_owned(sphere).unwrap(), 0);
+        assert_eq!(scene.attach_owned(mesh).unwrap(), 1);
+        scene
+    }
+
+    let device = Device::try_new(None).unwrap();
+    let scene = build_scene(&device);
+    let scene = scene.commit().unwrap();
+
+    let ray_at = |x| RayBuilder::new((x, 0.0, 0.0), (0.0, 0.0, 1.0)).build();
+    assert_eq!(
+        scene.intersect_1(ray_at(0.0)).unwrap().unwrap().hit.geomID,
+        0
+    );
+    assert_eq!(
+        scene.intersect_1(ray_at(3.0)).unwrap().unwrap().hit.geomID,
+        1
+    );
+}
+
+#[test]
+fn bounds_estimate_matches_committed_bounds() {
+    use crate::geometry::SphereGeometry;
+
+    let device = Device::try_new(None).unwrap();
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    assert!(scene.bounds_estimate().is_none());
+
+    let left = SphereGeometry::try_new(&device, (-2.0, 0.0, 5.0), 1.0).unwrap();
+    let right = SphereGeometry::try_new(&device, (3.0, 1.0, 5.0), 0.5).unwrap();
+    scene.attach_geometry(&left).unwrap();
+    scene.attach_geometry(&right).unwrap();
+
+    let estimate = scene.bounds_estimate().unwrap();
+    assert_eq!(estimate, Aabb::new([-3.0, -1.0, 4.0], [3.5, 1.5, 6.0]));
+
+    let bounds = Aabb::from(scene.commit().unwrap().bounds().unwrap());
+    for axis in 0..3 {
+        assert!((estimate.lower[axis] - bounds.lower[axis]).abs() < 1e-3);
+        assert!((estimate.upper[axis] - bounds.upper[axis]).abs() < 1e-3);
+    }
+}
+
+#[test]
+fn ray_id_and_flags_round_trip() {
+    use crate::geometry::SphereGeometry;
+
+    let device = Device::try_new(None).unwrap();
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 5.0), 1.0).unwrap();
+    scene.attach_geometry(&sphere).unwrap();
+    let scene = scene.commit().unwrap();
+
+    let ray = RayBuilder::new((0.0, 0.0, 0.0), (0.0, 0.0, 1.0))
+        .id(42)
+        .flags(0b101)
+        .build();
+    let hit = scene.intersect_1(ray).unwrap().unwrap();
+    assert_eq!(hit.ray.id, 42);
+    assert_eq!(hit.ray.flags, 0b101);
+
+    let hit = scene.intersect_1_bounded(ray).unwrap().unwrap();
+    assert_eq!(hit.ray.id, 42);
+}
+
+#[test]
+fn validate_out_of_range_index() {
+    use crate::geometry::{SphereGeometry, TriangleMeshGeometry};
+
+    let device = Device::try_new(None).unwrap();
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 5.0), 1.0).unwrap();
+    scene.attach_geometry(&sphere).unwrap();
+
+    let vertices = [(-1.0, -1.0, 0.0), (1.0, -1.0, 0.0), (0.0, 1.0, 0.0)];
+    let mesh = TriangleMeshGeometry::try_new(&device, &vertices, &[(0, 1, 2)]).unwrap();
+    scene.attach_geometry(&mesh).unwrap();
+    assert!(scene.validate().is_ok());
+
+    let broken =
+        TriangleMeshGeometry::try_new(&device, &vertices, &[(0, 1, 2), (2, 1, 7)]).unwrap();
+    let geom_id = scene.attach_geometry(&broken).unwrap();
+
+    let message = scene.validate().unwrap_err().to_string();
+    assert!(message.contains(&format!(
+        "geometry {}: triangle 1 has out-of-range index 7 (3 vertices)",
+        geom_id
+    )));
+}
+
+#[test]
+fn occluded_1_max_ignores_distant_occluders() {
+    use crate::geometry::SphereGeometry;
+
+    let device = Device::try_new(None).unwrap();
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 5.0), 1.0).unwrap();
+    scene.attach_geometry(&sphere).unwrap();
+    let scene = scene.commit().unwrap();
+
+    let origin = (0.0, 0.0, 0.0);
+    for direction in [(0.0, 0.0, 1.0), (0.0, 0.0, 0.1), (0.0, 0.0, 10.0)] {
+        assert!(!scene.occluded_1_max(origin, direction, 3.9).unwrap());
+        assert!(scene.occluded_1_max(origin, direction, 4.1).unwrap());
+    }
+    assert!(scene.occluded_1_max(origin, (0.0, 0.0, 0.0), 1.0).is_err());
+}
+
+#[test]
+fn intersection_of_triangle_hit() {
+    use crate::geometry::TriangleMeshGeometry;
+
+    let device = Device::try_new(None).unwrap();
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    let vertices = [(-1.0, -1.0, 5.0), (1.0, -1.0, 5.0), (0.0, 1.0, 5.0)];
+    let mesh = TriangleMeshGeometry::try_new(&device, &vertices, &[(0, 1, 2)]).unwrap();
+    let geom_id = scene.attach_geometry(&mesh).unwrap();
+    let scene = scene.commit().unwrap();
+
+    let ray = RayBuilder::new((0.0, 0.0, 1.0), (0.0, 0.0, 2.0)).build();
+    let hit = scene.intersect(ray).unwrap().unwrap();
+
+    let close = |a: &[f32], b: &[f32]| a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-4);
+    assert!((hit.t - 2.0).abs() < 1e-4);
+    assert!(close(&hit.point, &[0.0, 0.0, 5.0]));
+    assert!(close(&hit.normal, &[0.0, 0.0, 1.0]));
+    assert!(close(&hit.uv, &[0.25, 0.5]));
+    assert_eq!(hit.geom_id, geom_id);
+    assert_eq!(hit.prim_id, 0);
+    assert!(hit.inst_ids.is_empty());
+
+    let miss = RayBuilder::new((5.0, 0.0, 0.0), (0.0, 0.0, 1.0)).build();
+    assert_eq!(scene.intersect(miss).unwrap(), None);
+}
+
+#[cfg(feature = "log")]
+#[test]
+fn commit_emits_debug_record() {
+    let records = crate::captured_log_records();
+
+    let device = Device::try_new(None).unwrap();
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    scene.commit().unwrap();
+
+    let records = records.lock().unwrap();
+    assert!(
+        records
+            .iter()
+            .any(|(level, message)| *level == log::Level::Debug
+                && message.starts_with("Scene commit"))
+    );
+}
+
+#[test]
+fn commit_timed_large_scene() {
+    use crate::geometry::TriangleMeshGeometry;
+
+    const N: u32 = 256;
+
+    let device = Device::try_new(None).unwrap();
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+
+    let vertices = (0..=N)
+        .flat_map(|y| (0..=N).map(move |x| (x as f32, y as f32, 5.0)))
+        .collect::<Vec<_>>();
+    let indices = (0..N)
+        .flat_map(|y| (0..N).map(move |x| y * (N + 1) + x))
+        .flat_map(|i| [(i, i + 1, i + N + 1), (i + 1, i + N + 2, i + N + 1)])
+        .collect::<Vec<_>>();
+    let mesh = TriangleMeshGeometry::try_new(&device, &vertices, &indices).unwrap();
+    scene.attach_geometry(&mesh).unwrap();
+    assert_eq!(scene.primitive_count(), 2 * (N * N) as usize);
+
+    let (scene, elapsed) = scene.commit_timed().unwrap();
+    assert!(elapsed > Duration::ZERO);
+
+    let ray = RayBuilder::new((0.5, 0.5, 0.0), (0.0, 0.0, 1.0)).build();
+    assert!(scene.intersect_1(ray).unwrap().is_some());
+}
+
+#[test]
+fn any_hit_matches_occluded_1() {
+    use crate::geometry::SphereGeometry;
+
+    let device = Device::try_new(None).unwrap();
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 5.0), 1.0).unwrap();
+    scene.attach_geometry(&sphere).unwrap();
+    let scene = scene.commit().unwrap();
+
+    for (direction, tfar) in [
+        ((0.0, 0.0, 1.0), f32::INFINITY),
+        ((0.0, 0.0, 1.0), 2.0),
+        ((0.0, 1.0, 0.0), f32::INFINITY),
+        ((0.0, 0.0, -1.0), f32::INFINITY),
+    ] {
+        let ray = RayBuilder::new((0.0, 0.0, 0.0), direction)
+            .tfar(tfar)
+            .build();
+        assert_eq!(scene.any_hit(ray).unwrap(), scene.occluded_1(ray).unwrap());
+    }
+}
+
+#[test]
+fn commit_with_high_quality_after_low() {
+    use crate::geometry::SphereGeometry;
+    use embree4_sys::RTCBuildQuality;
+
+    let device = Device::try_new(None).unwrap();
+    let scene = Scene::try_new(&device, SceneOptions::occlusion_only()).unwrap();
+    let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 5.0), 1.0).unwrap();
+    scene.attach_geometry(&sphere).unwrap();
+
+    let ray = RayBuilder::new((0.0, 0.0, 0.0), (0.0, 0.0, 1.0)).build();
+    let preview = scene.commit().unwrap();
+    assert_eq!(preview.build_quality(), RTCBuildQuality::LOW);
+    assert!(preview.occluded_1(ray).unwrap());
+
+    let baked = scene.commit_with_quality(RTCBuildQuality::HIGH).unwrap();
+    assert_eq!(baked.build_quality(), RTCBuildQuality::HIGH);
+    assert_eq!(scene.build_quality(), RTCBuildQuality::LOW);
+
+    let hit = baked.intersect_1(ray).unwrap().unwrap();
+    assert!((hit.ray.tfar - 4.0).abs() < 1e-4);
+}
+
+#[test]
+fn intersect_iter_matches_loop() {
+    use crate::geometry::SphereGeometry;
+
+    let device = Device::try_new(None).unwrap();
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 5.0), 1.0).unwrap();
+    scene.attach_geometry(&sphere).unwrap();
+    let scene = scene.commit().unwrap();
+
+    let rays = (0..32)
+        .map(|i| RayBuilder::new((0.1 * i as f32 - 1.6, 0.0, 0.0), (0.0, 0.0, 1.0)).build())
+        .collect::<Vec<_>>();
+    let summary = |hit: Option<embree4_sys::RTCRayHit>| hit.map(|h| (h.hit.geomID, h.ray.tfar));
+
+    let mut expected = vec![];
+    for &ray in &rays {
+        expected.push(summary(scene.intersect_1(ray).unwrap()));
+    }
+
+    let hits = scene
+        .intersect_iter(rays.iter().copied())
+        .map(|hit| hit.map(summary))
+        .collect::<Result<Vec<_>>>()
+        .unwrap();
+    assert_eq!(hits, expected);
+    assert!(hits.iter().any(Option::is_some) && hits.iter().any(Option::is_none));
+
+    #[cfg(feature = "rayon")]
+    {
+        use rayon::iter::ParallelIterator;
+
+        let hits = scene
+            .par_intersect_iter(rays)
+            .map(|hit| hit.map(summary))
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(hits, expected);
+    }
+}
+
+#[test]
+fn geometry_user_data_from_scene() {
+    use crate::geometry::{SphereGeometry, UserGeometry, UserGeometryImpl};
+
+    struct Nothing(u64);
+
+    impl UserGeometryImpl for Nothing {
+        fn bounds(&self) -> RTCBounds {
+            RTCBounds::default()
+        }
+
+        fn intersect(
+            &self,
+            _geom_id: u32,
+            _prim_id: u32,
+            _ctx: &embree4_sys::RTCRayQueryContext,
+            _ray_hit: &mut embree4_sys::RTCRayHit,
+        ) {
+        }
+    }
+
+    let device = Device::try_new(None).unwrap();
+    let data = Nothing(42);
+    let user = UserGeometry::try_new(&device, &data).unwrap();
+    let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 5.0), 1.0).unwrap();
+
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    let sphere_id = scene.attach_geometry(&sphere).unwrap();
+    let user_id = scene.attach_geometry(&user).unwrap();
+    let scene = scene.commit().unwrap();
+
+    let ptr = scene.geometry_user_data(user_id).unwrap();
+    assert_eq!(ptr as *const Nothing, &data as *const Nothing);
+    assert_eq!(unsafe { (*(ptr as *const Nothing)).0 }, 42);
+
+    assert!(scene.geometry_user_data(sphere_id).unwrap().is_null());
+    assert!(scene.geometry_user_data(user_id + 1).is_none());
+}
+
+#[test]
+fn bounding_sphere_encloses_scene() {
+    use crate::geometry::SphereGeometry;
+
+    let device = Device::try_new(None).unwrap();
+    let spheres = [([-3.0, 0.0, 0.0], 1.0), ([2.0, 1.0, 4.0], 0.5)];
+
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    let empty = scene.commit().unwrap();
+    assert_eq!(empty.bounding_sphere().unwrap(), ([0.0; 3], 0.0));
+
+    for ([x, y, z], radius) in spheres {
+        let sphere = SphereGeometry::try_new(&device, (x, y, z), radius).unwrap();
+        scene.attach_owned(sphere).unwrap();
+    }
+    let scene = scene.commit().unwrap();
+
+    let (center, bounding_radius) = scene.bounding_sphere().unwrap();
+    for (origin, radius) in spheres {
+        let distance = (0..3)
+            .map(|axis| (origin[axis] - center[axis]).powi(2))
+            .sum::<f32>()
+            .sqrt();
+        assert!(distance + radius <= bounding_radius + 1e-4);
+    }
+}
+
+#[test]
+fn commit_during_pending_commit_fails() {
+    use crate::geometry::SphereGeometry;
+
+    let device = Device::try_new(None).unwrap();
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 5.0), 1.0).unwrap();
+    scene.attach_geometry(&sphere).unwrap();
+
+    let (commit, _progress) = scene.commit_with_progress();
+    assert!(scene.commit().is_err());
+    assert!(scene.commit_incremental().is_err());
+
+    // The scene cannot change while it is being built
+    assert!(scene.attach_geometry(&sphere).is_err());
+    assert!(scene.set_flags(RTCSceneFlags::ROBUST).is_err());
+
+    let (second, _) = scene.commit_with_progress();
+    assert!(second.join().is_err());
+
+    commit.join().unwrap();
+    scene.commit().unwrap();
+}
+
+#[test]
+fn closest_point_on_instanced_sphere_is_in_world_space() {
+    use crate::geometry::{InstanceGeometry, SphereGeometry};
+
+    let device = Device::try_new(None).unwrap();
+    let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 0.0), 1.0).unwrap();
+    let object = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    let sphere_id = object.attach_geometry(&sphere).unwrap();
+    let object = object.commit().unwrap();
+
+    let translate = [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 10.0, 0.0, 0.0];
+    let instance = InstanceGeometry::try_new(&device, &object, &translate).unwrap();
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    let inst_id = scene.attach_geometry(&instance).unwrap();
+    let scene = scene.commit().unwrap();
+
+    let closest = scene
+        .closest_point([0.0, 0.0, 0.0], f32::INFINITY)
+        .unwrap()
+        .unwrap();
+    assert!((closest.point[0] - 9.0).abs() < 1e-5);
+    assert!(closest.point[1].abs() < 1e-5 && closest.point[2].abs() < 1e-5);
+    assert!((closest.distance - 9.0).abs() < 1e-5);
+    assert_eq!(closest.geom_id, sphere_id);
+    assert_eq!(closest.inst_ids, vec![inst_id]);
+
+    assert!(scene.closest_point([0.0, 0.0, 0.0], 8.0).unwrap().is_none());
+}
+
+#[test]
+fn closest_point_skips_unsupported_geometries() {
+    use crate::geometry::{ConeGeometry, TriangleMeshGeometry};
+
+    let device = Device::try_new(None).unwrap();
+    let vertices = [(-1.0, -1.0, 5.0), (1.0, -1.0, 5.0), (0.0, 1.0, 5.0)];
+    let mesh = TriangleMeshGeometry::try_new(&device, &vertices, &[(0, 1, 2)]).unwrap();
+    // The query does not read the user data of the geometries
+    unsafe { embree4_sys::rtcSetGeometryUserData(mesh.geometry(), 0x1234 as *mut c_void) };
+    let cone = ConeGeometry::try_new(&device, (0.0, 0.0, 1.0), (0.0, 0.0, 2.0), 0.5).unwrap();
+
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    let mesh_id = scene.attach_geometry(&mesh).unwrap();
+    scene.attach_geometry(&cone).unwrap();
+    let scene = scene.commit().unwrap();
+
+    let closest = scene
+        .closest_point([0.0, 0.0, 0.0], f32::INFINITY)
+        .unwrap()
+        .unwrap();
+    assert_eq!(closest.geom_id, mesh_id);
+    assert!((closest.distance - 5.0).abs() < 1e-5);
+}
+
+#[test]
+fn snapshot_reads_time_steps_set_after_attaching() {
+    use crate::geometry::TriangleMeshGeometry;
+
+    let device = Device::try_new(None).unwrap();
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    let triangle = [(-1.0, -1.0, 5.0), (1.0, -1.0, 5.0), (0.0, 1.0, 5.0)];
+    let mesh = TriangleMeshGeometry::try_new(&device, &triangle, &[(0, 1, 2)]).unwrap();
+    scene.attach_geometry(&mesh).unwrap();
+    let single = scene.snapshot().unwrap();
+
+    let moved = triangle.map(|(x, y, z)| (x, y, z + 1.0));
+    mesh.set_time_steps(&[&triangle, &moved]).unwrap();
+    let blob = scene.snapshot().unwrap();
+    assert_eq!(blob.len(), single.len() + 9 * size_of::<f32>());
+
+    let replay = Scene::from_snapshot(&device, &blob).unwrap();
+    assert_eq!(replay.snapshot().unwrap(), blob);
+}
+
+#[test]
+fn snapshot_round_trip() {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use crate::geometry::{SphereGeometry, TriangleMeshGeometry};
+
+    let device = Device::try_new(None).unwrap();
+    let options = SceneOptions {
+        build_quality: embree4_sys::RTCBuildQuality::HIGH,
+        flags: RTCSceneFlags::ROBUST,
+    };
+    let scene = Scene::try_new(&device, options).unwrap();
+
+    let quad = [
+        (-1.0, -1.0, 5.0),
+        (1.0, -1.0, 5.0),
+        (1.0, 1.0, 6.0),
+        (-1.0, 1.0, 6.0),
+    ];
+    let moved = quad.map(|(x, y, z)| (x, y, z + 1.0));
+    let mesh = TriangleMeshGeometry::try_new(&device, &quad, &[(0, 1, 2), (2, 3, 0)]).unwrap();
+    mesh.set_time_steps(&[&quad, &moved]).unwrap();
+    scene.attach_owned(mesh).unwrap();
+    let sphere = SphereGeometry::try_new(&device, (0.5, 0.5, 3.0), 0.25).unwrap();
+    scene.attach_owned(sphere).unwrap();
+
+    let blob = scene.snapshot().unwrap();
+    let replay = Scene::from_snapshot(&device, &blob).unwrap();
+    assert_eq!(replay.build_quality(), scene.build_quality());
+    assert_eq!(replay.flags(), scene.flags());
+    assert_eq!(replay.snapshot().unwrap(), blob);
+
+    let (scene, replay) = (scene.commit().unwrap(), replay.commit().unwrap());
+    let mut rng = StdRng::seed_from_u64(0);
+    for _ in 0..64 {
+        let ray = RayBuilder::new(
+            (rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.0),
+            (0.0, 0.0, 1.0),
+        )
+        .time(rng.gen())
+        .build();
+        let hit = |scene: &CommittedScene| {
+            scene
+                .intersect_1(ray)
+                .unwrap()
+                .map(|hit| (hit.ray.tfar, hit.hit.geomID, hit.hit.primID))
+        };
+        assert_eq!(hit(&scene), hit(&replay));
+    }
+
+    assert!(Scene::from_snapshot(&device, &blob[..blob.len() - 1]).is_err());
+}
+
+#[test]
+fn intersect_where_restricts_geometry() {
+    use crate::geometry::SphereGeometry;
+
+    let device = Device::try_new(None).unwrap();
+    let options = SceneOptions {
+        flags: RTCSceneFlags::FILTER_FUNCTION_IN_ARGUMENTS,
+        ..Default::default()
+    };
+    let scene = Scene::try_new(&device, options).unwrap();
+    let near = SphereGeometry::try_new(&device, (0.0, 0.0, 3.0), 1.0).unwrap();
+    let far = SphereGeometry::try_new(&device, (0.0, 0.0, 8.0), 1.0).unwrap();
+    let near_id = scene.attach_geometry(&near).unwrap();
+    let far_id = scene.attach_geometry(&far).unwrap();
+    let scene = scene.commit().unwrap();
+
+    let ray = RayBuilder::new((0.0, 0.0, 0.0), (0.0, 0.0, 1.0)).build();
+    let hit = scene.intersect_1_where(ray, |_, _| true).unwrap().unwrap();
+    assert_eq!(hit.hit.geomID, near_id);
+
+    let mut candidates = Vec::new();
+    let hit = scene
+        .intersect_1_where(ray, |geom_id, prim_id| {
+            candidates.push((geom_id, prim_id));
+            geom_id == far_id
+        })
+        .unwrap()
+        .unwrap();
+    assert_eq!(hit.hit.geomID, far_id);
+    assert!((hit.ray.tfar - 7.0).abs() < 1e-4);
+    assert!(candidates.contains(&(near_id, 0)));
+
+    assert!(scene
+        .intersect_1_where(ray, |_, _| false)
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn attaching_uncommitted_geometry_fails() {
+    use std::cell::Cell;
+
+    // A mesh managing its handle itself, as custom geometries do
+    struct CustomMesh {
+        handle: embree4_sys::RTCGeometry,
+        committed: Cell<bool>,
+    }
+
+    impl CustomMesh {
+        fn commit(&self) {
+            unsafe { embree4_sys::rtcCommitGeometry(self.handle) };
+            self.committed.set(true);
+        }
+    }
+
+    impl Geometry for CustomMesh {
+        fn geometry(&self) -> embree4_sys::RTCGeometry {
+            self.handle
+        }
+
+        fn kind(&self) -> embree4_sys::RTCGeometryType {
+            embree4_sys::RTCGeometryType::TRIANGLE
+        }
+
+        fn primitive_count(&self) -> usize {
+            1
+        }
+
+        fn is_committed(&self) -> bool {
+            self.committed.get()
+        }
+    }
+
+    impl Drop for CustomMesh {
+        fn drop(&mut self) {
+            unsafe { embree4_sys::rtcReleaseGeometry(self.handle) };
+        }
+    }
+
+    let device = Device::try_new(None).unwrap();
+    let mesh = CustomMesh {
+        handle: device
+            .new_geometry(embree4_sys::RTCGeometryType::TRIANGLE)
+            .unwrap()
+            .into_raw(),
+        committed: Cell::new(false),
+    };
+    unsafe {
+        let vertices = embree4_sys::rtcSetNewGeometryBuffer(
+            mesh.handle,
+            embree4_sys::RTCBufferType::VERTEX,
+            0,
+            embree4_sys::RTCFormat::FLOAT3,
+            12,
+            3,
+        ) as *mut [f32; 3];
+        vertices.write([-1.0, -1.0, 5.0]);
+        vertices.add(1).write([1.0, -1.0, 5.0]);
+        vertices.add(2).write([0.0, 1.0, 5.0]);
+        let indices = embree4_sys::rtcSetNewGeometryBuffer(
+            mesh.handle,
+            embree4_sys::RTCBufferType::INDEX,
+            0,
+            embree4_sys::RTCFormat::UINT3,
+            12,
+            1,
+        ) as *mut [u32; 3];
+        indices.write([0, 1, 2]);
+    }
+
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    let err = scene.attach_geometry(&mesh).unwrap_err();
+    assert!(err.to_string().contains("not committed"));
+    assert_eq!(scene.primitive_count(), 0);
+
+    mesh.commit();
+    scene.attach_geometry(&mesh).unwrap();
+    let scene = scene.commit().unwrap();
+    let ray = RayBuilder::new((0.0, 0.0, 0.0), (0.0, 0.0, 1.0)).build();
+    assert!(scene.intersect_1(ray).unwrap().is_some());
+}
+
+#[test]
+fn world_normal_of_rotated_instance() {
+    use crate::geometry::{InstanceGeometry, TriangleMeshGeometry};
+
+    let device = Device::try_new(None).unwrap();
+    let vertices = [(-1.0, -1.0, 0.0), (1.0, -1.0, 0.0), (0.0, 1.0, 0.0)];
+    let mesh = TriangleMeshGeometry::try_new(&device, &vertices, &[(0, 1, 2)]).unwrap();
+    let object = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    object.attach_geometry(&mesh).unwrap();
+    let object = object.commit().unwrap();
+
+    // Rotates +z onto +x around the y axis, stretches y, then moves the object to x = 5
+    let rotate_y = [0.0, 0.0, -1.0, 0.0, 3.0, 0.0, 1.0, 0.0, 0.0, 5.0, 0.0, 0.0];
+    let instance = InstanceGeometry::try_new(&device, &object, &rotate_y).unwrap();
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    scene.attach_geometry(&instance).unwrap();
+    let scene = scene.commit().unwrap();
+
+    let close = |a: [f32; 3], b: [f32; 3]| (0..3).all(|k| (a[k] - b[k]).abs() < 1e-4);
+    let ray = RayBuilder::new((0.0, 0.0, 0.0), (1.0, 0.0, 0.0)).build();
+
+    let local = scene.intersect(ray).unwrap().unwrap();
+    assert!(close(local.normal, [0.0, 0.0, 1.0]));
+
+    let world = scene.intersect_world(ray).unwrap().unwrap();
+    assert!(close(world.point, [5.0, 0.0, 0.0]));
+    assert!(close(world.normal, [1.0, 0.0, 0.0]));
+    assert_eq!(world.inst_ids, local.inst_ids);
+}
+
+#[test]
+fn intersect_shade_takes_hit_and_miss_branches() {
+    use crate::geometry::SphereGeometry;
+
+    let device = Device::try_new(None).unwrap();
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 5.0), 1.0).unwrap();
+    let geom_id = scene.attach_geometry(&sphere).unwrap();
+    let scene = scene.commit().unwrap();
+
+    let towards = RayBuilder::new((0.0, 0.0, 0.0), (0.0, 0.0, 1.0)).build();
+    let away = RayBuilder::new((0.0, 0.0, 0.0), (0.0, 0.0, -1.0)).build();
+
+    #[derive(Debug, PartialEq)]
+    enum Shaded {
+        Surface(u32),
+        Sky(f32),
+    }
+
+    impl From<embree4_sys::RTCRayHit> for Shaded {
+        fn from(hit: embree4_sys::RTCRayHit) -> Self {
+            Shaded::Surface(hit.hit.geomID)
+        }
+    }
+
+    let shade = |ray| {
+        scene
+            .intersect_shade(
+                ray,
+                |hit| Shaded::Surface(hit.hit.geomID),
+                |ray| Shaded::Sky(ray.dir_z),
+            )
+            .unwrap()
+    };
+    assert_eq!(shade(towards), Shaded::Surface(geom_id));
+    assert_eq!(shade(away), Shaded::Sky(-1.0));
+
+    let sky = |ray: &embree4_sys::RTCRay| Shaded::Sky(ray.dir_z);
+    assert_eq!(
+        scene.intersect_or(towards, sky).unwrap(),
+        Shaded::Surface(geom_id)
+    );
+    assert_eq!(scene.intersect_or(away, sky).unwrap(), Shaded::Sky(-1.0));
+
+    let hit: Intersection = scene
+        .intersect_or(towards, |_| panic!("The ray hits the sphere"))
+        .unwrap();
+    assert_eq!(hit.geom_id, geom_id);
+}
+
+#[test]
+fn ray_epsilon_prevents_self_intersection() {
+    use crate::geometry::TriangleMeshGeometry;
+
+    let device = Device::try_new(None).unwrap();
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    let vertices = [(-1.0, -1.0, 1.0), (1.0, -1.0, 1.0), (0.0, 1.0, 1.0)];
+    let plane = TriangleMeshGeometry::try_new(&device, &vertices, &[(0, 1, 2)]).unwrap();
+    scene.attach_geometry(&plane).unwrap();
+    let scene = scene.commit().unwrap();
+    assert_eq!(scene.ray_epsilon(), DEFAULT_RAY_EPSILON);
+
+    let mut hit = scene
+        .intersect_1(RayBuilder::new((0.0, 0.0, 0.0), (0.0, 0.0, 1.0)).build())
+        .unwrap()
+        .unwrap();
+    // A hit point rounded to just before the surface
+    hit.ray.tfar = 1.0 - 1e-6;
+
+    let scene = scene.with_ray_epsilon(1e-9);
+    assert!(scene
+        .continue_from(&hit, (0.0, 0.0, 1.0))
+        .unwrap()
+        .is_some());
+    let scene = scene.with_ray_epsilon(1e-3);
+    assert!(scene
+        .continue_from(&hit, (0.0, 0.0, 1.0))
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn intersect_all_reports_each_surface_once() {
+    use crate::geometry::TriangleMeshGeometry;
+
+    let device = Device::try_new(None).unwrap();
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    let planes: Vec<_> = [1.0, 2.0, 2.01]
+        .into_iter()
+        .map(|z| {
+            let vertices = [(-1.0, -1.0, z), (1.0, -1.0, z), (0.0, 1.0, z)];
+            TriangleMeshGeometry::try_new(&device, &vertices, &[(0, 1, 2)]).unwrap()
+        })
+        .collect();
+    for plane in &planes {
+        scene.attach_geometry(plane).unwrap();
+    }
+    let scene = scene.commit().unwrap();
+
+    let ray = RayBuilder::new((0.0, 0.0, 0.0), (0.0, 0.0, 1.0)).build();
+    let geom_ids = |hits: Vec<embree4_sys::RTCRayHit>| -> Vec<u32> {
+        hits.iter().map(|hit| hit.hit.geomID).collect()
+    };
+    assert_eq!(geom_ids(scene.intersect_all(ray).unwrap()), [0, 1, 2]);
+    let scene = scene.with_ray_epsilon(0.1);
+    assert_eq!(geom_ids(scene.intersect_all(ray).unwrap()), [0, 1]);
+}
+
+#[test]
+fn reused_context_matches_per_call_context() {
+    use crate::geometry::SphereGeometry;
+
+    let device = Device::try_new(None).unwrap();
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    let spheres: Vec<_> = (0..4)
+        .map(|i| SphereGeometry::try_new(&device, (i as f32, 0.0, 5.0), 0.4).unwrap())
+        .collect();
+    for sphere in &spheres {
+        scene.attach_geometry(sphere).unwrap();
+    }
+    let scene = scene.commit().unwrap();
+
+    let mut context = RayQueryContext::new();
+    for i in 0..64 {
+        let ray = RayBuilder::new((i as f32 / 16.0 - 0.5, 0.1, 0.0), (0.0, 0.0, 1.0)).build();
+        let expected = scene
+            .intersect_1_with_context(ray, &RayQueryContext::new())
+            .unwrap();
+        let hit = scene.intersect_1_reuse(ray, &mut context).unwrap();
+        assert_eq!(hit.is_some(), expected.is_some());
+        if let (Some(hit), Some(expected)) = (hit, expected) {
+            assert_eq!(hit.hit.geomID, expected.hit.geomID);
+            assert_eq!(hit.ray.tfar, expected.ray.tfar);
+        }
+        assert!(context.instance_stack().is_empty());
+    }
+}
+
+#[test]
+fn denormals_are_preserved_outside_queries() {
+    use std::hint::black_box;
+
+    use crate::geometry::SphereGeometry;
+
+    let device = Device::try_new(None).unwrap();
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 5.0), 1.0).unwrap();
+    scene.attach_geometry(&sphere).unwrap();
+    let scene = scene.commit().unwrap();
+
+    let denormal = || black_box(f32::MIN_POSITIVE) / black_box(2.0);
+    assert_ne!(denormal(), 0.0);
+    for _ in 0..4 {
+        let ray = RayBuilder::new((0.0, 0.0, 0.0), (0.0, 0.0, 1.0)).build();
+        assert!(scene.intersect_1(ray).unwrap().is_some());
+        assert!(scene.occluded_1(ray).unwrap());
+        assert_ne!(denormal(), 0.0);
+    }
+}
+
+#[test]
+fn nearest_hit_across_scenes() {
+    use crate::geometry::SphereGeometry;
+
+    let device = Device::try_new(None).unwrap();
+    // A far wall of spheres, and a scene with a single nearer sphere
+    let far = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    for x in [-2.0, 0.0, 2.0] {
+        far.attach_owned(SphereGeometry::try_new(&device, (x, 0.0, 10.0), 1.0).unwrap())
+            .unwrap();
+    }
+    let far = far.commit().unwrap();
+    let near = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    near.attach_owned(SphereGeometry::try_new(&device, (0.0, 0.0, 5.0), 1.0).unwrap())
+        .unwrap();
+    let near = near.commit().unwrap();
+
+    let ray = |x: f32| RayBuilder::new((x, 0.0, 0.0), (0.0, 0.0, 1.0)).build();
+    for scenes in [[&far, &near], [&near, &far]] {
+        let near_index = if std::ptr::eq(scenes[0], &near) { 0 } else { 1 };
+
+        let (index, hit) = intersect_nearest(&scenes, ray(0.0)).unwrap().unwrap();
+        assert_eq!(index, near_index);
+        assert!((hit.ray.tfar - 4.0).abs() < 1e-4);
+
+        let (index, hit) = intersect_nearest(&scenes, ray(2.0)).unwrap().unwrap();
+        assert_eq!(index, 1 - near_index);
+        assert!((hit.ray.tfar - 9.0).abs() < 1e-4);
+
+        assert!(intersect_nearest(&scenes, ray(5.0)).unwrap().is_none());
+    }
+    assert!(intersect_nearest(&[], ray(0.0)).unwrap().is_none());
+}
+
+#[test]
+fn content_hash_follows_vertices() {
+    use crate::geometry::{SphereGeometry, TriangleMeshGeometry};
+
+    let device = Device::try_new(None).unwrap();
+    let vertices = [(0.0, 0.0, 0.0), (1.0, 0.0, 0.0), (0.0, 1.0, 0.0)];
+    let build = || {
+        let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+        let mesh = TriangleMeshGeometry::try_new(&device, &vertices, &[(0, 1, 2)]).unwrap();
+        scene.attach_geometry(&mesh).unwrap();
+        let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 5.0), 1.0).unwrap();
+        scene.attach_owned(sphere).unwrap();
+        (scene, mesh)
+    };
+
+    let (first, _) = build();
+    let (second, mesh) = build();
+    assert_eq!(first.content_hash(), second.content_hash());
+
+    let mut moved = vertices;
+    moved[1].0 = 1.5;
+    mesh.set_vertices(&moved).unwrap();
+    assert_ne!(first.content_hash(), second.content_hash());
+    mesh.set_vertices(&vertices).unwrap();
+    assert_eq!(first.content_hash(), second.content_hash());
+
+    second.set_flags(RTCSceneFlags::ROBUST).unwrap();
+    assert_ne!(first.content_hash(), second.content_hash());
+}
+
+#[test]
+fn content_hash_follows_instances_and_time_steps() {
+    use crate::geometry::{InstanceGeometry, SphereGeometry, TriangleMeshGeometry};
+
+    let device = Device::try_new(None).unwrap();
+    let object = |radius| {
+        let object = Scene::try_new(&device, SceneOptions::default()).unwrap();
+        let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 0.0), radius).unwrap();
+        object.attach_owned(sphere).unwrap();
+        object
+    };
+    let (small_object, large_object) = (object(1.0), object(2.0));
+    let small = small_object.commit().unwrap();
+    let large = large_object.commit().unwrap();
+    let identity = [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0];
+    let instance = InstanceGeometry::try_new(&device, &small, &identity).unwrap();
+    let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    scene.attach_geometry(&instance).unwrap();
+    let hash = scene.content_hash();
+
+    let mut moved = identity;
+    moved[9] = 5.0;
+    instance
+        .set_transform(embree4_sys::RTCFormat::FLOAT3X4_COLUMN_MAJOR, &moved)
+        .unwrap();
+    assert_ne!(scene.content_hash(), hash);
+    instance
+        .set_transform(embree4_sys::RTCFormat::FLOAT3X4_COLUMN_MAJOR, &identity)
+        .unwrap();
+    assert_eq!(scene.content_hash(), hash);
+
+    instance.set_scene(&large).unwrap();
+    assert_ne!(scene.content_hash(), hash);
+
+    // A second time step set after attaching is hashed
+    let vertices = [(0.0, 0.0, 0.0), (1.0, 0.0, 0.0), (0.0, 1.0, 0.0)];
+    let mesh = TriangleMeshGeometry::try_new(&device, &vertices, &[(0, 1, 2)]).unwrap();
+    let meshes = Scene::try_new(&device, SceneOptions::default()).unwrap();
+    meshes.attach_geometry(&mesh).unwrap();
+    let single = meshes.content_hash();
+    mesh.set_time_steps(&[&vertices, &vertices]).unwrap();
+    let repeated = meshes.content_hash();
+    assert_ne!(repeated, single);
+    let mut moved = vertices;
+    moved[0].2 = 1.0;
+    mesh.set_time_steps(&[&vertices, &moved]).unwrap();
+    assert_ne!(meshes.content_hash(), repeated);
+}
+
+#[test]
+fn fnv1a_reference_values() {
+    let hash = |bytes: &[u8]| {
+        let mut hash = Fnv1a::new();
+        hash.write(bytes);
+        hash.finish()
+    };
+    assert_eq!(hash(b""), 0xcbf2_9ce4_8422_2325);
+    assert_eq!(hash(b"a"), 0xaf63_dc4c_8601_ec8c);
+}
+
+#[test]
+fn aggregated_progress_reaches_one() {
+    use crate::geometry::TriangleMeshGeometry;
+
+    let device = Device::try_new(None).unwrap();
+    let scene_of = |num_tris: u32| {
+        let vertices: Vec<_> = (0..3 * num_tris)
+            .map(|i| {
+                let f = i as f32;
+                (f.sin(), f.cos(), 0.001 * f)
+            })
+            .collect();
+        let indices: Vec<_> = (0..num_tris)
+            .map(|i| (3 * i, 3 * i + 1, 3 * i + 2))
+            .collect();
+        let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+        let mesh = TriangleMeshGeometry::try_new(&device, &vertices, &indices).unwrap();
+        scene.attach_owned(mesh).unwrap();
+        scene
+    };
+    let scenes = [scene_of(100_000), scene_of(50_000)];
+
+    let mut aggregator = ProgressAggregator::new();
+    assert!(aggregator.is_done());
+    let commits: Vec<_> = scenes
+        .iter()
+        .map(|scene| {
+            let (commit, progress) = scene.commit_with_progress();
+            aggregator.add(progress);
+            commit
+        })
+        .collect();
+    assert!(!aggregator.is_done());
+
+    let mut fractions = Vec::new();
+    while !aggregator.is_done() {
+        fractions.push(aggregator.poll());
+        std::thread::yield_now();
+    }
+    assert!(fractions.iter().all(|f| (0.0..=1.0).contains(f)));
+    assert!(fractions.windows(2).all(|w| w[0] <= w[1]));
+    assert_eq!(fractions.last(), Some(&1.0));
+    assert_eq!(aggregator.fraction(), 1.0);
+
+    for commit in commits {
+        commit.join().unwrap();
+    }
+}
+
+#[test]
+fn cancelled_commit_leaves_the_scene_reusable() {
+    use crate::geometry::TriangleMeshGeometry;
+
+    let device = Device::try_new(None).unwrap();
+    let mut scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+
+    let num_tris = 100_000;
+    let vertices: Vec<_> = (0..3 * num_tris)
+        .map(|i| {
+            let f = i as f32;
+            (f.sin(), f.cos(), 0.001 * f)
+        })
+        .collect();
+    let indices: Vec<_> = (0..num_tris as u32)
+        .map(|i| (3 * i, 3 * i + 1, 3 * i + 2))
+        .collect();
+    let mesh = TriangleMeshGeometry::try_new(&device, &vertices, &indices).unwrap();
+    scene.attach_geometry(&mesh).unwrap();
+
+    {
+        let _scope = scene.register_scene_progress_monitor_callback(|_| false);
+        let err = scene.commit().err().unwrap();
+        assert_eq!(
+            err.downcast_ref::<EmbreeError>(),
+            Some(&EmbreeError::Cancelled)
+        );
+    }
+
+    let scene = scene.commit().unwrap();
+    // Through the centroid of the first triangle
+    let ray = RayBuilder::new((0.583, 0.375, -1.0), (0.0, 0.0, 1.0)).build();
+    assert!(scene.intersect_1(ray).unwrap().is_some());
+}
+
+#[test]
+fn committed_scene_outlives_its_scene() {
+    use crate::geometry::SphereGeometry;
+
+    let device = Device::try_new(None).unwrap();
+    let committed = {
+        let sphere = SphereGeometry::try_new(&device, (0.0, 0.0, 5.0), 1.0).unwrap();
+        let scene = Scene::try_new(&device, SceneOptions::default()).unwrap();
+        scene.attach_geometry(&sphere).unwrap();
+        scene.commit().unwrap()
+    };
+
+    let ray = RayBuilder::new((0.0, 0.0, 0.0), (0.0, 0.0, 1.0)).build();
+    let hit = committed.intersect_1(ray).unwrap().unwrap();
+    assert!((hit.ray.tfar - 4.0).abs() < 1e-3);
+}
@@ -0,0 +1,174 @@
+use anyhow::{bail, Result};
+use embree4_sys::{RTCBufferType, RTCBuildQuality, RTCSceneFlags};
+
+use crate::{
+    device::Device,
+    geometry::{Layout, SphereGeometry, TriangleMeshGeometry, Validation},
+    scene::{Scene, SceneOptions},
+};
+
+// Snapshot layout, all values little-endian:
+//   magic, version, build quality, flags, geometry count
+//   per geometry: geometry ID, kind, then
+//     triangles: vertex count, triangle count, time step count, the vertices of each time step
+//                as 3 floats, the indices as 3 u32
+//     sphere: center and radius as 4 floats
+const MAGIC: &[u8; 8] = b"EMB4SNAP";
+const VERSION: u32 = 1;
+
+const TRIANGLES: u32 = 0;
+const SPHERE: u32 = 1;
+
+pub(crate) fn write(options: &SceneOptions, geometries: &[(u32, Validation)]) -> Result<Vec<u8>> {
+    let mut blob = MAGIC.to_vec();
+    let mut put = |value: u32| blob.extend_from_slice(&value.to_le_bytes());
+    put(VERSION);
+    put(options.build_quality.0);
+    put(options.flags.0);
+    put(geometries.len() as u32);
+
+    for (geom_id, validation) in geometries {
+        blob.extend_from_slice(&geom_id.to_le_bytes());
+        // The layout has the time step count of the geometry as it is now, not when attached
+        match validation.layout() {
+            Layout::Triangles {
+                vertex_count,
+                triangle_count,
+                time_step_count,
+            } => {
+                for value in [
+                    TRIANGLES,
+                    vertex_count as u32,
+                    triangle_count as u32,
+                    time_step_count,
+                ] {
+                    blob.extend_from_slice(&value.to_le_bytes());
+                }
+                for slot in 0..time_step_count {
+                    let vertices =
+                        validation.buffer::<f32>(RTCBufferType::VERTEX, slot, 3 * vertex_count);
+                    extend_le(&mut blob, vertices.iter().map(|x| x.to_bits()));
+                }
+                let indices = validation.buffer::<u32>(RTCBufferType::INDEX, 0, 3 * triangle_count);
+                extend_le(&mut blob, indices.iter().copied());
+            }
+            Layout::Sphere => {
+                blob.extend_from_slice(&SPHERE.to_le_bytes());
+                let sphere = validation.buffer::<f32>(RTCBufferType::VERTEX, 0, 4);
+                extend_le(&mut blob, sphere.iter().map(|x| x.to_bits()));
+            }
+            Layout::Curves { .. } | Layout::Subdivision { .. } => {
+                bail!(
+                    "Geometry {}: only triangle meshes and spheres can be snapshotted",
+                    geom_id
+                );
+            }
+        }
+    }
+
+    Ok(blob)
+}
+
+pub(crate) fn read<'a>(device: &'a Device, blob: &[u8]) -> Result<Scene<'a>> {
+    let Some(blob) = blob.strip_prefix(MAGIC) else {
+        bail!("Not a scene snapshot");
+    };
+    let mut reader = Reader { blob };
+
+    let version = reader.u32()?;
+    if version != VERSION {
+        bail!("Unsupported scene snapshot version {}", version);
+    }
+    let options = SceneOptions {
+        build_quality: RTCBuildQuality(reader.u32()?),
+        flags: RTCSceneFlags(reader.u32()?),
+    };
+    let scene = Scene::try_new(device, options)?;
+
+    for _ in 0..reader.u32()? {
+        let geom_id = reader.u32()?;
+        let attached_id = match reader.u32()? {
+            TRIANGLES => {
+                let vertex_count = reader.u32()? as usize;
+                let triangle_count = reader.u32()? as usize;
+                let time_step_count = reader.u32()? as usize;
+
+                let time_steps = (0..time_step_count)
+                    .map(|_| {
+                        let vertices = reader.f32s(3 * vertex_count)?;
+                        Ok(vertices
+                            .chunks_exact(3)
+                            .map(|v| (v[0], v[1], v[2]))
+                            .collect::<Vec<_>>())
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                let indices = reader.u32s(3 * triangle_count)?;
+                let indices = indices
+                    .chunks_exact(3)
+                    .map(|i| (i[0], i[1], i[2]))
+                    .collect::<Vec<_>>();
+
+                let Some(first) = time_steps.first() else {
+                    bail!("Snapshot of geometry {} has no time step", geom_id);
+                };
+                let mesh = TriangleMeshGeometry::try_new(device, first, &indices)?;
+                if time_steps.len() > 1 {
+                    let time_steps = time_steps.iter().map(Vec::as_slice).collect::<Vec<_>>();
+                    mesh.set_time_steps(&time_steps)?;
+                }
+                scene.attach_owned(mesh)?
+            }
+            SPHERE => {
+                let sphere = reader.f32s(4)?;
+                let sphere =
+                    SphereGeometry::try_new(device, (sphere[0], sphere[1], sphere[2]), sphere[3])?;
+                scene.attach_owned(sphere)?
+            }
+            kind => bail!("Unknown geometry kind {} in scene snapshot", kind),
+        };
+
+        if attached_id != geom_id {
+            bail!(
+                "Snapshot geometry {} was attached with ID {}",
+                geom_id,
+                attached_id
+            );
+        }
+    }
+
+    if !reader.blob.is_empty() {
+        bail!("Scene snapshot has {} trailing bytes", reader.blob.len());
+    }
+    Ok(scene)
+}
+
+fn extend_le(blob: &mut Vec<u8>, values: impl Iterator<Item = u32>) {
+    for value in values {
+        blob.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+struct Reader<'b> {
+    blob: &'b [u8],
+}
+
+impl Reader<'_> {
+    fn u32(&mut self) -> Result<u32> {
+        let Some((value, rest)) = self.blob.split_first_chunk::<4>() else {
+            bail!("Scene snapshot is truncated");
+        };
+        self.blob = rest;
+        Ok(u32::from_le_bytes(*value))
+    }
+
+    fn u32s(&mut self, len: usize) -> Result<Vec<u32>> {
+        if self.blob.len() / 4 < len {
+            bail!("Scene snapshot is truncated");
+        }
+        (0..len).map(|_| self.u32()).collect()
+    }
+
+    fn f32s(&mut self, len: usize) -> Result<Vec<f32>> {
+        Ok(self.u32s(len)?.into_iter().map(f32::from_bits).collect())
+    }
+}
@@ -0,0 +1,105 @@
+//! Interop with Embree's SYCL backend, for geometry buffers living in unified shared memory.
+//!
+//! Requires linking against Embree 4.3 or later, which added
+//! [rtcSetSharedGeometryBufferHostDevice](https://github.com/embree/embree/blob/master/doc/src/api/rtcSetSharedGeometryBufferHostDevice.md):
+//! the crate does not link against older versions with the `sycl` feature enabled.
+
+use std::ffi::c_void;
+
+use anyhow::Result;
+use embree4_sys::{RTCBufferType, RTCFormat, RTCGeometry};
+
+use crate::{clear_device_error, device::Device, device_error_or};
+
+// Not generated by the bindings, which predate Embree 4.3
+extern "C" {
+    fn rtcSetSharedGeometryBufferHostDevice(
+        geometry: RTCGeometry,
+        buffer_type: RTCBufferType,
+        slot: u32,
+        format: RTCFormat,
+        ptr: *const c_void,
+        dptr: *const c_void,
+        byte_offset: usize,
+        byte_stride: usize,
+        item_count: usize,
+    );
+}
+
+/// A geometry buffer shared between the host and the device, e.g. allocated as USM.
+#[derive(Debug, Clone, Copy)]
+pub struct HostDeviceBuffer {
+    /// The type of the buffer.
+    pub buffer_type: RTCBufferType,
+    /// The slot of the buffer, for buffer types with several slots.
+    pub slot: u32,
+    /// The format of the items.
+    pub format: RTCFormat,
+    /// The data as seen from the host.
+    pub host_ptr: *const c_void,
+    /// The data as seen from the SYCL device. Ignored by CPU devices.
+    pub device_ptr: *const c_void,
+    /// The offset of the first item, in bytes.
+    pub byte_offset: usize,
+    /// The stride between items, in bytes.
+    pub byte_stride: usize,
+    /// The number of items.
+    pub item_count: usize,
+}
+
+/// Shares a buffer living in host and device memory with a geometry.
+///
+/// Embree reads the host data when building the BVH, and the device data while tracing rays on
+/// the GPU.
+///
+/// # Safety
+/// Both pointers must remain valid, and the data unchanged unless the geometry is recommitted,
+/// for as long as the geometry uses the buffer. `geometry` must be a valid geometry handle of
+/// `device`.
+///
+/// # Returns
+/// An error if Embree rejects the buffer.
+pub unsafe fn set_shared_buffer_host_device(
+    device: &Device,
+    geometry: RTCGeometry,
+    buffer: &HostDeviceBuffer,
+) -> Result<()> {
+    clear_device_error(device.handle);
+    rtcSetSharedGeometryBufferHostDevice(
+        geometry,
+        buffer.buffer_type,
+        buffer.slot,
+        buffer.format,
+        buffer.host_ptr,
+        buffer.device_ptr,
+        buffer.byte_offset,
+        buffer.byte_stride,
+        buffer.item_count,
+    );
+    device_error_or(device, (), "Could not set host-device shared buffer")
+}
+
+#[test]
+fn host_buffer_is_shared_with_geometry() {
+    use embree4_sys::RTCGeometryType;
+
+    let device = Device::try_new(None).unwrap();
+    let geometry = device.new_geometry(RTCGeometryType::TRIANGLE).unwrap();
+    let vertices = crate::buffer::AlignedVec::from_slice(&[0.0f32; 9]);
+    let buffer = HostDeviceBuffer {
+        buffer_type: RTCBufferType::VERTEX,
+        slot: 0,
+        format: RTCFormat::FLOAT3,
+        host_ptr: vertices.as_ptr() as *const c_void,
+        device_ptr: vertices.as_ptr() as *const c_void,
+        byte_offset: 0,
+        byte_stride: 3 * size_of::<f32>(),
+        item_count: 3,
+    };
+
+    unsafe { set_shared_buffer_host_device(&device, geometry.handle(), &buffer) }.unwrap();
+    let data = unsafe {
+        embree4_sys::rtcGetGeometryBufferData(geometry.handle(), RTCBufferType::VERTEX, 0)
+    };
+    assert_eq!(data as *const c_void, buffer.host_ptr);
+}